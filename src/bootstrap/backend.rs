@@ -0,0 +1,143 @@
+//! Parses `PG_TEST_BACKEND` into the backend that runs test clusters.
+//!
+//! Unknown values and backends missing from this build fail with a
+//! `SKIP-TEST-CLUSTER` error listing the supported values, rather than falling
+//! back to the default.
+
+use std::env::{self, VarError};
+
+use color_eyre::eyre::eyre;
+
+use crate::error::{BootstrapError, BootstrapResult};
+
+const BACKEND_ENV: &str = "PG_TEST_BACKEND";
+
+/// Identifies a cluster backend selected via `PG_TEST_BACKEND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BackendKind {
+    /// Native `PostgreSQL` managed by `postgresql_embedded` (the default).
+    PostgresqlEmbedded,
+    /// `PostgreSQL` compiled to WebAssembly via `pglite-oxide` (the `pglite`
+    /// feature).
+    PgliteOxide,
+    /// An existing server named by `DATABASE_URL`.
+    External,
+}
+
+impl BackendKind {
+    const ALL: [Self; 3] = [Self::PostgresqlEmbedded, Self::PgliteOxide, Self::External];
+
+    /// Returns the `PG_TEST_BACKEND` value selecting this backend.
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::PostgresqlEmbedded => "postgresql_embedded",
+            Self::PgliteOxide => "pglite_oxide",
+            Self::External => "external",
+        }
+    }
+
+    /// Reports whether this build can run the backend.
+    const fn is_available(self) -> bool {
+        match self {
+            Self::PostgresqlEmbedded | Self::External => true,
+            Self::PgliteOxide => cfg!(feature = "pglite"),
+        }
+    }
+
+    /// Reports whether the backend serves more than one database.
+    ///
+    /// `pglite-oxide` routes every connection to `template1`, whatever
+    /// database it names.
+    pub(crate) const fn serves_multiple_databases(self) -> bool {
+        !matches!(self, Self::PgliteOxide)
+    }
+
+    /// Reads the selected backend, defaulting to `postgresql_embedded` when
+    /// `PG_TEST_BACKEND` is unset or empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SKIP-TEST-CLUSTER` error for unknown values and for backends
+    /// this build does not include.
+    pub(crate) fn from_env() -> BootstrapResult<Self> {
+        let raw = match env::var(BACKEND_ENV) {
+            Ok(raw) => raw,
+            Err(VarError::NotPresent) => String::new(),
+            Err(VarError::NotUnicode(value)) => {
+                return Err(unsupported(&value.to_string_lossy()));
+            }
+        };
+        let kind = Self::parse(&raw)?;
+        if kind.is_available() {
+            Ok(kind)
+        } else {
+            Err(BootstrapError::from(eyre!(
+                "SKIP-TEST-CLUSTER: requested backend \"{}\" is not available in this build; \
+                 supported backends: {}",
+                kind.as_str(),
+                supported_backends()
+            )))
+        }
+    }
+
+    fn parse(raw: &str) -> BootstrapResult<Self> {
+        let trimmed = raw.trim();
+        if trimmed.is_empty() {
+            return Ok(Self::PostgresqlEmbedded);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == trimmed)
+            .ok_or_else(|| unsupported(trimmed))
+    }
+}
+
+fn unsupported(value: &str) -> BootstrapError {
+    BootstrapError::from(eyre!(
+        "SKIP-TEST-CLUSTER: unsupported {BACKEND_ENV} '{value}'; supported backends: {}",
+        supported_backends()
+    ))
+}
+
+fn supported_backends() -> String {
+    BackendKind::ALL
+        .into_iter()
+        .filter(|kind| kind.is_available())
+        .map(BackendKind::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::empty("", BackendKind::PostgresqlEmbedded)]
+    #[case::padded(" postgresql_embedded ", BackendKind::PostgresqlEmbedded)]
+    #[case::pglite("pglite_oxide", BackendKind::PgliteOxide)]
+    #[case::external("external", BackendKind::External)]
+    fn parse_recognises_backend_names(#[case] raw: &str, #[case] expected: BackendKind) {
+        assert_eq!(BackendKind::parse(raw).expect("known backend"), expected);
+    }
+
+    #[test]
+    fn parse_is_case_sensitive() {
+        let err = BackendKind::parse("PGLITE_OXIDE").expect_err("names are exact");
+        let expected = if cfg!(feature = "pglite") {
+            "supported backends: postgresql_embedded, pglite_oxide, external"
+        } else {
+            "supported backends: postgresql_embedded, external"
+        };
+
+        assert!(err.to_string().contains(expected), "got: {err}");
+    }
+
+    #[test]
+    fn only_pglite_serves_a_single_database() {
+        assert!(BackendKind::PostgresqlEmbedded.serves_multiple_databases());
+        assert!(BackendKind::External.serves_multiple_databases());
+        assert!(!BackendKind::PgliteOxide.serves_multiple_databases());
+    }
+}
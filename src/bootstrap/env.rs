@@ -1,8 +1,10 @@
 //! Parses environment variables used by the bootstrapper and surfaces the
 //! resulting configuration for the filesystem preparers.
+use crate::bootstrap::CleanupMode;
 pub use crate::bootstrap::env_types::TestBootstrapEnvironment;
 use crate::bootstrap::env_types::TimezoneEnv;
 pub(super) use crate::bootstrap::env_types::XdgDirs;
+use crate::bootstrap::hba::AuthMethod;
 use crate::bootstrap::mode::ExecutionPrivileges;
 use crate::error::{BootstrapError, BootstrapErrorKind, BootstrapResult};
 use crate::fs::ambient_dir_and_path;
@@ -22,6 +24,13 @@ const WORKER_BINARY_NAME: &str = "pg_worker.exe";
 pub(super) const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
 const MAX_SHUTDOWN_TIMEOUT_SECS: u64 = 600;
 const SHUTDOWN_TIMEOUT_ENV: &str = "PG_SHUTDOWN_TIMEOUT_SECS";
+const CLEANUP_MODE_ENV: &str = "PG_CLEANUP_MODE";
+const SERVER_LOG_FORWARD_ENV: &str = "PG_SERVER_LOG_FORWARD";
+const UNIX_SOCKET_ONLY_ENV: &str = "PG_UNIX_SOCKET_ONLY";
+const TLS_ENV: &str = "PG_TLS";
+const PERSISTENT_WORKER_ENV: &str = "PG_PERSISTENT_WORKER";
+const ALLOW_UNVERIFIED_ARCHIVE_ENV: &str = "PG_BINARY_ARCHIVE_ALLOW_UNVERIFIED";
+const AUTH_METHOD_ENV: &str = "PG_AUTH_METHOD";
 
 fn discover_worker_from_path() -> BootstrapResult<Option<Utf8PathBuf>> {
     discover_worker_from_path_value(env::var_os("PATH"))
@@ -147,6 +156,99 @@ pub(super) fn shutdown_timeout_from_env() -> BootstrapResult<Duration> {
     }
 }
 
+/// Reads the cleanup mode from `PG_CLEANUP_MODE`, defaulting to
+/// [`CleanupMode::DataOnly`] when unset.
+pub(super) fn cleanup_mode_from_env() -> BootstrapResult<CleanupMode> {
+    match env::var(CLEANUP_MODE_ENV) {
+        Ok(raw) => CleanupMode::parse(&raw).ok_or_else(|| {
+            BootstrapError::from(color_eyre::eyre::eyre!(
+                "{CLEANUP_MODE_ENV} must be one of data_only, full, none, or keep_on_failure (received '{}')",
+                raw.trim()
+            ))
+        }),
+        Err(VarError::NotPresent) => Ok(CleanupMode::default()),
+        Err(VarError::NotUnicode(value)) => Err(BootstrapError::from(color_eyre::eyre::eyre!(
+            "{CLEANUP_MODE_ENV} must contain a valid UTF-8 value (received {:?})",
+            value
+        ))),
+    }
+}
+
+/// Reads whether server log lines should be forwarded into `tracing`.
+pub(super) fn server_log_forward_from_env() -> BootstrapResult<bool> {
+    Ok(bool_from_env(SERVER_LOG_FORWARD_ENV)?.unwrap_or(false))
+}
+
+/// Reads whether clusters should listen only on a Unix-domain socket.
+///
+/// Returns `None` when `PG_UNIX_SOCKET_ONLY` is unset so builder overrides can
+/// take precedence.
+pub(super) fn unix_socket_only_from_env() -> BootstrapResult<Option<bool>> {
+    bool_from_env(UNIX_SOCKET_ONLY_ENV)
+}
+
+/// Reads whether clusters should serve TLS with generated certificates.
+///
+/// Returns `None` when `PG_TLS` is unset so builder overrides can take
+/// precedence.
+pub(super) fn tls_from_env() -> BootstrapResult<Option<bool>> {
+    bool_from_env(TLS_ENV)
+}
+
+/// Reads whether root runs should keep one worker per cluster alive.
+///
+/// Returns `None` when `PG_PERSISTENT_WORKER` is unset so builder overrides
+/// can take precedence.
+pub(super) fn persistent_worker_from_env() -> BootstrapResult<Option<bool>> {
+    bool_from_env(PERSISTENT_WORKER_ENV)
+}
+
+/// Reads whether release archives without a SHA-256 digest may be imported.
+///
+/// Returns `None` when `PG_BINARY_ARCHIVE_ALLOW_UNVERIFIED` is unset so
+/// builder overrides can take precedence.
+pub(super) fn allow_unverified_archive_from_env() -> BootstrapResult<Option<bool>> {
+    bool_from_env(ALLOW_UNVERIFIED_ARCHIVE_ENV)
+}
+
+/// Reads the authentication method applied to the default `pg_hba.conf`
+/// rules, or `None` when `PG_AUTH_METHOD` is unset or empty.
+pub(super) fn auth_method_from_env() -> BootstrapResult<Option<AuthMethod>> {
+    match env::var(AUTH_METHOD_ENV) {
+        Ok(raw) if raw.trim().is_empty() => Ok(None),
+        Ok(raw) => AuthMethod::parse(&raw).map(Some).ok_or_else(|| {
+            BootstrapError::from(color_eyre::eyre::eyre!(
+                "{AUTH_METHOD_ENV} must be one of trust, reject, scram-sha-256, md5, password, peer, or cert (received '{}')",
+                raw.trim()
+            ))
+        }),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(value)) => Err(BootstrapError::from(color_eyre::eyre::eyre!(
+            "{AUTH_METHOD_ENV} must contain a valid UTF-8 value (received {:?})",
+            value
+        ))),
+    }
+}
+
+/// Parses a boolean flag such as `1`, `true`, `off`, or an empty value.
+fn bool_from_env(name: &str) -> BootstrapResult<Option<bool>> {
+    match env::var(name) {
+        Ok(raw) => match raw.trim().to_ascii_lowercase().as_str() {
+            "" | "0" | "false" | "no" | "off" => Ok(Some(false)),
+            "1" | "true" | "yes" | "on" => Ok(Some(true)),
+            _ => Err(BootstrapError::from(color_eyre::eyre::eyre!(
+                "{name} must be a boolean such as 1 or 0 (received '{}')",
+                raw.trim()
+            ))),
+        },
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(value)) => Err(BootstrapError::from(color_eyre::eyre::eyre!(
+            "{name} must contain a valid UTF-8 value (received {:?})",
+            value
+        ))),
+    }
+}
+
 pub(super) fn worker_binary_from_env(
     privileges: ExecutionPrivileges,
 ) -> BootstrapResult<Option<Utf8PathBuf>> {
@@ -181,6 +283,65 @@ pub(super) fn worker_binary_from_env(
     Ok(None)
 }
 
+/// Where privileged lifecycle operations run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum WorkerLocation {
+    /// A `pg_worker` binary named by `PG_EMBEDDED_WORKER` or found on `PATH`.
+    Binary(Utf8PathBuf),
+    /// The current program, re-executed through its hidden worker entry point.
+    #[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+    Embedded,
+    /// No worker; lifecycle operations run in-process.
+    None,
+}
+
+impl WorkerLocation {
+    /// Splits the location into the `worker_binary` and `embedded_worker`
+    /// bootstrap settings.
+    pub(super) fn into_settings(self) -> (Option<Utf8PathBuf>, bool) {
+        match self {
+            Self::Binary(path) => (Some(path), false),
+            #[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+            Self::Embedded => (Some(Utf8PathBuf::from(crate::worker_entry::SELF_EXE)), true),
+            Self::None => (None, false),
+        }
+    }
+}
+
+/// Resolves the worker for privileged operations, falling back to the
+/// embedded worker when a root run finds no `pg_worker` binary and the
+/// `embedded-worker` feature is enabled.
+pub(super) fn resolve_worker(privileges: ExecutionPrivileges) -> BootstrapResult<WorkerLocation> {
+    Ok(select_worker(
+        privileges,
+        worker_binary_from_env(privileges)?,
+    ))
+}
+
+fn select_worker(privileges: ExecutionPrivileges, found: Option<Utf8PathBuf>) -> WorkerLocation {
+    match found {
+        Some(path) => WorkerLocation::Binary(path),
+        None if privileges == ExecutionPrivileges::Root => embedded_worker(),
+        None => WorkerLocation::None,
+    }
+}
+
+/// Re-executes the current program, whose `.init_array` hook diverts into
+/// the worker before `main` runs.
+#[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+fn embedded_worker() -> WorkerLocation {
+    tracing::info!(
+        target: crate::observability::LOG_TARGET,
+        "no pg_worker binary found; re-executing the current program as the worker"
+    );
+    WorkerLocation::Embedded
+}
+
+#[cfg(not(all(target_os = "linux", feature = "embedded-worker")))]
+const fn embedded_worker() -> WorkerLocation {
+    WorkerLocation::None
+}
+
 fn validate_worker_path(path: &Utf8PathBuf) -> BootstrapResult<()> {
     if path.as_str().is_empty() {
         return Err(BootstrapError::from(color_eyre::eyre::eyre!(
@@ -1,6 +1,16 @@
 //! Tests for bootstrap environment discovery helpers.
 
-use super::{BootstrapErrorKind, WORKER_BINARY_NAME, discover_worker_from_path_value};
+use super::{
+    ALLOW_UNVERIFIED_ARCHIVE_ENV, AUTH_METHOD_ENV, BootstrapErrorKind, CLEANUP_MODE_ENV,
+    PERSISTENT_WORKER_ENV, SERVER_LOG_FORWARD_ENV, UNIX_SOCKET_ONLY_ENV, WORKER_BINARY_NAME,
+    WorkerLocation, allow_unverified_archive_from_env, auth_method_from_env, cleanup_mode_from_env,
+    discover_worker_from_path_value, persistent_worker_from_env, select_worker,
+    server_log_forward_from_env, unix_socket_only_from_env,
+};
+use crate::test_support::scoped_env;
+use crate::{AuthMethod, CleanupMode, ExecutionPrivileges};
+use camino::Utf8PathBuf;
+use rstest::rstest;
 use std::ffi::OsString;
 use std::os::unix::ffi::OsStringExt;
 use std::os::unix::fs::PermissionsExt;
@@ -43,3 +53,166 @@ fn discover_worker_errors_on_non_utf8_path_entry() {
         "expected error mentioning PATH non-UTF-8, got: {message}"
     );
 }
+
+#[test]
+fn select_worker_prefers_a_found_binary() {
+    let found = Utf8PathBuf::from("/usr/local/bin/pg_worker");
+    assert_eq!(
+        select_worker(ExecutionPrivileges::Root, Some(found.clone())),
+        WorkerLocation::Binary(found)
+    );
+}
+
+#[test]
+fn select_worker_leaves_unprivileged_runs_in_process() {
+    assert_eq!(
+        select_worker(ExecutionPrivileges::Unprivileged, None),
+        WorkerLocation::None
+    );
+}
+
+#[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+#[test]
+fn select_worker_re_executes_the_current_program_for_root() {
+    let location = select_worker(ExecutionPrivileges::Root, None);
+    assert_eq!(location, WorkerLocation::Embedded);
+    let (worker, embedded) = location.into_settings();
+    assert_eq!(worker, Some(Utf8PathBuf::from("/proc/self/exe")));
+    assert!(embedded);
+}
+
+#[cfg(not(all(target_os = "linux", feature = "embedded-worker")))]
+#[test]
+fn select_worker_finds_no_worker_for_root_without_the_embedded_worker() {
+    assert_eq!(
+        select_worker(ExecutionPrivileges::Root, None),
+        WorkerLocation::None
+    );
+}
+
+#[rstest]
+#[case::unset(None, CleanupMode::DataOnly)]
+#[case::full(Some("full"), CleanupMode::Full)]
+#[case::keep_on_failure(Some("keep_on_failure"), CleanupMode::KeepOnFailure)]
+#[case::hyphenated(Some(" Keep-On-Failure "), CleanupMode::KeepOnFailure)]
+fn cleanup_mode_from_env_parses_modes(#[case] raw: Option<&str>, #[case] expected: CleanupMode) {
+    let _guard = scoped_env([(OsString::from(CLEANUP_MODE_ENV), raw.map(OsString::from))]);
+    let mode = cleanup_mode_from_env().expect("cleanup mode should parse");
+    assert_eq!(mode, expected);
+}
+
+#[test]
+fn cleanup_mode_from_env_rejects_unknown_modes() {
+    let _guard = scoped_env([(
+        OsString::from(CLEANUP_MODE_ENV),
+        Some(OsString::from("sometimes")),
+    )]);
+    let err = cleanup_mode_from_env().expect_err("unknown cleanup mode should fail");
+    assert!(
+        err.to_string().contains("keep_on_failure"),
+        "expected error listing valid modes, got: {err}"
+    );
+}
+
+#[rstest]
+#[case::unset(None, false)]
+#[case::enabled(Some("1"), true)]
+#[case::spelled(Some(" On "), true)]
+#[case::disabled(Some("false"), false)]
+fn server_log_forward_from_env_parses_booleans(#[case] raw: Option<&str>, #[case] expected: bool) {
+    let _guard = scoped_env([(
+        OsString::from(SERVER_LOG_FORWARD_ENV),
+        raw.map(OsString::from),
+    )]);
+    let forward = server_log_forward_from_env().expect("forward flag should parse");
+    assert_eq!(forward, expected);
+}
+
+#[test]
+fn server_log_forward_from_env_rejects_non_booleans() {
+    let _guard = scoped_env([(
+        OsString::from(SERVER_LOG_FORWARD_ENV),
+        Some(OsString::from("sometimes")),
+    )]);
+    let err = server_log_forward_from_env().expect_err("non-boolean flag should fail");
+    assert!(
+        err.to_string().contains(SERVER_LOG_FORWARD_ENV),
+        "expected error naming the variable, got: {err}"
+    );
+}
+
+#[rstest]
+#[case::unset(None, None)]
+#[case::enabled(Some("yes"), Some(true))]
+#[case::disabled(Some("0"), Some(false))]
+fn unix_socket_only_from_env_parses_optional_booleans(
+    #[case] raw: Option<&str>,
+    #[case] expected: Option<bool>,
+) {
+    let _guard = scoped_env([(
+        OsString::from(UNIX_SOCKET_ONLY_ENV),
+        raw.map(OsString::from),
+    )]);
+    let socket_only = unix_socket_only_from_env().expect("socket flag should parse");
+    assert_eq!(socket_only, expected);
+}
+
+#[rstest]
+#[case::unset(None, None)]
+#[case::enabled(Some("on"), Some(true))]
+#[case::disabled(Some("false"), Some(false))]
+fn persistent_worker_from_env_parses_optional_booleans(
+    #[case] raw: Option<&str>,
+    #[case] expected: Option<bool>,
+) {
+    let _guard = scoped_env([(
+        OsString::from(PERSISTENT_WORKER_ENV),
+        raw.map(OsString::from),
+    )]);
+    let persistent = persistent_worker_from_env().expect("persistent worker flag should parse");
+    assert_eq!(persistent, expected);
+}
+
+#[rstest]
+#[case::unset(None, None)]
+#[case::numeric(Some("1"), Some(true))]
+#[case::disabled(Some("off"), Some(false))]
+fn allow_unverified_archive_from_env_parses_optional_booleans(
+    #[case] raw: Option<&str>,
+    #[case] expected: Option<bool>,
+) {
+    let _guard = scoped_env([(
+        OsString::from(ALLOW_UNVERIFIED_ARCHIVE_ENV),
+        raw.map(OsString::from),
+    )]);
+    let allowed = allow_unverified_archive_from_env().expect("archive flag should parse");
+    assert_eq!(allowed, expected);
+}
+
+#[rstest]
+#[case::unset(None, None)]
+#[case::empty(Some("  "), None)]
+#[case::scram(Some("scram-sha-256"), Some(AuthMethod::ScramSha256))]
+#[case::uppercase(Some("TRUST"), Some(AuthMethod::Trust))]
+fn auth_method_from_env_parses_methods(
+    #[case] raw: Option<&str>,
+    #[case] expected: Option<AuthMethod>,
+) {
+    let _guard = scoped_env([(OsString::from(AUTH_METHOD_ENV), raw.map(OsString::from))]);
+    let method = auth_method_from_env().expect("auth method should parse");
+    assert_eq!(method, expected);
+}
+
+#[test]
+fn auth_method_from_env_rejects_unknown_methods() {
+    let _guard = scoped_env([(
+        OsString::from(AUTH_METHOD_ENV),
+        Some(OsString::from("kerberos")),
+    )]);
+    let err = auth_method_from_env().expect_err("unknown method should fail");
+    let message = err.to_string();
+    assert!(
+        message.contains(AUTH_METHOD_ENV) && message.contains("kerberos"),
+        "expected error naming the variable and value, got: {message}"
+    );
+}
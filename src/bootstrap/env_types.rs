@@ -1,6 +1,7 @@
 //! Types describing the bootstrap environment configuration.
 
 use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub(super) struct TimezoneEnv {
@@ -25,7 +26,7 @@ pub(super) struct TimezoneEnv {
 /// };
 /// assert_eq!(environment.to_env().len(), 6);
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestBootstrapEnvironment {
     /// Effective home directory for the `PostgreSQL` user during the tests.
     pub home: Utf8PathBuf,
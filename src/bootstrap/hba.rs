@@ -0,0 +1,536 @@
+//! Client authentication rules written to `pg_hba.conf`.
+//!
+//! `postgresql_embedded` always runs `initdb --auth=password`. [`HbaConfig`]
+//! replaces the resulting `pg_hba.conf` after setup and before the server
+//! starts, either with the `initdb` default rules under another method (the
+//! equivalent of `initdb --auth`) or with an explicit rule list. Methods that
+//! `PostgreSQL` refuses for a connection type, or that would lock out the
+//! crate's password-authenticated superuser, are rejected before anything is
+//! written.
+
+use crate::error::{BootstrapError, BootstrapResult};
+use color_eyre::eyre::{Context, eyre};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// File name of the host-based authentication configuration.
+pub(crate) const PG_HBA_FILE: &str = "pg_hba.conf";
+
+/// Authentication method named in a `pg_hba.conf` rule.
+///
+/// # Examples
+/// ```
+/// use pg_embedded_setup_unpriv::AuthMethod;
+///
+/// assert_eq!(AuthMethod::parse("SCRAM-SHA-256"), Some(AuthMethod::ScramSha256));
+/// assert_eq!(AuthMethod::Md5.to_string(), "md5");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AuthMethod {
+    /// Allows the connection unconditionally.
+    Trust,
+    /// Rejects the connection unconditionally.
+    Reject,
+    /// Requires a SCRAM-SHA-256 password exchange.
+    ScramSha256,
+    /// Requires an MD5 or, for SCRAM-hashed passwords, SCRAM password exchange.
+    Md5,
+    /// Requires a clear-text password.
+    Password,
+    /// Uses the client's operating system user name (local sockets only).
+    Peer,
+    /// Requires a client certificate (TLS connections only).
+    Cert,
+}
+
+impl AuthMethod {
+    /// Returns the keyword `pg_hba.conf` uses for the method.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Trust => "trust",
+            Self::Reject => "reject",
+            Self::ScramSha256 => "scram-sha-256",
+            Self::Md5 => "md5",
+            Self::Password => "password",
+            Self::Peer => "peer",
+            Self::Cert => "cert",
+        }
+    }
+
+    /// Parses a method keyword, ignoring case and surrounding whitespace.
+    #[must_use]
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "trust" => Some(Self::Trust),
+            "reject" => Some(Self::Reject),
+            "scram-sha-256" => Some(Self::ScramSha256),
+            "md5" => Some(Self::Md5),
+            "password" => Some(Self::Password),
+            "peer" => Some(Self::Peer),
+            "cert" => Some(Self::Cert),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Connection type matched by a `pg_hba.conf` rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HbaConnectionType {
+    /// Unix-domain socket connections.
+    Local,
+    /// TCP connections, with or without TLS.
+    Host,
+    /// TCP connections using TLS.
+    HostSsl,
+    /// TCP connections without TLS.
+    HostNoSsl,
+}
+
+impl HbaConnectionType {
+    /// Returns the keyword `pg_hba.conf` uses for the connection type.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Host => "host",
+            Self::HostSsl => "hostssl",
+            Self::HostNoSsl => "hostnossl",
+        }
+    }
+}
+
+/// One `pg_hba.conf` rule.
+///
+/// Rules match every database and user until narrowed with
+/// [`Self::database`] and [`Self::user`].
+///
+/// # Examples
+/// ```
+/// use pg_embedded_setup_unpriv::{AuthMethod, HbaRule};
+///
+/// let rule = HbaRule::host("127.0.0.1/32", AuthMethod::Md5)
+///     .database("app")
+///     .user("app_user");
+/// assert_eq!(rule.to_string(), "host app app_user 127.0.0.1/32 md5");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HbaRule {
+    connection_type: HbaConnectionType,
+    database: String,
+    user: String,
+    address: Option<String>,
+    method: AuthMethod,
+}
+
+impl HbaRule {
+    /// Matches Unix-domain socket connections.
+    #[must_use]
+    pub fn local(method: AuthMethod) -> Self {
+        Self::new(HbaConnectionType::Local, None, method)
+    }
+
+    /// Matches TCP connections from `address`, such as `127.0.0.1/32`.
+    #[must_use]
+    pub fn host(address: impl Into<String>, method: AuthMethod) -> Self {
+        Self::new(HbaConnectionType::Host, Some(address.into()), method)
+    }
+
+    /// Matches TLS connections from `address`.
+    #[must_use]
+    pub fn host_ssl(address: impl Into<String>, method: AuthMethod) -> Self {
+        Self::new(HbaConnectionType::HostSsl, Some(address.into()), method)
+    }
+
+    /// Matches non-TLS TCP connections from `address`.
+    #[must_use]
+    pub fn host_no_ssl(address: impl Into<String>, method: AuthMethod) -> Self {
+        Self::new(HbaConnectionType::HostNoSsl, Some(address.into()), method)
+    }
+
+    fn new(
+        connection_type: HbaConnectionType,
+        address: Option<String>,
+        method: AuthMethod,
+    ) -> Self {
+        Self {
+            connection_type,
+            database: "all".into(),
+            user: "all".into(),
+            address,
+            method,
+        }
+    }
+
+    /// Restricts the rule to `database` (or a keyword such as `replication`).
+    #[must_use]
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = database.into();
+        self
+    }
+
+    /// Restricts the rule to `user` (or a `+group` reference).
+    #[must_use]
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = user.into();
+        self
+    }
+
+    /// Returns the authentication method the rule applies.
+    #[must_use]
+    pub const fn method(&self) -> AuthMethod {
+        self.method
+    }
+}
+
+impl fmt::Display for HbaRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.connection_type.as_str(),
+            token(&self.database),
+            token(&self.user)
+        )?;
+        if let Some(address) = &self.address {
+            write!(f, " {}", token(address))?;
+        }
+        write!(f, " {}", self.method)
+    }
+}
+
+/// Quotes a `pg_hba.conf` field that would otherwise split or start a comment.
+///
+/// `pg_hba.conf` has no escape for a double quote inside a quoted field, so
+/// embedded quotes are dropped.
+fn token(value: &str) -> String {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '#' | ',' | '"'));
+    if needs_quotes {
+        format!("\"{}\"", value.replace('"', ""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Client authentication configuration applied before the server starts.
+///
+/// The default leaves the `pg_hba.conf` written by `initdb` untouched. Setting
+/// [`Self::auth_method`] rewrites the default local and loopback rules with
+/// that method, mirroring `initdb --auth`. [`AuthMethod::Peer`] only applies
+/// to the local rules, and the loopback rules then use
+/// [`AuthMethod::ScramSha256`]. [`AuthMethod::Reject`] and [`AuthMethod::Cert`]
+/// are refused as an `auth_method`, because the crate's administrative
+/// connections log in with a password over TCP. A non-empty [`Self::rules`]
+/// list replaces the file entirely, and `auth_method` is then ignored.
+///
+/// Rules are matched top to bottom. Keep a rule admitting the superuser from
+/// the cluster's host, since the crate's own administrative connections use
+/// it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HbaConfig {
+    /// Method applied to the `initdb` default rules.
+    pub auth_method: Option<AuthMethod>,
+    /// Explicit rules that replace `pg_hba.conf`.
+    pub rules: Vec<HbaRule>,
+}
+
+impl HbaConfig {
+    /// Renders the `pg_hba.conf` contents, or `None` to keep the `initdb`
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`Self::validate`] rejects the configuration.
+    ///
+    /// # Examples
+    /// ```
+    /// use pg_embedded_setup_unpriv::{AuthMethod, HbaConfig};
+    ///
+    /// let hba = HbaConfig {
+    ///     auth_method: Some(AuthMethod::Trust),
+    ///     ..HbaConfig::default()
+    /// };
+    /// let rendered = hba.render()?.expect("rules are rendered");
+    /// assert!(rendered.contains("host all all 127.0.0.1/32 trust"));
+    /// assert_eq!(HbaConfig::default().render()?, None);
+    /// # Ok::<(), pg_embedded_setup_unpriv::BootstrapError>(())
+    /// ```
+    pub fn render(&self) -> BootstrapResult<Option<String>> {
+        self.validate()?;
+        let rules = if self.rules.is_empty() {
+            let Some(method) = self.auth_method else {
+                return Ok(None);
+            };
+            default_rules(method)
+        } else {
+            self.rules.clone()
+        };
+        let mut contents = String::from("# Generated by pg-embed-setup-unpriv.\n");
+        for rule in rules {
+            contents.push_str(&rule.to_string());
+            contents.push('\n');
+        }
+        Ok(Some(contents))
+    }
+
+    /// Checks that every rule uses a method valid for its connection type
+    /// and that [`Self::auth_method`] keeps the superuser able to log in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a rule uses [`AuthMethod::Peer`] on a TCP
+    /// connection type or [`AuthMethod::Cert`] outside `hostssl`, or if the
+    /// default rules would use [`AuthMethod::Reject`] or [`AuthMethod::Cert`].
+    pub fn validate(&self) -> BootstrapResult<()> {
+        if self.rules.is_empty() {
+            return self.auth_method.map_or(Ok(()), validate_auth_method);
+        }
+        self.rules.iter().try_for_each(validate_rule)
+    }
+}
+
+/// Refuses default-rule methods that would lock out the superuser.
+fn validate_auth_method(method: AuthMethod) -> BootstrapResult<()> {
+    if matches!(method, AuthMethod::Reject | AuthMethod::Cert) {
+        return Err(BootstrapError::from(eyre!(
+            "auth method {method} would lock out the superuser, which logs in \
+             with a password over TCP; use hba_rule() to write the rules explicitly"
+        )));
+    }
+    Ok(())
+}
+
+/// Refuses methods `PostgreSQL` rejects for the rule's connection type.
+fn validate_rule(rule: &HbaRule) -> BootstrapResult<()> {
+    let valid = match rule.method {
+        AuthMethod::Peer => rule.connection_type == HbaConnectionType::Local,
+        AuthMethod::Cert => rule.connection_type == HbaConnectionType::HostSsl,
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(BootstrapError::from(eyre!(
+            "auth method {} is not valid for {} rules: {rule}",
+            rule.method,
+            rule.connection_type.as_str()
+        )))
+    }
+}
+
+/// Writes the rendered rules over `<data_dir>/pg_hba.conf`.
+///
+/// Does nothing when `hba` keeps the `initdb` file. The existing file is
+/// truncated in place, so its owner and mode are preserved.
+pub(crate) fn write_pg_hba(data_dir: &Path, hba: &HbaConfig) -> BootstrapResult<()> {
+    let Some(contents) = hba.render()? else {
+        return Ok(());
+    };
+    let path = data_dir.join(PG_HBA_FILE);
+    fs::write(&path, contents)
+        .wrap_err_with(|| format!("failed to write {}", path.display()))
+        .map_err(BootstrapError::from)
+}
+
+/// Returns the rules `initdb --auth=<method>` writes.
+///
+/// Peer authentication only exists for local sockets, so the loopback rules
+/// fall back to SCRAM, as with `initdb --auth-local=peer`.
+fn default_rules(method: AuthMethod) -> Vec<HbaRule> {
+    let host_method = if method == AuthMethod::Peer {
+        AuthMethod::ScramSha256
+    } else {
+        method
+    };
+    vec![
+        HbaRule::local(method),
+        HbaRule::host("127.0.0.1/32", host_method),
+        HbaRule::host("::1/128", host_method),
+        HbaRule::local(method).database("replication"),
+        HbaRule::host("127.0.0.1/32", host_method).database("replication"),
+        HbaRule::host("::1/128", host_method).database("replication"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const ALL_METHODS: [AuthMethod; 7] = [
+        AuthMethod::Trust,
+        AuthMethod::Reject,
+        AuthMethod::ScramSha256,
+        AuthMethod::Md5,
+        AuthMethod::Password,
+        AuthMethod::Peer,
+        AuthMethod::Cert,
+    ];
+
+    /// Parses a rendered rule line back into its connection type and method.
+    fn parse_line(line: &str) -> (&str, Option<AuthMethod>) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let connection_type = fields.first().copied().unwrap_or_default();
+        let method = fields.last().and_then(|raw| AuthMethod::parse(raw));
+        (connection_type, method)
+    }
+
+    #[test]
+    fn render_rewrites_default_rules_with_auth_method() {
+        let hba = HbaConfig {
+            auth_method: Some(AuthMethod::ScramSha256),
+            rules: Vec::new(),
+        };
+
+        let rendered = hba.render().expect("valid rules").expect("rendered rules");
+        let lines: Vec<&str> = rendered.lines().skip(1).collect();
+
+        assert_eq!(
+            lines,
+            [
+                "local all all scram-sha-256",
+                "host all all 127.0.0.1/32 scram-sha-256",
+                "host all all ::1/128 scram-sha-256",
+                "local replication all scram-sha-256",
+                "host replication all 127.0.0.1/32 scram-sha-256",
+                "host replication all ::1/128 scram-sha-256",
+            ]
+        );
+    }
+
+    #[test]
+    fn render_prefers_explicit_rules() {
+        let hba = HbaConfig {
+            auth_method: Some(AuthMethod::Trust),
+            rules: vec![
+                HbaRule::host_ssl("0.0.0.0/0", AuthMethod::Md5).user("app user"),
+                HbaRule::host_no_ssl("0.0.0.0/0", AuthMethod::Reject),
+            ],
+        };
+
+        let rendered = hba.render().expect("valid rules").expect("rendered rules");
+
+        assert!(rendered.contains("hostssl all \"app user\" 0.0.0.0/0 md5\n"));
+        assert!(rendered.contains("hostnossl all all 0.0.0.0/0 reject\n"));
+        assert!(!rendered.contains("trust"));
+    }
+
+    #[rstest]
+    #[case::trust(AuthMethod::Trust, "trust", "trust")]
+    #[case::scram(AuthMethod::ScramSha256, "scram-sha-256", "scram-sha-256")]
+    #[case::md5(AuthMethod::Md5, "md5", "md5")]
+    #[case::password(AuthMethod::Password, "password", "password")]
+    #[case::peer(AuthMethod::Peer, "peer", "scram-sha-256")]
+    fn render_uses_methods_valid_for_each_connection_type(
+        #[case] method: AuthMethod,
+        #[case] local_method: &str,
+        #[case] host_method: &str,
+    ) {
+        let hba = HbaConfig {
+            auth_method: Some(method),
+            rules: Vec::new(),
+        };
+
+        let rendered = hba.render().expect("valid rules").expect("rendered rules");
+
+        for line in rendered.lines().skip(1) {
+            let (connection_type, parsed) = parse_line(line);
+            let expected = if connection_type == "local" {
+                local_method
+            } else {
+                host_method
+            };
+            assert_eq!(parsed.map(AuthMethod::as_str), Some(expected), "{line}");
+        }
+    }
+
+    #[rstest]
+    #[case::reject(AuthMethod::Reject)]
+    #[case::cert(AuthMethod::Cert)]
+    fn render_refuses_methods_that_lock_out_the_superuser(#[case] method: AuthMethod) {
+        let hba = HbaConfig {
+            auth_method: Some(method),
+            rules: Vec::new(),
+        };
+
+        let err = hba.render().expect_err("method should be refused");
+        assert!(
+            err.to_string().contains("lock out the superuser"),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[test]
+    fn validate_checks_each_method_against_connection_types() {
+        for method in ALL_METHODS {
+            let local = HbaConfig {
+                rules: vec![HbaRule::local(method)],
+                ..HbaConfig::default()
+            };
+            let host = HbaConfig {
+                rules: vec![HbaRule::host("127.0.0.1/32", method)],
+                ..HbaConfig::default()
+            };
+            let host_ssl = HbaConfig {
+                rules: vec![HbaRule::host_ssl("127.0.0.1/32", method)],
+                ..HbaConfig::default()
+            };
+
+            assert_eq!(
+                local.validate().is_ok(),
+                method != AuthMethod::Cert,
+                "{method}"
+            );
+            assert_eq!(
+                host.validate().is_ok(),
+                !matches!(method, AuthMethod::Peer | AuthMethod::Cert),
+                "{method}"
+            );
+            assert_eq!(
+                host_ssl.validate().is_ok(),
+                method != AuthMethod::Peer,
+                "{method}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_pg_hba_keeps_initdb_file_without_configuration() {
+        let data_dir = tempfile::tempdir().expect("tempdir");
+        let path = data_dir.path().join(PG_HBA_FILE);
+        fs::write(&path, "host all all 127.0.0.1/32 password\n").expect("seed pg_hba.conf");
+
+        write_pg_hba(data_dir.path(), &HbaConfig::default()).expect("no-op write");
+        assert_eq!(
+            fs::read_to_string(&path).expect("read pg_hba.conf"),
+            "host all all 127.0.0.1/32 password\n"
+        );
+
+        let hba = HbaConfig {
+            rules: vec![HbaRule::local(AuthMethod::Peer)],
+            ..HbaConfig::default()
+        };
+        write_pg_hba(data_dir.path(), &hba).expect("write rules");
+        let written = fs::read_to_string(&path).expect("read pg_hba.conf");
+        assert!(written.ends_with("local all all peer\n"), "got: {written}");
+    }
+
+    #[test]
+    fn auth_method_parse_round_trips() {
+        for method in ALL_METHODS {
+            assert_eq!(AuthMethod::parse(method.as_str()), Some(method));
+        }
+        assert_eq!(AuthMethod::parse("kerberos"), None);
+    }
+}
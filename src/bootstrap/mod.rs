@@ -2,10 +2,16 @@
 //!
 //! Provides [`bootstrap_for_tests`] so suites can retrieve structured settings and
 //! prepared environment variables without reimplementing bootstrap orchestration.
+mod backend;
 mod env;
 mod env_types;
+mod external;
+mod hba;
 mod mode;
+mod overrides;
+mod pglite;
 mod prepare;
+mod system_binaries;
 
 use std::time::Duration;
 
@@ -16,15 +22,23 @@ use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex, OnceLock};
 
 use crate::{
-    PgEnvCfg,
-    error::{BootstrapResult, Result as CrateResult},
+    PgBinarySourceCfg, PgEnvCfg,
+    error::{BootstrapError, BootstrapResult, Result as CrateResult},
 };
 
+pub(crate) use backend::BackendKind;
 pub use env::{TestBootstrapEnvironment, find_timezone_dir};
+pub(crate) use hba::write_pg_hba;
+pub use hba::{AuthMethod, HbaConfig, HbaConnectionType, HbaRule};
 pub use mode::{ExecutionMode, ExecutionPrivileges, detect_execution_privileges};
+pub(crate) use overrides::BootstrapOverrides;
 
 use self::{
-    env::{shutdown_timeout_from_env, worker_binary_from_env},
+    env::{
+        allow_unverified_archive_from_env, auth_method_from_env, cleanup_mode_from_env,
+        persistent_worker_from_env, resolve_worker, server_log_forward_from_env,
+        shutdown_timeout_from_env, tls_from_env, unix_socket_only_from_env,
+    },
     mode::determine_execution_mode,
     prepare::prepare_bootstrap,
 };
@@ -46,7 +60,11 @@ enum BootstrapKind {
 }
 
 /// Controls cleanup behaviour when a cluster is dropped.
+///
+/// New modes may be added in minor releases, so matches outside this crate
+/// need a wildcard arm.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[non_exhaustive]
 pub enum CleanupMode {
     /// Remove only the data directory.
     #[default]
@@ -55,6 +73,36 @@ pub enum CleanupMode {
     Full,
     /// Skip cleanup entirely (useful for debugging).
     None,
+    /// Remove only the data directory, unless the cluster is dropped while
+    /// its thread is panicking. A failing test then keeps the data directory,
+    /// a copy of the server log beside it, and logs how to inspect them.
+    KeepOnFailure,
+}
+
+impl CleanupMode {
+    /// Parses a cleanup mode name: `data_only`, `full`, `none`, or
+    /// `keep_on_failure`. Hyphens are accepted in place of underscores.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pg_embedded_setup_unpriv::CleanupMode;
+    ///
+    /// assert_eq!(
+    ///     CleanupMode::parse("keep-on-failure"),
+    ///     Some(CleanupMode::KeepOnFailure)
+    /// );
+    /// ```
+    #[must_use]
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "data_only" => Some(Self::DataOnly),
+            "full" => Some(Self::Full),
+            "none" => Some(Self::None),
+            "keep_on_failure" => Some(Self::KeepOnFailure),
+            _ => None,
+        }
+    }
 }
 
 /// Structured settings returned from [`bootstrap_for_tests`].
@@ -70,6 +118,10 @@ pub struct TestBootstrapSettings {
     pub environment: TestBootstrapEnvironment,
     /// Optional path to the helper binary used for subprocess execution.
     pub worker_binary: Option<camino::Utf8PathBuf>,
+    /// Whether `worker_binary` re-executes the current program through its
+    /// hidden worker entry point because no `pg_worker` binary was found.
+    /// Only set on Linux with the `embedded-worker` feature.
+    pub embedded_worker: bool,
     /// Maximum time to allow the worker to complete the setup phase.
     pub setup_timeout: Duration,
     /// Maximum time to allow the worker to complete the start phase.
@@ -78,11 +130,61 @@ pub struct TestBootstrapSettings {
     pub shutdown_timeout: Duration,
     /// Controls cleanup behaviour when the cluster drops.
     pub cleanup_mode: CleanupMode,
+    /// Forwards server log lines into `tracing` while the cluster runs.
+    pub forward_server_log: bool,
+    /// Client authentication rules written to `pg_hba.conf` before start.
+    pub hba: HbaConfig,
+    /// How clients address the server beyond host, port, and credentials.
+    pub client: ClientSettings,
     /// Optional override for the binary cache directory.
     ///
     /// When set, `TestCluster` uses this directory instead of the default
     /// resolved from environment variables.
     pub binary_cache_dir: Option<camino::Utf8PathBuf>,
+    /// Optional local release archive used to seed the binary cache.
+    pub binary_archive: Option<camino::Utf8PathBuf>,
+    /// Expected SHA-256 digest of `binary_archive`.
+    pub binary_archive_sha256: Option<String>,
+    /// Whether `binary_archive` may be imported when no digest is available.
+    pub binary_archive_allow_unverified: bool,
+    /// `bin` directory of the system installation selected via
+    /// `PG_SYSTEM_BINARIES`.
+    ///
+    /// Such clusters bypass the binary cache, and cleanup never removes the
+    /// installation.
+    pub system_binaries: Option<camino::Utf8PathBuf>,
+}
+
+/// Connection details that clients need beyond [`Settings`].
+///
+/// Clusters this crate starts use the defaults. An external server keeps the
+/// database and TLS parameters named by `DATABASE_URL`.
+///
+/// # Examples
+/// ```
+/// use pg_embedded_setup_unpriv::ClientSettings;
+///
+/// let client = ClientSettings::default();
+/// assert_eq!(client.admin_database, "postgres");
+/// assert!(client.url_params.is_empty());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ClientSettings {
+    /// Database that administrative connections use.
+    pub admin_database: String,
+    /// Decoded query parameters appended to every connection URL, such as
+    /// `sslmode` and `sslrootcert`.
+    pub url_params: Vec<(String, String)>,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            admin_database: "postgres".to_owned(),
+            url_params: Vec::new(),
+        }
+    }
 }
 
 /// Bootstraps an embedded `PostgreSQL` instance, downloads the distribution,
@@ -96,6 +198,9 @@ pub struct TestBootstrapSettings {
 /// - `PG_DATA_DIR`: Overrides the data directory used for initialisation.
 /// - `PG_SUPERUSER`: Sets the superuser account name.
 /// - `PG_PASSWORD`: Supplies the superuser password.
+/// - `PG_BINARY_ARCHIVE`: Seeds the binary cache from a local release archive.
+/// - `PG_RELEASES_URL`: Overrides the release source; `file://` names a local mirror.
+/// - `PG_PERSISTENT_WORKER`: Keeps one worker alive per cluster when running as `root`.
 ///
 /// When executed as `root` on Unix platforms the runtime drops privileges to the `nobody` user
 /// and prepares the filesystem on that user's behalf. Unprivileged executions reuse the current
@@ -144,54 +249,129 @@ pub fn bootstrap_for_tests() -> BootstrapResult<TestBootstrapSettings> {
     orchestrate_bootstrap(BootstrapKind::Test)
 }
 
+/// Bootstraps `PostgreSQL` for tests with programmatic overrides layered over
+/// the environment-derived configuration.
+pub(crate) fn bootstrap_for_tests_with(
+    overrides: &BootstrapOverrides,
+) -> BootstrapResult<TestBootstrapSettings> {
+    orchestrate_bootstrap_with(BootstrapKind::Test, overrides)
+}
+
 fn orchestrate_bootstrap(kind: BootstrapKind) -> BootstrapResult<TestBootstrapSettings> {
+    orchestrate_bootstrap_with(kind, &BootstrapOverrides::default())
+}
+
+fn orchestrate_bootstrap_with(
+    kind: BootstrapKind,
+    overrides: &BootstrapOverrides,
+) -> BootstrapResult<TestBootstrapSettings> {
     install_color_eyre();
     if matches!(kind, BootstrapKind::Test) {
-        validate_backend_selection()?;
+        match BackendKind::from_env()? {
+            BackendKind::External => return external::bootstrap_external(overrides),
+            BackendKind::PgliteOxide => return pglite::bootstrap_pglite(overrides),
+            BackendKind::PostgresqlEmbedded => {}
+        }
     }
 
     let privileges = detect_execution_privileges();
-    let cfg = PgEnvCfg::load().context("failed to load configuration via OrthoConfig")?;
-    let settings = match kind {
+    let env_cfg = PgEnvCfg::load().context("failed to load configuration via OrthoConfig")?;
+    let cfg = overrides.layer_config(env_cfg);
+    let binary_source = overrides.layer_binary_source(
+        PgBinarySourceCfg::load().context("failed to load configuration via OrthoConfig")?,
+    );
+    let mut settings = match kind {
         BootstrapKind::Default => cfg.to_settings()?,
         BootstrapKind::Test => cfg.to_settings_for_tests()?,
     };
-    let worker_binary = worker_binary_from_env(privileges)?;
-    let execution_mode = determine_execution_mode(privileges, worker_binary.as_ref())?;
-    let shutdown_timeout = shutdown_timeout_from_env()?;
-    let prepared = prepare_bootstrap(privileges, settings, &cfg)?;
+    binary_source.apply_releases_url(&mut settings);
+    let unix_socket_only = resolve_flag(overrides.unix_socket_only, unix_socket_only_from_env)?;
+    if resolve_flag(overrides.tls, tls_from_env)? {
+        enable_tls(&mut settings, unix_socket_only)?;
+    }
+    overrides.apply_server_configuration(&mut settings);
+    let (worker_binary, embedded_worker) = resolve_worker(privileges)?.into_settings();
+    let persistent_worker = resolve_flag(overrides.persistent_worker, persistent_worker_from_env)?;
+    let execution_mode =
+        determine_execution_mode(privileges, worker_binary.as_ref(), persistent_worker)?;
+    let shutdown_timeout = match overrides.shutdown_timeout {
+        Some(timeout) => timeout,
+        None => shutdown_timeout_from_env()?,
+    };
+    let mut prepared = prepare_bootstrap(privileges, settings, &cfg, unix_socket_only)?;
+    let system_binaries = system_binaries::apply_system_binaries(
+        &cfg,
+        &binary_source,
+        &mut prepared.settings,
+        &prepared.environment.xdg_runtime_dir,
+    )?;
 
-    Ok(TestBootstrapSettings {
+    let mut bootstrap = TestBootstrapSettings {
         privileges,
         execution_mode,
         settings: prepared.settings,
         environment: prepared.environment,
         worker_binary,
+        embedded_worker,
         setup_timeout: DEFAULT_SETUP_TIMEOUT,
         start_timeout: DEFAULT_START_TIMEOUT,
         shutdown_timeout,
-        cleanup_mode: CleanupMode::default(),
+        cleanup_mode: cleanup_mode_from_env()?,
+        forward_server_log: server_log_forward_from_env()?,
+        hba: HbaConfig {
+            auth_method: resolve_auth_method(overrides.auth_method)?,
+            rules: overrides.hba_rules.clone(),
+        },
+        client: ClientSettings::default(),
         binary_cache_dir: cfg.binary_cache_dir,
-    })
+        binary_archive: binary_source.binary_archive,
+        binary_archive_sha256: binary_source.binary_archive_sha256,
+        binary_archive_allow_unverified: resolve_flag(
+            overrides.allow_unverified_archive,
+            allow_unverified_archive_from_env,
+        )?,
+        system_binaries,
+    };
+    overrides.apply_lifecycle(&mut bootstrap);
+    bootstrap.hba.validate()?;
+    Ok(bootstrap)
 }
 
-fn install_color_eyre() {
-    if let Err(err) = color_eyre::install() {
-        tracing::debug!("color_eyre already installed: {err}");
+/// Prefers a builder override over the environment, defaulting to `false`.
+fn resolve_flag(
+    override_value: Option<bool>,
+    from_env: fn() -> BootstrapResult<Option<bool>>,
+) -> BootstrapResult<bool> {
+    match override_value {
+        Some(enabled) => Ok(enabled),
+        None => Ok(from_env()?.unwrap_or(false)),
     }
 }
 
-fn validate_backend_selection() -> BootstrapResult<()> {
-    let raw = std::env::var_os("PG_TEST_BACKEND").unwrap_or_default();
-    let value = raw.to_string_lossy();
-    let trimmed = value.trim();
-    if trimmed.is_empty() || trimmed == "postgresql_embedded" {
-        return Ok(());
+/// Prefers a builder auth method over `PG_AUTH_METHOD`.
+fn resolve_auth_method(override_value: Option<AuthMethod>) -> BootstrapResult<Option<AuthMethod>> {
+    override_value.map_or_else(auth_method_from_env, |method| Ok(Some(method)))
+}
+
+/// Enables TLS, which needs the `tls` feature and the TCP listener
+/// socket-only clusters disable.
+fn enable_tls(settings: &mut Settings, unix_socket_only: bool) -> BootstrapResult<()> {
+    if cfg!(not(feature = "tls")) {
+        return Err(crate::cluster::tls_missing_feature());
+    }
+    if unix_socket_only {
+        return Err(BootstrapError::from(eyre!(
+            "TLS requires a TCP listener; disable unix_socket_only (PG_UNIX_SOCKET_ONLY) or TLS (PG_TLS)"
+        )));
+    }
+    crate::cluster::enable_tls(settings);
+    Ok(())
+}
+
+fn install_color_eyre() {
+    if let Err(err) = color_eyre::install() {
+        tracing::debug!("color_eyre already installed: {err}");
     }
-    Err(eyre!(
-        "SKIP-TEST-CLUSTER: unsupported PG_TEST_BACKEND '{trimmed}'; supported backends: postgresql_embedded"
-    )
-    .into())
 }
 
 /// Executes the setup-only lifecycle for CLI invocations.
@@ -240,12 +240,14 @@ fn assert_environment(settings: &TestBootstrapSettings, runtime_path: &Utf8PathB
 #[case::empty(Some(""), true)]
 #[case::embedded(Some("postgresql_embedded"), true)]
 #[case::unsupported(Some("sqlite"), false)]
-fn validate_backend_selection_respects_pg_test_backend(
+#[case::external(Some("external"), true)]
+#[case::pglite(Some("pglite_oxide"), cfg!(feature = "pglite"))]
+fn backend_selection_respects_pg_test_backend(
     #[case] backend: Option<&str>,
     #[case] should_succeed: bool,
 ) {
     let _guard = scoped_env(env_vars([("PG_TEST_BACKEND", backend)]));
-    let result = validate_backend_selection();
+    let result = BackendKind::from_env();
     assert_eq!(
         result.is_ok(),
         should_succeed,
@@ -259,3 +261,30 @@ fn validate_backend_selection_respects_pg_test_backend(
         );
     }
 }
+
+#[cfg(feature = "tls")]
+#[test]
+fn enable_tls_rejects_socket_only_clusters() {
+    let mut settings = Settings::default();
+
+    let err = enable_tls(&mut settings, true).expect_err("socket-only TLS should fail");
+
+    assert!(err.to_string().contains("TCP listener"), "got: {err}");
+    assert!(!settings.configuration.contains_key("ssl"));
+}
+
+#[rstest]
+#[case::override_wins(Some(false), Some("1"), false)]
+#[case::env_fallback(None, Some("on"), true)]
+#[case::default_off(None, None, false)]
+fn resolve_flag_prefers_overrides(
+    #[case] override_value: Option<bool>,
+    #[case] raw: Option<&str>,
+    #[case] expected: bool,
+) {
+    let _guard = scoped_env(env_vars([("PG_TLS", raw)]));
+
+    let resolved = resolve_flag(override_value, env::tls_from_env).expect("flag should resolve");
+
+    assert_eq!(resolved, expected);
+}
@@ -1,6 +1,7 @@
 //! Detects execution privileges and selects the appropriate orchestration mode.
 
 use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
 
 use crate::error::{BootstrapError, BootstrapResult};
 
@@ -17,7 +18,11 @@ pub enum ExecutionPrivileges {
 }
 
 /// Selects how `PostgreSQL` lifecycle commands run when privileged execution is required.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// New modes may be added in minor releases, so matches outside this crate
+/// need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum ExecutionMode {
     /// Execute lifecycle commands directly within the current process.
     ///
@@ -25,6 +30,13 @@ pub enum ExecutionMode {
     InProcess,
     /// Delegate lifecycle commands to a helper subprocess executed with reduced privileges.
     Subprocess,
+    /// Delegate lifecycle commands to one long-lived helper subprocess per cluster.
+    ///
+    /// The helper is spawned with reduced privileges on first use and exits when the
+    /// cluster drops. Only the setup, start, stop, and cleanup operations go
+    /// through it; database and role management run over client connections
+    /// from the calling process, as in every other mode.
+    PersistentWorker,
 }
 
 /// Detects whether the process is running with root privileges.
@@ -60,6 +72,7 @@ pub fn detect_execution_privileges() -> ExecutionPrivileges {
 pub(super) fn determine_execution_mode(
     privileges: ExecutionPrivileges,
     worker_binary: Option<&Utf8PathBuf>,
+    persistent: bool,
 ) -> BootstrapResult<ExecutionMode> {
     #[cfg(unix)]
     {
@@ -67,8 +80,11 @@ pub(super) fn determine_execution_mode(
             ExecutionPrivileges::Root => {
                 if worker_binary.is_none() {
                     Err(BootstrapError::from(color_eyre::eyre::eyre!(
-                        "PG_EMBEDDED_WORKER must be set when running with root privileges"
+                        "PG_EMBEDDED_WORKER must be set when running with root privileges \
+                         (or enable the embedded-worker feature on Linux)"
                     )))
+                } else if persistent {
+                    Ok(ExecutionMode::PersistentWorker)
                 } else {
                     Ok(ExecutionMode::Subprocess)
                 }
@@ -81,6 +97,7 @@ pub(super) fn determine_execution_mode(
     {
         let _ = worker_binary;
         let _ = privileges;
+        let _ = persistent;
         Ok(ExecutionMode::InProcess)
     }
 }
@@ -94,7 +111,7 @@ mod tests {
     #[cfg(unix)]
     #[test]
     fn determine_execution_mode_requires_worker_when_root() {
-        let err = determine_execution_mode(ExecutionPrivileges::Root, None)
+        let err = determine_execution_mode(ExecutionPrivileges::Root, None, false)
             .expect_err("root execution without worker must error");
         let message = err.to_string();
         assert!(
@@ -107,15 +124,24 @@ mod tests {
     #[test]
     fn determine_execution_mode_allows_subprocess_with_worker() {
         let worker = Utf8PathBuf::from("/tmp/pg_worker");
-        let mode = determine_execution_mode(ExecutionPrivileges::Root, Some(&worker))
+        let mode = determine_execution_mode(ExecutionPrivileges::Root, Some(&worker), false)
             .expect("root execution with worker should succeed");
         assert_eq!(mode, ExecutionMode::Subprocess);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn determine_execution_mode_keeps_worker_alive_when_persistent() {
+        let worker = Utf8PathBuf::from("/tmp/pg_worker");
+        let mode = determine_execution_mode(ExecutionPrivileges::Root, Some(&worker), true)
+            .expect("root execution with worker should succeed");
+        assert_eq!(mode, ExecutionMode::PersistentWorker);
+    }
+
     #[cfg(unix)]
     #[test]
     fn determine_execution_mode_in_process_when_unprivileged() {
-        let mode = determine_execution_mode(ExecutionPrivileges::Unprivileged, None)
+        let mode = determine_execution_mode(ExecutionPrivileges::Unprivileged, None, true)
             .expect("unprivileged execution should succeed");
         assert_eq!(mode, ExecutionMode::InProcess);
     }
@@ -124,8 +150,9 @@ mod tests {
     #[test]
     fn determine_execution_mode_ignores_worker_when_unprivileged() {
         let worker = Utf8PathBuf::from("/tmp/pg_worker");
-        let mode = determine_execution_mode(ExecutionPrivileges::Unprivileged, Some(&worker))
-            .expect("unprivileged execution should succeed with worker configured");
+        let mode =
+            determine_execution_mode(ExecutionPrivileges::Unprivileged, Some(&worker), false)
+                .expect("unprivileged execution should succeed with worker configured");
         assert_eq!(mode, ExecutionMode::InProcess);
     }
 
@@ -133,7 +160,7 @@ mod tests {
     #[test]
     fn determine_execution_mode_defaults_to_in_process() {
         let worker = Utf8PathBuf::from("/tmp/pg_worker");
-        let mode = determine_execution_mode(ExecutionPrivileges::Root, Some(&worker))
+        let mode = determine_execution_mode(ExecutionPrivileges::Root, Some(&worker), false)
             .expect("non-unix execution should succeed");
         assert_eq!(mode, ExecutionMode::InProcess);
     }
@@ -0,0 +1,280 @@
+//! Programmatic overrides layered on top of environment-derived configuration.
+//!
+//! [`BootstrapOverrides`] captures values supplied through
+//! [`TestClusterBuilder`](crate::TestClusterBuilder). Environment variables read
+//! by [`PgEnvCfg::load`] remain the base layer; any value set here wins.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use postgresql_embedded::Settings;
+
+use super::{AuthMethod, CleanupMode, HbaRule, TestBootstrapSettings};
+use crate::{PgBinarySourceCfg, PgEnvCfg};
+
+/// Per-cluster configuration applied over the environment-derived defaults.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BootstrapOverrides {
+    /// Configuration fields that take precedence over `PG_*` variables.
+    pub(crate) config: PgEnvCfg,
+    /// Binary source fields that take precedence over `PG_*` variables.
+    pub(crate) binary_source: PgBinarySourceCfg,
+    /// Cleanup behaviour applied when the cluster drops.
+    pub(crate) cleanup_mode: Option<CleanupMode>,
+    /// Maximum time allowed for the setup phase.
+    pub(crate) setup_timeout: Option<Duration>,
+    /// Maximum time allowed for the start phase.
+    pub(crate) start_timeout: Option<Duration>,
+    /// Grace period granted to `PostgreSQL` during drop.
+    pub(crate) shutdown_timeout: Option<Duration>,
+    /// Whether server log lines are forwarded into `tracing`.
+    pub(crate) forward_server_log: Option<bool>,
+    /// Whether the server listens only on a Unix-domain socket.
+    pub(crate) unix_socket_only: Option<bool>,
+    /// Whether the server serves TLS with generated certificates.
+    pub(crate) tls: Option<bool>,
+    /// Whether root runs keep one worker alive for the whole cluster.
+    pub(crate) persistent_worker: Option<bool>,
+    /// Whether release archives without a SHA-256 digest may be imported.
+    pub(crate) allow_unverified_archive: Option<bool>,
+    /// Method applied to the default `pg_hba.conf` rules.
+    pub(crate) auth_method: Option<AuthMethod>,
+    /// Explicit `pg_hba.conf` rules, in match order.
+    pub(crate) hba_rules: Vec<HbaRule>,
+    /// Extra `postgresql.conf` entries written into the server configuration.
+    pub(crate) server_configuration: BTreeMap<String, String>,
+}
+
+impl BootstrapOverrides {
+    /// Layers the override configuration over `base`, preferring override values.
+    pub(crate) fn layer_config(&self, base: PgEnvCfg) -> PgEnvCfg {
+        let overrides = self.config.clone();
+        PgEnvCfg {
+            version_req: overrides.version_req.or(base.version_req),
+            port: overrides.port.or(base.port),
+            superuser: overrides.superuser.or(base.superuser),
+            password: overrides.password.or(base.password),
+            data_dir: overrides.data_dir.or(base.data_dir),
+            runtime_dir: overrides.runtime_dir.or(base.runtime_dir),
+            locale: overrides.locale.or(base.locale),
+            encoding: overrides.encoding.or(base.encoding),
+            binary_cache_dir: overrides.binary_cache_dir.or(base.binary_cache_dir),
+        }
+    }
+
+    /// Layers the override binary source over `base`, preferring override
+    /// values.
+    pub(crate) fn layer_binary_source(&self, base: PgBinarySourceCfg) -> PgBinarySourceCfg {
+        let overrides = self.binary_source.clone();
+        PgBinarySourceCfg {
+            releases_url: overrides.releases_url.or(base.releases_url),
+            binary_archive: overrides.binary_archive.or(base.binary_archive),
+            binary_archive_sha256: overrides
+                .binary_archive_sha256
+                .or(base.binary_archive_sha256),
+            system_binaries: overrides.system_binaries.or(base.system_binaries),
+        }
+    }
+
+    /// Names the overrides that only apply to native clusters this crate
+    /// starts.
+    ///
+    /// An external server keeps its own binaries, configuration, and
+    /// authentication rules, and a `pglite-oxide` cluster runs from its own
+    /// WebAssembly build, so these overrides cannot take effect there.
+    pub(crate) fn native_only(&self) -> Vec<&'static str> {
+        let config = &self.config;
+        let source = &self.binary_source;
+        [
+            ("version_req", config.version_req.is_some()),
+            ("port", config.port.is_some()),
+            ("superuser", config.superuser.is_some()),
+            ("password", config.password.is_some()),
+            ("data_dir", config.data_dir.is_some()),
+            ("runtime_dir", config.runtime_dir.is_some()),
+            ("locale", config.locale.is_some()),
+            ("encoding", config.encoding.is_some()),
+            ("binary_cache_dir", config.binary_cache_dir.is_some()),
+            ("releases_url", source.releases_url.is_some()),
+            ("binary_archive", source.binary_archive.is_some()),
+            (
+                "binary_archive_sha256",
+                source.binary_archive_sha256.is_some(),
+            ),
+            ("system_binaries", source.system_binaries.is_some()),
+            (
+                "allow_unverified_archive",
+                self.allow_unverified_archive == Some(true),
+            ),
+            ("forward_server_log", self.forward_server_log == Some(true)),
+            ("unix_socket_only", self.unix_socket_only == Some(true)),
+            ("tls", self.tls == Some(true)),
+            ("persistent_worker", self.persistent_worker == Some(true)),
+            ("auth_method", self.auth_method.is_some()),
+            ("hba_rule", !self.hba_rules.is_empty()),
+            ("server_setting", !self.server_configuration.is_empty()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+
+    /// Inserts the extra server configuration, replacing any default entries.
+    pub(crate) fn apply_server_configuration(&self, settings: &mut Settings) {
+        for (key, value) in &self.server_configuration {
+            settings.configuration.insert(key.clone(), value.clone());
+        }
+    }
+
+    /// Applies lifecycle overrides to fully prepared bootstrap settings.
+    pub(crate) const fn apply_lifecycle(&self, bootstrap: &mut TestBootstrapSettings) {
+        if let Some(mode) = self.cleanup_mode {
+            bootstrap.cleanup_mode = mode;
+        }
+        if let Some(timeout) = self.setup_timeout {
+            bootstrap.setup_timeout = timeout;
+        }
+        if let Some(timeout) = self.start_timeout {
+            bootstrap.start_timeout = timeout;
+        }
+        if let Some(timeout) = self.shutdown_timeout {
+            bootstrap.shutdown_timeout = timeout;
+        }
+        if let Some(forward) = self.forward_server_log {
+            bootstrap.forward_server_log = forward;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExecutionPrivileges;
+    use crate::test_support::{dummy_settings, scoped_env};
+    use std::ffi::OsString;
+
+    #[test]
+    fn layer_config_prefers_override_values() {
+        let overrides = BootstrapOverrides {
+            config: PgEnvCfg {
+                version_req: Some("=16.4.0".into()),
+                superuser: Some("builder_user".into()),
+                ..PgEnvCfg::default()
+            },
+            ..BootstrapOverrides::default()
+        };
+        let base = PgEnvCfg {
+            version_req: Some("=17.0.0".into()),
+            port: Some(6543),
+            superuser: Some("env_user".into()),
+            ..PgEnvCfg::default()
+        };
+
+        let layered = overrides.layer_config(base);
+
+        assert_eq!(layered.version_req.as_deref(), Some("=16.4.0"));
+        assert_eq!(layered.superuser.as_deref(), Some("builder_user"));
+        assert_eq!(layered.port, Some(6543), "unset overrides keep env values");
+    }
+
+    #[test]
+    fn layer_binary_source_prefers_override_values() {
+        let _guard = scoped_env([
+            (
+                OsString::from("PG_BINARY_ARCHIVE"),
+                Some(OsString::from("/srv/env.tar.gz")),
+            ),
+            (
+                OsString::from("PG_BINARY_ARCHIVE_SHA256"),
+                Some(OsString::from("abc123")),
+            ),
+        ]);
+        let overrides = BootstrapOverrides {
+            binary_source: PgBinarySourceCfg {
+                binary_archive: Some("/srv/builder.tar.gz".into()),
+                ..PgBinarySourceCfg::default()
+            },
+            ..BootstrapOverrides::default()
+        };
+
+        let layered = overrides
+            .layer_binary_source(PgBinarySourceCfg::load().expect("binary source should load"));
+
+        assert_eq!(
+            layered.binary_archive.as_deref(),
+            Some(camino::Utf8Path::new("/srv/builder.tar.gz"))
+        );
+        assert_eq!(layered.binary_archive_sha256.as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn server_side_names_overrides_for_native_clusters() {
+        let overrides = BootstrapOverrides {
+            config: PgEnvCfg {
+                port: Some(6543),
+                ..PgEnvCfg::default()
+            },
+            cleanup_mode: Some(CleanupMode::None),
+            shutdown_timeout: Some(Duration::from_secs(5)),
+            tls: Some(false),
+            hba_rules: vec![HbaRule::local(AuthMethod::Trust)],
+            ..BootstrapOverrides::default()
+        };
+
+        assert_eq!(overrides.native_only(), ["port", "hba_rule"]);
+        assert!(BootstrapOverrides::default().native_only().is_empty());
+    }
+
+    #[test]
+    fn server_configuration_replaces_defaults() {
+        let mut settings = Settings::default();
+        settings
+            .configuration
+            .insert("max_connections".into(), "20".into());
+        let overrides = BootstrapOverrides {
+            server_configuration: BTreeMap::from([
+                ("max_connections".to_owned(), "50".to_owned()),
+                ("log_statement".to_owned(), "all".to_owned()),
+            ]),
+            ..BootstrapOverrides::default()
+        };
+
+        overrides.apply_server_configuration(&mut settings);
+
+        assert_eq!(
+            settings
+                .configuration
+                .get("max_connections")
+                .map(String::as_str),
+            Some("50")
+        );
+        assert_eq!(
+            settings
+                .configuration
+                .get("log_statement")
+                .map(String::as_str),
+            Some("all")
+        );
+    }
+
+    #[test]
+    fn apply_lifecycle_overrides_only_supplied_values() {
+        let mut bootstrap = dummy_settings(ExecutionPrivileges::Unprivileged);
+        let original_start = bootstrap.start_timeout;
+        let overrides = BootstrapOverrides {
+            cleanup_mode: Some(CleanupMode::None),
+            setup_timeout: Some(Duration::from_secs(7)),
+            shutdown_timeout: Some(Duration::from_secs(3)),
+            forward_server_log: Some(true),
+            ..BootstrapOverrides::default()
+        };
+
+        overrides.apply_lifecycle(&mut bootstrap);
+
+        assert_eq!(bootstrap.cleanup_mode, CleanupMode::None);
+        assert_eq!(bootstrap.setup_timeout, Duration::from_secs(7));
+        assert_eq!(bootstrap.shutdown_timeout, Duration::from_secs(3));
+        assert_eq!(bootstrap.start_timeout, original_start);
+        assert!(bootstrap.forward_server_log);
+    }
+}
@@ -0,0 +1,97 @@
+//! Bootstrap settings for clusters run by `pglite-oxide`.
+//!
+//! `PG_TEST_BACKEND=pglite_oxide` skips the download, `initdb`, and the
+//! worker: the cluster runs in-process from a WebAssembly build of
+//! `PostgreSQL`. The settings describe its loopback proxy, whose port is only
+//! known once the backend starts, and every path except the cache points into
+//! a private sandbox. Builder overrides that only shape a native cluster are
+//! rejected rather than ignored.
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::eyre;
+use postgresql_embedded::Settings;
+
+use super::external::sandboxed_bootstrap;
+use super::prepare::prepare_sandbox;
+use super::{BootstrapOverrides, ClientSettings, TestBootstrapSettings};
+use crate::error::{BootstrapError, BootstrapResult};
+
+/// Address the proxy listens on.
+const PGLITE_HOST: &str = "127.0.0.1";
+/// Superuser the proxy accepts without a password.
+const PGLITE_SUPERUSER: &str = "postgres";
+/// The only database the proxy serves.
+const PGLITE_DATABASE: &str = "template1";
+
+/// Builds settings for a `pglite-oxide` cluster.
+///
+/// Lifecycle overrides (timeouts and cleanup mode) apply as usual; any other
+/// override fails, since it would be silently ignored.
+pub(super) fn bootstrap_pglite(
+    overrides: &BootstrapOverrides,
+) -> BootstrapResult<TestBootstrapSettings> {
+    let unsupported = overrides.native_only();
+    if !unsupported.is_empty() {
+        return Err(BootstrapError::from(eyre!(
+            "PG_TEST_BACKEND=pglite_oxide cannot apply {} to a WebAssembly cluster",
+            unsupported.join(", ")
+        )));
+    }
+    let mut xdg = prepare_sandbox("pglite")?;
+    // `pglite-oxide` caches the compiled WebAssembly module under
+    // `XDG_CACHE_HOME`; keeping the caller's cache lets later clusters reuse it
+    // instead of compiling it again.
+    if let Some(cache) = dirs::cache_dir().and_then(|dir| Utf8PathBuf::from_path_buf(dir).ok()) {
+        xdg.cache = cache;
+    }
+    let settings = Settings {
+        host: PGLITE_HOST.to_owned(),
+        port: 0,
+        username: PGLITE_SUPERUSER.to_owned(),
+        password: String::new(),
+        temporary: false,
+        installation_dir: xdg.home.join("install").into(),
+        data_dir: xdg.home.join("data").into(),
+        password_file: xdg.home.join(".pgpass").into(),
+        ..Settings::default()
+    };
+    let client = ClientSettings {
+        admin_database: PGLITE_DATABASE.to_owned(),
+        // The proxy does not negotiate TLS.
+        url_params: vec![("sslmode".to_owned(), "disable".to_owned())],
+    };
+    Ok(sandboxed_bootstrap(settings, xdg, client, overrides))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_only_overrides_are_rejected() {
+        let overrides = BootstrapOverrides {
+            persistent_worker: Some(true),
+            ..BootstrapOverrides::default()
+        };
+
+        let err = bootstrap_pglite(&overrides).expect_err("persistent_worker cannot apply");
+
+        assert!(
+            err.to_string().contains("cannot apply persistent_worker"),
+            "got: {err}"
+        );
+    }
+
+    #[test]
+    fn settings_describe_the_loopback_proxy() {
+        let bootstrap = bootstrap_pglite(&BootstrapOverrides::default()).expect("bootstrap");
+        let sandbox = bootstrap.environment.home.clone();
+
+        assert_eq!(bootstrap.settings.host, PGLITE_HOST);
+        assert_eq!(bootstrap.settings.username, "postgres");
+        assert!(bootstrap.settings.password.is_empty());
+        assert_eq!(bootstrap.client.admin_database, "template1");
+        assert!(bootstrap.settings.data_dir.starts_with(&sandbox));
+        std::fs::remove_dir_all(sandbox).expect("sandbox should be removable");
+    }
+}
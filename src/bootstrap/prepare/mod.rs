@@ -1,7 +1,6 @@
 //! Prepares filesystem state for the bootstrap flows.
 
 use camino::{Utf8Path, Utf8PathBuf};
-#[cfg(unix)]
 use color_eyre::eyre::{Context, eyre};
 use postgresql_embedded::Settings;
 
@@ -25,24 +24,34 @@ use std::net::TcpListener;
 use tracing::debug;
 
 const PGPASS_MODE: u32 = 0o600;
+const SOCKET_DIR_MODE: u32 = 0o700;
+/// Port used to name the socket file of socket-only clusters.
+///
+/// Each cluster has a private socket directory, so the port never collides.
+const SOCKET_ONLY_PORT: u16 = 5432;
 
 pub(super) fn prepare_bootstrap(
     privileges: super::mode::ExecutionPrivileges,
     settings: Settings,
     cfg: &PgEnvCfg,
+    unix_socket_only: bool,
 ) -> BootstrapResult<PreparedBootstrap> {
     #[cfg(unix)]
     {
         match privileges {
-            super::mode::ExecutionPrivileges::Root => bootstrap_with_root(settings, cfg),
-            super::mode::ExecutionPrivileges::Unprivileged => bootstrap_unprivileged(settings, cfg),
+            super::mode::ExecutionPrivileges::Root => {
+                bootstrap_with_root(settings, cfg, unix_socket_only)
+            }
+            super::mode::ExecutionPrivileges::Unprivileged => {
+                bootstrap_unprivileged(settings, cfg, unix_socket_only)
+            }
         }
     }
 
     #[cfg(not(unix))]
     {
         let _ = privileges;
-        bootstrap_unprivileged(settings, cfg)
+        bootstrap_unprivileged(settings, cfg, unix_socket_only)
     }
 }
 
@@ -55,11 +64,14 @@ pub(super) struct PreparedBootstrap {
 fn bootstrap_with_root(
     mut settings: Settings,
     cfg: &PgEnvCfg,
+    unix_socket_only: bool,
 ) -> BootstrapResult<PreparedBootstrap> {
     // Worker subprocesses drop after each operation; keep the data dir so start can
     // proceed after setup.
     settings.temporary = false;
-    ensure_root_port(&mut settings)?;
+    if !unix_socket_only {
+        ensure_root_port(&mut settings)?;
+    }
 
     let nobody_user = User::from_name("nobody")
         .context("failed to resolve user 'nobody'")?
@@ -76,6 +88,13 @@ fn bootstrap_with_root(
     let timezone = prepare_timezone_env()?;
     let xdg = prepare_xdg_dirs(&paths.install_dir)?;
     ensure_xdg_dirs_owned_by_user(&xdg, &nobody_user)?;
+    if unix_socket_only {
+        // The worker starts the server as `nobody`, which must be able to
+        // create the socket file.
+        let socket_dir = socket_dir_for(&xdg);
+        ensure_dir_for_user(&socket_dir, &nobody_user, SOCKET_DIR_MODE)?;
+        apply_unix_socket_only(&mut settings, &socket_dir);
+    }
 
     ensure_pgpass_for_user(&paths.password_file, &nobody_user)?;
 
@@ -121,6 +140,7 @@ fn root_bind_host(settings: &Settings) -> &str {
 fn bootstrap_unprivileged(
     mut settings: Settings,
     cfg: &PgEnvCfg,
+    unix_socket_only: bool,
 ) -> BootstrapResult<PreparedBootstrap> {
     let paths = resolve_settings_paths_for_current_user(&mut settings, cfg)?;
     log_sanitized_settings(&settings);
@@ -133,6 +153,11 @@ fn bootstrap_unprivileged(
 
     let timezone = prepare_timezone_env()?;
     let xdg = prepare_xdg_dirs(&paths.install_dir)?;
+    if unix_socket_only {
+        let socket_dir = socket_dir_for(&xdg);
+        ensure_dir_with_mode(&socket_dir, SOCKET_DIR_MODE)?;
+        apply_unix_socket_only(&mut settings, &socket_dir);
+    }
     let environment = TestBootstrapEnvironment::from_components(xdg, paths.password_file, timezone);
     Ok(PreparedBootstrap {
         settings,
@@ -140,6 +165,43 @@ fn bootstrap_unprivileged(
     })
 }
 
+/// Creates a private sandbox for a cluster whose server this crate does not
+/// install, named after `backend`.
+///
+/// The sandbox holds the `.pgpass` file and XDG directories applied while the
+/// cluster runs; the guard removes it on drop.
+pub(super) fn prepare_sandbox(backend: &str) -> BootstrapResult<XdgDirs> {
+    let sandbox = tempfile::Builder::new()
+        .prefix(&format!("pg-embed-{backend}-"))
+        .tempdir()
+        .with_context(|| format!("failed to create sandbox for the {backend} backend"))?
+        .keep();
+    let home = Utf8PathBuf::from_path_buf(sandbox)
+        .map_err(|path| eyre!("sandbox path {} is not valid UTF-8", path.display()))?;
+    ensure_dir_with_mode(&home, 0o700)?;
+    prepare_xdg_dirs(&home)
+}
+
+/// Returns the private socket directory inside the runtime directory.
+fn socket_dir_for(xdg: &XdgDirs) -> Utf8PathBuf {
+    xdg.runtime.join("socket")
+}
+
+/// Switches the server to Unix-domain socket connections only.
+///
+/// `listen_addresses` is emptied so no TCP listener is opened, and the socket
+/// directory doubles as the host so libpq-style clients pick the socket.
+fn apply_unix_socket_only(settings: &mut Settings, socket_dir: &Utf8Path) {
+    settings.socket_dir = Some(socket_dir.as_std_path().to_path_buf());
+    settings.host = socket_dir.to_string();
+    settings
+        .configuration
+        .insert("listen_addresses".into(), "''".into());
+    if settings.port == 0 {
+        settings.port = SOCKET_ONLY_PORT;
+    }
+}
+
 struct SettingsPaths {
     install_dir: Utf8PathBuf,
     data_dir: Utf8PathBuf,
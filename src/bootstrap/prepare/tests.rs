@@ -105,7 +105,7 @@ mod behaviour_tests {
             ),
             (OsString::from("TZ"), Some(OsString::from("UTC"))),
         ]);
-        let prepared = bootstrap_unprivileged(settings, &cfg).expect("bootstrap");
+        let prepared = bootstrap_unprivileged(settings, &cfg, false).expect("bootstrap");
 
         assert_eq!(prepared.environment.home, runtime_dir);
         assert!(prepared.environment.xdg_cache_home.exists());
@@ -122,6 +122,54 @@ mod behaviour_tests {
         assert_eq!(observed_install, runtime_dir);
         assert_eq!(observed_data, data_dir);
     }
+
+    #[test]
+    fn bootstrap_unprivileged_prepares_private_socket_dir() {
+        let runtime = tempdir().expect("runtime dir");
+        let runtime_dir =
+            Utf8PathBuf::from_path_buf(runtime.path().to_path_buf()).expect("runtime dir utf8");
+        let cfg = PgEnvCfg {
+            runtime_dir: Some(runtime_dir.clone()),
+            data_dir: Some(runtime_dir.join("data")),
+            ..PgEnvCfg::default()
+        };
+        let settings = cfg.to_settings().expect("settings");
+
+        let _guard = scoped_env(vec![
+            (
+                OsString::from("TZDIR"),
+                Some(OsString::from(runtime_dir.as_str())),
+            ),
+            (OsString::from("TZ"), Some(OsString::from("UTC"))),
+        ]);
+        let prepared = bootstrap_unprivileged(settings, &cfg, true).expect("bootstrap");
+
+        let socket_dir = prepared.environment.xdg_runtime_dir.join("socket");
+        assert!(socket_dir.is_dir(), "socket dir should be created");
+        assert_eq!(
+            prepared.settings.socket_dir.as_deref(),
+            Some(socket_dir.as_std_path())
+        );
+        assert_eq!(prepared.settings.host, socket_dir.as_str());
+        assert_eq!(prepared.settings.port, SOCKET_ONLY_PORT);
+        assert_eq!(
+            prepared
+                .settings
+                .configuration
+                .get("listen_addresses")
+                .map(String::as_str),
+            Some("''")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&socket_dir)
+                .expect("socket dir metadata")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, SOCKET_DIR_MODE);
+        }
+    }
 }
 
 #[cfg(unix)]
@@ -0,0 +1,471 @@
+//! Discovers system-installed `PostgreSQL` binaries for `PG_SYSTEM_BINARIES`.
+//!
+//! `auto` searches the directories distribution packages install into, then
+//! `PATH`, and picks the newest installation providing `initdb`, `pg_ctl`,
+//! and `postgres` that satisfies `PG_VERSION_REQ`. Any other value names a
+//! `bin` directory directly. The chosen installation is trusted as-is, so the
+//! crate never downloads, caches, or removes it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::eyre;
+use postgresql_embedded::{Settings, Version, VersionReq};
+use tracing::info;
+
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+use crate::{PgBinarySourceCfg, PgEnvCfg};
+
+/// Tools an installation must provide.
+const REQUIRED_TOOLS: [&str; 3] = ["initdb", "pg_ctl", "postgres"];
+
+/// Directories holding one installation per entry, with the entry prefix
+/// that marks a `PostgreSQL` installation.
+const VERSIONED_ROOTS: [(&str, &str); 5] = [
+    ("/usr/lib/postgresql", ""),
+    ("/usr", "pgsql-"),
+    ("/opt/homebrew/opt", "postgresql"),
+    ("/usr/local/opt", "postgresql"),
+    ("C:\\Program Files\\PostgreSQL", ""),
+];
+
+/// `bin` directories of unversioned source installs.
+const FIXED_BIN_DIRS: [&str; 1] = ["/usr/local/pgsql/bin"];
+
+/// Server setting naming the directories that hold the Unix socket.
+const SOCKET_DIRECTORIES_SETTING: &str = "unix_socket_directories";
+
+/// How `PG_SYSTEM_BINARIES` selects an installation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SystemBinaries {
+    /// Search well-known locations and `PATH`.
+    Auto,
+    /// Use the installation whose `bin` directory is given.
+    BinDir(PathBuf),
+}
+
+impl SystemBinaries {
+    /// Parses the configured value; unset or empty disables the mode.
+    fn parse(raw: Option<&str>) -> Option<Self> {
+        let trimmed = raw?.trim();
+        if trimmed.is_empty() {
+            None
+        } else if trimmed.eq_ignore_ascii_case("auto") {
+            Some(Self::Auto)
+        } else {
+            Some(Self::BinDir(PathBuf::from(trimmed)))
+        }
+    }
+}
+
+/// An installation providing the required tools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SystemInstallation {
+    bin_dir: Utf8PathBuf,
+    version: Version,
+}
+
+/// Points `settings` at the system binaries selected by `cfg`, returning
+/// their `bin` directory.
+///
+/// The installation must satisfy `settings.version` when `PG_VERSION_REQ` is
+/// configured; otherwise any version is accepted. Unless a socket directory
+/// is configured, the server's socket moves into `runtime_dir`. Returns
+/// `Ok(None)` when the mode is disabled.
+///
+/// # Errors
+///
+/// Returns an error when no installation provides the required tools, when
+/// none satisfies the version requirement, or when the chosen path is not
+/// valid UTF-8.
+pub(super) fn apply_system_binaries(
+    cfg: &PgEnvCfg,
+    binary_source: &PgBinarySourceCfg,
+    settings: &mut Settings,
+    runtime_dir: &Utf8Path,
+) -> BootstrapResult<Option<Utf8PathBuf>> {
+    let Some(mode) = SystemBinaries::parse(binary_source.system_binaries.as_deref()) else {
+        return Ok(None);
+    };
+    let version_req = if cfg.version_req.is_some() {
+        settings.version.clone()
+    } else {
+        VersionReq::STAR
+    };
+    let installation = match mode {
+        SystemBinaries::Auto => discover(&version_req)?,
+        SystemBinaries::BinDir(dir) => inspect_explicit(&dir, &version_req)?,
+    };
+    point_settings_at(settings, &installation, runtime_dir)?;
+    log_system_binaries(&installation, &version_req);
+    Ok(Some(installation.bin_dir))
+}
+
+/// Runs the cluster from `installation` in place.
+fn point_settings_at(
+    settings: &mut Settings,
+    installation: &SystemInstallation,
+    runtime_dir: &Utf8Path,
+) -> BootstrapResult<()> {
+    let prefix = installation.bin_dir.parent().ok_or_else(|| {
+        eyre!(
+            "system binaries directory {} has no parent installation directory",
+            installation.bin_dir
+        )
+    })?;
+    settings.installation_dir = prefix.as_std_path().to_path_buf();
+    settings.trust_installation_dir = true;
+    settings.version = exact_version_req(&installation.version)?;
+    // Distribution builds default their socket to a system directory such as
+    // `/var/run/postgresql`, which the cluster user cannot write.
+    if settings.socket_dir.is_none() {
+        settings
+            .configuration
+            .entry(SOCKET_DIRECTORIES_SETTING.to_owned())
+            .or_insert_with(|| runtime_dir.to_string());
+    }
+    Ok(())
+}
+
+fn log_system_binaries(installation: &SystemInstallation, version_req: &VersionReq) {
+    info!(
+        target: LOG_TARGET,
+        bin_dir = %installation.bin_dir,
+        version = %installation.version,
+        version_req = %version_req,
+        "using system postgres binaries"
+    );
+}
+
+/// Inspects the installation named by an explicit `bin` directory.
+fn inspect_explicit(dir: &Path, version_req: &VersionReq) -> BootstrapResult<SystemInstallation> {
+    let installation = inspect(dir)?.ok_or_else(|| {
+        eyre!(
+            "PG_SYSTEM_BINARIES directory {} must contain {}",
+            dir.display(),
+            REQUIRED_TOOLS.join(", ")
+        )
+    })?;
+    if version_req.matches(&installation.version) {
+        Ok(installation)
+    } else {
+        Err(BootstrapError::from(eyre!(
+            "PostgreSQL {} in {} does not satisfy PG_VERSION_REQ {version_req}",
+            installation.version,
+            installation.bin_dir
+        )))
+    }
+}
+
+/// Picks the newest discovered installation satisfying `version_req`.
+fn discover(version_req: &VersionReq) -> BootstrapResult<SystemInstallation> {
+    let mut found: Vec<SystemInstallation> = Vec::new();
+    for installation in candidate_bin_dirs()
+        .iter()
+        .filter_map(|dir| inspect(dir).ok().flatten())
+    {
+        if !found.contains(&installation) {
+            found.push(installation);
+        }
+    }
+    if found.is_empty() {
+        return Err(BootstrapError::from(eyre!(
+            "PG_SYSTEM_BINARIES=auto found no PostgreSQL installation providing {}; \
+             set PG_SYSTEM_BINARIES to its bin directory",
+            REQUIRED_TOOLS.join(", ")
+        )));
+    }
+    select_newest(found, version_req)
+}
+
+/// Returns the newest installation satisfying `version_req`, or an error
+/// listing what was found.
+fn select_newest(
+    found: Vec<SystemInstallation>,
+    version_req: &VersionReq,
+) -> BootstrapResult<SystemInstallation> {
+    let listing = found
+        .iter()
+        .map(|installation| format!("{} ({})", installation.version, installation.bin_dir))
+        .collect::<Vec<_>>()
+        .join(", ");
+    found
+        .into_iter()
+        .filter(|installation| version_req.matches(&installation.version))
+        .max_by(|a, b| a.version.cmp(&b.version))
+        .ok_or_else(|| {
+            BootstrapError::from(eyre!(
+                "no system PostgreSQL installation satisfies PG_VERSION_REQ {version_req}; \
+                 found {listing}"
+            ))
+        })
+}
+
+/// Lists `bin` directories worth inspecting.
+fn candidate_bin_dirs() -> Vec<PathBuf> {
+    let versioned = VERSIONED_ROOTS
+        .iter()
+        .flat_map(|(root, prefix)| versioned_bin_dirs(Path::new(root), prefix));
+    let fixed = FIXED_BIN_DIRS.iter().map(PathBuf::from);
+    let on_path = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    versioned.chain(fixed).chain(on_path).collect()
+}
+
+/// Returns `<root>/<entry>/bin` for each entry of `root` starting with
+/// `prefix`.
+fn versioned_bin_dirs(root: &Path, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path().join("bin"))
+        .collect()
+}
+
+/// Reads the installation `postgres` in `dir` belongs to, returning
+/// `Ok(None)` when a required tool is missing.
+///
+/// Symlinked tools, such as `/usr/local/bin/postgres` pointing into a
+/// packaged installation, resolve to that installation.
+fn inspect(dir: &Path) -> BootstrapResult<Option<SystemInstallation>> {
+    let Ok(postgres) = fs::canonicalize(tool_path(dir, "postgres")) else {
+        return Ok(None);
+    };
+    let Some(real_dir) = postgres.parent() else {
+        return Ok(None);
+    };
+    let has_tools = REQUIRED_TOOLS
+        .iter()
+        .all(|tool| tool_path(real_dir, tool).is_file());
+    if !has_tools {
+        return Ok(None);
+    }
+    let bin_dir = Utf8PathBuf::from_path_buf(real_dir.to_path_buf())
+        .map_err(|path| eyre!("system binaries path {} is not valid UTF-8", path.display()))?;
+    let version = postgres_version(&bin_dir)?;
+    Ok(Some(SystemInstallation { bin_dir, version }))
+}
+
+fn tool_path(dir: &Path, tool: &str) -> PathBuf {
+    dir.join(format!("{tool}{}", env::consts::EXE_SUFFIX))
+}
+
+/// Runs `postgres --version` from `bin_dir`.
+fn postgres_version(bin_dir: &Utf8PathBuf) -> BootstrapResult<Version> {
+    let postgres = tool_path(bin_dir.as_std_path(), "postgres");
+    let output = Command::new(&postgres)
+        .arg("--version")
+        .output()
+        .map_err(|err| eyre!("failed to run {} --version: {err}", postgres.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_version_output(&stdout).ok_or_else(|| {
+        BootstrapError::from(eyre!(
+            "could not read the PostgreSQL version from {} --version: {}",
+            postgres.display(),
+            stdout.trim()
+        ))
+    })
+}
+
+/// Parses output such as `postgres (PostgreSQL) 16.4 (Debian 16.4-1)`.
+///
+/// Missing minor or patch components are zero; suffixes such as `beta1`
+/// are dropped.
+fn parse_version_output(output: &str) -> Option<Version> {
+    let token = output
+        .split("(PostgreSQL)")
+        .nth(1)?
+        .split_whitespace()
+        .next()?;
+    let numeric = token
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?;
+    let mut parts = numeric.split('.').map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().transpose().ok()?.unwrap_or(0);
+    let patch = parts.next().transpose().ok()?.unwrap_or(0);
+    Some(Version::new(major, minor, patch))
+}
+
+/// Pins the settings to `version`, so nothing resolves it remotely.
+fn exact_version_req(version: &Version) -> BootstrapResult<VersionReq> {
+    VersionReq::parse(&format!("={version}"))
+        .map_err(|err| BootstrapError::from(eyre!("invalid system PostgreSQL version: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::auto(Some("auto"), Some(SystemBinaries::Auto))]
+    #[case::auto_upper(Some(" AUTO "), Some(SystemBinaries::Auto))]
+    #[case::bin_dir(
+        Some("/usr/lib/postgresql/16/bin"),
+        Some(SystemBinaries::BinDir(PathBuf::from("/usr/lib/postgresql/16/bin")))
+    )]
+    #[case::empty(Some("  "), None)]
+    #[case::unset(None, None)]
+    fn parse_selects_mode(#[case] raw: Option<&str>, #[case] expected: Option<SystemBinaries>) {
+        assert_eq!(SystemBinaries::parse(raw), expected);
+    }
+
+    #[rstest]
+    #[case::distro("postgres (PostgreSQL) 16.4 (Debian 16.4-1.pgdg120+1)\n", "16.4.0")]
+    #[case::patch("postgres (PostgreSQL) 9.6.24\n", "9.6.24")]
+    #[case::major_only("postgres (PostgreSQL) 17\n", "17.0.0")]
+    #[case::beta("postgres (PostgreSQL) 18beta1\n", "18.0.0")]
+    fn parse_version_output_reads_versions(#[case] output: &str, #[case] expected: &str) {
+        let version = Version::parse(expected).expect("test version should parse");
+
+        assert_eq!(parse_version_output(output), Some(version));
+    }
+
+    #[test]
+    fn parse_version_output_rejects_unrelated_output() {
+        assert_eq!(parse_version_output("pg_ctl: command not found"), None);
+    }
+
+    fn installation(version: &str) -> SystemInstallation {
+        SystemInstallation {
+            bin_dir: Utf8PathBuf::from(format!("/opt/pg/{version}/bin")),
+            version: Version::parse(version).expect("test version should parse"),
+        }
+    }
+
+    #[test]
+    fn select_newest_prefers_the_highest_matching_version() {
+        let found = vec![
+            installation("15.8.0"),
+            installation("17.2.0"),
+            installation("16.4.0"),
+        ];
+        let req = VersionReq::parse("<17").expect("req");
+
+        let chosen = select_newest(found, &req).expect("a match");
+
+        assert_eq!(chosen, installation("16.4.0"));
+    }
+
+    #[test]
+    fn select_newest_lists_installations_when_none_match() {
+        let req = VersionReq::parse("^18").expect("req");
+
+        let err = select_newest(vec![installation("16.4.0")], &req).expect_err("no match");
+
+        let message = err.to_string();
+        assert!(message.contains("PG_VERSION_REQ ^18"), "got: {message}");
+        assert!(
+            message.contains("16.4.0 (/opt/pg/16.4.0/bin)"),
+            "got: {message}"
+        );
+    }
+
+    #[cfg(unix)]
+    fn fake_installation(root: &Path, version_output: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let bin_dir = root.join("bin");
+        fs::create_dir_all(&bin_dir).expect("create bin dir");
+        for tool in ["initdb", "pg_ctl"] {
+            fs::write(bin_dir.join(tool), "#!/bin/sh\n").expect("write tool stub");
+        }
+        let postgres = bin_dir.join("postgres");
+        fs::write(&postgres, format!("#!/bin/sh\necho '{version_output}'\n"))
+            .expect("write postgres stub");
+        fs::set_permissions(&postgres, fs::Permissions::from_mode(0o755))
+            .expect("make postgres executable");
+        bin_dir
+    }
+
+    fn config(version_req: Option<&str>) -> PgEnvCfg {
+        PgEnvCfg {
+            version_req: version_req.map(str::to_owned),
+            ..PgEnvCfg::default()
+        }
+    }
+
+    fn binary_source(bin_dir: &Path) -> PgBinarySourceCfg {
+        PgBinarySourceCfg {
+            system_binaries: Some(bin_dir.display().to_string()),
+            ..PgBinarySourceCfg::default()
+        }
+    }
+
+    fn settings_for(cfg: &PgEnvCfg) -> Settings {
+        cfg.to_settings().expect("test config should convert")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn explicit_bin_dir_points_settings_at_the_installation() {
+        let root = tempfile::tempdir().expect("tempdir");
+        let bin_dir = fake_installation(root.path(), "postgres (PostgreSQL) 16.4");
+        let cfg = config(Some("^16"));
+        let source = binary_source(&bin_dir);
+        let mut settings = settings_for(&cfg);
+
+        let chosen = apply_system_binaries(&cfg, &source, &mut settings, Utf8Path::new("/tmp/run"))
+            .expect("system binaries should apply")
+            .expect("mode enabled");
+
+        let canonical_root = fs::canonicalize(root.path()).expect("canonical root");
+        assert_eq!(chosen.as_std_path(), canonical_root.join("bin"));
+        assert_eq!(settings.installation_dir, canonical_root);
+        assert!(settings.trust_installation_dir);
+        assert_eq!(settings.version.to_string(), "=16.4.0");
+        assert_eq!(
+            settings
+                .configuration
+                .get(SOCKET_DIRECTORIES_SETTING)
+                .map(String::as_str),
+            Some("/tmp/run")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn explicit_bin_dir_must_satisfy_the_version_requirement() {
+        let root = tempfile::tempdir().expect("tempdir");
+        let bin_dir = fake_installation(root.path(), "postgres (PostgreSQL) 15.2");
+        let cfg = config(Some("^16"));
+        let source = binary_source(&bin_dir);
+        let mut settings = settings_for(&cfg);
+
+        let err = apply_system_binaries(&cfg, &source, &mut settings, Utf8Path::new("/tmp/run"))
+            .expect_err("15.2 does not satisfy ^16");
+
+        assert!(
+            err.to_string()
+                .contains("does not satisfy PG_VERSION_REQ ^16"),
+            "got: {err}"
+        );
+        assert!(!settings.trust_installation_dir);
+    }
+
+    #[test]
+    fn explicit_bin_dir_requires_the_tools() {
+        let root = tempfile::tempdir().expect("tempdir");
+        let cfg = config(None);
+        let source = binary_source(root.path());
+        let mut settings = settings_for(&cfg);
+
+        let err = apply_system_binaries(&cfg, &source, &mut settings, Utf8Path::new("/tmp/run"))
+            .expect_err("empty directory has no tools");
+
+        assert!(
+            err.to_string()
+                .contains("must contain initdb, pg_ctl, postgres"),
+            "got: {err}"
+        );
+    }
+}
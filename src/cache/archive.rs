@@ -0,0 +1,558 @@
+//! Offline provisioning of the binary cache from local release archives.
+//!
+//! Sandboxed CI runs cannot reach GitHub, so the cache can be seeded from a
+//! `PostgreSQL` release archive on disk (`PG_BINARY_ARCHIVE`) or from a
+//! `file://` mirror directory (`PG_RELEASES_URL`) holding archives named like
+//! the upstream assets, for example
+//! `postgresql-17.4.0-x86_64-unknown-linux-gnu.tar.gz`. Archives are verified
+//! against a SHA-256 digest before they are extracted; archives without one
+//! are rejected unless the caller allows unverified archives
+//! (`PG_BINARY_ARCHIVE_ALLOW_UNVERIFIED`).
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{Context, eyre};
+use flate2::read::GzDecoder;
+use postgresql_embedded::{Version, VersionReq};
+use std::fs::{self, File};
+use std::io::BufReader;
+use tracing::{debug, info, warn};
+
+use super::lock::CacheLock;
+use super::operations::{CacheLookupResult, check_cache, populate_cache, sha256_file};
+use crate::error::BootstrapResult;
+
+/// Observability target for cache operations.
+const LOG_TARGET: &str = "pg_embed::cache";
+
+/// File name prefix shared by upstream release archives.
+const ARCHIVE_PREFIX: &str = "postgresql-";
+
+/// File name suffix of gzip-compressed release archives.
+const ARCHIVE_SUFFIX: &str = ".tar.gz";
+
+/// Suffix of the checksum file published alongside each release archive.
+const CHECKSUM_SUFFIX: &str = ".sha256";
+
+/// URL scheme identifying a local mirror directory.
+const FILE_URL_SCHEME: &str = "file://";
+
+/// Environment variable that permits importing archives without a digest.
+const ALLOW_UNVERIFIED_ENV: &str = "PG_BINARY_ARCHIVE_ALLOW_UNVERIFIED";
+
+/// Extracts the `PostgreSQL` version from a release archive file name.
+///
+/// Expects names of the form `postgresql-<version>-<target>.tar.gz`, matching
+/// the assets published by the upstream binary releases.
+///
+/// # Examples
+///
+/// ```
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::archive_version;
+///
+/// let archive = Utf8Path::new("postgresql-17.4.0-x86_64-unknown-linux-gnu.tar.gz");
+/// let version = archive_version(archive).expect("version");
+/// assert_eq!(version.to_string(), "17.4.0");
+/// ```
+#[must_use]
+pub fn archive_version(archive: &Utf8Path) -> Option<Version> {
+    let (version, _target) = split_archive_name(archive.file_name()?)?;
+    Version::parse(version).ok()
+}
+
+/// Splits an archive file name into its version and target triple.
+fn split_archive_name(name: &str) -> Option<(&str, &str)> {
+    name.strip_prefix(ARCHIVE_PREFIX)?
+        .strip_suffix(ARCHIVE_SUFFIX)?
+        .split_once('-')
+}
+
+/// Checks that an archive provides a version satisfying the requirement,
+/// before any extraction work is done.
+///
+/// Returns the archive version.
+///
+/// # Errors
+///
+/// Returns an error if the file name does not identify a version or the
+/// version does not satisfy `version_req`.
+pub fn ensure_archive_satisfies(
+    archive: &Utf8Path,
+    version_req: &VersionReq,
+) -> BootstrapResult<Version> {
+    let Some(version) = archive_version(archive) else {
+        return Err(
+            eyre!("cannot determine PostgreSQL version from archive name: {archive}").into(),
+        );
+    };
+    if version_req.matches(&version) {
+        return Ok(version);
+    }
+    Err(eyre!(
+        "archive {archive} provides PostgreSQL {version}, which does not satisfy {version_req}"
+    )
+    .into())
+}
+
+/// Returns the mirror directory named by a `file://` releases URL.
+///
+/// Returns `None` for any other scheme, leaving remote mirrors to
+/// `postgresql_embedded`.
+///
+/// # Examples
+///
+/// ```
+/// use pg_embedded_setup_unpriv::cache::mirror_dir_from_url;
+///
+/// let dir = mirror_dir_from_url("file:///srv/pg-mirror").expect("local mirror");
+/// assert_eq!(dir.as_str(), "/srv/pg-mirror");
+/// assert!(mirror_dir_from_url("https://github.com/theseus-rs/postgresql-binaries").is_none());
+/// ```
+#[must_use]
+pub fn mirror_dir_from_url(url: &str) -> Option<Utf8PathBuf> {
+    let path = url.trim().strip_prefix(FILE_URL_SCHEME)?;
+    (!path.is_empty()).then(|| Utf8PathBuf::from(path))
+}
+
+/// Finds the newest archive in a mirror directory that satisfies the version
+/// requirement and targets the current platform.
+///
+/// Returns the matched version and the archive path, or `None` if the
+/// directory cannot be read or holds no suitable archive.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::find_mirror_archive;
+/// use postgresql_embedded::VersionReq;
+///
+/// let mirror = Utf8Path::new("/srv/pg-mirror");
+/// let req = VersionReq::parse("^17").expect("valid version req");
+/// if let Some((version, archive)) = find_mirror_archive(mirror, &req) {
+///     println!("found {version} at {archive}");
+/// }
+/// ```
+#[must_use]
+pub fn find_mirror_archive(
+    mirror_dir: &Utf8Path,
+    version_req: &VersionReq,
+) -> Option<(Version, Utf8PathBuf)> {
+    let entries = fs::read_dir(mirror_dir).ok()?;
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter_map(|name| mirror_candidate(&name, version_req).map(|v| (v, name)))
+        .max_by(|(left, _), (right, _)| left.cmp(right))
+        .map(|(version, name)| (version, mirror_dir.join(name)))
+}
+
+/// Returns the archive version if the file name targets this platform and
+/// satisfies the requirement.
+fn mirror_candidate(name: &str, version_req: &VersionReq) -> Option<Version> {
+    let (raw_version, target) = split_archive_name(name)?;
+    if target != target_triple::TARGET {
+        return None;
+    }
+    let version = Version::parse(raw_version).ok()?;
+    version_req.matches(&version).then_some(version)
+}
+
+/// Imports the newest suitable archive from a mirror directory into the cache.
+///
+/// Combines [`find_mirror_archive`] and [`import_archive`], reading the
+/// digest from the `.sha256` file beside the chosen archive. Archives without
+/// one are imported only when `allow_unverified` is set.
+/// Returns the imported version.
+///
+/// # Errors
+///
+/// Returns an error if the mirror holds no archive that satisfies
+/// `version_req` for this platform, or if the import fails.
+pub fn import_from_mirror(
+    mirror_dir: &Utf8Path,
+    version_req: &VersionReq,
+    allow_unverified: bool,
+    cache_dir: &Utf8Path,
+) -> BootstrapResult<Version> {
+    let Some((_version, archive)) = find_mirror_archive(mirror_dir, version_req) else {
+        return Err(eyre!(
+            "no archive satisfying {version_req} for {} found in mirror {mirror_dir}",
+            target_triple::TARGET
+        )
+        .into());
+    };
+    import_archive(&archive, None, allow_unverified, cache_dir)
+}
+
+/// Reads the digest from the `<archive>.sha256` file next to an archive.
+///
+/// The file may hold the bare digest or `sha256sum` output; only the first
+/// whitespace-separated token is used.
+fn read_checksum_sidecar(archive: &Utf8Path) -> BootstrapResult<Option<String>> {
+    let sidecar = Utf8PathBuf::from(format!("{archive}{CHECKSUM_SUFFIX}"));
+    if !sidecar.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&sidecar)
+        .with_context(|| format!("failed to read checksum file: {sidecar}"))?;
+    Ok(contents.split_whitespace().next().map(str::to_owned))
+}
+
+/// Verifies a release archive against its expected SHA-256 digest.
+///
+/// The digest is taken from `expected_sha256` when supplied, otherwise from
+/// an `<archive>.sha256` file beside the archive. When neither is available
+/// the archive is rejected, unless `allow_unverified` is set (see
+/// [`TestClusterBuilder::allow_unverified_archive`](crate::TestClusterBuilder::allow_unverified_archive)),
+/// in which case it is accepted with a warning.
+///
+/// # Errors
+///
+/// Returns an error if no digest is available and unverified archives are
+/// not allowed, if the archive or checksum file cannot be read, or if the
+/// computed digest does not match the expected one.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::verify_archive_checksum;
+///
+/// let archive = Utf8Path::new("/srv/pg-mirror/postgresql-17.4.0-x86_64-unknown-linux-gnu.tar.gz");
+/// verify_archive_checksum(archive, None, false)?;
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn verify_archive_checksum(
+    archive: &Utf8Path,
+    expected_sha256: Option<&str>,
+    allow_unverified: bool,
+) -> BootstrapResult<()> {
+    let expected = match expected_sha256 {
+        Some(digest) => Some(digest.trim().to_owned()),
+        None => read_checksum_sidecar(archive)?,
+    };
+    let Some(expected_digest) = expected else {
+        return accept_unverified_archive(archive, allow_unverified);
+    };
+
+    let actual = sha256_file(archive)?;
+    if actual.eq_ignore_ascii_case(&expected_digest) {
+        debug!(target: LOG_TARGET, archive = %archive, "archive checksum verified");
+        return Ok(());
+    }
+    Err(eyre!(
+        "checksum mismatch for archive {archive}: expected sha256 {expected_digest}, found {actual}"
+    )
+    .into())
+}
+
+/// Accepts an archive without a digest only when explicitly allowed.
+fn accept_unverified_archive(archive: &Utf8Path, allow_unverified: bool) -> BootstrapResult<()> {
+    if !allow_unverified {
+        return Err(eyre!(
+            "no SHA-256 digest available for archive {archive}: set \
+             PG_BINARY_ARCHIVE_SHA256, place the digest in {archive}{CHECKSUM_SUFFIX}, \
+             or set {ALLOW_UNVERIFIED_ENV}=1 (allow_unverified_archive on the builder) \
+             to import it unverified"
+        )
+        .into());
+    }
+    log_unverified_archive(archive);
+    Ok(())
+}
+
+/// Logs that an archive is used without checksum verification.
+fn log_unverified_archive(archive: &Utf8Path) {
+    warn!(
+        target: LOG_TARGET,
+        archive = %archive,
+        "no SHA-256 digest available for archive; importing it unverified as allowed"
+    );
+}
+
+/// Imports a release archive into the binary cache.
+///
+/// Verifies the archive checksum (see [`verify_archive_checksum`]), extracts
+/// it under an exclusive [`CacheLock`], and records it as a complete cache
+/// entry. The version is taken from the archive file name. Importing a
+/// version that is already cached is a no-op.
+///
+/// Returns the imported version.
+///
+/// # Errors
+///
+/// Returns an error if the file name does not identify a version, the
+/// checksum does not match, the cache lock cannot be acquired, or extraction
+/// or population fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::import_archive;
+///
+/// let archive = Utf8Path::new("/srv/pg/postgresql-17.4.0-x86_64-unknown-linux-gnu.tar.gz");
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let version = import_archive(archive, None, false, cache_dir)?;
+/// assert_eq!(version.to_string(), "17.4.0");
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn import_archive(
+    archive: &Utf8Path,
+    expected_sha256: Option<&str>,
+    allow_unverified: bool,
+    cache_dir: &Utf8Path,
+) -> BootstrapResult<Version> {
+    let version = archive_version(archive)
+        .ok_or_else(|| eyre!("cannot determine PostgreSQL version from archive name: {archive}"))?;
+    let version_str = version.to_string();
+
+    fs::create_dir_all(cache_dir)
+        .with_context(|| format!("failed to create cache directory: {cache_dir}"))?;
+    let _lock = CacheLock::acquire_exclusive(cache_dir, &version_str)
+        .with_context(|| format!("failed to lock cache entry for {version_str}"))?;
+
+    if matches!(
+        check_cache(cache_dir, &version_str),
+        CacheLookupResult::Hit { .. }
+    ) {
+        log_already_cached(&version_str);
+        return Ok(version);
+    }
+
+    verify_archive_checksum(archive, expected_sha256, allow_unverified)?;
+    extract_into_cache(archive, cache_dir, &version_str)?;
+    log_import_complete(archive, &version_str, cache_dir);
+    Ok(version)
+}
+
+/// Logs that an imported version was already present in the cache.
+fn log_already_cached(version: &str) {
+    debug!(target: LOG_TARGET, version = %version, "archive version already cached");
+}
+
+/// Logs the completion of an archive import.
+fn log_import_complete(archive: &Utf8Path, version: &str, cache_dir: &Utf8Path) {
+    info!(
+        target: LOG_TARGET,
+        archive = %archive,
+        version = %version,
+        cache_dir = %cache_dir,
+        "imported binary archive into cache"
+    );
+}
+
+/// Unpacks an archive into a staging directory inside the cache, then
+/// populates the cache entry from it.
+fn extract_into_cache(
+    archive: &Utf8Path,
+    cache_dir: &Utf8Path,
+    version: &str,
+) -> BootstrapResult<()> {
+    let staging = tempfile::tempdir_in(cache_dir)
+        .with_context(|| format!("failed to create staging directory in {cache_dir}"))?;
+    let staging_root = Utf8Path::from_path(staging.path())
+        .ok_or_else(|| eyre!("staging directory is not valid UTF-8"))?;
+
+    let file = File::open(archive).with_context(|| format!("failed to open archive: {archive}"))?;
+    tar::Archive::new(GzDecoder::new(BufReader::new(file)))
+        .unpack(staging_root)
+        .with_context(|| format!("failed to extract archive: {archive}"))?;
+
+    let source = distribution_root(staging_root)?;
+    populate_cache(&source, cache_dir, version)
+}
+
+/// Locates the directory holding `bin/` within an extracted archive.
+///
+/// Upstream archives wrap the distribution in a single top-level directory;
+/// archives packed without one are accepted as-is.
+fn distribution_root(staging_root: &Utf8Path) -> BootstrapResult<Utf8PathBuf> {
+    if staging_root.join("bin").is_dir() {
+        return Ok(staging_root.to_owned());
+    }
+    let mut entries = staging_root
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read extracted archive: {staging_root}"))?
+        .filter_map(Result::ok)
+        .map(camino::Utf8DirEntry::into_path)
+        .filter(|path| path.join("bin").is_dir());
+    match (entries.next(), entries.next()) {
+        (Some(root), None) => Ok(root),
+        _ => Err(eyre!(
+            "archive does not contain a single PostgreSQL distribution with a bin/ directory"
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tempfile::tempdir;
+
+    fn platform_archive_name(version: &str) -> String {
+        format!(
+            "{ARCHIVE_PREFIX}{version}-{}{ARCHIVE_SUFFIX}",
+            target_triple::TARGET
+        )
+    }
+
+    /// Writes a minimal release archive with an executable `bin/postgres`.
+    fn write_archive(dir: &Utf8Path, version: &str) -> Utf8PathBuf {
+        let name = platform_archive_name(version);
+        let path = dir.join(&name);
+        let file = File::create(&path).expect("create archive");
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+        let top = name.strip_suffix(ARCHIVE_SUFFIX).expect("archive suffix");
+        let body = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(body.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{top}/bin/postgres"), &body[..])
+            .expect("append binary");
+        builder
+            .into_inner()
+            .expect("finish tar")
+            .finish()
+            .expect("finish gzip");
+        path
+    }
+
+    fn utf8(temp: &tempfile::TempDir) -> &Utf8Path {
+        Utf8Path::from_path(temp.path()).expect("utf8 path")
+    }
+
+    #[rstest]
+    #[case::upstream("postgresql-17.4.0-x86_64-unknown-linux-gnu.tar.gz", Some("17.4.0"))]
+    #[case::darwin("postgresql-16.8.0-aarch64-apple-darwin.tar.gz", Some("16.8.0"))]
+    #[case::wrong_extension("postgresql-17.4.0-x86_64-unknown-linux-gnu.zip", None)]
+    #[case::not_a_version("postgresql-latest-x86_64-unknown-linux-gnu.tar.gz", None)]
+    fn parses_archive_versions(#[case] name: &str, #[case] expected: Option<&str>) {
+        let version = archive_version(Utf8Path::new(name)).map(|v| v.to_string());
+        assert_eq!(version.as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case::absolute("file:///srv/mirror", Some("/srv/mirror"))]
+    #[case::https("https://example.invalid/releases", None)]
+    #[case::empty("file://", None)]
+    fn resolves_mirror_directories(#[case] url: &str, #[case] expected: Option<&str>) {
+        let dir = mirror_dir_from_url(url);
+        assert_eq!(dir.as_deref().map(Utf8Path::as_str), expected);
+    }
+
+    #[test]
+    fn find_mirror_archive_prefers_newest_matching_platform_archive() {
+        let temp = tempdir().expect("tempdir");
+        let mirror = utf8(&temp);
+        for version in ["16.8.0", "17.2.0", "17.4.0"] {
+            fs::write(mirror.join(platform_archive_name(version)), "").expect("write archive");
+        }
+        fs::write(
+            mirror.join("postgresql-17.9.0-other-unknown-target.tar.gz"),
+            "",
+        )
+        .expect("write foreign archive");
+
+        let req = VersionReq::parse("^17").expect("version req");
+        let (version, path) = find_mirror_archive(mirror, &req).expect("matching archive");
+        assert_eq!(version.to_string(), "17.4.0");
+        assert_eq!(path, mirror.join(platform_archive_name("17.4.0")));
+    }
+
+    #[test]
+    fn verify_archive_checksum_uses_sidecar_and_rejects_mismatch() {
+        let temp = tempdir().expect("tempdir");
+        let archive = write_archive(utf8(&temp), "17.4.0");
+        let digest = sha256_file(&archive).expect("digest");
+
+        fs::write(
+            format!("{archive}{CHECKSUM_SUFFIX}"),
+            format!("{digest}  archive\n"),
+        )
+        .expect("write sidecar");
+        verify_archive_checksum(&archive, None, false).expect("sidecar digest should match");
+
+        let err = verify_archive_checksum(&archive, Some(&"0".repeat(64)), false)
+            .expect_err("explicit digest should override sidecar");
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+    }
+
+    #[rstest]
+    #[case::rejected(false)]
+    #[case::allowed(true)]
+    fn verify_archive_checksum_requires_a_digest_unless_allowed(#[case] allow_unverified: bool) {
+        let temp = tempdir().expect("tempdir");
+        let archive = write_archive(utf8(&temp), "17.4.0");
+
+        let result = verify_archive_checksum(&archive, None, allow_unverified);
+
+        assert_eq!(result.is_ok(), allow_unverified, "{result:?}");
+        if let Err(err) = result {
+            assert!(err.to_string().contains(ALLOW_UNVERIFIED_ENV), "{err}");
+        }
+    }
+
+    #[test]
+    fn import_archive_rejects_archive_without_digest() {
+        let archive_dir = tempdir().expect("archive dir");
+        let cache = tempdir().expect("cache dir");
+        let archive = write_archive(utf8(&archive_dir), "17.4.0");
+
+        let err = import_archive(&archive, None, false, utf8(&cache))
+            .expect_err("archive without digest should be rejected");
+
+        assert!(err.to_string().contains("no SHA-256 digest"), "{err}");
+        assert!(matches!(
+            check_cache(utf8(&cache), "17.4.0"),
+            CacheLookupResult::Miss
+        ));
+    }
+
+    #[test]
+    fn import_archive_populates_cache_entry() {
+        let archive_dir = tempdir().expect("archive dir");
+        let cache = tempdir().expect("cache dir");
+        let archive = write_archive(utf8(&archive_dir), "17.4.0");
+        let digest = sha256_file(&archive).expect("digest");
+
+        let version = import_archive(&archive, Some(&digest), false, utf8(&cache)).expect("import");
+        assert_eq!(version.to_string(), "17.4.0");
+
+        let CacheLookupResult::Hit { source_dir } = check_cache(utf8(&cache), "17.4.0") else {
+            panic!("imported archive should be a cache hit");
+        };
+        let binary = source_dir.join("bin/postgres");
+        assert!(binary.is_file(), "binary should be extracted to {binary}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&binary)
+                .expect("metadata")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o111, 0o111, "binary should stay executable");
+        }
+    }
+
+    #[test]
+    fn import_archive_rejects_tampered_archive() {
+        let archive_dir = tempdir().expect("archive dir");
+        let cache = tempdir().expect("cache dir");
+        let archive = write_archive(utf8(&archive_dir), "17.4.0");
+
+        let err = import_archive(&archive, Some(&"f".repeat(64)), false, utf8(&cache))
+            .expect_err("tampered archive should be rejected");
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+        assert!(matches!(
+            check_cache(utf8(&cache), "17.4.0"),
+            CacheLookupResult::Miss
+        ));
+    }
+}
@@ -1,34 +1,139 @@
 //! Configuration for the shared binary cache.
 //!
 //! Resolves the cache directory from environment variables with XDG-compliant
-//! fallback paths.
+//! fallback paths, along with the integrity check applied to cache hits, the
+//! strategy used to place cached files, the retention policy, and whether
+//! data directory snapshots are reused.
 
 use camino::Utf8PathBuf;
 use std::path::PathBuf;
 
+use super::retention::{RetentionPolicy, resolve_retention_policy};
+
 /// Subdirectory path within the XDG cache home.
 const CACHE_SUBDIR: &str = "pg-embedded/binaries";
 
+/// How thoroughly a cache entry is checked against its manifest before use.
+///
+/// Entries written by [`populate_cache`](super::populate_cache) carry a
+/// manifest recording the size and SHA-256 digest of every file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegrityCheck {
+    /// Trust the completion marker and `bin/` directory alone.
+    Marker,
+    /// Also compare every file size with the manifest, when one exists.
+    #[default]
+    Sizes,
+    /// Also compare the SHA-256 digest of every file with the manifest.
+    Digests,
+}
+
+impl IntegrityCheck {
+    /// Parses an integrity level name (`marker`, `sizes`, or `digests`).
+    #[must_use]
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "marker" => Some(Self::Marker),
+            "sizes" => Some(Self::Sizes),
+            "digests" => Some(Self::Digests),
+            _ => None,
+        }
+    }
+}
+
+/// How cached files are placed in a sandbox installation directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyStrategy {
+    /// Copy every file.
+    Copy,
+    /// Clone files with `FICLONE` where the filesystem supports reflinks,
+    /// copying otherwise.
+    #[default]
+    Reflink,
+    /// Hardlink files, clearing their write bits in the cache, and copy
+    /// wherever linking fails. Root processes reflink instead.
+    Hardlink,
+}
+
+impl CopyStrategy {
+    /// Parses a strategy name (`copy`, `reflink`, or `hardlink`).
+    #[must_use]
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "copy" => Some(Self::Copy),
+            "reflink" => Some(Self::Reflink),
+            "hardlink" => Some(Self::Hardlink),
+            _ => None,
+        }
+    }
+}
+
 /// Configuration for the shared binary cache.
+///
+/// Fields may be added in minor releases, so build values with
+/// [`new`](Self::new), [`with_dir`](Self::with_dir), or
+/// [`from_env_with_dir`](Self::from_env_with_dir) and adjust the fields.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct BinaryCacheConfig {
     /// Root directory for cached `PostgreSQL` binaries.
     pub cache_dir: Utf8PathBuf,
+    /// Integrity check applied to an entry before it is copied into a sandbox.
+    pub integrity: IntegrityCheck,
+    /// How cached files are placed in a sandbox.
+    pub copy_strategy: CopyStrategy,
+    /// Limits enforced by eviction after the cache is used.
+    pub retention: RetentionPolicy,
+    /// Whether initialised data directories are snapshotted and reused.
+    pub data_snapshots: bool,
 }
 
 impl BinaryCacheConfig {
     /// Creates a new cache configuration using the resolved cache directory.
+    ///
+    /// The remaining settings are resolved from the environment as by
+    /// [`from_env_with_dir`](Self::from_env_with_dir).
     #[must_use]
     pub fn new() -> Self {
+        Self::from_env_with_dir(resolve_cache_dir())
+    }
+
+    /// Creates a cache configuration with a custom directory and default
+    /// settings.
+    ///
+    /// The environment is not consulted: entries are checked by size, files
+    /// are reflinked, retention is unbounded, and data snapshots are enabled.
+    #[must_use]
+    pub const fn with_dir(cache_dir: Utf8PathBuf) -> Self {
         Self {
-            cache_dir: resolve_cache_dir(),
+            cache_dir,
+            integrity: IntegrityCheck::Sizes,
+            copy_strategy: CopyStrategy::Reflink,
+            retention: RetentionPolicy {
+                max_entries: None,
+                max_bytes: None,
+                max_age: None,
+            },
+            data_snapshots: true,
         }
     }
 
-    /// Creates a cache configuration with a custom directory.
+    /// Creates a cache configuration with a custom directory, resolving the
+    /// other settings from the environment.
+    ///
+    /// The integrity check, copy strategy, retention policy, and snapshot
+    /// toggle are resolved by [`resolve_integrity_check`],
+    /// [`resolve_copy_strategy`], [`resolve_retention_policy`], and
+    /// [`resolve_data_snapshots`].
     #[must_use]
-    pub const fn with_dir(cache_dir: Utf8PathBuf) -> Self {
-        Self { cache_dir }
+    pub fn from_env_with_dir(cache_dir: Utf8PathBuf) -> Self {
+        Self {
+            cache_dir,
+            integrity: resolve_integrity_check(),
+            copy_strategy: resolve_copy_strategy(),
+            retention: resolve_retention_policy(),
+            data_snapshots: resolve_data_snapshots(),
+        }
     }
 }
 
@@ -80,6 +185,77 @@ pub fn resolve_cache_dir() -> Utf8PathBuf {
     })
 }
 
+/// Resolves the integrity check from `PG_BINARY_CACHE_VERIFY`.
+///
+/// Accepts `marker`, `sizes`, or `digests`; unset, empty, or unrecognised
+/// values fall back to [`IntegrityCheck::Sizes`].
+///
+/// # Examples
+///
+/// ```
+/// use pg_embedded_setup_unpriv::cache::{IntegrityCheck, resolve_integrity_check};
+///
+/// let check = resolve_integrity_check();
+/// assert!(matches!(
+///     check,
+///     IntegrityCheck::Marker | IntegrityCheck::Sizes | IntegrityCheck::Digests
+/// ));
+/// ```
+#[must_use]
+pub fn resolve_integrity_check() -> IntegrityCheck {
+    std::env::var("PG_BINARY_CACHE_VERIFY")
+        .ok()
+        .and_then(|raw| IntegrityCheck::parse(&raw))
+        .unwrap_or_default()
+}
+
+/// Resolves the copy strategy from `PG_BINARY_CACHE_COPY`.
+///
+/// Accepts `copy`, `reflink`, or `hardlink`; unset, empty, or unrecognised
+/// values fall back to [`CopyStrategy::Reflink`].
+///
+/// # Examples
+///
+/// ```
+/// use pg_embedded_setup_unpriv::cache::{CopyStrategy, resolve_copy_strategy};
+///
+/// if resolve_copy_strategy() == CopyStrategy::Hardlink {
+///     println!("cached files are shared with sandboxes");
+/// }
+/// ```
+#[must_use]
+pub fn resolve_copy_strategy() -> CopyStrategy {
+    std::env::var("PG_BINARY_CACHE_COPY")
+        .ok()
+        .and_then(|raw| CopyStrategy::parse(&raw))
+        .unwrap_or_default()
+}
+
+/// Resolves whether data directory snapshots are used from
+/// `PG_DATA_SNAPSHOT_CACHE`.
+///
+/// Snapshots are enabled unless the variable is `0`, `false`, `no`, or `off`
+/// (case-insensitive).
+///
+/// # Examples
+///
+/// ```
+/// use pg_embedded_setup_unpriv::cache::resolve_data_snapshots;
+///
+/// if !resolve_data_snapshots() {
+///     println!("every cluster runs initdb");
+/// }
+/// ```
+#[must_use]
+pub fn resolve_data_snapshots() -> bool {
+    std::env::var("PG_DATA_SNAPSHOT_CACHE").map_or(true, |raw| {
+        !matches!(
+            raw.trim().to_ascii_lowercase().as_str(),
+            "0" | "false" | "no" | "off"
+        )
+    })
+}
+
 /// Attempts to resolve cache directory from `PG_BINARY_CACHE_DIR` environment variable.
 fn resolve_from_env() -> Option<Utf8PathBuf> {
     let raw = std::env::var("PG_BINARY_CACHE_DIR").ok()?;
@@ -164,6 +340,48 @@ mod tests {
         assert!(config.cache_dir.as_str().contains("pg-embedded"));
     }
 
+    #[rstest]
+    #[case::unset(None, IntegrityCheck::Sizes)]
+    #[case::marker(Some("marker"), IntegrityCheck::Marker)]
+    #[case::digests_mixed_case(Some(" Digests "), IntegrityCheck::Digests)]
+    #[case::unknown(Some("paranoid"), IntegrityCheck::Sizes)]
+    fn resolve_integrity_check_reads_env(
+        #[case] raw: Option<&str>,
+        #[case] expected: IntegrityCheck,
+    ) {
+        let _guard = scoped_env([(
+            OsString::from("PG_BINARY_CACHE_VERIFY"),
+            raw.map(OsString::from),
+        )]);
+        assert_eq!(resolve_integrity_check(), expected);
+    }
+
+    #[rstest]
+    #[case::unset(None, CopyStrategy::Reflink)]
+    #[case::copy(Some("copy"), CopyStrategy::Copy)]
+    #[case::hardlink_mixed_case(Some(" HardLink "), CopyStrategy::Hardlink)]
+    #[case::unknown(Some("symlink"), CopyStrategy::Reflink)]
+    fn resolve_copy_strategy_reads_env(#[case] raw: Option<&str>, #[case] expected: CopyStrategy) {
+        let _guard = scoped_env([(
+            OsString::from("PG_BINARY_CACHE_COPY"),
+            raw.map(OsString::from),
+        )]);
+        assert_eq!(resolve_copy_strategy(), expected);
+    }
+
+    #[rstest]
+    #[case::unset(None, true)]
+    #[case::enabled(Some("1"), true)]
+    #[case::disabled(Some(" OFF "), false)]
+    #[case::false_word(Some("false"), false)]
+    fn resolve_data_snapshots_reads_env(#[case] raw: Option<&str>, #[case] expected: bool) {
+        let _guard = scoped_env([(
+            OsString::from("PG_DATA_SNAPSHOT_CACHE"),
+            raw.map(OsString::from),
+        )]);
+        assert_eq!(resolve_data_snapshots(), expected);
+    }
+
     #[test]
     fn binary_cache_config_with_dir_uses_provided_path() {
         let custom_path = Utf8PathBuf::from("/custom/path");
@@ -73,20 +73,55 @@ impl CacheLock {
         Self::acquire(cache_dir, version, LockType::Shared)
     }
 
+    /// Acquires an exclusive lock only if no other holder exists.
+    ///
+    /// Returns `Ok(None)` without blocking when another process holds a
+    /// shared or exclusive lock on the version. Eviction uses this so that
+    /// binaries in use elsewhere are never removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock file cannot be created or locking fails
+    /// for a reason other than contention.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use camino::Utf8Path;
+    /// use pg_embedded_setup_unpriv::cache::CacheLock;
+    ///
+    /// let cache_dir = Utf8Path::new("/tmp/pg-cache");
+    /// if let Some(_lock) = CacheLock::try_acquire_exclusive(cache_dir, "17.4.0")? {
+    ///     // No other process is using version 17.4.0
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(unix)]
+    pub fn try_acquire_exclusive(cache_dir: &Utf8Path, version: &str) -> io::Result<Option<Self>> {
+        let file = open_lock_file(cache_dir, version)?;
+        // SAFETY: `file` owns a valid descriptor for the duration of the call.
+        let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if result == 0 {
+            return Ok(Some(Self { _file: file }));
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            Ok(None)
+        } else {
+            Err(err)
+        }
+    }
+
+    /// Non-blocking exclusive lock on non-Unix platforms; always succeeds.
+    #[cfg(not(unix))]
+    pub fn try_acquire_exclusive(cache_dir: &Utf8Path, version: &str) -> io::Result<Option<Self>> {
+        Self::acquire(cache_dir, version, LockType::Exclusive).map(Some)
+    }
+
     /// Acquires a lock with the specified type.
     #[cfg(unix)]
     fn acquire(cache_dir: &Utf8Path, version: &str, lock_type: LockType) -> io::Result<Self> {
-        validate_version(version)?;
-        let locks_dir = cache_dir.join(LOCKS_SUBDIR);
-        std::fs::create_dir_all(&locks_dir)?;
-
-        let lock_path = locks_dir.join(format!("{version}.lock"));
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(false)
-            .open(&lock_path)?;
+        let file = open_lock_file(cache_dir, version)?;
 
         let flock_arg = match lock_type {
             LockType::Exclusive => libc::LOCK_EX,
@@ -135,6 +170,22 @@ impl CacheLock {
     }
 }
 
+/// Opens (creating if needed) the lock file for a version.
+#[cfg(unix)]
+fn open_lock_file(cache_dir: &Utf8Path, version: &str) -> io::Result<File> {
+    validate_version(version)?;
+    let locks_dir = cache_dir.join(LOCKS_SUBDIR);
+    std::fs::create_dir_all(&locks_dir)?;
+
+    let lock_path = locks_dir.join(format!("{version}.lock"));
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+}
+
 /// Type of lock to acquire.
 #[derive(Debug, Clone, Copy)]
 enum LockType {
@@ -222,6 +273,22 @@ mod tests {
         drop(lock2);
     }
 
+    #[rstest]
+    fn try_acquire_exclusive_yields_to_shared_holders(
+        cache_fixture: (TempDir, camino::Utf8PathBuf),
+    ) {
+        let (_temp, cache_dir) = cache_fixture;
+
+        let shared = CacheLock::acquire_shared(&cache_dir, "17.4.0").expect("acquire shared");
+        let contended =
+            CacheLock::try_acquire_exclusive(&cache_dir, "17.4.0").expect("try exclusive");
+        assert!(contended.is_none(), "shared holder should block eviction");
+
+        drop(shared);
+        let free = CacheLock::try_acquire_exclusive(&cache_dir, "17.4.0").expect("try exclusive");
+        assert!(free.is_some(), "lock should be free once released");
+    }
+
     #[rstest]
     #[case::parent_dir_exclusive("..")]
     #[case::parent_dir_shared("..")]
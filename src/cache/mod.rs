@@ -19,14 +19,69 @@
 //! The cache uses file-based locking to coordinate downloads across parallel
 //! test runners. Locks are per-version, allowing different versions to be
 //! downloaded concurrently.
+//!
+//! # Placement
+//!
+//! [`copy_from_cache_with`] places an entry in a sandbox by copying,
+//! reflinking, or hardlinking its files according to [`CopyStrategy`],
+//! falling back to a copy wherever linking is refused.
+//!
+//! # Integrity
+//!
+//! [`populate_cache`] records the size and SHA-256 digest of every file in a
+//! manifest beside the completion marker. [`check_cache_with`] compares an
+//! entry against it at the depth chosen by [`IntegrityCheck`], and
+//! [`quarantine_if_corrupt`] moves entries that fail aside so they are
+//! re-populated on the next run.
+//!
+//! # Retention
+//!
+//! Each use of an entry is recorded by [`record_access`]. [`evict_cache`]
+//! removes the least recently used versions until the [`RetentionPolicy`]
+//! resolved by [`resolve_retention_policy`] holds, skipping versions that
+//! other processes still have locked.
+//!
+//! # Data Directory Snapshots
+//!
+//! [`store_data_snapshot`] captures a freshly initialised data directory
+//! beside the binaries, keyed by [`DataSnapshotKey`], and
+//! [`restore_data_snapshot`] copies it into a new cluster so `initdb` can be
+//! skipped.
+//!
+//! # Offline Provisioning
+//!
+//! [`import_archive`] seeds the cache from a local release archive, so test
+//! runs without network access can still obtain binaries.
+//!
+//! # Maintenance
+//!
+//! [`warm_cache`], [`list_cached_versions`], [`verify_cache`], and
+//! [`prune_cache`] back the `pg_embedded_setup_unpriv cache` subcommands used
+//! to pre-populate and audit the cache in CI images.
 
+mod archive;
 mod config;
 mod lock;
 mod operations;
+mod retention;
+mod snapshot;
+mod warm;
 
-pub use config::{BinaryCacheConfig, resolve_cache_dir};
+pub use archive::{
+    archive_version, ensure_archive_satisfies, find_mirror_archive, import_archive,
+    import_from_mirror, mirror_dir_from_url, verify_archive_checksum,
+};
+pub use config::{
+    BinaryCacheConfig, CopyStrategy, IntegrityCheck, resolve_cache_dir, resolve_copy_strategy,
+    resolve_data_snapshots, resolve_integrity_check,
+};
 pub use lock::CacheLock;
 pub use operations::{
-    CacheLookupResult, check_cache, copy_from_cache, find_matching_cached_version, populate_cache,
-    try_populate_cache, try_use_cache,
+    CacheEntryProblem, CacheLookupResult, CacheVerification, CachedVersion, check_cache,
+    check_cache_with, copy_from_cache, copy_from_cache_with, evict_cache,
+    find_matching_cached_version, list_cached_versions, populate_cache, prune_cache,
+    quarantine_if_corrupt, record_access, try_populate_cache, try_use_cache, verify_cache,
 };
+pub use retention::{RetentionPolicy, resolve_retention_policy};
+pub use snapshot::{DataSnapshotKey, restore_data_snapshot, snapshot_dir, store_data_snapshot};
+pub use warm::warm_cache;
@@ -1,6 +1,7 @@
 //! File and directory copy operations for the binary cache.
 //!
-//! Provides recursive directory copying with permission preservation.
+//! Provides recursive directory copying with permission preservation, placing
+//! each file by copy, reflink, or hardlink as chosen by [`CopyStrategy`].
 
 use camino::Utf8Path;
 use color_eyre::eyre::Context;
@@ -9,6 +10,7 @@ use std::io;
 use std::path::Path;
 use tracing::debug;
 
+use crate::cache::CopyStrategy;
 use crate::error::BootstrapResult;
 
 /// Observability target for cache operations.
@@ -17,7 +19,8 @@ const LOG_TARGET: &str = "pg_embed::cache";
 /// Copies cached binaries to the target installation directory.
 ///
 /// Performs a recursive copy of the source directory contents to the target,
-/// preserving directory structure and file permissions.
+/// preserving directory structure and file permissions. Equivalent to
+/// [`copy_from_cache_with`] using [`CopyStrategy::Copy`].
 ///
 /// # Arguments
 ///
@@ -43,24 +46,76 @@ const LOG_TARGET: &str = "pg_embed::cache";
 /// # Ok::<(), color_eyre::Report>(())
 /// ```
 pub fn copy_from_cache(source: &Utf8Path, target: &Utf8Path) -> BootstrapResult<()> {
-    log_copy_start(source, target);
+    copy_from_cache_with(source, target, CopyStrategy::Copy)
+}
+
+/// Places cached binaries in the target installation directory using
+/// `strategy`.
+///
+/// Reflinks and hardlinks fall back to a plain copy per file wherever the
+/// filesystem refuses them, such as across mount points. Hardlinked files
+/// share an inode with the cache entry, so their write permission bits are
+/// cleared in the cache first. Root processes reflink instead of hardlinking,
+/// because the sandbox tree is later re-owned by `nobody` and a shared inode
+/// would hand the cached file to that user.
+///
+/// # Errors
+///
+/// Returns an error if the target directory cannot be created or a file can
+/// be neither linked nor copied.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{CopyStrategy, copy_from_cache_with};
+///
+/// let source = Utf8Path::new("/home/user/.cache/pg-embedded/binaries/17.4.0");
+/// let target = Utf8Path::new("/tmp/sandbox/install");
+/// copy_from_cache_with(source, target, CopyStrategy::Hardlink)?;
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn copy_from_cache_with(
+    source: &Utf8Path,
+    target: &Utf8Path,
+    strategy: CopyStrategy,
+) -> BootstrapResult<()> {
+    let effective = effective_strategy(strategy);
+    log_copy_start(source, target, effective);
 
     fs::create_dir_all(target)
         .with_context(|| format!("failed to create target directory for cache copy: {target}"))?;
 
-    copy_dir_recursive(source.as_std_path(), target.as_std_path())
+    copy_tree(source.as_std_path(), target.as_std_path(), effective)
         .with_context(|| format!("failed to copy cached binaries from {source} to {target}"))?;
 
     log_copy_complete(source, target);
     Ok(())
 }
 
+/// Downgrades hardlinks to reflinks for root processes.
+#[cfg(unix)]
+fn effective_strategy(strategy: CopyStrategy) -> CopyStrategy {
+    if strategy == CopyStrategy::Hardlink && nix::unistd::geteuid().is_root() {
+        CopyStrategy::Reflink
+    } else {
+        strategy
+    }
+}
+
+/// Uses the requested strategy unchanged on non-Unix platforms.
+#[cfg(not(unix))]
+const fn effective_strategy(strategy: CopyStrategy) -> CopyStrategy {
+    strategy
+}
+
 /// Logs the start of a cache copy operation.
-fn log_copy_start(source: &Utf8Path, target: &Utf8Path) {
+fn log_copy_start(source: &Utf8Path, target: &Utf8Path, strategy: CopyStrategy) {
     debug!(
         target: LOG_TARGET,
         source = %source,
         target = %target,
+        strategy = ?strategy,
         "copying binaries from cache"
     );
 }
@@ -79,6 +134,11 @@ fn log_copy_complete(source: &Utf8Path, target: &Utf8Path) {
 ///
 /// Preserves directory structure and copies file metadata where possible.
 pub(crate) fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
+    copy_tree(src, dst, CopyStrategy::Copy)
+}
+
+/// Recursively places a directory's contents using `strategy`.
+fn copy_tree(src: &Path, dst: &Path, strategy: CopyStrategy) -> io::Result<()> {
     if !dst.exists() {
         fs::create_dir_all(dst)?;
     }
@@ -90,11 +150,11 @@ pub(crate) fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
         let dst_path = dst.join(entry.file_name());
 
         if file_type.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
+            copy_tree(&src_path, &dst_path, strategy)?;
         } else if file_type.is_symlink() {
             copy_symlink(&src_path, &dst_path)?;
         } else {
-            copy_file_with_permissions(&src_path, &dst_path)?;
+            place_file(&src_path, &dst_path, strategy)?;
         }
     }
 
@@ -103,6 +163,79 @@ pub(crate) fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Places a single file, falling back to a plain copy when linking fails.
+fn place_file(src: &Path, dst: &Path, strategy: CopyStrategy) -> io::Result<()> {
+    remove_existing_file(dst)?;
+    if strategy == CopyStrategy::Hardlink && hardlink_read_only(src, dst).is_ok() {
+        return Ok(());
+    }
+    if strategy != CopyStrategy::Copy && reflink_file(src, dst).is_ok() {
+        copy_permissions(src, dst);
+        return Ok(());
+    }
+    copy_file_with_permissions(src, dst)
+}
+
+/// Removes a file left by an earlier placement, which may be a read-only
+/// hardlink that cannot be overwritten in place.
+fn remove_existing_file(dst: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(dst) {
+        Ok(metadata) if !metadata.is_dir() => fs::remove_file(dst),
+        _ => Ok(()),
+    }
+}
+
+/// Hardlinks `src` to `dst` after clearing the shared inode's write bits.
+fn hardlink_read_only(src: &Path, dst: &Path) -> io::Result<()> {
+    let mut permissions = fs::metadata(src)?.permissions();
+    if !permissions.readonly() {
+        clear_write_bits(&mut permissions);
+        fs::set_permissions(src, permissions)?;
+    }
+    fs::hard_link(src, dst)
+}
+
+/// Clears every write permission bit.
+#[cfg(unix)]
+fn clear_write_bits(permissions: &mut fs::Permissions) {
+    use std::os::unix::fs::PermissionsExt;
+
+    permissions.set_mode(permissions.mode() & !0o222);
+}
+
+/// Marks the permissions read-only.
+#[cfg(not(unix))]
+fn clear_write_bits(permissions: &mut fs::Permissions) {
+    permissions.set_readonly(true);
+}
+
+/// Clones `src` into a new `dst` with the `FICLONE` ioctl.
+///
+/// Fails on filesystems without reflink support, leaving no `dst` behind.
+#[cfg(target_os = "linux")]
+fn reflink_file(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let source = fs::File::open(src)?;
+    let target = fs::File::options().write(true).create_new(true).open(dst)?;
+    // SAFETY: both descriptors are owned by open `File`s for the duration of
+    // the call, and `FICLONE` takes the source descriptor by value.
+    let result = unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) };
+    if result == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    drop(target);
+    fs::remove_file(dst)?;
+    Err(err)
+}
+
+/// Reflinks are only attempted on Linux.
+#[cfg(not(target_os = "linux"))]
+fn reflink_file(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
 /// Copies a file preserving its permissions.
 fn copy_file_with_permissions(src: &Path, dst: &Path) -> io::Result<()> {
     fs::copy(src, dst)?;
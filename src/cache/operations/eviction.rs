@@ -0,0 +1,173 @@
+//! Last-access tracking and retention-based eviction.
+//!
+//! Each use of a cache entry touches an access file inside it, so eviction can
+//! remove the least recently used versions first. Eviction only takes
+//! per-version locks without blocking and skips any version another process
+//! still holds.
+
+use camino::Utf8Path;
+use color_eyre::eyre::Context;
+use postgresql_embedded::Version;
+use std::fs::{self, File};
+use std::time::SystemTime;
+use tracing::{debug, info};
+
+use super::lookup::COMPLETION_MARKER;
+use super::maintenance::{CachedVersion, list_cached_versions};
+use crate::cache::{CacheLock, RetentionPolicy};
+use crate::error::BootstrapResult;
+
+/// Observability target for cache operations.
+const LOG_TARGET: &str = "pg_embed::cache";
+
+/// File whose modification time records the last use of a cache entry.
+pub(crate) const ACCESS_FILE: &str = ".last-access";
+
+/// Records that a cache entry was just used.
+///
+/// Call this while holding a shared [`CacheLock`] for the version so the entry
+/// cannot be evicted or replaced concurrently. The access time is stored as
+/// the modification time of a `.last-access` file in the entry.
+///
+/// # Errors
+///
+/// Returns an error if the access file cannot be created or updated.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{CacheLock, record_access};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let _lock = CacheLock::acquire_shared(cache_dir, "17.4.0")?;
+/// record_access(cache_dir, "17.4.0")?;
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn record_access(cache_dir: &Utf8Path, version: &str) -> BootstrapResult<()> {
+    let path = cache_dir.join(version).join(ACCESS_FILE);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("failed to open cache access file: {path}"))?;
+    file.set_modified(SystemTime::now())
+        .with_context(|| format!("failed to update cache access time: {path}"))?;
+    Ok(())
+}
+
+/// Returns when a cache entry was last used.
+///
+/// Entries that were never read fall back to the time they were completed.
+fn last_access(version_dir: &Utf8Path) -> SystemTime {
+    [ACCESS_FILE, COMPLETION_MARKER]
+        .iter()
+        .find_map(|name| version_dir.join(name).metadata().ok()?.modified().ok())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Removes least recently used versions until the cache satisfies `policy`.
+///
+/// Versions unused for longer than `max_age` are removed first, then the
+/// least recently used versions until both `max_entries` and `max_bytes` hold.
+/// Each removal takes the version's exclusive [`CacheLock`] without blocking;
+/// a version that another process is reading or populating is skipped and
+/// still counts towards the limits. Incomplete entries are left to
+/// [`prune_cache`](super::prune_cache). Returns the evicted versions.
+///
+/// # Errors
+///
+/// Returns an error if the cache directory cannot be read, locking fails for
+/// a reason other than contention, or an entry cannot be removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{RetentionPolicy, evict_cache};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let policy = RetentionPolicy {
+///     max_entries: Some(2),
+///     ..RetentionPolicy::default()
+/// };
+/// let evicted = evict_cache(cache_dir, &policy)?;
+/// println!("evicted {} versions", evicted.len());
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn evict_cache(
+    cache_dir: &Utf8Path,
+    policy: &RetentionPolicy,
+) -> BootstrapResult<Vec<Version>> {
+    if policy.is_unbounded() {
+        return Ok(Vec::new());
+    }
+    let now = SystemTime::now();
+    let mut entries: Vec<(CachedVersion, SystemTime)> = list_cached_versions(cache_dir)?
+        .into_iter()
+        .filter(|entry| entry.complete)
+        .map(|entry| {
+            let accessed = last_access(&entry.path);
+            (entry, accessed)
+        })
+        .collect();
+    entries.sort_by_key(|(_entry, accessed)| *accessed);
+
+    let mut remaining = entries.len();
+    let mut total_bytes: u64 = entries.iter().map(|(entry, _)| entry.size_bytes).sum();
+    let mut evicted = Vec::new();
+    for (entry, accessed) in entries {
+        let expired = policy
+            .max_age
+            .is_some_and(|max_age| now.duration_since(accessed).is_ok_and(|age| age > max_age));
+        let over_entries = policy.max_entries.is_some_and(|max| remaining > max);
+        let over_bytes = policy.max_bytes.is_some_and(|max| total_bytes > max);
+        if !(expired || over_entries || over_bytes) {
+            continue;
+        }
+        if evict_entry(cache_dir, &entry)? {
+            remaining -= 1;
+            total_bytes = total_bytes.saturating_sub(entry.size_bytes);
+            evicted.push(entry.version);
+        }
+    }
+    Ok(evicted)
+}
+
+/// Deletes a cache entry unless another process holds its lock.
+fn evict_entry(cache_dir: &Utf8Path, entry: &CachedVersion) -> BootstrapResult<bool> {
+    let version = entry.version.to_string();
+    let lock = CacheLock::try_acquire_exclusive(cache_dir, &version)
+        .with_context(|| format!("failed to lock cache entry for {version}"))?;
+    let Some(_lock) = lock else {
+        log_entry_in_use(&version);
+        return Ok(false);
+    };
+    if !entry.path.is_dir() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(&entry.path)
+        .with_context(|| format!("failed to evict cache entry: {}", entry.path))?;
+    log_entry_evicted(&version, entry.size_bytes);
+    Ok(true)
+}
+
+/// Logs a version skipped because another process holds its lock.
+fn log_entry_in_use(version: &str) {
+    debug!(
+        target: LOG_TARGET,
+        version = %version,
+        "cache entry in use, skipping eviction"
+    );
+}
+
+/// Logs an evicted version.
+fn log_entry_evicted(version: &str, size_bytes: u64) {
+    info!(
+        target: LOG_TARGET,
+        version = %version,
+        size_bytes,
+        "evicted cache entry"
+    );
+}
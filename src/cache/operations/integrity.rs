@@ -0,0 +1,311 @@
+//! Manifest-based integrity checks and quarantine for cache entries.
+//!
+//! [`populate_cache`](super::populate_cache) records the size and SHA-256
+//! digest of every file in a manifest. Lookups compare entries against it, and
+//! entries that no longer match are moved aside under the exclusive
+//! [`CacheLock`] so the next run re-populates them instead of failing deep
+//! inside `initdb`.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+use super::eviction::ACCESS_FILE;
+use super::lookup::COMPLETION_MARKER;
+use super::maintenance::CacheEntryProblem;
+use crate::cache::{CacheLock, IntegrityCheck};
+use crate::error::BootstrapResult;
+
+/// Observability target for cache operations.
+const LOG_TARGET: &str = "pg_embed::cache";
+
+/// Manifest file name within a cache entry.
+pub(crate) const MANIFEST_FILE: &str = ".manifest.json";
+
+/// Subdirectory of the cache holding quarantined entries.
+const QUARANTINE_SUBDIR: &str = ".quarantine";
+
+/// Number of quarantined entries kept for inspection.
+const QUARANTINE_RETAINED: usize = 2;
+
+/// Size and digest of every regular file in a cache entry.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    files: Vec<ManifestEntry>,
+}
+
+/// Recorded state of a single file, keyed by its `/`-separated relative path.
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestEntry {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+/// Computes the SHA-256 digest of a file as lowercase hex.
+pub(crate) fn sha256_file(path: &Utf8Path) -> BootstrapResult<String> {
+    let file = File::open(path).with_context(|| format!("failed to open {path}"))?;
+    let mut reader = BufReader::new(file);
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher).with_context(|| format!("failed to read {path}"))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Writes the manifest for a populated cache entry.
+///
+/// Symlinks are not recorded; the files they point at are.
+pub(super) fn write_manifest(version_dir: &Utf8Path) -> BootstrapResult<()> {
+    let mut files = Vec::new();
+    collect_entries(version_dir, "", &mut files)?;
+    files.sort_by(|left, right| left.path.cmp(&right.path));
+    let manifest_path = version_dir.join(MANIFEST_FILE);
+    let contents =
+        serde_json::to_vec(&Manifest { files }).context("failed to serialise cache manifest")?;
+    fs::write(&manifest_path, contents)
+        .with_context(|| format!("failed to write cache manifest: {manifest_path}"))?;
+    Ok(())
+}
+
+/// Records every regular file beneath `dir`, skipping cache bookkeeping files.
+fn collect_entries(
+    dir: &Utf8Path,
+    prefix: &str,
+    files: &mut Vec<ManifestEntry>,
+) -> BootstrapResult<()> {
+    let entries = dir
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read {dir}"))?;
+    for dir_entry in entries {
+        let entry = dir_entry.with_context(|| format!("failed to read entry in {dir}"))?;
+        let relative = format!("{prefix}{}", entry.file_name());
+        if prefix.is_empty() && is_bookkeeping_file(entry.file_name()) {
+            continue;
+        }
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("failed to stat {}", entry.path()))?;
+        if file_type.is_dir() {
+            collect_entries(entry.path(), &format!("{relative}/"), files)?;
+        } else if file_type.is_file() {
+            files.push(ManifestEntry {
+                size: entry.path().metadata().map_or(0, |meta| meta.len()),
+                sha256: sha256_file(entry.path())?,
+                path: relative,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` for the marker, manifest, and access files at the entry root.
+fn is_bookkeeping_file(name: &str) -> bool {
+    [COMPLETION_MARKER, MANIFEST_FILE, ACCESS_FILE].contains(&name)
+}
+
+/// Compares a cache entry with its manifest at the requested depth.
+///
+/// [`IntegrityCheck::Marker`] performs no manifest checks. Entries written
+/// before manifests were introduced carry none and are trusted on their
+/// completion marker alone.
+pub(crate) fn manifest_problems(
+    version_dir: &Utf8Path,
+    check: IntegrityCheck,
+) -> Vec<CacheEntryProblem> {
+    if check == IntegrityCheck::Marker {
+        return Vec::new();
+    }
+    let manifest = match read_manifest(version_dir) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => return Vec::new(),
+        Err(err) => return vec![CacheEntryProblem::UnreadableManifest(err.to_string())],
+    };
+    manifest
+        .files
+        .iter()
+        .filter_map(|entry| file_problem(version_dir, entry, check))
+        .collect()
+}
+
+/// Loads the manifest, returning `None` when the entry has none.
+fn read_manifest(version_dir: &Utf8Path) -> BootstrapResult<Option<Manifest>> {
+    let path = version_dir.join(MANIFEST_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read(&path).with_context(|| format!("failed to read {path}"))?;
+    let manifest =
+        serde_json::from_slice(&contents).with_context(|| format!("failed to parse {path}"))?;
+    Ok(Some(manifest))
+}
+
+/// Checks one manifest entry against the file on disk.
+fn file_problem(
+    version_dir: &Utf8Path,
+    entry: &ManifestEntry,
+    check: IntegrityCheck,
+) -> Option<CacheEntryProblem> {
+    let path = version_dir.join(&entry.path);
+    let Ok(metadata) = path.metadata() else {
+        return Some(CacheEntryProblem::MissingFile(entry.path.clone()));
+    };
+    if metadata.len() != entry.size {
+        return Some(CacheEntryProblem::SizeMismatch {
+            path: entry.path.clone(),
+            expected: entry.size,
+            actual: metadata.len(),
+        });
+    }
+    if check != IntegrityCheck::Digests {
+        return None;
+    }
+    match sha256_file(&path) {
+        Ok(digest) if digest == entry.sha256 => None,
+        _ => Some(CacheEntryProblem::DigestMismatch(entry.path.clone())),
+    }
+}
+
+/// Verifies a cache entry and moves it to quarantine if it is corrupt.
+///
+/// The entry is first checked under a shared [`CacheLock`], so healthy
+/// entries never block concurrent readers. A corrupt entry is re-checked under
+/// the exclusive lock, then renamed into `.quarantine/` within the cache so
+/// the next population writes a fresh copy. Returns the quarantine path when
+/// the entry was moved.
+///
+/// Only the newest quarantined entries are kept for inspection; older ones
+/// are removed each time another entry is quarantined.
+///
+/// # Errors
+///
+/// Returns an error if a lock cannot be acquired or the entry cannot be moved.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{IntegrityCheck, quarantine_if_corrupt};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// if let Some(moved) = quarantine_if_corrupt(cache_dir, "17.4.0", IntegrityCheck::Digests)? {
+///     println!("quarantined corrupt entry at {moved}");
+/// }
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn quarantine_if_corrupt(
+    cache_dir: &Utf8Path,
+    version: &str,
+    check: IntegrityCheck,
+) -> BootstrapResult<Option<Utf8PathBuf>> {
+    let version_dir = cache_dir.join(version);
+    {
+        let _shared = CacheLock::acquire_shared(cache_dir, version)
+            .with_context(|| format!("failed to lock cache entry for {version}"))?;
+        if manifest_problems(&version_dir, check).is_empty() {
+            return Ok(None);
+        }
+    }
+
+    let _exclusive = CacheLock::acquire_exclusive(cache_dir, version)
+        .with_context(|| format!("failed to lock cache entry for {version}"))?;
+    let problems = manifest_problems(&version_dir, check);
+    if problems.is_empty() || !version_dir.is_dir() {
+        return Ok(None);
+    }
+    let destination = quarantine_path(cache_dir, version);
+    move_to_quarantine(&version_dir, &destination)?;
+    log_quarantined(version, &destination, &problems);
+    prune_quarantine(cache_dir);
+    Ok(Some(destination))
+}
+
+/// Builds a unique quarantine destination for a version.
+fn quarantine_path(cache_dir: &Utf8Path, version: &str) -> Utf8PathBuf {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    cache_dir
+        .join(QUARANTINE_SUBDIR)
+        .join(format!("{version}-{stamp}"))
+}
+
+/// Renames a corrupt entry into the quarantine directory.
+fn move_to_quarantine(version_dir: &Utf8Path, destination: &Utf8Path) -> BootstrapResult<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create quarantine directory: {parent}"))?;
+    }
+    fs::rename(version_dir, destination)
+        .with_context(|| format!("failed to quarantine cache entry {version_dir}"))?;
+    Ok(())
+}
+
+/// Removes all but the newest quarantined entries, ordered by their timestamp.
+///
+/// Pruning is best-effort: a failed removal is logged and left for the next
+/// quarantine, and an entry already removed by another process is ignored.
+fn prune_quarantine(cache_dir: &Utf8Path) {
+    let Ok(entries) = cache_dir.join(QUARANTINE_SUBDIR).read_dir_utf8() else {
+        return;
+    };
+    let mut quarantined: Vec<(u128, Utf8PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let (_version, stamp) = entry.file_name().rsplit_once('-')?;
+            Some((stamp.parse().ok()?, entry.into_path()))
+        })
+        .collect();
+    quarantined.sort_by_key(|(stamp, _path)| Reverse(*stamp));
+    for (_stamp, path) in quarantined.into_iter().skip(QUARANTINE_RETAINED) {
+        remove_quarantined(&path);
+    }
+}
+
+/// Deletes one quarantined entry, logging the outcome.
+fn remove_quarantined(path: &Utf8Path) {
+    match fs::remove_dir_all(path) {
+        Ok(()) => log_quarantine_removed(path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => log_quarantine_removal_failed(path, &err),
+    }
+}
+
+/// Logs a pruned quarantined entry.
+fn log_quarantine_removed(path: &Utf8Path) {
+    debug!(
+        target: LOG_TARGET,
+        path = %path,
+        "removed old quarantined cache entry"
+    );
+}
+
+/// Logs a quarantined entry that could not be pruned.
+fn log_quarantine_removal_failed(path: &Utf8Path, err: &io::Error) {
+    warn!(
+        target: LOG_TARGET,
+        path = %path,
+        error = %err,
+        "failed to remove old quarantined cache entry"
+    );
+}
+
+/// Logs a quarantined entry with the problems that triggered it.
+fn log_quarantined(version: &str, destination: &Utf8Path, problems: &[CacheEntryProblem]) {
+    let summary = problems
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ");
+    warn!(
+        target: LOG_TARGET,
+        version = %version,
+        quarantine = %destination,
+        problems = %summary,
+        "quarantined corrupt cache entry; it will be re-populated"
+    );
+}
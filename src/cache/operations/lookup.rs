@@ -8,6 +8,8 @@ use std::fs;
 use tracing::{debug, warn};
 
 use super::copy::copy_from_cache;
+use super::integrity::manifest_problems;
+use crate::cache::IntegrityCheck;
 
 /// Marker file name indicating a complete cache entry.
 pub(crate) const COMPLETION_MARKER: &str = ".complete";
@@ -67,24 +69,70 @@ fn is_cache_entry_complete(version_dir: &Utf8Path) -> bool {
 /// }
 /// ```
 pub fn check_cache(cache_dir: &Utf8Path, version: &str) -> CacheLookupResult {
+    check_cache_with(cache_dir, version, IntegrityCheck::Marker)
+}
+
+/// Checks the cache like [`check_cache`], also verifying the entry's manifest.
+///
+/// With [`IntegrityCheck::Sizes`] every recorded file must exist with its
+/// recorded size; [`IntegrityCheck::Digests`] also re-hashes each file. An
+/// entry that fails verification is reported as a miss and logged as a
+/// warning; use [`quarantine_if_corrupt`](super::quarantine_if_corrupt) to
+/// move it aside.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{CacheLookupResult, IntegrityCheck, check_cache_with};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let result = check_cache_with(cache_dir, "17.4.0", IntegrityCheck::Digests);
+/// assert!(matches!(result, CacheLookupResult::Hit { .. }));
+/// ```
+pub fn check_cache_with(
+    cache_dir: &Utf8Path,
+    version: &str,
+    check: IntegrityCheck,
+) -> CacheLookupResult {
     let version_dir = cache_dir.join(version);
 
-    if is_cache_entry_complete(&version_dir) {
-        debug!(
-            target: LOG_TARGET,
-            version = %version,
-            path = %version_dir,
-            "cache hit"
-        );
+    if !is_cache_entry_complete(&version_dir) {
+        log_cache_miss(&version_dir, version);
+        return CacheLookupResult::Miss;
+    }
+    let problems = manifest_problems(&version_dir, check);
+    if problems.is_empty() {
+        log_cache_hit(&version_dir, version);
         CacheLookupResult::Hit {
             source_dir: version_dir,
         }
     } else {
-        log_cache_miss(&version_dir, version);
+        log_corrupt_entry(version, problems.len());
         CacheLookupResult::Miss
     }
 }
 
+/// Logs a cache hit.
+fn log_cache_hit(version_dir: &Utf8Path, version: &str) {
+    debug!(
+        target: LOG_TARGET,
+        version = %version,
+        path = %version_dir,
+        "cache hit"
+    );
+}
+
+/// Logs a complete cache entry that failed manifest verification.
+fn log_corrupt_entry(version: &str, problem_count: usize) {
+    warn!(
+        target: LOG_TARGET,
+        version = %version,
+        problem_count,
+        "cache entry failed integrity verification"
+    );
+}
+
 /// Logs details about a cache miss for debugging.
 fn log_cache_miss(version_dir: &Utf8Path, version: &str) {
     let marker = version_dir.join(COMPLETION_MARKER);
@@ -0,0 +1,286 @@
+//! Cache inspection and pruning for maintenance tooling.
+//!
+//! Provides the listing, verification, and pruning operations behind the
+//! `pg_embedded_setup_unpriv cache` subcommands.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::Context;
+use postgresql_embedded::Version;
+use std::fmt;
+use std::fs;
+use tracing::info;
+
+use super::integrity::manifest_problems;
+use super::lookup::COMPLETION_MARKER;
+use crate::cache::{CacheLock, IntegrityCheck};
+use crate::error::BootstrapResult;
+
+/// Observability target for cache operations.
+const LOG_TARGET: &str = "pg_embed::cache";
+
+/// Binaries every usable cache entry must provide under `bin/`.
+const REQUIRED_BINARIES: [&str; 3] = ["postgres", "initdb", "pg_ctl"];
+
+/// A version directory found in the binary cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedVersion {
+    /// Version parsed from the directory name.
+    pub version: Version,
+    /// Path to the version directory.
+    pub path: Utf8PathBuf,
+    /// Whether the entry carries its completion marker.
+    pub complete: bool,
+    /// Total size of the files in the entry, in bytes.
+    pub size_bytes: u64,
+}
+
+/// Problem detected while verifying a cache entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEntryProblem {
+    /// The `.complete` marker is missing, so population never finished.
+    MissingMarker,
+    /// The `bin/` directory is missing.
+    MissingBinDir,
+    /// A required executable is missing from `bin/`.
+    MissingBinary(String),
+    /// The manifest exists but cannot be read or parsed.
+    UnreadableManifest(String),
+    /// A file recorded in the manifest is missing.
+    MissingFile(String),
+    /// A file's size differs from the manifest.
+    SizeMismatch {
+        /// Path relative to the cache entry.
+        path: String,
+        /// Size recorded in the manifest, in bytes.
+        expected: u64,
+        /// Size found on disk, in bytes.
+        actual: u64,
+    },
+    /// A file's SHA-256 digest differs from the manifest.
+    DigestMismatch(String),
+}
+
+impl fmt::Display for CacheEntryProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingMarker => f.write_str("completion marker is missing"),
+            Self::MissingBinDir => f.write_str("bin/ directory is missing"),
+            Self::MissingBinary(name) => write!(f, "bin/{name} is missing"),
+            Self::UnreadableManifest(reason) => write!(f, "manifest is unreadable: {reason}"),
+            Self::MissingFile(path) => write!(f, "{path} is missing"),
+            Self::SizeMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(f, "{path} is {actual} bytes, expected {expected}"),
+            Self::DigestMismatch(path) => write!(f, "{path} does not match its recorded digest"),
+        }
+    }
+}
+
+/// Verification outcome for a single cache entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheVerification {
+    /// Version of the verified entry.
+    pub version: Version,
+    /// Problems found; empty when the entry is usable.
+    pub problems: Vec<CacheEntryProblem>,
+}
+
+impl CacheVerification {
+    /// Returns `true` when no problems were found.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Lists the version directories in the binary cache, oldest first.
+///
+/// Hidden directories (such as `.locks`) and directories whose names are not
+/// versions are skipped. A missing cache directory yields an empty list.
+///
+/// # Errors
+///
+/// Returns an error if the cache directory exists but cannot be read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::list_cached_versions;
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// for entry in list_cached_versions(cache_dir)? {
+///     println!("{} ({} bytes)", entry.version, entry.size_bytes);
+/// }
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn list_cached_versions(cache_dir: &Utf8Path) -> BootstrapResult<Vec<CachedVersion>> {
+    if !cache_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let entries = cache_dir
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read cache directory: {cache_dir}"))?;
+
+    let mut versions: Vec<CachedVersion> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| cached_version(entry.into_path()))
+        .collect();
+    versions.sort_by(|left, right| left.version.cmp(&right.version));
+    Ok(versions)
+}
+
+/// Describes a cache directory if its name parses as a version.
+fn cached_version(path: Utf8PathBuf) -> Option<CachedVersion> {
+    let version = Version::parse(path.file_name()?).ok()?;
+    Some(CachedVersion {
+        version,
+        complete: path.join(COMPLETION_MARKER).is_file(),
+        size_bytes: directory_size(&path),
+        path,
+    })
+}
+
+/// Sums the sizes of regular files beneath `path` without following symlinks.
+fn directory_size(path: &Utf8Path) -> u64 {
+    let Ok(entries) = path.read_dir_utf8() else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.path().symlink_metadata() {
+            Ok(meta) if meta.is_dir() => directory_size(entry.path()),
+            Ok(meta) if meta.is_file() => meta.len(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Verifies every entry in the binary cache.
+///
+/// Each entry is checked under a shared [`CacheLock`], so entries that are
+/// being populated are inspected only once population finishes. Files are
+/// compared against the entry's manifest by size and SHA-256 digest,
+/// regardless of the configured [`IntegrityCheck`](crate::cache::IntegrityCheck).
+///
+/// # Errors
+///
+/// Returns an error if the cache directory cannot be read or a lock cannot
+/// be acquired.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::verify_cache;
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let broken = verify_cache(cache_dir)?
+///     .into_iter()
+///     .filter(|report| !report.is_valid())
+///     .count();
+/// assert_eq!(broken, 0);
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn verify_cache(cache_dir: &Utf8Path) -> BootstrapResult<Vec<CacheVerification>> {
+    list_cached_versions(cache_dir)?
+        .into_iter()
+        .map(|entry| {
+            let version = entry.version.to_string();
+            let _lock = CacheLock::acquire_shared(cache_dir, &version)
+                .with_context(|| format!("failed to lock cache entry for {version}"))?;
+            Ok(CacheVerification {
+                problems: entry_problems(&entry.path),
+                version: entry.version,
+            })
+        })
+        .collect()
+}
+
+/// Collects the structural problems of a single cache entry.
+fn entry_problems(version_dir: &Utf8Path) -> Vec<CacheEntryProblem> {
+    let mut problems = Vec::new();
+    if !version_dir.join(COMPLETION_MARKER).is_file() {
+        problems.push(CacheEntryProblem::MissingMarker);
+    }
+    let bin_dir = version_dir.join("bin");
+    if bin_dir.is_dir() {
+        problems.extend(
+            REQUIRED_BINARIES
+                .iter()
+                .filter(|name| !bin_dir.join(name).is_file())
+                .map(|name| CacheEntryProblem::MissingBinary((*name).to_owned())),
+        );
+    } else {
+        problems.push(CacheEntryProblem::MissingBinDir);
+    }
+    problems.extend(manifest_problems(version_dir, IntegrityCheck::Digests));
+    problems
+}
+
+/// Removes all but the newest `keep` complete versions from the cache.
+///
+/// Incomplete entries are always removed. Each removal holds an exclusive
+/// [`CacheLock`], so it waits for readers and writers of that version to
+/// finish. Returns the removed versions.
+///
+/// # Errors
+///
+/// Returns an error if the cache directory cannot be read, a lock cannot be
+/// acquired, or an entry cannot be removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::prune_cache;
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let removed = prune_cache(cache_dir, 2)?;
+/// println!("removed {} versions", removed.len());
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn prune_cache(cache_dir: &Utf8Path, keep: usize) -> BootstrapResult<Vec<Version>> {
+    let mut entries = list_cached_versions(cache_dir)?;
+    // Newest first, so the retained versions form a prefix of the complete entries.
+    entries.reverse();
+    let mut retained = 0_usize;
+    let mut removed = Vec::new();
+    for entry in entries {
+        if entry.complete && retained < keep {
+            retained += 1;
+            continue;
+        }
+        if remove_entry(cache_dir, &entry)? {
+            removed.push(entry.version);
+        }
+    }
+    Ok(removed)
+}
+
+/// Deletes a cache entry while holding its exclusive lock.
+///
+/// Returns `false` without deleting anything when the entry vanished, or
+/// when an entry that was incomplete at listing time has since been finished
+/// by a concurrent writer.
+fn remove_entry(cache_dir: &Utf8Path, entry: &CachedVersion) -> BootstrapResult<bool> {
+    let version = entry.version.to_string();
+    let _lock = CacheLock::acquire_exclusive(cache_dir, &version)
+        .with_context(|| format!("failed to lock cache entry for {version}"))?;
+    let finished_meanwhile = !entry.complete && entry.path.join(COMPLETION_MARKER).is_file();
+    if finished_meanwhile || !entry.path.is_dir() {
+        return Ok(false);
+    }
+    fs::remove_dir_all(&entry.path)
+        .with_context(|| format!("failed to remove cache entry: {}", entry.path))?;
+    info!(
+        target: LOG_TARGET,
+        version = %version,
+        path = %entry.path,
+        "pruned cache entry"
+    );
+    Ok(true)
+}
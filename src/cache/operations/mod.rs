@@ -1,14 +1,28 @@
 //! Cache lookup, population, and validation operations.
 //!
 //! Provides functions for checking cache status, copying binaries from cache,
-//! and populating the cache after downloads.
+//! populating the cache after downloads, verifying entries against their
+//! manifests, inspecting or pruning entries, and evicting unused versions.
 
 mod copy;
+mod eviction;
+mod integrity;
 mod lookup;
+mod maintenance;
 mod populate;
 
-pub use copy::copy_from_cache;
-pub use lookup::{CacheLookupResult, check_cache, find_matching_cached_version, try_use_cache};
+pub(crate) use copy::copy_dir_recursive;
+pub use copy::{copy_from_cache, copy_from_cache_with};
+pub use eviction::{evict_cache, record_access};
+pub use integrity::quarantine_if_corrupt;
+pub(crate) use integrity::sha256_file;
+pub use lookup::{
+    CacheLookupResult, check_cache, check_cache_with, find_matching_cached_version, try_use_cache,
+};
+pub use maintenance::{
+    CacheEntryProblem, CacheVerification, CachedVersion, list_cached_versions, prune_cache,
+    verify_cache,
+};
 pub use populate::{populate_cache, try_populate_cache};
 
 #[cfg(test)]
@@ -8,6 +8,7 @@ use std::fs;
 use tracing::{debug, warn};
 
 use super::copy::copy_dir_recursive;
+use super::integrity::write_manifest;
 use crate::error::BootstrapResult;
 
 /// Marker file name indicating a complete cache entry.
@@ -19,7 +20,8 @@ const LOG_TARGET: &str = "pg_embed::cache";
 /// Populates the cache with binaries from the given source directory.
 ///
 /// After a successful download, call this function to copy binaries to the
-/// cache and write the completion marker.
+/// cache, record their sizes and SHA-256 digests in a manifest, and write the
+/// completion marker.
 ///
 /// # Arguments
 ///
@@ -32,7 +34,7 @@ const LOG_TARGET: &str = "pg_embed::cache";
 /// Returns an error if:
 /// - The cache directory cannot be created
 /// - Copying binaries fails
-/// - Writing the completion marker fails
+/// - Writing the manifest or completion marker fails
 ///
 /// # Examples
 ///
@@ -60,6 +62,7 @@ pub fn populate_cache(
     copy_dir_recursive(source.as_std_path(), version_dir.as_std_path())
         .with_context(|| format!("failed to copy binaries to cache: {version_dir}"))?;
 
+    write_manifest(&version_dir)?;
     write_completion_marker(&version_dir)?;
 
     log_populate_complete(version, &version_dir);
@@ -2,6 +2,7 @@
 
 use super::lookup::COMPLETION_MARKER;
 use super::*;
+use crate::cache::{CopyStrategy, IntegrityCheck};
 use camino::Utf8Path;
 use postgresql_embedded::VersionReq;
 use rstest::{fixture, rstest};
@@ -116,6 +117,47 @@ fn copy_from_cache_copies_files() {
     assert!(target.join("bin/pg_ctl").exists());
 }
 
+#[rstest]
+#[case::copy(CopyStrategy::Copy)]
+#[case::reflink(CopyStrategy::Reflink)]
+#[case::hardlink(CopyStrategy::Hardlink)]
+fn copy_from_cache_with_places_files_and_replaces_stale_ones(#[case] strategy: CopyStrategy) {
+    let source_temp = tempdir().expect("source tempdir");
+    let target_temp = tempdir().expect("target tempdir");
+    let source = Utf8Path::from_path(source_temp.path()).expect("utf8 source");
+    let target = Utf8Path::from_path(target_temp.path()).expect("utf8 target");
+    create_mock_binaries(source);
+
+    copy_from_cache_with(source, target, strategy).expect("first placement");
+    copy_from_cache_with(source, target, strategy).expect("repeat placement");
+
+    let placed = fs::read_to_string(target.join("bin/postgres")).expect("read placed binary");
+    assert_eq!(placed, "mock postgres binary");
+}
+
+#[cfg(unix)]
+#[test]
+fn copy_from_cache_with_hardlink_leaves_shared_files_read_only() {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let source_temp = tempdir().expect("source tempdir");
+    let target_temp = tempdir().expect("target tempdir");
+    let source = Utf8Path::from_path(source_temp.path()).expect("utf8 source");
+    let target = Utf8Path::from_path(target_temp.path()).expect("utf8 target");
+    create_mock_binaries(source);
+
+    copy_from_cache_with(source, target, CopyStrategy::Hardlink).expect("hardlink placement");
+
+    let cached = fs::metadata(source.join("bin/postgres")).expect("cached metadata");
+    let placed = fs::metadata(target.join("bin/postgres")).expect("placed metadata");
+    if nix::unistd::geteuid().is_root() {
+        assert_ne!(cached.ino(), placed.ino(), "root must not share inodes");
+    } else {
+        assert_eq!(cached.ino(), placed.ino());
+        assert_eq!(cached.permissions().mode() & 0o222, 0);
+    }
+}
+
 #[test]
 fn populate_cache_creates_version_directory() {
     let source_temp = tempdir().expect("source tempdir");
@@ -130,6 +172,92 @@ fn populate_cache_creates_version_directory() {
     let version_dir = cache_dir.join("17.4.0");
     assert!(version_dir.join(COMPLETION_MARKER).exists());
     assert!(version_dir.join("bin/postgres").exists());
+    assert!(version_dir.join(".manifest.json").is_file());
+}
+
+/// Populates a cache entry from mock binaries so it carries a manifest.
+fn create_manifested_cache_entry(cache_dir: &Utf8Path, version: &str) -> camino::Utf8PathBuf {
+    let source_temp = tempdir().expect("source tempdir");
+    let source = Utf8Path::from_path(source_temp.path()).expect("utf8 source");
+    create_mock_binaries(source);
+    populate_cache(source, cache_dir, version).expect("populate cache");
+    cache_dir.join(version)
+}
+
+#[rstest]
+#[case::marker_ignores_truncation(IntegrityCheck::Marker, "mock", false)]
+#[case::sizes_detect_truncation(IntegrityCheck::Sizes, "mock", true)]
+#[case::sizes_miss_same_length_tampering(IntegrityCheck::Sizes, "MOCK postgres binary", false)]
+#[case::digests_detect_tampering(IntegrityCheck::Digests, "MOCK postgres binary", true)]
+fn check_cache_with_verifies_manifest(
+    #[case] check: IntegrityCheck,
+    #[case] replacement: &str,
+    #[case] expect_miss: bool,
+) {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    let version_dir = create_manifested_cache_entry(cache_dir, "17.4.0");
+    fs::write(version_dir.join("bin/postgres"), replacement).expect("corrupt binary");
+
+    let result = check_cache_with(cache_dir, "17.4.0", check);
+
+    assert_eq!(matches!(result, CacheLookupResult::Miss), expect_miss);
+}
+
+#[test]
+fn check_cache_with_trusts_entries_without_manifest() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    create_complete_cache_entry(cache_dir, "17.4.0");
+
+    let result = check_cache_with(cache_dir, "17.4.0", IntegrityCheck::Digests);
+
+    assert!(matches!(result, CacheLookupResult::Hit { .. }));
+}
+
+#[test]
+fn quarantine_if_corrupt_moves_only_corrupt_entries() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    create_manifested_cache_entry(cache_dir, "16.8.0");
+    let corrupt_dir = create_manifested_cache_entry(cache_dir, "17.4.0");
+    fs::remove_file(corrupt_dir.join("bin/pg_ctl")).expect("remove binary");
+
+    let healthy = quarantine_if_corrupt(cache_dir, "16.8.0", IntegrityCheck::Sizes)
+        .expect("check healthy entry");
+    let moved = quarantine_if_corrupt(cache_dir, "17.4.0", IntegrityCheck::Sizes)
+        .expect("quarantine corrupt entry")
+        .expect("corrupt entry should be moved");
+
+    assert!(healthy.is_none());
+    assert!(!corrupt_dir.exists());
+    assert!(moved.join("bin/postgres").is_file());
+    assert!(moved.starts_with(cache_dir.join(".quarantine")));
+    assert_eq!(listed_versions(cache_dir), ["16.8.0"]);
+}
+
+#[test]
+fn quarantine_if_corrupt_keeps_only_the_newest_quarantined_entries() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    let mut moved = Vec::new();
+    for _ in 0..4 {
+        let corrupt_dir = create_manifested_cache_entry(cache_dir, "17.4.0");
+        fs::remove_file(corrupt_dir.join("bin/pg_ctl")).expect("remove binary");
+        moved.push(
+            quarantine_if_corrupt(cache_dir, "17.4.0", IntegrityCheck::Sizes)
+                .expect("quarantine corrupt entry")
+                .expect("corrupt entry should be moved"),
+        );
+    }
+
+    let remaining = fs::read_dir(cache_dir.join(".quarantine"))
+        .expect("read quarantine")
+        .count();
+    assert_eq!(remaining, 2, "only the newest two entries should remain");
+    for (index, path) in moved.iter().enumerate() {
+        assert_eq!(path.exists(), index >= 2, "unexpected state for {path}");
+    }
 }
 
 /// Asserts `try_use_cache` behaviour with configurable setup and expectations.
@@ -212,3 +340,194 @@ fn find_matching_cached_version_ignores_incomplete_entries(
 
     assert!(result.is_none());
 }
+
+/// Creates a complete cache entry containing every binary `verify_cache` requires.
+fn create_verified_cache_entry(cache_dir: &Utf8Path, version: &str) {
+    let version_dir = create_complete_cache_entry(cache_dir, version);
+    fs::write(version_dir.join("bin").join("initdb"), "mock initdb binary")
+        .expect("write mock binary");
+}
+
+fn listed_versions(cache_dir: &Utf8Path) -> Vec<String> {
+    list_cached_versions(cache_dir)
+        .expect("list cache")
+        .iter()
+        .map(|entry| entry.version.to_string())
+        .collect()
+}
+
+#[test]
+fn list_cached_versions_sorts_versions_and_skips_other_directories() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    create_complete_cache_entry(cache_dir, "17.4.0");
+    create_complete_cache_entry(cache_dir, "16.8.0");
+    fs::create_dir_all(cache_dir.join("17.5.0")).expect("create incomplete entry");
+    fs::create_dir_all(cache_dir.join(".locks")).expect("create locks dir");
+
+    let entries = list_cached_versions(cache_dir).expect("list cache");
+
+    assert_eq!(listed_versions(cache_dir), ["16.8.0", "17.4.0", "17.5.0"]);
+    let complete: Vec<bool> = entries.iter().map(|entry| entry.complete).collect();
+    assert_eq!(complete, [true, true, false]);
+    assert!(entries.first().is_some_and(|entry| entry.size_bytes > 0));
+}
+
+#[test]
+fn list_cached_versions_returns_empty_for_missing_directory() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    let missing = cache_dir.join("absent");
+    assert!(
+        list_cached_versions(&missing)
+            .expect("list cache")
+            .is_empty()
+    );
+}
+
+#[test]
+fn verify_cache_reports_manifest_mismatches() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    let version_dir = create_manifested_cache_entry(cache_dir, "17.4.0");
+    fs::write(version_dir.join("bin/postgres"), "MOCK postgres binary").expect("tamper");
+    fs::write(version_dir.join("bin/pg_ctl"), "short").expect("truncate");
+
+    let reports = verify_cache(cache_dir).expect("verify cache");
+    let problems = reports
+        .into_iter()
+        .flat_map(|report| report.problems)
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        problems,
+        [
+            CacheEntryProblem::MissingBinary("initdb".to_owned()),
+            CacheEntryProblem::SizeMismatch {
+                path: "bin/pg_ctl".to_owned(),
+                expected: 18,
+                actual: 5,
+            },
+            CacheEntryProblem::DigestMismatch("bin/postgres".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn verify_cache_reports_structural_problems() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    create_verified_cache_entry(cache_dir, "17.4.0");
+    create_complete_cache_entry(cache_dir, "16.8.0");
+    fs::create_dir_all(cache_dir.join("15.10.0")).expect("create incomplete entry");
+
+    let reports = verify_cache(cache_dir).expect("verify cache");
+    let problems: Vec<(String, Vec<CacheEntryProblem>)> = reports
+        .into_iter()
+        .map(|report| (report.version.to_string(), report.problems))
+        .collect();
+
+    assert_eq!(
+        problems,
+        [
+            (
+                "15.10.0".to_owned(),
+                vec![
+                    CacheEntryProblem::MissingMarker,
+                    CacheEntryProblem::MissingBinDir
+                ]
+            ),
+            (
+                "16.8.0".to_owned(),
+                vec![CacheEntryProblem::MissingBinary("initdb".to_owned())]
+            ),
+            ("17.4.0".to_owned(), Vec::new()),
+        ]
+    );
+}
+
+#[rstest]
+#[case::keep_one(1, &["17.4.0"])]
+#[case::keep_two(2, &["16.8.0", "17.4.0"])]
+#[case::keep_none(0, &[])]
+fn prune_cache_keeps_newest_complete_versions(#[case] keep: usize, #[case] expected: &[&str]) {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    for version in ["15.10.0", "16.8.0", "17.4.0"] {
+        create_complete_cache_entry(cache_dir, version);
+    }
+    fs::create_dir_all(cache_dir.join("18.0.0")).expect("create incomplete entry");
+
+    prune_cache(cache_dir, keep).expect("prune cache");
+
+    assert_eq!(listed_versions(cache_dir), expected);
+}
+
+/// Backdates an entry's last access by the given number of hours.
+fn set_last_access(cache_dir: &Utf8Path, version: &str, hours_ago: u64) {
+    record_access(cache_dir, version).expect("record access");
+    let accessed = std::time::SystemTime::now() - std::time::Duration::from_secs(hours_ago * 3600);
+    fs::File::options()
+        .write(true)
+        .open(cache_dir.join(version).join(".last-access"))
+        .expect("open access file")
+        .set_modified(accessed)
+        .expect("backdate access file");
+}
+
+/// Seeds three complete entries; `16.8.0` is the least recently used.
+fn seed_accessed_entries(cache_dir: &Utf8Path) {
+    for (version, hours_ago) in [("16.8.0", 48), ("17.4.0", 24), ("15.10.0", 1)] {
+        create_complete_cache_entry(cache_dir, version);
+        set_last_access(cache_dir, version, hours_ago);
+    }
+}
+
+#[rstest]
+#[case::max_entries(
+    crate::cache::RetentionPolicy { max_entries: Some(1), ..Default::default() },
+    &["15.10.0"]
+)]
+#[case::max_age(
+    crate::cache::RetentionPolicy {
+        max_age: Some(std::time::Duration::from_secs(36 * 3600)),
+        ..Default::default()
+    },
+    &["15.10.0", "17.4.0"]
+)]
+#[case::max_bytes(
+    crate::cache::RetentionPolicy { max_bytes: Some(80), ..Default::default() },
+    &["15.10.0", "17.4.0"]
+)]
+#[case::unbounded(crate::cache::RetentionPolicy::default(), &["15.10.0", "16.8.0", "17.4.0"])]
+fn evict_cache_removes_least_recently_used_first(
+    #[case] policy: crate::cache::RetentionPolicy,
+    #[case] expected: &[&str],
+) {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    seed_accessed_entries(cache_dir);
+
+    evict_cache(cache_dir, &policy).expect("evict cache");
+
+    assert_eq!(listed_versions(cache_dir), expected);
+}
+
+#[test]
+fn evict_cache_skips_versions_locked_by_readers() {
+    let temp = tempdir().expect("tempdir");
+    let cache_dir = Utf8Path::from_path(temp.path()).expect("utf8 path");
+    seed_accessed_entries(cache_dir);
+    let policy = crate::cache::RetentionPolicy {
+        max_entries: Some(2),
+        ..Default::default()
+    };
+
+    let reader = crate::cache::CacheLock::acquire_shared(cache_dir, "16.8.0").expect("lock");
+    let evicted = evict_cache(cache_dir, &policy).expect("evict cache");
+    drop(reader);
+
+    let evicted_names: Vec<String> = evicted.iter().map(ToString::to_string).collect();
+    assert_eq!(evicted_names, ["17.4.0"]);
+    assert_eq!(listed_versions(cache_dir), ["15.10.0", "16.8.0"]);
+}
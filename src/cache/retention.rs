@@ -0,0 +1,158 @@
+//! Retention policy for the shared binary cache.
+//!
+//! Limits are read from the environment so that CI runners can bound the disk
+//! used by the cache without code changes.
+
+use std::time::Duration;
+
+/// Limits applied by [`evict_cache`](super::evict_cache).
+///
+/// Every limit is optional; the default policy keeps everything. Entries are
+/// evicted least recently used first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Maximum number of complete versions to keep.
+    pub max_entries: Option<usize>,
+    /// Maximum total size of complete versions, in bytes.
+    pub max_bytes: Option<u64>,
+    /// Maximum time since an entry was last used.
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Returns `true` when no limit is configured.
+    #[must_use]
+    pub const fn is_unbounded(&self) -> bool {
+        self.max_entries.is_none() && self.max_bytes.is_none() && self.max_age.is_none()
+    }
+}
+
+/// Resolves the retention policy from the environment.
+///
+/// - `PG_BINARY_CACHE_MAX_ENTRIES`: number of versions, such as `3`.
+/// - `PG_BINARY_CACHE_MAX_BYTES`: bytes, optionally suffixed with `K`, `M`,
+///   or `G` (binary multiples), such as `2G`.
+/// - `PG_BINARY_CACHE_MAX_AGE`: seconds, optionally suffixed with `s`, `m`,
+///   `h`, or `d`, such as `30d`.
+///
+/// Unset, empty, or unparseable values leave the corresponding limit unset.
+///
+/// # Examples
+///
+/// ```
+/// use pg_embedded_setup_unpriv::cache::resolve_retention_policy;
+///
+/// let policy = resolve_retention_policy();
+/// if policy.is_unbounded() {
+///     println!("the binary cache is never evicted");
+/// }
+/// ```
+#[must_use]
+pub fn resolve_retention_policy() -> RetentionPolicy {
+    RetentionPolicy {
+        max_entries: env_value("PG_BINARY_CACHE_MAX_ENTRIES").and_then(|raw| raw.parse().ok()),
+        max_bytes: env_value("PG_BINARY_CACHE_MAX_BYTES").and_then(|raw| parse_bytes(&raw)),
+        max_age: env_value("PG_BINARY_CACHE_MAX_AGE").and_then(|raw| parse_age(&raw)),
+    }
+}
+
+/// Reads a trimmed, non-empty environment variable.
+fn env_value(key: &str) -> Option<String> {
+    let raw = std::env::var(key).ok()?;
+    let trimmed = raw.trim();
+    (!trimmed.is_empty()).then(|| trimmed.to_owned())
+}
+
+/// Splits a trailing ASCII letter unit from a number.
+fn split_unit(raw: &str) -> (&str, Option<char>) {
+    let mut chars = raw.chars();
+    match chars.next_back() {
+        Some(unit) if unit.is_ascii_alphabetic() => {
+            (chars.as_str(), Some(unit.to_ascii_lowercase()))
+        }
+        _ => (raw, None),
+    }
+}
+
+/// Parses a byte count such as `512`, `64M`, or `2G`.
+fn parse_bytes(raw: &str) -> Option<u64> {
+    let (number, unit) = split_unit(raw);
+    let multiplier: u64 = match unit {
+        None => 1,
+        Some('k') => 1 << 10,
+        Some('m') => 1 << 20,
+        Some('g') => 1 << 30,
+        Some(_) => return None,
+    };
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses an age such as `3600`, `90m`, or `30d`.
+fn parse_age(raw: &str) -> Option<Duration> {
+    let (number, unit) = split_unit(raw);
+    let multiplier: u64 = match unit {
+        None | Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some(_) => return None,
+    };
+    let seconds = number.trim().parse::<u64>().ok()?.checked_mul(multiplier)?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scoped_env;
+    use rstest::rstest;
+    use std::ffi::OsString;
+
+    #[rstest]
+    #[case::plain("512", Some(512))]
+    #[case::kibibytes("4k", Some(4096))]
+    #[case::gibibytes("2G", Some(2 << 30))]
+    #[case::unknown_unit("2T", None)]
+    #[case::not_a_number("lots", None)]
+    fn parse_bytes_handles_units(#[case] raw: &str, #[case] expected: Option<u64>) {
+        assert_eq!(parse_bytes(raw), expected);
+    }
+
+    #[rstest]
+    #[case::plain_seconds("90", Some(90))]
+    #[case::minutes("90m", Some(5400))]
+    #[case::days("30d", Some(2_592_000))]
+    #[case::unknown_unit("3w", None)]
+    fn parse_age_handles_units(#[case] raw: &str, #[case] expected: Option<u64>) {
+        assert_eq!(parse_age(raw), expected.map(Duration::from_secs));
+    }
+
+    #[test]
+    fn resolve_retention_policy_reads_env() {
+        let _guard = scoped_env([
+            (
+                OsString::from("PG_BINARY_CACHE_MAX_ENTRIES"),
+                Some(OsString::from("3")),
+            ),
+            (
+                OsString::from("PG_BINARY_CACHE_MAX_BYTES"),
+                Some(OsString::from(" ")),
+            ),
+            (
+                OsString::from("PG_BINARY_CACHE_MAX_AGE"),
+                Some(OsString::from("1h")),
+            ),
+        ]);
+
+        let policy = resolve_retention_policy();
+
+        assert_eq!(
+            policy,
+            RetentionPolicy {
+                max_entries: Some(3),
+                max_bytes: None,
+                max_age: Some(Duration::from_secs(3600)),
+            }
+        );
+    }
+}
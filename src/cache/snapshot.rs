@@ -0,0 +1,358 @@
+//! Cache tier for initialised data directories.
+//!
+//! Running `initdb` dominates cluster startup once binaries come from the
+//! binary cache. This tier stores a pristine data directory captured straight
+//! after `initdb`, keyed by everything that shapes its contents, so later
+//! clusters can copy it instead of initialising from scratch.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{Context, eyre};
+use sha2::{Digest, Sha256};
+use std::fs;
+use tracing::{debug, info};
+
+use super::lock::CacheLock;
+use super::operations::copy_dir_recursive;
+use crate::error::BootstrapResult;
+
+/// Observability target for cache operations.
+const LOG_TARGET: &str = "pg_embed::cache";
+
+/// Subdirectory of the binary cache holding data directory snapshots.
+const SNAPSHOT_SUBDIR: &str = ".snapshots";
+
+/// Marker file indicating a complete snapshot.
+const SNAPSHOT_MARKER: &str = ".snapshot-complete";
+
+/// Files that belong to a running server and are never captured.
+const EXCLUDED_FILES: [&str; 3] = ["postmaster.pid", "postmaster.opts", "start.log"];
+
+/// Number of digest characters appended to a snapshot's directory name.
+const KEY_DIGEST_LEN: usize = 16;
+
+/// Identifies the `initdb` inputs a data directory snapshot was built from.
+///
+/// Snapshots are only reused for clusters whose key matches exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataSnapshotKey {
+    /// Exact `PostgreSQL` version, such as `17.4.0`.
+    pub version: String,
+    /// Locale passed to `initdb`, if any.
+    pub locale: Option<String>,
+    /// Encoding passed to `initdb`, if any.
+    pub encoding: Option<String>,
+    /// Name of the superuser the cluster is configured for.
+    pub superuser: String,
+}
+
+impl DataSnapshotKey {
+    /// Returns the snapshot's directory name: the version plus a digest of
+    /// the remaining fields.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pg_embedded_setup_unpriv::cache::DataSnapshotKey;
+    ///
+    /// let key = DataSnapshotKey {
+    ///     version: "17.4.0".into(),
+    ///     locale: None,
+    ///     encoding: Some("UTF8".into()),
+    ///     superuser: "postgres".into(),
+    /// };
+    /// assert!(key.entry_name().starts_with("17.4.0-"));
+    /// ```
+    #[must_use]
+    pub fn entry_name(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            self.locale.as_deref().unwrap_or_default(),
+            self.encoding.as_deref().unwrap_or_default(),
+            self.superuser.as_str(),
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+        let digest = format!("{:x}", hasher.finalize());
+        let short = digest.get(..KEY_DIGEST_LEN).unwrap_or(&digest);
+        format!("{}-{short}", self.version)
+    }
+}
+
+/// Returns the directory holding data directory snapshots for a binary cache.
+///
+/// Snapshots live in a hidden subdirectory, so binary cache listings and
+/// lookups ignore them.
+#[must_use]
+pub fn snapshot_dir(cache_dir: &Utf8Path) -> Utf8PathBuf {
+    cache_dir.join(SNAPSHOT_SUBDIR)
+}
+
+/// Captures an initialised data directory as a snapshot.
+///
+/// Holds the snapshot's exclusive [`CacheLock`] while copying into a staging
+/// directory, then renames it into place, so readers never see a partial
+/// snapshot. Files that belong to a running server are skipped. Returns
+/// `false` when a snapshot for the key already exists.
+///
+/// # Errors
+///
+/// Returns an error if the lock cannot be acquired or the data directory
+/// cannot be copied.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{DataSnapshotKey, snapshot_dir, store_data_snapshot};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let key = DataSnapshotKey {
+///     version: "17.4.0".into(),
+///     locale: None,
+///     encoding: None,
+///     superuser: "postgres".into(),
+/// };
+/// store_data_snapshot(&snapshot_dir(cache_dir), &key, Utf8Path::new("/tmp/pg/data"))?;
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn store_data_snapshot(
+    snapshots: &Utf8Path,
+    key: &DataSnapshotKey,
+    data_dir: &Utf8Path,
+) -> BootstrapResult<bool> {
+    let name = key.entry_name();
+    fs::create_dir_all(snapshots)
+        .with_context(|| format!("failed to create snapshot directory: {snapshots}"))?;
+    let _lock = CacheLock::acquire_exclusive(snapshots, &name)
+        .with_context(|| format!("failed to lock data snapshot {name}"))?;
+    let entry = snapshots.join(&name);
+    if entry.join(SNAPSHOT_MARKER).is_file() {
+        return Ok(false);
+    }
+
+    let staging = tempfile::tempdir_in(snapshots)
+        .with_context(|| format!("failed to create staging directory in {snapshots}"))?;
+    copy_dir_recursive(data_dir.as_std_path(), staging.path())
+        .with_context(|| format!("failed to copy data directory {data_dir} into snapshot"))?;
+    for excluded in EXCLUDED_FILES {
+        let path = staging.path().join(excluded);
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to drop {excluded} from snapshot"))?;
+        }
+    }
+    fs::write(staging.path().join(SNAPSHOT_MARKER), "")
+        .context("failed to write snapshot marker")?;
+
+    if entry.exists() {
+        fs::remove_dir_all(&entry)
+            .with_context(|| format!("failed to remove incomplete snapshot {entry}"))?;
+    }
+    fs::rename(staging.keep(), &entry)
+        .with_context(|| format!("failed to move snapshot into place: {entry}"))?;
+    log_snapshot_stored(&name, data_dir);
+    Ok(true)
+}
+
+/// Copies a stored snapshot into an empty data directory.
+///
+/// Holds the snapshot's shared [`CacheLock`] while copying. The data
+/// directory is created if needed and its mode is clamped to `0700`, as
+/// `PostgreSQL` requires. Returns `false` when no snapshot exists for the key.
+///
+/// The restored cluster keeps the superuser password it was initialised
+/// with; callers must rewrite it before handing the cluster out.
+///
+/// # Errors
+///
+/// Returns an error if the data directory already holds files, the lock
+/// cannot be acquired, or copying fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::{DataSnapshotKey, restore_data_snapshot, snapshot_dir};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let key = DataSnapshotKey {
+///     version: "17.4.0".into(),
+///     locale: None,
+///     encoding: None,
+///     superuser: "postgres".into(),
+/// };
+/// if restore_data_snapshot(&snapshot_dir(cache_dir), &key, Utf8Path::new("/tmp/pg/data"))? {
+///     println!("skipped initdb");
+/// }
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn restore_data_snapshot(
+    snapshots: &Utf8Path,
+    key: &DataSnapshotKey,
+    data_dir: &Utf8Path,
+) -> BootstrapResult<bool> {
+    let name = key.entry_name();
+    let entry = snapshots.join(&name);
+    if !entry.join(SNAPSHOT_MARKER).is_file() {
+        log_snapshot_miss(&name);
+        return Ok(false);
+    }
+    ensure_empty_dir(data_dir)?;
+
+    let _lock = CacheLock::acquire_shared(snapshots, &name)
+        .with_context(|| format!("failed to lock data snapshot {name}"))?;
+    if !entry.join(SNAPSHOT_MARKER).is_file() {
+        log_snapshot_miss(&name);
+        return Ok(false);
+    }
+    copy_dir_recursive(entry.as_std_path(), data_dir.as_std_path())
+        .with_context(|| format!("failed to restore data snapshot into {data_dir}"))?;
+    fs::remove_file(data_dir.join(SNAPSHOT_MARKER))
+        .with_context(|| format!("failed to remove snapshot marker from {data_dir}"))?;
+    restrict_data_dir(data_dir)?;
+    log_snapshot_restored(&name, data_dir);
+    Ok(true)
+}
+
+/// Fails unless `dir` is absent or empty, so a restore never mixes clusters.
+fn ensure_empty_dir(dir: &Utf8Path) -> BootstrapResult<()> {
+    let Ok(mut entries) = dir.read_dir_utf8() else {
+        return Ok(());
+    };
+    if entries.next().is_some() {
+        return Err(
+            eyre!("data directory {dir} is not empty; refusing to restore snapshot").into(),
+        );
+    }
+    Ok(())
+}
+
+/// Clamps a restored data directory to owner-only access.
+#[cfg(unix)]
+fn restrict_data_dir(dir: &Utf8Path) -> BootstrapResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("failed to restrict permissions on {dir}"))?;
+    Ok(())
+}
+
+/// Permissions are left as copied on non-Unix platforms.
+#[cfg(not(unix))]
+fn restrict_data_dir(_dir: &Utf8Path) -> BootstrapResult<()> {
+    Ok(())
+}
+
+/// Logs a missing snapshot.
+fn log_snapshot_miss(name: &str) {
+    debug!(
+        target: LOG_TARGET,
+        snapshot = %name,
+        "no data directory snapshot"
+    );
+}
+
+/// Logs a stored snapshot.
+fn log_snapshot_stored(name: &str, data_dir: &Utf8Path) {
+    info!(
+        target: LOG_TARGET,
+        snapshot = %name,
+        source = %data_dir,
+        "stored data directory snapshot"
+    );
+}
+
+/// Logs a restored snapshot.
+fn log_snapshot_restored(name: &str, data_dir: &Utf8Path) {
+    debug!(
+        target: LOG_TARGET,
+        snapshot = %name,
+        target = %data_dir,
+        "restored data directory snapshot"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use tempfile::TempDir;
+
+    fn key(locale: Option<&str>) -> DataSnapshotKey {
+        DataSnapshotKey {
+            version: "17.4.0".into(),
+            locale: locale.map(String::from),
+            encoding: Some("UTF8".into()),
+            superuser: "postgres".into(),
+        }
+    }
+
+    fn utf8(temp: &TempDir) -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).expect("utf8 temp dir")
+    }
+
+    fn initialised_data_dir(root: &Utf8Path) -> Utf8PathBuf {
+        let data_dir = root.join("data");
+        fs::create_dir_all(data_dir.join("base/1")).expect("create base");
+        fs::write(data_dir.join("PG_VERSION"), "17\n").expect("write PG_VERSION");
+        fs::write(data_dir.join("postgresql.conf"), "").expect("write conf");
+        fs::write(data_dir.join("base/1/1259"), "heap").expect("write relation");
+        fs::write(data_dir.join("postmaster.pid"), "42").expect("write pid");
+        data_dir
+    }
+
+    #[rstest]
+    #[case::locale(key(Some("C")), key(Some("en_US.UTF-8")))]
+    #[case::superuser(key(None), DataSnapshotKey { superuser: "admin".into(), ..key(None) })]
+    fn entry_name_distinguishes_initdb_inputs(
+        #[case] first: DataSnapshotKey,
+        #[case] second: DataSnapshotKey,
+    ) {
+        assert_ne!(first.entry_name(), second.entry_name());
+        assert_eq!(first.entry_name(), first.clone().entry_name());
+    }
+
+    #[test]
+    fn snapshot_round_trip_skips_server_files() {
+        let temp = TempDir::new().expect("tempdir");
+        let root = utf8(&temp);
+        let source = initialised_data_dir(&root);
+        let snapshots = snapshot_dir(&root.join("cache"));
+        let target = root.join("restored");
+
+        assert!(store_data_snapshot(&snapshots, &key(None), &source).expect("store"));
+        assert!(!store_data_snapshot(&snapshots, &key(None), &source).expect("store again"));
+        assert!(restore_data_snapshot(&snapshots, &key(None), &target).expect("restore"));
+
+        assert_eq!(
+            fs::read_to_string(target.join("base/1/1259")).expect("read relation"),
+            "heap"
+        );
+        assert!(target.join("PG_VERSION").is_file());
+        assert!(!target.join("postmaster.pid").exists());
+        assert!(!target.join(SNAPSHOT_MARKER).exists());
+    }
+
+    #[test]
+    fn restore_reports_missing_snapshot() {
+        let temp = TempDir::new().expect("tempdir");
+        let root = utf8(&temp);
+        let restored = restore_data_snapshot(&snapshot_dir(&root), &key(None), &root.join("data"))
+            .expect("restore");
+        assert!(!restored);
+    }
+
+    #[test]
+    fn restore_refuses_non_empty_data_dir() {
+        let temp = TempDir::new().expect("tempdir");
+        let root = utf8(&temp);
+        let source = initialised_data_dir(&root);
+        let snapshots = snapshot_dir(&root.join("cache"));
+        store_data_snapshot(&snapshots, &key(None), &source).expect("store");
+
+        let result = restore_data_snapshot(&snapshots, &key(None), &source);
+
+        assert!(result.is_err());
+    }
+}
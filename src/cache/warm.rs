@@ -0,0 +1,122 @@
+//! Ahead-of-time population of the binary cache.
+//!
+//! Lets CI images fill the shared cache during a build step so later test
+//! runs start from a cache hit.
+
+use camino::Utf8Path;
+use color_eyre::eyre::{Context, eyre};
+use postgresql_embedded::{Version, VersionReq};
+use tracing::info;
+
+use super::archive::{import_from_mirror, mirror_dir_from_url};
+use super::lock::CacheLock;
+use super::operations::{
+    CacheLookupResult, check_cache, find_matching_cached_version, populate_cache,
+};
+use crate::cluster::runtime::run_with_runtime;
+use crate::error::BootstrapResult;
+
+/// Observability target for cache operations.
+const LOG_TARGET: &str = "pg_embed::cache";
+
+/// Ensures the cache holds a version satisfying `version_req`.
+///
+/// Returns the cached version straight away on a hit. Otherwise a `file://`
+/// `releases_url` is treated as a local mirror (see
+/// [`import_from_mirror`](super::import_from_mirror), which honours
+/// `allow_unverified`); any other URL is
+/// resolved and downloaded through `postgresql_embedded`'s release
+/// repository, then written to the cache under an exclusive [`CacheLock`].
+///
+/// # Errors
+///
+/// Returns an error if the version cannot be resolved, the download or
+/// extraction fails, or the cache cannot be populated.
+///
+/// # Examples
+///
+/// ```no_run
+/// use camino::Utf8Path;
+/// use pg_embedded_setup_unpriv::cache::warm_cache;
+/// use postgresql_embedded::{Settings, VersionReq};
+///
+/// let cache_dir = Utf8Path::new("/home/user/.cache/pg-embedded/binaries");
+/// let req = VersionReq::parse("^17").expect("valid version req");
+/// let version = warm_cache(cache_dir, &req, &Settings::default().releases_url, false)?;
+/// println!("cached {version}");
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+pub fn warm_cache(
+    cache_dir: &Utf8Path,
+    version_req: &VersionReq,
+    releases_url: &str,
+    allow_unverified: bool,
+) -> BootstrapResult<Version> {
+    if let Some(version) = cached_match(cache_dir, version_req) {
+        return Ok(version);
+    }
+    if let Some(mirror) = mirror_dir_from_url(releases_url) {
+        return import_from_mirror(&mirror, version_req, allow_unverified, cache_dir);
+    }
+    download_into_cache(cache_dir, version_req, releases_url)
+}
+
+/// Returns the newest cached version satisfying the requirement.
+fn cached_match(cache_dir: &Utf8Path, version_req: &VersionReq) -> Option<Version> {
+    let (version, _path) = find_matching_cached_version(cache_dir, version_req)?;
+    Version::parse(&version).ok()
+}
+
+/// Downloads and extracts a release, then records it in the cache.
+fn download_into_cache(
+    cache_dir: &Utf8Path,
+    version_req: &VersionReq,
+    releases_url: &str,
+) -> BootstrapResult<Version> {
+    std::fs::create_dir_all(cache_dir)
+        .with_context(|| format!("failed to create cache directory: {cache_dir}"))?;
+    run_with_runtime("warm_cache", |runtime| {
+        let version = runtime
+            .block_on(postgresql_archive::get_version(releases_url, version_req))
+            .context("failed to resolve PostgreSQL version")?;
+        let version_str = version.to_string();
+        let _lock = CacheLock::acquire_exclusive(cache_dir, &version_str)
+            .with_context(|| format!("failed to lock cache entry for {version_str}"))?;
+        if matches!(
+            check_cache(cache_dir, &version_str),
+            CacheLookupResult::Hit { .. }
+        ) {
+            return Ok(version);
+        }
+
+        let exact = VersionReq::parse(&format!("={version_str}"))
+            .context("failed to build exact version requirement")?;
+        let staging = tempfile::tempdir_in(cache_dir)
+            .with_context(|| format!("failed to create staging directory in {cache_dir}"))?;
+        let extract_dir = staging.path().join(&version_str);
+        runtime.block_on(async {
+            let (_resolved, bytes) = postgresql_archive::get_archive(releases_url, &exact)
+                .await
+                .context("failed to download PostgreSQL archive")?;
+            postgresql_archive::extract(releases_url, &bytes, &extract_dir)
+                .await
+                .context("failed to extract PostgreSQL archive")
+        })?;
+
+        let source = Utf8Path::from_path(&extract_dir)
+            .ok_or_else(|| eyre!("staging directory is not valid UTF-8"))?;
+        populate_cache(source, cache_dir, &version_str)?;
+        log_warm_complete(&version_str, cache_dir);
+        Ok(version)
+    })
+}
+
+/// Logs a completed download into the cache.
+fn log_warm_complete(version: &str, cache_dir: &Utf8Path) {
+    info!(
+        target: LOG_TARGET,
+        version = %version,
+        cache_dir = %cache_dir,
+        "downloaded binaries into cache"
+    );
+}
@@ -33,6 +33,19 @@ pub(crate) fn has_parent_dir(path: &Path) -> bool {
         .any(|component| matches!(component, Component::ParentDir))
 }
 
+/// Reports whether `install_dir` lies inside the cluster's sandbox, the
+/// directory holding its `.pgpass` file.
+///
+/// Full cleanup removes only such installations, so shared ones such as
+/// system binaries selected via `PG_SYSTEM_BINARIES` are never touched.
+pub(crate) fn is_sandboxed_installation(install_dir: &Path, password_file: &Path) -> bool {
+    password_file.parent().is_some_and(|sandbox| {
+        !is_empty_or_root(sandbox)
+            && !has_parent_dir(install_dir)
+            && install_dir.starts_with(sandbox)
+    })
+}
+
 /// Attempts to remove a directory tree, rejecting unsafe paths before deletion.
 ///
 /// # Examples
@@ -0,0 +1,189 @@
+//! Backend contract behind [`TestCluster`](super::TestCluster).
+//!
+//! A backend starts a cluster isolated from every other one (its own data
+//! directory and port) and returns a [`StartupOutcome`]. The outcome's
+//! bootstrap settings describe the endpoint, from which
+//! [`ClusterHandle`](super::ClusterHandle) renders connections, and its
+//! server resources move into the [`ClusterGuard`](super::ClusterGuard),
+//! which stops them on drop. The handle carries the backend's
+//! [`BackendKind`], so connections refuse operations a backend cannot serve.
+//! `PG_TEST_BACKEND` selects the backend; see
+//! `docs/adr-001-pglite-oxide-backend.md` for the design.
+
+#[cfg(not(feature = "pglite"))]
+use color_eyre::eyre::eyre;
+use tokio::runtime::Runtime;
+
+use super::external::{CreatedDatabases, ExternalAttachment, ExternalBackend};
+#[cfg(feature = "pglite")]
+use super::pglite::{PgliteAttachment, PgliteBackend};
+#[cfg(feature = "async-api")]
+use super::startup::start_postgres_async;
+use super::startup::{StartupOutcome, cache_config_from_bootstrap, start_postgres};
+use crate::TestBootstrapSettings;
+use crate::bootstrap::BackendKind;
+use crate::cache::BinaryCacheConfig;
+#[cfg(not(feature = "pglite"))]
+use crate::error::BootstrapError;
+use crate::error::BootstrapResult;
+
+/// What `TestCluster` needs from something that behaves like a `PostgreSQL`
+/// cluster.
+pub(super) trait ClusterBackend {
+    /// Identifies the backend in logs and errors.
+    fn kind(&self) -> BackendKind;
+
+    /// Starts an isolated cluster, blocking on the guard's `runtime`.
+    fn start(
+        &self,
+        runtime: &Runtime,
+        bootstrap: TestBootstrapSettings,
+        env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome>;
+
+    /// Starts an isolated cluster on the caller's runtime.
+    #[cfg(feature = "async-api")]
+    async fn start_async(
+        &self,
+        bootstrap: TestBootstrapSettings,
+        env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome>;
+}
+
+/// Selects the backend named by `PG_TEST_BACKEND` for `bootstrap`.
+///
+/// Call this before applying the cluster's environment: the embedded backend
+/// resolves its binary cache from the caller's `XDG_CACHE_HOME`, not the test
+/// sandbox's.
+pub(super) fn select_backend(
+    bootstrap: &TestBootstrapSettings,
+) -> BootstrapResult<SelectedBackend> {
+    match BackendKind::from_env()? {
+        BackendKind::External => Ok(SelectedBackend::External(ExternalBackend)),
+        BackendKind::PostgresqlEmbedded => Ok(SelectedBackend::Embedded(EmbeddedBackend {
+            cache_config: cache_config_from_bootstrap(bootstrap),
+        })),
+        #[cfg(feature = "pglite")]
+        BackendKind::PgliteOxide => Ok(SelectedBackend::Pglite(PgliteBackend)),
+        // `from_env` already rejects backends this build does not include.
+        #[cfg(not(feature = "pglite"))]
+        BackendKind::PgliteOxide => Err(BootstrapError::from(eyre!(
+            "PG_TEST_BACKEND=pglite_oxide requires the pglite feature"
+        ))),
+    }
+}
+
+/// Backend chosen for a cluster.
+pub(super) enum SelectedBackend {
+    Embedded(EmbeddedBackend),
+    External(ExternalBackend),
+    #[cfg(feature = "pglite")]
+    Pglite(PgliteBackend),
+}
+
+impl ClusterBackend for SelectedBackend {
+    fn kind(&self) -> BackendKind {
+        match self {
+            Self::Embedded(backend) => backend.kind(),
+            Self::External(backend) => backend.kind(),
+            #[cfg(feature = "pglite")]
+            Self::Pglite(backend) => backend.kind(),
+        }
+    }
+
+    fn start(
+        &self,
+        runtime: &Runtime,
+        bootstrap: TestBootstrapSettings,
+        env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        match self {
+            Self::Embedded(backend) => backend.start(runtime, bootstrap, env_vars),
+            Self::External(backend) => backend.start(runtime, bootstrap, env_vars),
+            #[cfg(feature = "pglite")]
+            Self::Pglite(backend) => backend.start(runtime, bootstrap, env_vars),
+        }
+    }
+
+    #[cfg(feature = "async-api")]
+    async fn start_async(
+        &self,
+        bootstrap: TestBootstrapSettings,
+        env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        match self {
+            Self::Embedded(backend) => backend.start_async(bootstrap, env_vars).await,
+            Self::External(backend) => backend.start_async(bootstrap, env_vars).await,
+            #[cfg(feature = "pglite")]
+            Self::Pglite(backend) => backend.start_async(bootstrap, env_vars).await,
+        }
+    }
+}
+
+/// Server resources a backend hands to the guard instead of a native
+/// `PostgreSQL` process, released when the guard drops them.
+#[derive(Debug)]
+pub(super) enum Attachment {
+    /// Databases created on an external server, swept on drop.
+    External(Box<ExternalAttachment>),
+    /// A running `pglite-oxide` proxy, stopped on drop.
+    #[cfg(feature = "pglite")]
+    Pglite(
+        #[expect(dead_code, reason = "held so the proxy stops when the guard drops")]
+        PgliteAttachment,
+    ),
+}
+
+impl Attachment {
+    /// Returns the databases tracked for an external server.
+    #[cfg_attr(
+        not(feature = "pglite"),
+        expect(
+            clippy::unnecessary_wraps,
+            reason = "the pglite variant, which tracks no databases, needs the pglite feature"
+        )
+    )]
+    pub(super) fn created_databases(&self) -> Option<CreatedDatabases> {
+        match self {
+            Self::External(attachment) => Some(attachment.created_databases()),
+            #[cfg(feature = "pglite")]
+            Self::Pglite(_) => None,
+        }
+    }
+}
+
+/// Native `PostgreSQL` provisioned by `postgresql_embedded`, optionally through
+/// the privilege-dropping worker.
+pub(super) struct EmbeddedBackend {
+    cache_config: BinaryCacheConfig,
+}
+
+impl ClusterBackend for EmbeddedBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::PostgresqlEmbedded
+    }
+
+    fn start(
+        &self,
+        runtime: &Runtime,
+        bootstrap: TestBootstrapSettings,
+        env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        start_postgres(runtime, bootstrap, env_vars, &self.cache_config)
+    }
+
+    #[cfg(feature = "async-api")]
+    async fn start_async(
+        &self,
+        bootstrap: TestBootstrapSettings,
+        env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        // Box::pin to avoid a large future on the stack.
+        Box::pin(start_postgres_async(
+            bootstrap,
+            env_vars,
+            &self.cache_config,
+        ))
+        .await
+    }
+}
@@ -0,0 +1,338 @@
+//! Programmatic configuration for [`TestCluster`].
+//!
+//! [`TestClusterBuilder`] lets a test describe its cluster in code rather than
+//! through `PG_*` environment variables, so tests sharing one binary can request
+//! different versions, credentials, or server settings without racing on the
+//! process environment. Environment variables still apply underneath: any value
+//! left unset on the builder falls back to the `PG_*` configuration.
+
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+
+use super::{ClusterGuard, ClusterHandle, TestCluster};
+use crate::bootstrap::{BootstrapOverrides, bootstrap_for_tests_with};
+use crate::error::BootstrapResult;
+use crate::{AuthMethod, CleanupMode, HbaRule, PgBinarySourceCfg, PgEnvCfg, TestBootstrapSettings};
+
+/// Builder that configures a [`TestCluster`] before it starts.
+///
+/// # Examples
+/// ```no_run
+/// use std::time::Duration;
+/// use pg_embedded_setup_unpriv::{CleanupMode, PgEnvCfg, TestCluster};
+///
+/// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+/// let cluster = TestCluster::builder()
+///     .config(PgEnvCfg {
+///         superuser: Some("app_owner".into()),
+///         password: Some("app_secret".into()),
+///         ..PgEnvCfg::default()
+///     })
+///     .version_req("=16.4.0")
+///     .cleanup_mode(CleanupMode::Full)
+///     .start_timeout(Duration::from_secs(120))
+///     .server_setting("log_statement", "all")
+///     .build()?;
+/// # drop(cluster);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct TestClusterBuilder {
+    overrides: BootstrapOverrides,
+}
+
+impl TestCluster {
+    /// Returns a builder for configuring a cluster programmatically.
+    ///
+    /// Values left unset on the builder fall back to the `PG_*` environment
+    /// variables honoured by [`bootstrap_for_tests`](crate::bootstrap_for_tests).
+    pub fn builder() -> TestClusterBuilder {
+        TestClusterBuilder::new()
+    }
+}
+
+impl TestClusterBuilder {
+    /// Creates a builder with no overrides applied.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the base configuration layered over the environment.
+    ///
+    /// Fields set to `Some` take precedence over the matching `PG_*` variable;
+    /// `None` fields defer to the environment. Later calls to the dedicated
+    /// setters such as [`version_req`](Self::version_req) override the values
+    /// supplied here.
+    pub fn config(mut self, config: PgEnvCfg) -> Self {
+        self.overrides.config = config;
+        self
+    }
+
+    /// Replaces the binary source configuration layered over the environment.
+    ///
+    /// Behaves like [`config`](Self::config) for the fields of
+    /// [`PgBinarySourceCfg`]; later calls to setters such as
+    /// [`binary_archive`](Self::binary_archive) override the values supplied
+    /// here.
+    pub fn binary_source(mut self, binary_source: PgBinarySourceCfg) -> Self {
+        self.overrides.binary_source = binary_source;
+        self
+    }
+
+    /// Sets the semver requirement constraining the `PostgreSQL` version.
+    pub fn version_req(mut self, version_req: impl Into<String>) -> Self {
+        self.overrides.config.version_req = Some(version_req.into());
+        self
+    }
+
+    /// Sets the port assigned to the `PostgreSQL` server.
+    pub const fn port(mut self, port: u16) -> Self {
+        self.overrides.config.port = Some(port);
+        self
+    }
+
+    /// Sets the directory used to share downloaded binaries across runs.
+    pub fn binary_cache_dir(mut self, dir: impl Into<Utf8PathBuf>) -> Self {
+        self.overrides.config.binary_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the base URL of the binary releases; `file://` URLs name a local
+    /// mirror directory.
+    pub fn releases_url(mut self, url: impl Into<String>) -> Self {
+        self.overrides.binary_source.releases_url = Some(url.into());
+        self
+    }
+
+    /// Sets a local release archive used to seed the binary cache.
+    pub fn binary_archive(mut self, archive: impl Into<Utf8PathBuf>) -> Self {
+        self.overrides.binary_source.binary_archive = Some(archive.into());
+        self
+    }
+
+    /// Sets the expected SHA-256 digest of the [`binary_archive`](Self::binary_archive).
+    pub fn binary_archive_sha256(mut self, digest: impl Into<String>) -> Self {
+        self.overrides.binary_source.binary_archive_sha256 = Some(digest.into());
+        self
+    }
+
+    /// Imports a [`binary_archive`](Self::binary_archive) that has no SHA-256
+    /// digest, with a warning, instead of rejecting it. When set,
+    /// `PG_BINARY_ARCHIVE_ALLOW_UNVERIFIED` is ignored for this cluster.
+    pub const fn allow_unverified_archive(mut self, allowed: bool) -> Self {
+        self.overrides.allow_unverified_archive = Some(allowed);
+        self
+    }
+
+    /// Uses system-installed binaries: `auto` or an installation's `bin`
+    /// directory.
+    pub fn system_binaries(mut self, source: impl Into<String>) -> Self {
+        self.overrides.binary_source.system_binaries = Some(source.into());
+        self
+    }
+
+    /// Accepts connections only on a Unix-domain socket instead of TCP.
+    ///
+    /// The socket lives in a private directory under the cluster's runtime
+    /// directory, so parallel clusters never collide on ports. When set,
+    /// `PG_UNIX_SOCKET_ONLY` is ignored for this cluster.
+    pub const fn unix_socket_only(mut self, enabled: bool) -> Self {
+        self.overrides.unix_socket_only = Some(enabled);
+        self
+    }
+
+    /// Serves TLS with a throwaway certificate authority and server
+    /// certificate generated into the data directory.
+    ///
+    /// Connection URLs then require `sslmode=verify-full` against the
+    /// generated authority. TLS needs a TCP listener, so it cannot be combined
+    /// with [`Self::unix_socket_only`]. When set, `PG_TLS` is ignored for this
+    /// cluster. Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub const fn tls(mut self, enabled: bool) -> Self {
+        self.overrides.tls = Some(enabled);
+        self
+    }
+
+    /// Keeps one privilege-dropped worker alive for the cluster's lifetime
+    /// instead of spawning `pg_worker` for every lifecycle operation.
+    ///
+    /// Only affects runs as `root`; the worker exits when the cluster drops.
+    /// When set, `PG_PERSISTENT_WORKER` is ignored for this cluster.
+    pub const fn persistent_worker(mut self, enabled: bool) -> Self {
+        self.overrides.persistent_worker = Some(enabled);
+        self
+    }
+
+    /// Rewrites the default `pg_hba.conf` rules with `method`, mirroring
+    /// `initdb --auth`.
+    ///
+    /// [`AuthMethod::Peer`] applies to the local rules only, with SCRAM on the
+    /// loopback rules. [`AuthMethod::Reject`] and [`AuthMethod::Cert`] fail
+    /// the build, since they would lock out the superuser.
+    ///
+    /// Ignored once any [`Self::hba_rule`] is added. When set,
+    /// `PG_AUTH_METHOD` is ignored for this cluster.
+    pub const fn auth_method(mut self, method: AuthMethod) -> Self {
+        self.overrides.auth_method = Some(method);
+        self
+    }
+
+    /// Appends a `pg_hba.conf` rule.
+    ///
+    /// Rules replace the file written by `initdb` and are matched in the order
+    /// they are added, so admit the superuser before narrower rules.
+    pub fn hba_rule(mut self, rule: HbaRule) -> Self {
+        self.overrides.hba_rules.push(rule);
+        self
+    }
+
+    /// Sets the cleanup behaviour applied when the cluster drops.
+    pub const fn cleanup_mode(mut self, cleanup_mode: CleanupMode) -> Self {
+        self.overrides.cleanup_mode = Some(cleanup_mode);
+        self
+    }
+
+    /// Sets the maximum time allowed for the setup phase.
+    pub const fn setup_timeout(mut self, timeout: Duration) -> Self {
+        self.overrides.setup_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time allowed for the start phase.
+    pub const fn start_timeout(mut self, timeout: Duration) -> Self {
+        self.overrides.start_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the grace period granted to `PostgreSQL` during drop.
+    ///
+    /// When set, `PG_SHUTDOWN_TIMEOUT_SECS` is ignored for this cluster.
+    pub const fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.overrides.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Forwards the server log into `tracing` while the cluster runs.
+    ///
+    /// Each line is emitted as an `INFO` event under the
+    /// `pg_embed::observability` target. When set, `PG_SERVER_LOG_FORWARD` is
+    /// ignored for this cluster.
+    pub const fn forward_server_log(mut self, forward: bool) -> Self {
+        self.overrides.forward_server_log = Some(forward);
+        self
+    }
+
+    /// Adds a `postgresql.conf` entry for the server.
+    ///
+    /// Entries replace the worker-limit defaults that
+    /// [`bootstrap_for_tests`](crate::bootstrap_for_tests) applies, so
+    /// `server_setting("max_connections", "100")` raises the connection limit.
+    pub fn server_setting(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides
+            .server_configuration
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Prepares the bootstrap settings without starting `PostgreSQL`.
+    ///
+    /// # Errors
+    /// Returns an error if the layered configuration is invalid or bootstrap
+    /// preparation fails.
+    pub fn bootstrap(&self) -> BootstrapResult<TestBootstrapSettings> {
+        bootstrap_for_tests_with(&self.overrides)
+    }
+
+    /// Boots the configured cluster.
+    ///
+    /// # Errors
+    /// Returns an error if the bootstrap configuration cannot be prepared or if
+    /// starting the embedded cluster fails.
+    pub fn build(&self) -> BootstrapResult<TestCluster> {
+        let (handle, guard) = self.build_split()?;
+        Ok(TestCluster { handle, guard })
+    }
+
+    /// Boots the configured cluster and returns a separate handle and guard.
+    ///
+    /// See [`TestCluster::new_split`] for guidance on using the split pair.
+    ///
+    /// # Errors
+    /// Returns an error if the bootstrap configuration cannot be prepared or if
+    /// starting the embedded cluster fails.
+    pub fn build_split(&self) -> BootstrapResult<(ClusterHandle, ClusterGuard)> {
+        TestCluster::new_split_with(&self.overrides)
+    }
+
+    /// Boots the configured cluster on the caller's async runtime.
+    ///
+    /// See [`TestCluster::start_async`] for shutdown expectations.
+    ///
+    /// # Errors
+    /// Returns an error if the bootstrap configuration cannot be prepared or if
+    /// starting the embedded cluster fails.
+    #[cfg(feature = "async-api")]
+    pub async fn start_async(&self) -> BootstrapResult<TestCluster> {
+        let (handle, guard) = self.start_async_split().await?;
+        Ok(TestCluster { handle, guard })
+    }
+
+    /// Boots the configured cluster asynchronously and returns a separate
+    /// handle and guard.
+    ///
+    /// # Errors
+    /// Returns an error if the bootstrap configuration cannot be prepared or if
+    /// starting the embedded cluster fails.
+    #[cfg(feature = "async-api")]
+    pub async fn start_async_split(&self) -> BootstrapResult<(ClusterHandle, ClusterGuard)> {
+        TestCluster::start_async_split_with(&self.overrides).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedicated_setters_override_base_config() {
+        let builder = TestCluster::builder()
+            .config(PgEnvCfg {
+                version_req: Some("=15.0.0".into()),
+                superuser: Some("owner".into()),
+                ..PgEnvCfg::default()
+            })
+            .version_req("=16.4.0")
+            .port(54_321);
+
+        let config = &builder.overrides.config;
+        assert_eq!(config.version_req.as_deref(), Some("=16.4.0"));
+        assert_eq!(config.port, Some(54_321));
+        assert_eq!(config.superuser.as_deref(), Some("owner"));
+    }
+
+    #[test]
+    fn config_replaces_previous_base() {
+        let builder = TestCluster::builder().port(1).config(PgEnvCfg::default());
+
+        assert!(builder.overrides.config.port.is_none());
+    }
+
+    #[test]
+    fn server_settings_accumulate() {
+        let builder = TestCluster::builder()
+            .server_setting("log_statement", "all")
+            .server_setting("work_mem", "8MB")
+            .server_setting("log_statement", "ddl");
+
+        let configuration = &builder.overrides.server_configuration;
+        assert_eq!(configuration.len(), 2);
+        assert_eq!(
+            configuration.get("log_statement").map(String::as_str),
+            Some("ddl")
+        );
+    }
+}
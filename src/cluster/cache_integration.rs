@@ -5,9 +5,12 @@
 
 use crate::TestBootstrapSettings;
 use crate::cache::{
-    BinaryCacheConfig, CacheLock, CacheLookupResult, check_cache, copy_from_cache,
-    find_matching_cached_version, populate_cache,
+    BinaryCacheConfig, CacheLock, CacheLookupResult, IntegrityCheck, check_cache, check_cache_with,
+    copy_from_cache_with, ensure_archive_satisfies, evict_cache, find_matching_cached_version,
+    import_archive, import_from_mirror, mirror_dir_from_url, populate_cache, quarantine_if_corrupt,
+    record_access,
 };
+use crate::error::{BootstrapError, BootstrapResult};
 use crate::observability::LOG_TARGET;
 use camino::Utf8PathBuf;
 use postgresql_embedded::{Settings, VersionReq};
@@ -39,6 +42,7 @@ fn set_exact_version(settings: &mut Settings, version: &str) {
     reason = "error handling branches for UTF-8 validation and copy operation"
 )]
 fn copy_cached_binaries(
+    config: &BinaryCacheConfig,
     source_dir: &Utf8PathBuf,
     version: &str,
     settings: &mut Settings,
@@ -53,7 +57,7 @@ fn copy_cached_binaries(
 
     let target_version_dir = target.join(version);
 
-    if let Err(err) = copy_from_cache(source_dir, &target_version_dir) {
+    if let Err(err) = copy_from_cache_with(source_dir, &target_version_dir, config.copy_strategy) {
         warn!(
             target: LOG_TARGET,
             version = %version,
@@ -74,17 +78,18 @@ fn copy_cached_binaries(
 /// Copies binaries from the cache source directory to the target installation directory,
 /// updates bootstrap settings to use the cached version, and logs success.
 fn apply_cached_binaries(
+    config: &BinaryCacheConfig,
     source_dir: &Utf8PathBuf,
     version: &str,
-    version_req: &VersionReq,
     bootstrap: &mut TestBootstrapSettings,
 ) -> bool {
     let Some(target_version_dir) =
-        copy_cached_binaries(source_dir, version, &mut bootstrap.settings)
+        copy_cached_binaries(config, source_dir, version, &mut bootstrap.settings)
     else {
         return false;
     };
 
+    let version_req = bootstrap.settings.version.clone();
     set_exact_version(&mut bootstrap.settings, version);
 
     info!(
@@ -125,6 +130,80 @@ fn log_cache_entry_invalid(version: &str) {
     );
 }
 
+/// Quarantines corrupt cache entries matching the version requirement.
+///
+/// Runs before offline provisioning and the cache lookup, so a truncated or
+/// tampered entry is moved aside and re-populated instead of being copied
+/// into the sandbox. Each quarantine falls back to the next-newest matching
+/// entry. Failures are logged and leave the cache untouched.
+pub(super) fn heal_binary_cache(config: &BinaryCacheConfig, version_req: &VersionReq) {
+    if config.integrity == IntegrityCheck::Marker {
+        return;
+    }
+    while let Some((version, _path)) = find_matching_cached_version(&config.cache_dir, version_req)
+    {
+        match quarantine_if_corrupt(&config.cache_dir, &version, config.integrity) {
+            Ok(Some(_)) => {}
+            Ok(None) => return,
+            Err(err) => {
+                log_heal_failed(&version, &err);
+                return;
+            }
+        }
+    }
+}
+
+/// Logs a failed attempt to quarantine a corrupt cache entry.
+fn log_heal_failed(version: &str, err: &BootstrapError) {
+    warn!(
+        target: LOG_TARGET,
+        version = %version,
+        error = %err,
+        "failed to quarantine cache entry"
+    );
+}
+
+/// Seeds the binary cache from a local archive or `file://` mirror.
+///
+/// Runs before the cache lookup so that an offline source turns what would
+/// be a download into a cache hit. Does nothing when a matching version is
+/// already cached or no offline source is configured.
+///
+/// # Errors
+///
+/// Returns an error if the configured archive does not satisfy the version
+/// requirement, fails checksum verification, or cannot be imported, or if a
+/// `file://` mirror holds no matching archive for this platform.
+pub(super) fn provision_offline_binaries(
+    config: &BinaryCacheConfig,
+    version_req: &VersionReq,
+    bootstrap: &TestBootstrapSettings,
+) -> BootstrapResult<()> {
+    if find_matching_cached_version(&config.cache_dir, version_req).is_some() {
+        return Ok(());
+    }
+    if let Some(archive) = bootstrap.binary_archive.as_deref() {
+        ensure_archive_satisfies(archive, version_req)?;
+        import_archive(
+            archive,
+            bootstrap.binary_archive_sha256.as_deref(),
+            bootstrap.binary_archive_allow_unverified,
+            &config.cache_dir,
+        )?;
+        return Ok(());
+    }
+    let Some(mirror) = mirror_dir_from_url(&bootstrap.settings.releases_url) else {
+        return Ok(());
+    };
+    import_from_mirror(
+        &mirror,
+        version_req,
+        bootstrap.binary_archive_allow_unverified,
+        &config.cache_dir,
+    )?;
+    Ok(())
+}
+
 /// Attempts to use cached binaries for the given version requirement.
 ///
 /// Returns `true` if binaries were successfully copied from cache, `false` otherwise.
@@ -145,9 +224,13 @@ pub(super) fn try_use_binary_cache(
         return false;
     };
 
-    match check_cache(&config.cache_dir, &version) {
+    match check_cache_with(&config.cache_dir, &version, config.integrity) {
         CacheLookupResult::Hit { source_dir } => {
-            apply_cached_binaries(&source_dir, &version, version_req, bootstrap)
+            let applied = apply_cached_binaries(config, &source_dir, &version, bootstrap);
+            if applied {
+                touch_cache_entry(config, &version);
+            }
+            applied
         }
         CacheLookupResult::Miss => {
             log_cache_entry_invalid(&version);
@@ -156,6 +239,43 @@ pub(super) fn try_use_binary_cache(
     }
 }
 
+/// Records a cache hit so eviction treats the entry as recently used.
+///
+/// Callers hold the version's shared lock.
+fn touch_cache_entry(config: &BinaryCacheConfig, version: &str) {
+    if let Err(err) = record_access(&config.cache_dir, version) {
+        debug!(
+            target: LOG_TARGET,
+            version = %version,
+            error = %err,
+            "failed to record cache access"
+        );
+    }
+}
+
+/// Evicts cache entries that exceed the configured retention policy.
+///
+/// Runs after startup has finished with the cache. Versions locked by other
+/// processes are skipped, and failures are logged rather than propagated.
+pub(super) fn enforce_cache_retention(config: &BinaryCacheConfig) {
+    if config.retention.is_unbounded() {
+        return;
+    }
+    if let Err(err) = evict_cache(&config.cache_dir, &config.retention) {
+        log_eviction_failed(config, &err);
+    }
+}
+
+/// Logs a failed eviction pass.
+fn log_eviction_failed(config: &BinaryCacheConfig, err: &BootstrapError) {
+    warn!(
+        target: LOG_TARGET,
+        cache_dir = %config.cache_dir,
+        error = %err,
+        "cache eviction failed"
+    );
+}
+
 /// Attempts to populate the cache with binaries from the installation directory.
 ///
 /// This is called after a successful setup to cache the downloaded binaries for future use.
@@ -249,7 +369,7 @@ pub(super) fn try_populate_binary_cache(config: &BinaryCacheConfig, settings: &S
 /// Expects paths like `/path/to/install/17.4.0/` and extracts `17.4.0`.
 /// Returns `None` if the directory name is not a valid semver version,
 /// ensuring consistency with cache lookup which parses directory names as versions.
-fn extract_version_from_path(path: &std::path::Path) -> Option<String> {
+pub(super) fn extract_version_from_path(path: &std::path::Path) -> Option<String> {
     let name = path.file_name()?.to_str()?;
     // Validate that the name parses as a semver version to ensure
     // consistency with find_matching_cached_version which uses Version::parse.
@@ -1,12 +1,15 @@
 //! Cleanup helpers for `TestCluster` shutdown.
 
-use crate::cleanup_helpers::{RemovalOutcome, has_parent_dir, try_remove_dir_all};
+use crate::cleanup_helpers::{
+    RemovalOutcome, has_parent_dir, is_sandboxed_installation, try_remove_dir_all,
+};
 use crate::observability::LOG_TARGET;
 use crate::{CleanupMode, TestBootstrapSettings};
 use postgresql_embedded::Settings;
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use super::server_log;
 use super::worker_invoker::WorkerInvoker as ClusterWorkerInvoker;
 use super::worker_operation;
 
@@ -98,21 +101,105 @@ fn cleanup_install_dir(cleanup_mode: CleanupMode, settings: &Settings, context:
     if !should_remove_install(cleanup_mode) {
         return;
     }
+    let Some(parent) = settings.password_file.parent() else {
+        return;
+    };
+    if !is_sandboxed_installation(&settings.installation_dir, &settings.password_file) {
+        // The sandbox still holds `.pgpass`, which a later run must not reuse.
+        log_install_kept(&settings.installation_dir, context);
+        remove_dir_all_if_exists(parent, DirectoryLabel::InstallationRoot, context);
+        return;
+    }
     remove_dir_all_if_exists(
         &settings.installation_dir,
         DirectoryLabel::Installation,
         context,
     );
-    let Some(parent) = settings.password_file.parent() else {
-        return;
-    };
     if should_remove_install_root(parent, settings) {
         remove_dir_all_if_exists(parent, DirectoryLabel::InstallationRoot, context);
     }
 }
 
+fn log_install_kept(path: &Path, context: &str) {
+    tracing::debug!(
+        target: LOG_TARGET,
+        context = %context,
+        path = %path.display(),
+        "keeping installation outside the cluster sandbox"
+    );
+}
+
+/// Copies the server log beside a preserved data directory and logs how to
+/// inspect the cluster.
+///
+/// Called when a [`CleanupMode::KeepOnFailure`] cluster is dropped during a
+/// panic. The copy survives even if the data directory is later reused.
+pub(super) fn report_preserved_cluster(bootstrap: &TestBootstrapSettings, context: &str) {
+    let log_path = copy_server_log(&bootstrap.settings);
+    tracing::warn!(
+        target: LOG_TARGET,
+        "{}",
+        preserved_cluster_message(bootstrap, &log_path, context)
+    );
+}
+
+/// Copies the server log beside the data directory, returning the path to cite.
+///
+/// Falls back to the original log path when the copy fails.
+fn copy_server_log(settings: &Settings) -> PathBuf {
+    let server_log = server_log::server_log_path(settings);
+    let preserved_log = preserved_log_path(&settings.data_dir);
+    match std::fs::copy(&server_log, &preserved_log) {
+        Ok(_) => preserved_log,
+        Err(err) => {
+            log_server_log_copy_failure(&server_log, &err);
+            server_log
+        }
+    }
+}
+
+fn log_server_log_copy_failure(path: &Path, err: &std::io::Error) {
+    tracing::debug!(
+        target: LOG_TARGET,
+        path = %path.display(),
+        error = %err,
+        "failed to copy server log of preserved cluster"
+    );
+}
+
+/// Describes a preserved cluster without revealing the superuser password.
+///
+/// The URL omits the password; the message cites the password file named by
+/// `PGPASSFILE` instead, which the installation directory keeps.
+fn preserved_cluster_message(
+    bootstrap: &TestBootstrapSettings,
+    log_path: &Path,
+    context: &str,
+) -> String {
+    let settings = &bootstrap.settings;
+    let data_dir = settings.data_dir.display();
+    format!(
+        "test panicked; kept postgres data directory {data_dir} and server log {} \
+         ({context}); inspect with `pg_ctl -D {data_dir} start` and {} using the \
+         password in {}",
+        log_path.display(),
+        super::connection::redacted_database_url(settings, "postgres"),
+        bootstrap.environment.pgpass_file
+    )
+}
+
+/// Returns `<data_dir>.server.log`, a sibling of the data directory.
+fn preserved_log_path(data_dir: &Path) -> PathBuf {
+    let mut name = data_dir.as_os_str().to_owned();
+    name.push(".server.log");
+    PathBuf::from(name)
+}
+
 const fn should_remove_data(cleanup_mode: CleanupMode) -> bool {
-    matches!(cleanup_mode, CleanupMode::DataOnly | CleanupMode::Full)
+    matches!(
+        cleanup_mode,
+        CleanupMode::DataOnly | CleanupMode::Full | CleanupMode::KeepOnFailure
+    )
 }
 
 const fn should_remove_install(cleanup_mode: CleanupMode) -> bool {
@@ -121,7 +208,9 @@ const fn should_remove_install(cleanup_mode: CleanupMode) -> bool {
 
 const fn cleanup_operation(cleanup_mode: CleanupMode) -> Option<worker_operation::WorkerOperation> {
     match cleanup_mode {
-        CleanupMode::DataOnly => Some(worker_operation::WorkerOperation::Cleanup),
+        CleanupMode::DataOnly | CleanupMode::KeepOnFailure => {
+            Some(worker_operation::WorkerOperation::Cleanup)
+        }
         CleanupMode::Full => Some(worker_operation::WorkerOperation::CleanupFull),
         CleanupMode::None => None,
     }
@@ -207,8 +296,10 @@ fn warn_cleanup_removal_failure(
 
 #[cfg(test)]
 mod tests {
-    use super::cleanup_in_process;
+    use super::{cleanup_in_process, preserved_cluster_message, report_preserved_cluster};
     use crate::CleanupMode;
+    use crate::ExecutionPrivileges;
+    use crate::test_support::dummy_settings;
     use postgresql_embedded::Settings;
     use rstest::rstest;
     use std::fs;
@@ -216,6 +307,7 @@ mod tests {
 
     #[rstest]
     #[case::data_only(CleanupMode::DataOnly, false, true)]
+    #[case::keep_on_failure(CleanupMode::KeepOnFailure, false, true)]
     #[case::full(CleanupMode::Full, false, false)]
     #[case::none(CleanupMode::None, true, true)]
     fn cleanup_in_process_respects_mode(
@@ -234,6 +326,7 @@ mod tests {
         let settings = Settings {
             data_dir,
             installation_dir: install_dir,
+            password_file: sandbox.path().join(".pgpass"),
             ..Settings::default()
         };
 
@@ -251,4 +344,77 @@ mod tests {
             "installation directory presence should match cleanup mode",
         );
     }
+
+    #[test]
+    fn full_cleanup_keeps_installations_outside_the_sandbox() {
+        let root = tempdir().expect("tempdir");
+        let system = tempdir().expect("tempdir");
+        let data_dir = root.path().join("data");
+        let sandbox = root.path().join("install");
+        fs::create_dir_all(&data_dir).expect("create data dir");
+        fs::create_dir_all(&sandbox).expect("create sandbox");
+        fs::write(sandbox.join(".pgpass"), b"secret").expect("write password file");
+        fs::create_dir_all(system.path().join("bin")).expect("create system bin dir");
+        let settings = Settings {
+            data_dir,
+            installation_dir: system.path().to_path_buf(),
+            password_file: sandbox.join(".pgpass"),
+            ..Settings::default()
+        };
+
+        cleanup_in_process(CleanupMode::Full, &settings, "cleanup-test");
+
+        assert!(
+            !settings.data_dir.exists(),
+            "data directory should be removed"
+        );
+        assert!(!sandbox.exists(), "sandbox should be removed");
+        assert!(
+            system.path().join("bin").exists(),
+            "system installation must be kept"
+        );
+    }
+
+    #[test]
+    fn report_preserved_cluster_copies_server_log_beside_data_dir() {
+        let sandbox = tempdir().expect("tempdir");
+        let data_dir = sandbox.path().join("data");
+        fs::create_dir_all(&data_dir).expect("create data dir");
+        fs::write(data_dir.join("start.log"), b"FATAL: boom").expect("write server log");
+        let mut bootstrap = dummy_settings(ExecutionPrivileges::Unprivileged);
+        bootstrap.settings.data_dir = data_dir;
+
+        report_preserved_cluster(&bootstrap, "cleanup-test");
+
+        let preserved = fs::read_to_string(sandbox.path().join("data.server.log"))
+            .expect("preserved server log");
+        assert_eq!(preserved, "FATAL: boom");
+        assert!(
+            bootstrap.settings.data_dir.exists(),
+            "data directory must be kept"
+        );
+    }
+
+    #[test]
+    fn preserved_cluster_message_omits_the_password() {
+        let mut bootstrap = dummy_settings(ExecutionPrivileges::Unprivileged);
+        bootstrap.settings.username = "postgres".into();
+        bootstrap.settings.password = "hunter2-secret".into();
+
+        let message = preserved_cluster_message(
+            &bootstrap,
+            &bootstrap.settings.data_dir.join("start.log"),
+            "cleanup-test",
+        );
+
+        assert!(
+            !message.contains("hunter2-secret"),
+            "message must not reveal the password: {message}"
+        );
+        assert!(message.contains("postgresql://postgres@"), "{message}");
+        assert!(
+            message.contains(bootstrap.environment.pgpass_file.as_str()),
+            "message should cite the password file: {message}"
+        );
+    }
 }
@@ -0,0 +1,280 @@
+//! Data directory snapshot integration for `TestCluster`.
+//!
+//! On a binary cache hit, restores a pristine post-`initdb` data directory
+//! from the snapshot tier so `setup()` skips `initdb`, then rewrites the
+//! superuser password in single-user mode. When `initdb` does run, the fresh
+//! data directory is captured between the `Setup` and `Start` steps.
+
+use crate::cache::store_data_snapshot;
+use crate::cache::{BinaryCacheConfig, DataSnapshotKey, restore_data_snapshot, snapshot_dir};
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+use crate::{ExecutionPrivileges, TestBootstrapSettings};
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{Context, eyre};
+use postgresql_embedded::{BOOTSTRAP_DATABASE, BOOTSTRAP_SUPERUSER, Settings};
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{info, warn};
+
+use super::cache_integration::extract_version_from_path;
+use super::installation;
+
+/// Builds the snapshot key for a cluster from its settings.
+///
+/// Returns `None` when the installed version cannot be determined.
+pub(super) fn snapshot_key(settings: &Settings) -> Option<DataSnapshotKey> {
+    let installed_dir = installation::resolve_installed_dir(settings)?;
+    let version = extract_version_from_path(&installed_dir)?;
+    Some(DataSnapshotKey {
+        version,
+        locale: settings.configuration.get("locale").cloned(),
+        encoding: settings.configuration.get("encoding").cloned(),
+        superuser: settings.username.clone(),
+    })
+}
+
+/// Snapshot capture to perform once the `Setup` step has finished.
+#[derive(Clone, Copy)]
+pub(super) struct SnapshotCapture<'a> {
+    config: Option<&'a BinaryCacheConfig>,
+}
+
+impl<'a> SnapshotCapture<'a> {
+    /// Plans a capture unless snapshots are disabled or one was just restored.
+    pub(super) const fn new(config: &'a BinaryCacheConfig, restored: bool) -> Self {
+        let enabled = config.data_snapshots && !restored;
+        Self {
+            config: if enabled { Some(config) } else { None },
+        }
+    }
+
+    /// Stores the freshly initialised data directory in the snapshot tier.
+    ///
+    /// Must run before the server starts. Failures are logged and otherwise
+    /// ignored, since the cluster itself is unaffected.
+    pub(super) fn after_setup(self, settings: &Settings) {
+        let Some(config) = self.config else {
+            return;
+        };
+        let Some(key) = snapshot_key(settings) else {
+            return;
+        };
+        let Ok(data_dir) = Utf8PathBuf::from_path_buf(settings.data_dir.clone()) else {
+            return;
+        };
+        if !is_pristine(&data_dir) {
+            return;
+        }
+        if let Err(err) = store_data_snapshot(&snapshot_dir(&config.cache_dir), &key, &data_dir) {
+            log_capture_failed(&key, &err);
+        }
+    }
+}
+
+/// Returns `true` for an initialised data directory that has never started.
+fn is_pristine(data_dir: &Utf8Path) -> bool {
+    data_dir.join("PG_VERSION").is_file()
+        && data_dir.join("postgresql.conf").is_file()
+        && !data_dir.join("postmaster.pid").exists()
+}
+
+/// Restores a data directory snapshot in place of `initdb`.
+///
+/// Only runs on a binary cache hit for a data directory that has not been
+/// initialised. Returns `true` when the restored cluster is ready for
+/// `setup()` to skip `initdb`. Any failure is logged, the partially restored
+/// directory is emptied, and `false` is returned so `initdb` runs as usual.
+pub(super) fn try_restore_data_snapshot(
+    config: &BinaryCacheConfig,
+    cache_hit: bool,
+    bootstrap: &TestBootstrapSettings,
+) -> bool {
+    if !config.data_snapshots || !cache_hit {
+        return false;
+    }
+    let Ok(data_dir) = Utf8PathBuf::from_path_buf(bootstrap.settings.data_dir.clone()) else {
+        return false;
+    };
+    if data_dir.join("postgresql.conf").exists() {
+        return false;
+    }
+    let Some(key) = snapshot_key(&bootstrap.settings) else {
+        return false;
+    };
+
+    match restore_into(config, &key, &data_dir, bootstrap) {
+        Ok(restored) => restored,
+        Err(err) => {
+            log_restore_failed(&key, &err);
+            if let Err(clear_err) = clear_dir(&data_dir) {
+                log_restore_failed(&key, &clear_err);
+            }
+            false
+        }
+    }
+}
+
+/// Copies the snapshot, fixes ownership, and resets the password.
+fn restore_into(
+    config: &BinaryCacheConfig,
+    key: &DataSnapshotKey,
+    data_dir: &Utf8Path,
+    bootstrap: &TestBootstrapSettings,
+) -> BootstrapResult<bool> {
+    if !restore_data_snapshot(&snapshot_dir(&config.cache_dir), key, data_dir)? {
+        return Ok(false);
+    }
+    let owner = DataDirOwner::resolve(bootstrap.privileges)?;
+    owner.claim(data_dir)?;
+    rewrite_password(&bootstrap.settings, &owner)?;
+    log_restored(key, data_dir);
+    Ok(true)
+}
+
+/// Removes everything inside `dir`, keeping the directory itself.
+fn clear_dir(dir: &Utf8Path) -> BootstrapResult<()> {
+    let Ok(entries) = dir.read_dir_utf8() else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry
+            .context("failed to read data directory entry")?
+            .into_path();
+        let removed = if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed.with_context(|| format!("failed to remove {path}"))?;
+    }
+    Ok(())
+}
+
+/// The account that must own the data directory and run `postgres`.
+enum DataDirOwner {
+    /// The current user.
+    Current,
+    /// The `nobody` user that root runs drop to.
+    #[cfg(unix)]
+    Nobody(nix::unistd::User),
+}
+
+impl DataDirOwner {
+    /// Resolves the owner for the given execution privileges.
+    fn resolve(privileges: ExecutionPrivileges) -> BootstrapResult<Self> {
+        #[cfg(unix)]
+        if privileges == ExecutionPrivileges::Root {
+            let nobody = nix::unistd::User::from_name("nobody")
+                .context("failed to resolve user 'nobody'")?
+                .ok_or_else(|| eyre!("user 'nobody' not found"))?;
+            return Ok(Self::Nobody(nobody));
+        }
+        #[cfg(not(unix))]
+        let _ = privileges;
+        Ok(Self::Current)
+    }
+
+    /// Hands a restored data directory to the owner.
+    ///
+    /// Snapshots copied by root belong to root, so root runs re-own the whole
+    /// tree before `PostgreSQL` runs as `nobody`.
+    fn claim(&self, data_dir: &Utf8Path) -> BootstrapResult<()> {
+        match self {
+            Self::Current => Ok(()),
+            #[cfg(unix)]
+            Self::Nobody(user) => {
+                crate::privileges::ensure_tree_owned_by_user(data_dir, user)?;
+                crate::privileges::make_data_dir_private(data_dir, user)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Configures `command` to run as the owner.
+    fn apply(&self, command: &mut Command) {
+        match self {
+            Self::Current => {}
+            #[cfg(unix)]
+            Self::Nobody(user) => {
+                use std::os::unix::process::CommandExt;
+
+                command.uid(user.uid.as_raw()).gid(user.gid.as_raw());
+            }
+        }
+    }
+}
+
+/// Sets the bootstrap superuser's password using single-user mode.
+///
+/// The restored cluster still carries the password of the cluster the
+/// snapshot was taken from. The new password is passed on standard input so
+/// it never appears in the process list.
+fn rewrite_password(settings: &Settings, owner: &DataDirOwner) -> BootstrapResult<()> {
+    let postgres = settings.installation_dir.join("bin").join("postgres");
+    let mut command = Command::new(&postgres);
+    command
+        .arg("--single")
+        .arg("-j")
+        .arg("-D")
+        .arg(&settings.data_dir)
+        .arg(BOOTSTRAP_DATABASE)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    owner.apply(&mut command);
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("failed to run {}", postgres.display()))?;
+    let statement = format!(
+        "ALTER ROLE \"{BOOTSTRAP_SUPERUSER}\" WITH PASSWORD '{}';\n\n",
+        settings.password.replace('\'', "''")
+    );
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| eyre!("postgres single-user stdin unavailable"))?
+        .write_all(statement.as_bytes())
+        .context("failed to send password to postgres single-user mode")?;
+    let output = child
+        .wait_with_output()
+        .context("failed to wait for postgres single-user mode")?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || stderr.contains("ERROR:") {
+        return Err(eyre!("failed to reset superuser password: {}", stderr.trim()).into());
+    }
+    Ok(())
+}
+
+/// Logs a restored snapshot.
+fn log_restored(key: &DataSnapshotKey, data_dir: &Utf8Path) {
+    info!(
+        target: LOG_TARGET,
+        version = %key.version,
+        data_dir = %data_dir,
+        "restored data directory snapshot, skipping initdb"
+    );
+}
+
+/// Logs a failed restore.
+fn log_restore_failed(key: &DataSnapshotKey, err: &BootstrapError) {
+    warn!(
+        target: LOG_TARGET,
+        version = %key.version,
+        error = %err,
+        "data directory snapshot restore failed, running initdb"
+    );
+}
+
+/// Logs a failed capture.
+fn log_capture_failed(key: &DataSnapshotKey, err: &BootstrapError) {
+    warn!(
+        target: LOG_TARGET,
+        version = %key.version,
+        error = %err,
+        "failed to store data directory snapshot"
+    );
+}
@@ -0,0 +1,276 @@
+//! Database names for lifecycle operations.
+//!
+//! `PostgreSQL` silently truncates identifiers longer than 63 bytes, so a
+//! guard holding the untruncated name would later drop a database that does
+//! not exist. [`DatabaseName::try_new`] rejects such names before they reach
+//! the server, and [`DatabaseName::unique`] generates names that always fit.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use color_eyre::eyre::eyre;
+use sha2::{Digest, Sha256};
+
+use crate::error::{BootstrapError, BootstrapResult};
+
+/// Longest identifier `PostgreSQL` stores without truncation (`NAMEDATALEN - 1`).
+pub const MAX_DATABASE_NAME_BYTES: usize = 63;
+
+/// Hex digits of the label hash kept when a generated name is truncated.
+const HASH_HEX_DIGITS: usize = 8;
+
+/// Distinguishes names generated within one process.
+static UNIQUE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A strongly-typed database name for use with lifecycle operations.
+///
+/// This newtype provides type safety for database name parameters, preventing
+/// accidental misuse of raw strings while still allowing convenient conversion
+/// from string literals. Lifecycle operations that create databases reject
+/// names `PostgreSQL` would truncate.
+///
+/// # Examples
+///
+/// ```
+/// use pg_embedded_setup_unpriv::DatabaseName;
+///
+/// // From string literal
+/// let name: DatabaseName = "my_database".into();
+/// assert_eq!(name.as_str(), "my_database");
+///
+/// // From owned String
+/// let name: DatabaseName = String::from("another_db").into();
+/// assert_eq!(name.as_str(), "another_db");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DatabaseName(String);
+
+impl DatabaseName {
+    /// Creates a new `DatabaseName` from a string without validating it.
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// Creates a `DatabaseName`, rejecting names `PostgreSQL` cannot store
+    /// verbatim.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is empty, longer than
+    /// [`MAX_DATABASE_NAME_BYTES`], or contains a NUL byte.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pg_embedded_setup_unpriv::DatabaseName;
+    ///
+    /// assert!(DatabaseName::try_new("orders_test").is_ok());
+    /// assert!(DatabaseName::try_new("x".repeat(64)).is_err());
+    /// ```
+    pub fn try_new(name: impl Into<String>) -> BootstrapResult<Self> {
+        let candidate = Self(name.into());
+        candidate.validate()?;
+        Ok(candidate)
+    }
+
+    /// Generates a name unique to this process, derived from `label`.
+    ///
+    /// The label is lowercased, characters other than ASCII letters, digits,
+    /// and underscores become underscores, and the process ID and a
+    /// per-process counter are appended. Labels too long to fit are truncated
+    /// and tagged with a hash of the full label, so distinct long labels stay
+    /// distinct.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use pg_embedded_setup_unpriv::DatabaseName;
+    ///
+    /// let first = DatabaseName::unique("Orders::Refunds");
+    /// let second = DatabaseName::unique("Orders::Refunds");
+    /// assert!(first.as_str().starts_with("orders__refunds_"));
+    /// assert_ne!(first, second);
+    /// assert!(DatabaseName::unique(&"long".repeat(40)).as_str().len() <= 63);
+    /// ```
+    #[must_use]
+    pub fn unique(label: &str) -> Self {
+        let count = UNIQUE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let suffix = format!("_{:x}_{count:x}", std::process::id());
+        Self(fit_label(label, &suffix))
+    }
+
+    /// Generates a unique name for the running test.
+    ///
+    /// The test harness names each test thread after the test path; its last
+    /// segment, the test function name, becomes the label passed to
+    /// [`Self::unique`]. Code running outside the harness, on the main thread
+    /// or an unnamed one, falls back to the label `test`.
+    #[must_use]
+    pub fn for_current_test() -> Self {
+        let thread = std::thread::current();
+        let label = thread
+            .name()
+            .filter(|name| *name != "main")
+            .and_then(|name| name.rsplit("::").next())
+            .unwrap_or("test");
+        Self::unique(label)
+    }
+
+    /// Returns the database name as a string slice.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Checks that `PostgreSQL` would store the name verbatim.
+    pub(crate) fn validate(&self) -> BootstrapResult<()> {
+        let problem = if self.0.is_empty() {
+            "must not be empty".to_owned()
+        } else if self.0.len() > MAX_DATABASE_NAME_BYTES {
+            format!(
+                "is {} bytes long; PostgreSQL truncates names longer than {MAX_DATABASE_NAME_BYTES} bytes",
+                self.0.len()
+            )
+        } else if self.0.contains('\0') {
+            "must not contain NUL bytes".to_owned()
+        } else {
+            return Ok(());
+        };
+        Err(BootstrapError::from(eyre!(
+            "invalid database name '{}': {problem}",
+            self.0.escape_debug()
+        )))
+    }
+}
+
+impl AsRef<str> for DatabaseName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for DatabaseName {
+    fn from(s: &str) -> Self {
+        Self(s.to_owned())
+    }
+}
+
+impl From<String> for DatabaseName {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+/// Sanitises `label` and appends `suffix`, truncating the label so the result
+/// fits in [`MAX_DATABASE_NAME_BYTES`].
+fn fit_label(label: &str, suffix: &str) -> String {
+    let sanitized: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let base = if sanitized.is_empty() {
+        "db".to_owned()
+    } else {
+        sanitized
+    };
+    let budget = MAX_DATABASE_NAME_BYTES.saturating_sub(suffix.len());
+    if base.len() <= budget {
+        return format!("{base}{suffix}");
+    }
+    let digest = format!("{:x}", Sha256::digest(label.as_bytes()));
+    let hash = digest.get(..HASH_HEX_DIGITS).unwrap_or_default();
+    let keep = budget.saturating_sub(HASH_HEX_DIGITS + 1);
+    // The sanitised label is ASCII, so any byte offset is a char boundary.
+    let head = base.get(..keep).unwrap_or_default();
+    format!("{head}_{hash}{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::too_long("a_rather_long_database_name_that_postgresql_would_silently_trunc")]
+    #[case::nul("bad\0name")]
+    fn try_new_rejects_names_postgres_would_alter(#[case] name: &str) {
+        let err = DatabaseName::try_new(name).expect_err("name should be rejected");
+        assert!(
+            err.to_string().contains("invalid database name"),
+            "got: {err}"
+        );
+    }
+
+    #[test]
+    fn try_new_accepts_names_at_the_limit() {
+        let name = "n".repeat(MAX_DATABASE_NAME_BYTES);
+
+        let parsed = DatabaseName::try_new(name.clone()).expect("63-byte name is valid");
+
+        assert_eq!(parsed.as_str(), name);
+    }
+
+    #[test]
+    fn long_labels_are_truncated_with_distinguishing_hash() {
+        let prefix = "integration::suite::".repeat(4);
+        let first = fit_label(&format!("{prefix}alpha"), "_1f_0");
+        let second = fit_label(&format!("{prefix}beta"), "_1f_0");
+
+        assert_eq!(first.len(), MAX_DATABASE_NAME_BYTES);
+        assert!(first.ends_with("_1f_0"));
+        assert_ne!(first, second);
+        assert!(DatabaseName::try_new(first).is_ok());
+    }
+
+    #[test]
+    fn unique_names_sanitise_labels() {
+        let name = DatabaseName::unique("Café Tests!");
+        let suffix = format!("_{:x}_", std::process::id());
+
+        assert!(name.as_str().starts_with("caf__tests__"), "got: {name:?}");
+        assert!(name.as_str().contains(&suffix));
+        assert!(DatabaseName::unique("").as_str().starts_with("db_"));
+    }
+
+    #[test]
+    fn for_current_test_uses_the_test_thread_name() {
+        let name = DatabaseName::for_current_test();
+
+        assert!(
+            name.as_str()
+                .starts_with("for_current_test_uses_the_test_thread_name_"),
+            "got: {name:?}"
+        );
+    }
+
+    fn for_current_test_on(thread: std::thread::Builder) -> DatabaseName {
+        thread
+            .spawn(DatabaseName::for_current_test)
+            .expect("spawn naming thread")
+            .join()
+            .expect("naming thread panicked")
+    }
+
+    #[rstest]
+    #[case::unnamed(std::thread::Builder::new(), "test_")]
+    #[case::main(std::thread::Builder::new().name("main".to_owned()), "test_")]
+    #[case::path(
+        std::thread::Builder::new().name("suite::module::orders".to_owned()),
+        "orders_"
+    )]
+    fn for_current_test_labels_threads_outside_the_harness(
+        #[case] thread: std::thread::Builder,
+        #[case] prefix: &str,
+    ) {
+        let name = for_current_test_on(thread);
+
+        assert!(name.as_str().starts_with(prefix), "got: {name:?}");
+    }
+}
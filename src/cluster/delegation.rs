@@ -4,7 +4,7 @@
 //! `TestCluster`, eliminating the need for callers to explicitly call `.connection()`
 //! before invoking methods like `create_database` or `drop_database`.
 
-use super::lifecycle::DatabaseName;
+use super::database_name::DatabaseName;
 use super::temporary_database::TemporaryDatabase;
 use super::{ClusterHandle, TestCluster};
 use crate::CleanupMode;
@@ -75,7 +75,11 @@ impl TestCluster {
     #[must_use]
     pub fn with_cleanup_mode(mut self, cleanup_mode: CleanupMode) -> Self {
         self.guard.bootstrap.cleanup_mode = cleanup_mode;
-        self.handle = ClusterHandle::new(self.guard.bootstrap.clone());
+        self.handle = ClusterHandle::new(
+            self.guard.bootstrap.clone(),
+            self.guard.backend,
+            self.guard.created_databases(),
+        );
         self
     }
 }
@@ -200,7 +204,8 @@ impl TestCluster {
     ///
     /// // Ensure template exists, running migrations if needed
     /// cluster.ensure_template_exists("my_template", |db_name| {
-    ///     // Run migrations on the newly created template database
+    ///     // Run migrations on `db_name`, the staging database that becomes
+    ///     // the template once this closure succeeds
     ///     Ok(())
     /// })?;
     ///
@@ -0,0 +1,194 @@
+//! Backend that attaches to an existing server instead of starting one.
+//!
+//! `PG_TEST_BACKEND=external` points the cluster at the server named by
+//! `DATABASE_URL`. The guard never stops that server; instead, handles and
+//! connections record the databases they create, and the guard drops those
+//! that still exist, along with the private sandbox holding `.pgpass`.
+//! Templates built by `ensure_template_exists` are not recorded, since other
+//! processes sharing the server may still be cloning them; they stay on the
+//! server for reuse.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::WrapErr;
+use postgres::Client;
+use tokio::runtime::Runtime;
+use tracing::{info, warn};
+
+use super::backend::{Attachment, ClusterBackend};
+#[cfg(feature = "async-api")]
+use super::connection::connect_admin_async;
+use super::connection::{ConnectionMetadata, connect_admin};
+use super::roles::record_pgpass_entry;
+use super::startup::StartupOutcome;
+use super::temporary_database::{off_runtime, terminate_and_drop};
+use crate::TestBootstrapSettings;
+use crate::bootstrap::BackendKind;
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+
+/// Lists the recorded databases that still exist.
+const EXISTING_DATABASES_SQL: &str = "SELECT datname FROM pg_database WHERE datname = ANY($1)";
+
+/// Databases created through a cluster attached to an external server.
+///
+/// Clones share one set, so every handle and connection of a cluster records
+/// into the set its guard sweeps.
+#[derive(Debug, Clone, Default)]
+pub(super) struct CreatedDatabases(Arc<Mutex<BTreeSet<String>>>);
+
+impl CreatedDatabases {
+    /// Records a database created through the cluster.
+    pub(super) fn record(&self, name: &str) {
+        self.lock().insert(name.to_owned());
+    }
+
+    /// Forgets a database dropped through the cluster.
+    pub(super) fn forget(&self, name: &str) {
+        self.lock().remove(name);
+    }
+
+    /// Empties the set, returning the recorded names.
+    pub(super) fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.lock()).into_iter().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Resources a cluster holds on an external server, released on drop.
+#[derive(Debug)]
+pub(super) struct ExternalAttachment {
+    created: CreatedDatabases,
+    metadata: ConnectionMetadata,
+    sandbox: Utf8PathBuf,
+}
+
+impl ExternalAttachment {
+    fn new(bootstrap: &TestBootstrapSettings) -> Self {
+        Self {
+            created: CreatedDatabases::default(),
+            metadata: ConnectionMetadata::from_settings(bootstrap),
+            sandbox: bootstrap.environment.home.clone(),
+        }
+    }
+
+    /// Returns the set shared with the cluster's handles.
+    pub(super) fn created_databases(&self) -> CreatedDatabases {
+        self.created.clone()
+    }
+
+    /// Drops every recorded database that still exists.
+    fn sweep(&self) -> BootstrapResult<()> {
+        let names = self.created.take();
+        if names.is_empty() {
+            return Ok(());
+        }
+        let mut client = connect_admin(&self.metadata.admin_url())?;
+        for name in existing_databases(&mut client, &names)? {
+            if let Err(err) = terminate_and_drop(&mut client, &name) {
+                warn_sweep_failed(&name, &err);
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_sandbox(&self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.sandbox) {
+            warn!(
+                target: LOG_TARGET,
+                sandbox = %self.sandbox,
+                error = %err,
+                "failed to remove external backend sandbox"
+            );
+        }
+    }
+}
+
+impl Drop for ExternalAttachment {
+    fn drop(&mut self) {
+        if let Err(err) = off_runtime(|| self.sweep()) {
+            warn!(
+                target: LOG_TARGET,
+                error = ?err,
+                "failed to sweep databases created on the external server"
+            );
+        }
+        self.remove_sandbox();
+    }
+}
+
+fn existing_databases(client: &mut Client, names: &[String]) -> BootstrapResult<Vec<String>> {
+    let rows = client
+        .query(EXISTING_DATABASES_SQL, &[&names])
+        .wrap_err("failed to list databases created on the external server")
+        .map_err(BootstrapError::from)?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+fn warn_sweep_failed(name: &str, error: &BootstrapError) {
+    warn!(
+        target: LOG_TARGET,
+        db = %name,
+        error = ?error,
+        "failed to sweep database created on the external server"
+    );
+}
+
+/// Attaches to the server named by `DATABASE_URL`.
+pub(super) struct ExternalBackend;
+
+impl ExternalBackend {
+    fn attach(bootstrap: TestBootstrapSettings) -> BootstrapResult<StartupOutcome> {
+        let attachment = ExternalAttachment::new(&bootstrap);
+        let settings = &bootstrap.settings;
+        record_pgpass_entry(&attachment.metadata, &settings.username, &settings.password)?;
+        info!(
+            target: LOG_TARGET,
+            host = %settings.host,
+            port = settings.port,
+            "attached to external postgres server"
+        );
+        Ok(StartupOutcome {
+            bootstrap,
+            postgres: None,
+            is_managed_via_worker: false,
+            attachment: Some(Attachment::External(Box::new(attachment))),
+        })
+    }
+}
+
+impl ClusterBackend for ExternalBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::External
+    }
+
+    fn start(
+        &self,
+        _runtime: &Runtime,
+        bootstrap: TestBootstrapSettings,
+        _env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        // Fail fast when the server is unreachable rather than on first use.
+        drop(connect_admin(
+            &ConnectionMetadata::from_settings(&bootstrap).admin_url(),
+        )?);
+        Self::attach(bootstrap)
+    }
+
+    #[cfg(feature = "async-api")]
+    async fn start_async(
+        &self,
+        bootstrap: TestBootstrapSettings,
+        _env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        drop(
+            connect_admin_async(&ConnectionMetadata::from_settings(&bootstrap).admin_url()).await?,
+        );
+        Self::attach(bootstrap)
+    }
+}
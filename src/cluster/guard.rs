@@ -12,13 +12,18 @@
 //!   variables when dropped
 //! - **Shutdown resources**: Runtime, `PostgreSQL` instance, and configuration
 //!   needed to cleanly stop the cluster
+//! - **Server log forwarder**: Optional thread relaying server log lines into
+//!   `tracing`
 //! - **Tracing span**: Keeps the cluster's observability span alive
 //!
 //! # Drop Behaviour
 //!
 //! When dropped, the guard:
 //! 1. Stops the `PostgreSQL` cluster (gracefully if possible)
-//! 2. Restores environment variables to their pre-cluster state
+//! 2. Stops forwarding server log lines into `tracing`, if enabled
+//! 3. Keeps the data directory and reports where to find it if the cleanup
+//!    mode is [`CleanupMode::KeepOnFailure`] and the thread is panicking
+//! 4. Restores environment variables to their pre-cluster state
 //!
 //! # Thread Safety
 //!
@@ -38,10 +43,15 @@
 //! - `start_async_split_creates_working_handle_and_guard`: Tests the async
 //!   variant with the same shutdown and restoration assertions.
 
+use super::backend::Attachment;
+use super::external::CreatedDatabases;
 use super::runtime_mode::ClusterRuntime;
-use super::shutdown;
+use super::server_log::ServerLogForwarder;
+use super::{cleanup, shutdown};
+use crate::bootstrap::BackendKind;
 use crate::env::ScopedEnv;
 use crate::observability::LOG_TARGET;
+use crate::worker_process::DaemonLease;
 use crate::{CleanupMode, TestBootstrapSettings};
 use postgresql_embedded::PostgreSQL;
 use tracing::{info, warn};
@@ -103,12 +113,21 @@ pub struct ClusterGuard {
     pub(super) postgres: Option<PostgreSQL>,
     /// Bootstrap settings needed for shutdown operations.
     pub(super) bootstrap: TestBootstrapSettings,
+    /// Backend that started the cluster.
+    pub(super) backend: BackendKind,
     /// Whether the cluster is managed via the worker subprocess.
     pub(super) is_managed_via_worker: bool,
     /// Environment variables applied to the cluster.
     pub(super) env_vars: Vec<(String, Option<String>)>,
     /// Optional worker environment guard.
     pub(super) worker_guard: Option<ScopedEnv>,
+    /// Forwards server log lines into `tracing` when enabled.
+    pub(super) log_forwarder: Option<ServerLogForwarder>,
+    /// Releases the resources of backends without a native server (sweeping
+    /// an external server, stopping a `pglite-oxide` proxy) when dropped.
+    pub(super) attachment: Option<Attachment>,
+    /// Stops the persistent worker after shutdown has run through it.
+    pub(super) _worker_daemon: Option<DaemonLease>,
     /// Main environment guard (must drop last among env guards).
     pub(super) _env_guard: ScopedEnv,
     /// Keeps the cluster span alive for the lifetime of the guard.
@@ -157,12 +176,27 @@ impl Drop for ClusterGuard {
         if self.should_skip_shutdown() {
             return;
         }
+        let is_preserved = self.preserve_on_failure();
         self.perform_shutdown();
+        // Flushes the shutdown messages before the forwarding thread exits.
+        drop(self.log_forwarder.take());
+        if is_preserved {
+            let context = shutdown::stop_context(&self.bootstrap.settings);
+            cleanup::report_preserved_cluster(&self.bootstrap, &context);
+        }
         // Environment guards drop after this block, restoring the process state.
     }
 }
 
 impl ClusterGuard {
+    /// Returns the databases tracked for an external server, if attached to
+    /// one.
+    pub(super) fn created_databases(&self) -> Option<CreatedDatabases> {
+        self.attachment
+            .as_ref()
+            .and_then(Attachment::created_databases)
+    }
+
     /// Returns true if shutdown should be skipped.
     ///
     /// Shutdown is skipped if the cluster was already stopped (e.g., via
@@ -171,6 +205,18 @@ impl ClusterGuard {
         self.postgres.is_none() && !self.is_managed_via_worker
     }
 
+    /// Disables data removal when a [`CleanupMode::KeepOnFailure`] cluster
+    /// is dropped during a panic.
+    ///
+    /// Returns `true` when the data directory is being preserved.
+    fn preserve_on_failure(&mut self) -> bool {
+        if self.bootstrap.cleanup_mode != CleanupMode::KeepOnFailure || !std::thread::panicking() {
+            return false;
+        }
+        self.bootstrap.cleanup_mode = CleanupMode::None;
+        true
+    }
+
     /// Performs cluster shutdown, logging and delegating to the appropriate path.
     fn perform_shutdown(&mut self) {
         let context = shutdown::stop_context(&self.bootstrap.settings);
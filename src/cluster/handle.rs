@@ -38,12 +38,24 @@
 //! }
 //! ```
 
+#[cfg(feature = "async-api")]
+use std::future::Future;
+
 use super::connection::TestClusterConnection;
-use super::lifecycle::DatabaseName;
+use super::database_name::DatabaseName;
+use super::external::CreatedDatabases;
+use super::migrations::MigrationRunner;
+use super::roles::{Grant, Role, RoleOptions, TemporaryRole};
+use super::server_log::{self, ServerLogMarker};
 use super::temporary_database::TemporaryDatabase;
+#[cfg(feature = "async-api")]
+use super::test_transaction::AsyncTestTransaction;
+use super::test_transaction::TestTransaction;
+use crate::bootstrap::BackendKind;
 use crate::error::BootstrapResult;
-use crate::{TestBootstrapEnvironment, TestBootstrapSettings};
+use crate::{HbaConfig, TestBootstrapEnvironment, TestBootstrapSettings};
 use postgresql_embedded::Settings;
+use std::path::{Path, PathBuf};
 
 /// Send-safe handle providing read-only access to a running `PostgreSQL` cluster.
 ///
@@ -72,6 +84,10 @@ use postgresql_embedded::Settings;
 #[derive(Debug, Clone)]
 pub struct ClusterHandle {
     bootstrap: TestBootstrapSettings,
+    /// Backend serving the cluster.
+    backend: BackendKind,
+    /// Databases to sweep when attached to an external server.
+    created_databases: Option<CreatedDatabases>,
 }
 
 // Compile-time assertions that ClusterHandle is Send + Sync.
@@ -84,14 +100,26 @@ const _: () = {
 
 impl From<TestBootstrapSettings> for ClusterHandle {
     fn from(bootstrap: TestBootstrapSettings) -> Self {
-        Self { bootstrap }
+        Self {
+            bootstrap,
+            backend: BackendKind::PostgresqlEmbedded,
+            created_databases: None,
+        }
     }
 }
 
 impl ClusterHandle {
     /// Creates a new handle from bootstrap settings.
-    pub(super) const fn new(bootstrap: TestBootstrapSettings) -> Self {
-        Self { bootstrap }
+    pub(super) const fn new(
+        bootstrap: TestBootstrapSettings,
+        backend: BackendKind,
+        created_databases: Option<CreatedDatabases>,
+    ) -> Self {
+        Self {
+            bootstrap,
+            backend,
+            created_databases,
+        }
     }
 
     /// Returns the prepared `PostgreSQL` settings for the running cluster.
@@ -162,6 +190,111 @@ impl ClusterHandle {
     #[must_use]
     pub fn connection(&self) -> TestClusterConnection {
         TestClusterConnection::new(&self.bootstrap)
+            .on_backend(self.backend)
+            .tracking_created_databases(self.created_databases.clone())
+    }
+
+    /// Returns the path of the file the server writes its log to.
+    ///
+    /// Test clusters log to `log/server.log` inside the data directory.
+    #[must_use]
+    pub fn server_log_path(&self) -> PathBuf {
+        server_log::server_log_path(&self.bootstrap.settings)
+    }
+
+    /// Reads the complete server log.
+    ///
+    /// A log that has not been created yet reads as empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log exists but cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::TestCluster;
+    ///
+    /// let (handle, _guard) = TestCluster::new_split()?;
+    /// assert!(handle.server_log()?.contains("database system is ready"));
+    /// # Ok::<(), pg_embedded_setup_unpriv::BootstrapError>(())
+    /// ```
+    pub fn server_log(&self) -> BootstrapResult<String> {
+        server_log::read_server_log(&self.bootstrap.settings, ServerLogMarker::default())
+    }
+
+    /// Returns a marker at the current end of the server log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log exists but cannot be inspected.
+    pub fn server_log_marker(&self) -> BootstrapResult<ServerLogMarker> {
+        server_log::current_marker(&self.bootstrap.settings)
+    }
+
+    /// Reads the server log written since `marker` was taken.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log exists but cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::TestCluster;
+    ///
+    /// let (handle, _guard) = TestCluster::new_split()?;
+    /// let marker = handle.server_log_marker()?;
+    /// // ... exercise the server ...
+    /// let recent = handle.server_log_since(marker)?;
+    /// # Ok::<(), pg_embedded_setup_unpriv::BootstrapError>(())
+    /// ```
+    pub fn server_log_since(&self, marker: ServerLogMarker) -> BootstrapResult<String> {
+        server_log::read_server_log(&self.bootstrap.settings, marker)
+    }
+
+    /// Replaces `pg_hba.conf` with `hba` and reloads the server.
+    ///
+    /// A default [`HbaConfig`] leaves the file unchanged, so the call only
+    /// reloads. Rules take effect for new connections; open sessions keep
+    /// their authentication.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`HbaConfig::validate`] rejects the rules, if the
+    /// file cannot be written, if the server rejects the new rules, or if the
+    /// reload fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::{AuthMethod, HbaConfig, HbaRule, TestCluster};
+    ///
+    /// let (handle, _guard) = TestCluster::new_split()?;
+    /// handle.apply_hba(&HbaConfig {
+    ///     rules: vec![
+    ///         HbaRule::host("127.0.0.1/32", AuthMethod::ScramSha256).user("postgres"),
+    ///         HbaRule::host("127.0.0.1/32", AuthMethod::Reject),
+    ///     ],
+    ///     ..HbaConfig::default()
+    /// })?;
+    /// # Ok::<(), pg_embedded_setup_unpriv::BootstrapError>(())
+    /// ```
+    pub fn apply_hba(&self, hba: &HbaConfig) -> BootstrapResult<()> {
+        crate::bootstrap::write_pg_hba(&self.bootstrap.settings.data_dir, hba)?;
+        self.connection().reload_configuration()
+    }
+
+    /// Asks the server to re-read `postgresql.conf` and `pg_hba.conf`.
+    ///
+    /// See [`TestClusterConnection::reload_configuration`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails, if `pg_hba.conf` contains
+    /// invalid rules, or if the reload fails.
+    pub fn reload_configuration(&self) -> BootstrapResult<()> {
+        self.connection().reload_configuration()
     }
 }
 
@@ -265,6 +398,399 @@ impl ClusterHandle {
         self.connection()
             .temporary_database_from_template(name, template)
     }
+
+    /// Returns a template database built from the migrations in `dir`.
+    ///
+    /// See [`TestClusterConnection::migrated_template`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be hashed, the template cannot
+    /// be created, or `runner` fails.
+    pub fn migrated_template(
+        &self,
+        dir: impl AsRef<Path>,
+        runner: &impl MigrationRunner,
+    ) -> BootstrapResult<DatabaseName> {
+        self.connection().migrated_template(dir, runner)
+    }
+
+    /// Creates a role with the given options.
+    ///
+    /// See [`TestClusterConnection::create_role`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists or the connection fails.
+    pub fn create_role(&self, name: &str, options: &RoleOptions) -> BootstrapResult<Role> {
+        self.connection().create_role(name, options)
+    }
+
+    /// Creates a role that is dropped when the guard is dropped.
+    ///
+    /// See [`TestClusterConnection::temporary_role`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists or the connection fails.
+    pub fn temporary_role(
+        &self,
+        name: &str,
+        options: &RoleOptions,
+    ) -> BootstrapResult<TemporaryRole> {
+        self.connection().temporary_role(name, options)
+    }
+
+    /// Drops a role.
+    ///
+    /// See [`TestClusterConnection::drop_role`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role does not exist, other objects depend on it,
+    /// or the connection fails.
+    pub fn drop_role(&self, name: &str) -> BootstrapResult<()> {
+        self.connection().drop_role(name)
+    }
+
+    /// Grants privileges on an object in `database`.
+    ///
+    /// See [`TestClusterConnection::grant`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub fn grant(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        self.connection().grant(database, grant)
+    }
+
+    /// Revokes privileges on an object in `database`.
+    ///
+    /// See [`TestClusterConnection::revoke`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub fn revoke(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        self.connection().revoke(database, grant)
+    }
+
+    /// Makes `member` a member of the role `group`.
+    ///
+    /// See [`TestClusterConnection::grant_role`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either role does not exist or the connection fails.
+    pub fn grant_role(&self, group: &str, member: &str) -> BootstrapResult<()> {
+        self.connection().grant_role(group, member)
+    }
+
+    /// Removes `member` from the role `group`.
+    ///
+    /// See [`TestClusterConnection::revoke_role`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either role does not exist or the connection fails.
+    pub fn revoke_role(&self, group: &str, member: &str) -> BootstrapResult<()> {
+        self.connection().revoke_role(group, member)
+    }
+
+    /// Builds a URL for `database` that authenticates as `role`.
+    ///
+    /// See [`TestClusterConnection::database_url_as`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `.pgpass` file cannot be updated.
+    pub fn database_url_as(&self, role: &Role, database: &str) -> BootstrapResult<String> {
+        self.connection().database_url_as(role, database)
+    }
+
+    /// Opens a connection to `database` inside a transaction that is rolled
+    /// back when the returned guard drops.
+    ///
+    /// See [`TestClusterConnection::test_transaction`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or the transaction cannot be
+    /// started.
+    pub fn test_transaction(&self, database: &str) -> BootstrapResult<TestTransaction> {
+        self.connection().test_transaction(database)
+    }
+
+    /// Runs `f` inside a transaction on `database` that is always rolled
+    /// back.
+    ///
+    /// See [`TestClusterConnection::with_test_transaction`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `f`, or an error if the connection fails or the
+    /// transaction cannot be started or rolled back.
+    pub fn with_test_transaction<T, F>(&self, database: &str, f: F) -> BootstrapResult<T>
+    where
+        F: FnOnce(&mut postgres::Transaction<'_>) -> BootstrapResult<T>,
+    {
+        self.connection().with_test_transaction(database, f)
+    }
+
+    /// Establishes a Diesel connection to `database` inside a test
+    /// transaction.
+    ///
+    /// See [`TestClusterConnection::diesel_test_transaction`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Diesel cannot connect or begin the transaction.
+    #[cfg(feature = "diesel-support")]
+    pub fn diesel_test_transaction(&self, database: &str) -> BootstrapResult<diesel::PgConnection> {
+        self.connection().diesel_test_transaction(database)
+    }
+}
+
+// Async delegation methods that forward to TestClusterConnection.
+#[cfg(feature = "async-api")]
+impl ClusterHandle {
+    /// Creates a new database without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::create_database_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database already exists or the connection fails.
+    pub async fn create_database_async(
+        &self,
+        name: impl Into<DatabaseName>,
+    ) -> BootstrapResult<()> {
+        self.connection().create_database_async(name).await
+    }
+
+    /// Creates a new database from a template without blocking the caller's
+    /// runtime.
+    ///
+    /// See [`TestClusterConnection::create_database_from_template_async`] for
+    /// details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target database already exists, the template does
+    /// not exist, or the connection fails.
+    pub async fn create_database_from_template_async(
+        &self,
+        name: impl Into<DatabaseName>,
+        template: impl Into<DatabaseName>,
+    ) -> BootstrapResult<()> {
+        self.connection()
+            .create_database_from_template_async(name, template)
+            .await
+    }
+
+    /// Drops an existing database without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::drop_database_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database does not exist or the connection fails.
+    pub async fn drop_database_async(&self, name: impl Into<DatabaseName>) -> BootstrapResult<()> {
+        self.connection().drop_database_async(name).await
+    }
+
+    /// Replaces `pg_hba.conf` and reloads the server without blocking the
+    /// caller's runtime.
+    ///
+    /// See [`ClusterHandle::apply_hba`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if [`HbaConfig::validate`] rejects the rules, if the
+    /// file cannot be written, if the server rejects the new rules, or if the
+    /// reload fails.
+    pub async fn apply_hba_async(&self, hba: &HbaConfig) -> BootstrapResult<()> {
+        crate::bootstrap::write_pg_hba(&self.bootstrap.settings.data_dir, hba)?;
+        self.connection().reload_configuration_async().await
+    }
+
+    /// Asks the server to re-read its configuration without blocking the
+    /// caller's runtime.
+    ///
+    /// See [`TestClusterConnection::reload_configuration_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails, if `pg_hba.conf` contains
+    /// invalid rules, or if the reload fails.
+    pub async fn reload_configuration_async(&self) -> BootstrapResult<()> {
+        self.connection().reload_configuration_async().await
+    }
+
+    /// Checks whether a database exists without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::database_exists_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails.
+    pub async fn database_exists_async(
+        &self,
+        name: impl Into<DatabaseName>,
+    ) -> BootstrapResult<bool> {
+        self.connection().database_exists_async(name).await
+    }
+
+    /// Ensures a template database exists, running an async setup closure if
+    /// needed.
+    ///
+    /// See [`TestClusterConnection::ensure_template_exists_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if database creation fails or if `setup_fn` resolves to
+    /// an error.
+    pub async fn ensure_template_exists_async<F, Fut>(
+        &self,
+        name: impl Into<DatabaseName>,
+        setup_fn: F,
+    ) -> BootstrapResult<()>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = BootstrapResult<()>>,
+    {
+        self.connection()
+            .ensure_template_exists_async(name, setup_fn)
+            .await
+    }
+
+    /// Creates a temporary database without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::temporary_database_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database already exists or the connection fails.
+    pub async fn temporary_database_async(
+        &self,
+        name: impl Into<DatabaseName>,
+    ) -> BootstrapResult<TemporaryDatabase> {
+        self.connection().temporary_database_async(name).await
+    }
+
+    /// Creates a temporary database from a template without blocking the
+    /// caller's runtime.
+    ///
+    /// See [`TestClusterConnection::temporary_database_from_template_async`] for
+    /// details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target database already exists, the template does
+    /// not exist, or the connection fails.
+    pub async fn temporary_database_from_template_async(
+        &self,
+        name: impl Into<DatabaseName>,
+        template: impl Into<DatabaseName>,
+    ) -> BootstrapResult<TemporaryDatabase> {
+        self.connection()
+            .temporary_database_from_template_async(name, template)
+            .await
+    }
+
+    /// Creates a role without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::create_role_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists or the connection fails.
+    pub async fn create_role_async(
+        &self,
+        name: &str,
+        options: &RoleOptions,
+    ) -> BootstrapResult<Role> {
+        self.connection().create_role_async(name, options).await
+    }
+
+    /// Creates a temporary role without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::temporary_role_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists or the connection fails.
+    pub async fn temporary_role_async(
+        &self,
+        name: &str,
+        options: &RoleOptions,
+    ) -> BootstrapResult<TemporaryRole> {
+        self.connection().temporary_role_async(name, options).await
+    }
+
+    /// Grants privileges on an object without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::grant_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub async fn grant_async(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        self.connection().grant_async(database, grant).await
+    }
+
+    /// Revokes privileges on an object without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::revoke_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub async fn revoke_async(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        self.connection().revoke_async(database, grant).await
+    }
+
+    /// Opens a `tokio-postgres` connection to `database` inside a
+    /// transaction that is rolled back when the returned guard drops.
+    ///
+    /// See [`TestClusterConnection::test_transaction_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or the transaction cannot be
+    /// started.
+    pub async fn test_transaction_async(
+        &self,
+        database: &str,
+    ) -> BootstrapResult<AsyncTestTransaction> {
+        self.connection().test_transaction_async(database).await
+    }
+
+    /// Runs the async closure `f` inside a transaction on `database` that is
+    /// always rolled back, without blocking the caller's runtime.
+    ///
+    /// See [`TestClusterConnection::with_test_transaction_async`] for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `f`, or an error if the connection fails or the
+    /// transaction cannot be started or rolled back.
+    pub async fn with_test_transaction_async<T, F>(
+        &self,
+        database: &str,
+        f: F,
+    ) -> BootstrapResult<T>
+    where
+        F: AsyncFnOnce(&mut tokio_postgres::Transaction<'_>) -> BootstrapResult<T>,
+    {
+        self.connection()
+            .with_test_transaction_async(database, f)
+            .await
+    }
 }
 
 // Process-exit shutdown hook registration.
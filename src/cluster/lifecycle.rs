@@ -2,6 +2,9 @@
 //!
 //! This module provides methods for creating, dropping, and managing databases
 //! on a running `PostgreSQL` cluster.
+//!
+//! Backends serving a single database (`pglite_oxide`) refuse the operations
+//! that create or drop databases before connecting.
 
 use std::sync::{Mutex, OnceLock};
 
@@ -10,67 +13,38 @@ use dashmap::DashMap;
 use tracing::info_span;
 
 use super::connection::{TestClusterConnection, escape_identifier};
+use super::database_name::DatabaseName;
+use super::template_lock::TemplateAdvisoryLock;
 use super::temporary_database::TemporaryDatabase;
 use crate::error::BootstrapResult;
 
-/// A strongly-typed database name for use with lifecycle operations.
-///
-/// This newtype provides type safety for database name parameters, preventing
-/// accidental misuse of raw strings while still allowing convenient conversion
-/// from string literals.
-///
-/// # Examples
-///
-/// ```
-/// use pg_embedded_setup_unpriv::DatabaseName;
-///
-/// // From string literal
-/// let name: DatabaseName = "my_database".into();
-/// assert_eq!(name.as_str(), "my_database");
-///
-/// // From owned String
-/// let name: DatabaseName = String::from("another_db").into();
-/// assert_eq!(name.as_str(), "another_db");
-/// ```
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct DatabaseName(String);
-
-impl DatabaseName {
-    /// Creates a new `DatabaseName` from a string.
-    #[must_use]
-    pub fn new(name: impl Into<String>) -> Self {
-        Self(name.into())
-    }
-
-    /// Returns the database name as a string slice.
-    #[must_use]
-    pub fn as_str(&self) -> &str {
-        &self.0
-    }
-}
-
-impl AsRef<str> for DatabaseName {
-    fn as_ref(&self) -> &str {
-        &self.0
-    }
-}
-
-impl From<&str> for DatabaseName {
-    fn from(s: &str) -> Self {
-        Self(s.to_owned())
-    }
-}
+/// Lists the `pg_hba.conf` lines the server could not parse.
+pub(super) const HBA_ERRORS_QUERY: &str =
+    "SELECT line_number, error FROM pg_hba_file_rules WHERE error IS NOT NULL";
 
-impl From<String> for DatabaseName {
-    fn from(s: String) -> Self {
-        Self(s)
+/// Turns `pg_hba_file_rules` errors into a bootstrap error.
+pub(super) fn reject_hba_errors(errors: &[(Option<i32>, String)]) -> BootstrapResult<()> {
+    if errors.is_empty() {
+        return Ok(());
     }
+    let details: Vec<String> = errors
+        .iter()
+        .map(|(line, error)| {
+            line.map_or_else(|| error.clone(), |number| format!("line {number}: {error}"))
+        })
+        .collect();
+    Err(crate::error::BootstrapError::from(color_eyre::eyre::eyre!(
+        "pg_hba.conf contains invalid rules, so the server keeps its previous rules:\n{}",
+        details.join("\n")
+    )))
 }
 
 /// Global per-template locks to prevent concurrent template creation.
 ///
-/// Uses a `DashMap` to allow lock-free reads and concurrent access to
-/// different templates while serialising access to the same template.
+/// These serialise threads within one process before they contend for the
+/// cross-process advisory lock. Uses a `DashMap` to allow lock-free reads and
+/// concurrent access to different templates while serialising access to the
+/// same template.
 static TEMPLATE_LOCKS: OnceLock<DashMap<String, Mutex<()>>> = OnceLock::new();
 
 fn template_locks() -> &'static DashMap<String, Mutex<()>> {
@@ -120,8 +94,12 @@ impl TestClusterConnection {
     /// ```
     pub fn create_database(&self, name: impl Into<DatabaseName>) -> BootstrapResult<()> {
         let db_name = name.into();
+        self.require_multiple_databases("create_database")?;
         let _span = info_span!("create_database", db = %db_name.as_str()).entered();
-        self.execute_ddl_command("CREATE DATABASE {}", db_name.as_str(), "create")
+        db_name.validate()?;
+        self.execute_ddl_command("CREATE DATABASE {}", db_name.as_str(), "create")?;
+        self.record_created(db_name.as_str());
+        Ok(())
     }
 
     /// Creates a new database by cloning an existing template.
@@ -162,9 +140,11 @@ impl TestClusterConnection {
         template: impl Into<DatabaseName>,
     ) -> BootstrapResult<()> {
         let db_name = name.into();
+        self.require_multiple_databases("create_database_from_template")?;
         let template_name = template.into();
         let _span =
             info_span!("create_database_from_template", db = %db_name.as_str(), template = %template_name.as_str()).entered();
+        db_name.validate()?;
         let mut client = self.admin_client()?;
         let escaped_name = escape_identifier(db_name.as_str());
         let escaped_template = escape_identifier(template_name.as_str());
@@ -176,7 +156,9 @@ impl TestClusterConnection {
                 db_name.as_str(),
                 template_name.as_str()
             ))
-            .map_err(crate::error::BootstrapError::from)
+            .map_err(crate::error::BootstrapError::from)?;
+        self.record_created(db_name.as_str());
+        Ok(())
     }
 
     /// Drops an existing database.
@@ -203,8 +185,11 @@ impl TestClusterConnection {
     /// ```
     pub fn drop_database(&self, name: impl Into<DatabaseName>) -> BootstrapResult<()> {
         let db_name = name.into();
+        self.require_multiple_databases("drop_database")?;
         let _span = info_span!("drop_database", db = %db_name.as_str()).entered();
-        self.execute_ddl_command("DROP DATABASE {}", db_name.as_str(), "drop")
+        self.execute_ddl_command("DROP DATABASE {}", db_name.as_str(), "drop")?;
+        self.forget_created(db_name.as_str());
+        Ok(())
     }
 
     /// Checks whether a database with the given name exists.
@@ -238,16 +223,57 @@ impl TestClusterConnection {
         Ok(row.get(0))
     }
 
+    /// Asks the server to re-read `postgresql.conf` and `pg_hba.conf`.
+    ///
+    /// The `pg_hba.conf` on disk is checked through `pg_hba_file_rules` first.
+    /// `PostgreSQL` keeps its current rules when the file has errors, so those
+    /// errors are reported instead of reloading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails, if `pg_hba.conf` contains
+    /// invalid rules, or if the reload cannot be signalled.
+    pub fn reload_configuration(&self) -> BootstrapResult<()> {
+        let _span = info_span!("reload_configuration").entered();
+        let mut client = self.admin_client()?;
+        let errors: Vec<(Option<i32>, String)> = client
+            .query(HBA_ERRORS_QUERY, &[])
+            .wrap_err("failed to inspect pg_hba_file_rules")
+            .map_err(crate::error::BootstrapError::from)?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        reject_hba_errors(&errors)?;
+        client
+            .execute("SELECT pg_reload_conf()", &[])
+            .wrap_err("failed to reload server configuration")
+            .map_err(crate::error::BootstrapError::from)?;
+        Ok(())
+    }
+
     /// Ensures a template database exists, creating it if necessary.
     ///
     /// Uses per-template locking to prevent concurrent creation attempts when
-    /// multiple tests race to initialise the same template. The `setup_fn` is
-    /// called only if the template does not already exist.
+    /// multiple tests race to initialise the same template. Creation is
+    /// serialised both within the process and across processes sharing the
+    /// cluster via a `PostgreSQL` advisory lock, so suites run under
+    /// `cargo nextest` do not collide.
+    ///
+    /// The `setup_fn` is called only if the template does not exist yet. It
+    /// populates a staging database, whose name it receives, and the staging
+    /// database is renamed to the template name only once `setup_fn`
+    /// succeeds, terminating any sessions still connected to it. A setup that
+    /// fails or is interrupted, even by a crash, therefore never leaves a
+    /// database under the template name; the next call drops the staging
+    /// database and starts again. An existing database without a readiness
+    /// marker, such as one created outside this method or by an older
+    /// release, is used as it is.
     ///
     /// # Errors
     ///
-    /// Returns an error if database creation fails or if `setup_fn` returns
-    /// an error.
+    /// Returns an error if the advisory lock cannot be acquired, if database
+    /// creation, renaming, or removal of an incomplete template fails, or if
+    /// `setup_fn` returns an error.
     ///
     /// # Examples
     ///
@@ -259,7 +285,8 @@ impl TestClusterConnection {
     ///
     /// // Ensure template exists, running migrations if needed
     /// cluster.connection().ensure_template_exists("my_template", |db_name| {
-    ///     // Run migrations on the newly created template database
+    ///     // Run migrations on `db_name`, the staging database that becomes
+    ///     // the template once this closure succeeds
     ///     // e.g., diesel::migration::run(&mut conn)?;
     ///     Ok(())
     /// })?;
@@ -278,6 +305,7 @@ impl TestClusterConnection {
         F: FnOnce(&str) -> BootstrapResult<()>,
     {
         let db_name = name.into();
+        self.require_multiple_databases("ensure_template_exists")?;
         let _span = info_span!("ensure_template_exists", template = %db_name.as_str()).entered();
         let locks = template_locks();
         let lock = locks
@@ -286,10 +314,12 @@ impl TestClusterConnection {
         let _guard = lock
             .lock()
             .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut advisory = TemplateAdvisoryLock::acquire(self.admin_client()?, db_name.as_str())?;
 
-        if !self.database_exists(db_name.as_str())? {
-            self.create_database(db_name.as_str())?;
-            setup_fn(db_name.as_str())?;
+        if advisory.needs_setup()? {
+            let staging = advisory.create_staging()?;
+            setup_fn(&staging)?;
+            advisory.publish()?;
         }
         Ok(())
     }
@@ -328,8 +358,7 @@ impl TestClusterConnection {
         self.create_database(db_name.as_str())?;
         Ok(TemporaryDatabase::new(
             db_name.as_str().to_owned(),
-            self.database_url("postgres"),
-            self.database_url(db_name.as_str()),
+            self.metadata(),
         ))
     }
 
@@ -377,8 +406,7 @@ impl TestClusterConnection {
         self.create_database_from_template(db_name.as_str(), template_name.as_str())?;
         Ok(TemporaryDatabase::new(
             db_name.as_str().to_owned(),
-            self.database_url("postgres"),
-            self.database_url(db_name.as_str()),
+            self.metadata(),
         ))
     }
 }
@@ -0,0 +1,380 @@
+//! Async database lifecycle operations for `TestClusterConnection`.
+//!
+//! These mirror the blocking methods in `lifecycle.rs` but use `tokio-postgres`
+//! on the caller's runtime, so they are safe to call from `#[tokio::test]`
+//! without nesting runtimes or stalling the executor. Enable the `async-api`
+//! feature to use them.
+
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+
+use color_eyre::eyre::WrapErr;
+use dashmap::DashMap;
+use tracing::{Instrument, info_span};
+
+use super::connection::{TestClusterConnection, escape_identifier};
+use super::database_name::DatabaseName;
+use super::lifecycle::{HBA_ERRORS_QUERY, reject_hba_errors};
+use super::template_lock::AsyncTemplateAdvisoryLock;
+use super::temporary_database::TemporaryDatabase;
+use crate::error::BootstrapResult;
+
+/// Per-template async locks serialising template creation within a process.
+///
+/// Entries are reference counted so the `DashMap` shard lock is never held
+/// across an `.await`.
+static ASYNC_TEMPLATE_LOCKS: OnceLock<DashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+    OnceLock::new();
+
+fn async_template_lock(name: &str) -> Arc<tokio::sync::Mutex<()>> {
+    ASYNC_TEMPLATE_LOCKS
+        .get_or_init(DashMap::new)
+        .entry(name.to_owned())
+        .or_default()
+        .clone()
+}
+
+impl TestClusterConnection {
+    /// Executes a DDL statement against the `postgres` database.
+    async fn execute_ddl_async(&self, sql: &str, context: String) -> BootstrapResult<()> {
+        let client = self.admin_client_async().await?;
+        client
+            .batch_execute(sql)
+            .await
+            .wrap_err(context)
+            .map_err(crate::error::BootstrapError::from)
+    }
+
+    /// Creates a new database with the given name without blocking the
+    /// caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::create_database`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database already exists or if the connection
+    /// fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::TestCluster;
+    ///
+    /// # async fn demo() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+    /// let cluster = TestCluster::start_async().await?;
+    /// cluster.connection().create_database_async("my_test_db").await?;
+    /// cluster.stop_async().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_database_async(
+        &self,
+        name: impl Into<DatabaseName>,
+    ) -> BootstrapResult<()> {
+        let db_name = name.into();
+        self.require_multiple_databases("create_database_async")?;
+        db_name.validate()?;
+        let span = info_span!("create_database", db = %db_name.as_str(), async_mode = true);
+        let escaped = escape_identifier(db_name.as_str());
+        self.execute_ddl_async(
+            &format!("CREATE DATABASE \"{escaped}\""),
+            format!("failed to create database '{}'", db_name.as_str()),
+        )
+        .instrument(span)
+        .await?;
+        self.record_created(db_name.as_str());
+        Ok(())
+    }
+
+    /// Creates a new database by cloning an existing template without
+    /// blocking the caller's runtime.
+    ///
+    /// Async counterpart of
+    /// [`TestClusterConnection::create_database_from_template`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target database already exists, the template
+    /// does not exist, the template has active connections, or the connection
+    /// fails.
+    pub async fn create_database_from_template_async(
+        &self,
+        name: impl Into<DatabaseName>,
+        template: impl Into<DatabaseName>,
+    ) -> BootstrapResult<()> {
+        let db_name = name.into();
+        self.require_multiple_databases("create_database_from_template_async")?;
+        let template_name = template.into();
+        db_name.validate()?;
+        let span = info_span!(
+            "create_database_from_template",
+            db = %db_name.as_str(),
+            template = %template_name.as_str(),
+            async_mode = true
+        );
+        let escaped_name = escape_identifier(db_name.as_str());
+        let escaped_template = escape_identifier(template_name.as_str());
+        self.execute_ddl_async(
+            &format!("CREATE DATABASE \"{escaped_name}\" TEMPLATE \"{escaped_template}\""),
+            format!(
+                "failed to create database '{}' from template '{}'",
+                db_name.as_str(),
+                template_name.as_str()
+            ),
+        )
+        .instrument(span)
+        .await?;
+        self.record_created(db_name.as_str());
+        Ok(())
+    }
+
+    /// Drops an existing database without blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::drop_database`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database does not exist, has active
+    /// connections, or if the connection fails.
+    pub async fn drop_database_async(&self, name: impl Into<DatabaseName>) -> BootstrapResult<()> {
+        let db_name = name.into();
+        self.require_multiple_databases("drop_database_async")?;
+        let span = info_span!("drop_database", db = %db_name.as_str(), async_mode = true);
+        let escaped = escape_identifier(db_name.as_str());
+        self.execute_ddl_async(
+            &format!("DROP DATABASE \"{escaped}\""),
+            format!("failed to drop database '{}'", db_name.as_str()),
+        )
+        .instrument(span)
+        .await?;
+        self.forget_created(db_name.as_str());
+        Ok(())
+    }
+
+    /// Checks whether a database with the given name exists without blocking
+    /// the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::database_exists`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails.
+    pub async fn database_exists_async(
+        &self,
+        name: impl Into<DatabaseName>,
+    ) -> BootstrapResult<bool> {
+        let db_name = name.into();
+        let client = self.admin_client_async().await?;
+        let row = client
+            .query_one(
+                "SELECT EXISTS(SELECT 1 FROM pg_database WHERE datname = $1)",
+                &[&db_name.as_str()],
+            )
+            .await
+            .wrap_err("failed to query pg_database")
+            .map_err(crate::error::BootstrapError::from)?;
+        Ok(row.get(0))
+    }
+
+    /// Asks the server to re-read `postgresql.conf` and `pg_hba.conf` without
+    /// blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::reload_configuration`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails, if `pg_hba.conf` contains
+    /// invalid rules, or if the reload cannot be signalled.
+    pub async fn reload_configuration_async(&self) -> BootstrapResult<()> {
+        let client = self.admin_client_async().await?;
+        let errors: Vec<(Option<i32>, String)> = client
+            .query(HBA_ERRORS_QUERY, &[])
+            .await
+            .wrap_err("failed to inspect pg_hba_file_rules")
+            .map_err(crate::error::BootstrapError::from)?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+        reject_hba_errors(&errors)?;
+        client
+            .execute("SELECT pg_reload_conf()", &[])
+            .await
+            .wrap_err("failed to reload server configuration")
+            .map_err(crate::error::BootstrapError::from)?;
+        Ok(())
+    }
+
+    /// Ensures a template database exists, running an async setup closure if
+    /// it must be created.
+    ///
+    /// Async counterpart of [`TestClusterConnection::ensure_template_exists`]
+    /// with the same locking and staging semantics. The closure receives
+    /// the name of the staging database to populate and returns a future, so
+    /// migrations can run on an async driver.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the advisory lock cannot be acquired, if database
+    /// creation, renaming, or removal of an incomplete template fails, or if
+    /// the future
+    /// returned by `setup_fn` resolves to an error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::TestCluster;
+    ///
+    /// # async fn demo() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+    /// let cluster = TestCluster::start_async().await?;
+    /// let connection = cluster.connection();
+    ///
+    /// connection
+    ///     .ensure_template_exists_async("my_template", |db_name| async move {
+    ///         // Run migrations against `db_name` with an async driver.
+    ///         let _ = db_name;
+    ///         Ok(())
+    ///     })
+    ///     .await?;
+    ///
+    /// let temp_db = connection
+    ///     .temporary_database_from_template_async("test_db", "my_template")
+    ///     .await?;
+    /// temp_db.drop_database_async().await?;
+    /// cluster.stop_async().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn ensure_template_exists_async<F, Fut>(
+        &self,
+        name: impl Into<DatabaseName>,
+        setup_fn: F,
+    ) -> BootstrapResult<()>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = BootstrapResult<()>>,
+    {
+        let db_name = name.into();
+        self.require_multiple_databases("ensure_template_exists_async")?;
+        let span =
+            info_span!("ensure_template_exists", template = %db_name.as_str(), async_mode = true);
+        async {
+            let lock = async_template_lock(db_name.as_str());
+            let _guard = lock.lock().await;
+            let advisory = AsyncTemplateAdvisoryLock::acquire(
+                self.admin_client_async().await?,
+                db_name.as_str(),
+            )
+            .await?;
+
+            if advisory.needs_setup().await? {
+                let staging = advisory.create_staging().await?;
+                setup_fn(staging).await?;
+                advisory.publish().await?;
+            }
+            advisory.release().await;
+            Ok(())
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Creates a temporary database without blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::temporary_database`].
+    /// Prefer [`TemporaryDatabase::drop_database_async`] for cleanup; if the
+    /// guard is simply dropped, the blocking cleanup runs on a helper thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database already exists or if the connection
+    /// fails.
+    pub async fn temporary_database_async(
+        &self,
+        name: impl Into<DatabaseName>,
+    ) -> BootstrapResult<TemporaryDatabase> {
+        let db_name = name.into();
+        self.create_database_async(db_name.as_str()).await?;
+        Ok(TemporaryDatabase::new(
+            db_name.as_str().to_owned(),
+            self.metadata(),
+        ))
+    }
+
+    /// Creates a temporary database from a template without blocking the
+    /// caller's runtime.
+    ///
+    /// Async counterpart of
+    /// [`TestClusterConnection::temporary_database_from_template`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target database already exists, the template
+    /// does not exist, the template has active connections, or if the
+    /// connection fails.
+    pub async fn temporary_database_from_template_async(
+        &self,
+        name: impl Into<DatabaseName>,
+        template: impl Into<DatabaseName>,
+    ) -> BootstrapResult<TemporaryDatabase> {
+        let db_name = name.into();
+        let template_name = template.into();
+        self.create_database_from_template_async(db_name.as_str(), template_name.as_str())
+            .await?;
+        Ok(TemporaryDatabase::new(
+            db_name.as_str().to_owned(),
+            self.metadata(),
+        ))
+    }
+
+    /// Ensures a template database exists, applying sqlx migrations when it
+    /// must be created.
+    ///
+    /// Wraps [`TestClusterConnection::ensure_template_exists_async`] with a
+    /// setup step that runs `migrator` (typically produced by
+    /// `sqlx::migrate!()`) against the new template, so every clone starts
+    /// from the migrated schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if template creation fails, if sqlx cannot connect to
+    /// the template, or if a migration fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::TestCluster;
+    ///
+    /// # async fn demo() -> color_eyre::eyre::Result<()> {
+    /// // In application code this is usually `sqlx::migrate!("./migrations")`.
+    /// let migrator = sqlx::migrate::Migrator::new(std::path::Path::new("./migrations")).await?;
+    ///
+    /// let cluster = TestCluster::start_async().await?;
+    /// let connection = cluster.connection();
+    /// connection
+    ///     .ensure_template_with_sqlx_migrations("migrated_template", &migrator)
+    ///     .await?;
+    /// let temp_db = connection
+    ///     .temporary_database_from_template_async("test_db", "migrated_template")
+    ///     .await?;
+    /// let pool = temp_db.sqlx_pool().await?;
+    /// # drop(pool);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "sqlx-support")]
+    pub async fn ensure_template_with_sqlx_migrations(
+        &self,
+        name: impl Into<DatabaseName>,
+        migrator: &sqlx::migrate::Migrator,
+    ) -> BootstrapResult<()> {
+        self.ensure_template_exists_async(name, |db_name| async move {
+            let mut conn = self.sqlx_connection(&db_name).await?;
+            migrator
+                .run(&mut conn)
+                .await
+                .wrap_err(format!("failed to apply sqlx migrations to '{db_name}'"))
+                .map_err(crate::error::BootstrapError::from)
+        })
+        .await
+    }
+}
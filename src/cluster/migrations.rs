@@ -0,0 +1,495 @@
+//! Migration-versioned template databases.
+//!
+//! [`TestClusterConnection::migrated_template`] names a template after the
+//! hash of a migrations directory, builds it once with a [`MigrationRunner`],
+//! flags it with `datistemplate`, and drops templates built from earlier
+//! versions of the same directory. Changing a migration therefore produces a
+//! fresh template on the next run without any manual cache invalidation.
+
+use std::path::{Path, PathBuf};
+
+use cap_std::{ambient_authority, fs::Dir};
+use color_eyre::eyre::{WrapErr, eyre};
+use sha2::{Digest, Sha256};
+use tracing::{info, info_span, warn};
+
+use super::connection::TestClusterConnection;
+use super::database_name::DatabaseName;
+use super::template_lock::{TemplateAdvisoryLock, drop_template_database, set_template_flag};
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+use crate::test_support::hash_directory;
+
+/// Prefix shared by every template created by `migrated_template`.
+const MIGRATED_TEMPLATE_PREFIX: &str = "tpl_migrated_";
+
+/// Hex digits of the directory path hash identifying a template family.
+const DIR_KEY_LEN: usize = 8;
+
+/// Hex digits of the contents hash identifying one version of a family.
+const CONTENT_KEY_LEN: usize = 16;
+
+/// Applies the migrations in a directory to a freshly created template.
+///
+/// Implementations receive the connection helpers for the cluster, the name
+/// of the template database, and the migrations directory. They must close
+/// any connections to the template before returning, because `PostgreSQL`
+/// cannot clone a database with active sessions.
+///
+/// Closures with the same signature implement the trait, which makes it easy
+/// to plug in tools without a built-in runner, such as refinery:
+///
+/// ```ignore
+/// use pg_embedded_setup_unpriv::{BootstrapError, TestCluster, TestClusterConnection};
+///
+/// mod embedded {
+///     refinery::embed_migrations!("migrations");
+/// }
+///
+/// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+/// let cluster = TestCluster::new()?;
+/// let refinery_runner = |conn: &TestClusterConnection, db: &str, _dir: &std::path::Path| {
+///     let mut client = postgres::Client::connect(&conn.database_url(db), postgres::NoTls)
+///         .map_err(|err| BootstrapError::from(color_eyre::eyre::eyre!(err)))?;
+///     embedded::migrations::runner()
+///         .run(&mut client)
+///         .map_err(|err| BootstrapError::from(color_eyre::eyre::eyre!(err)))?;
+///     Ok(())
+/// };
+/// let template = cluster.migrated_template("migrations", &refinery_runner)?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait MigrationRunner {
+    /// Applies the migrations in `dir` to `database`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a migration cannot be read or applied.
+    fn run(
+        &self,
+        connection: &TestClusterConnection,
+        database: &str,
+        dir: &Path,
+    ) -> BootstrapResult<()>;
+}
+
+impl<F> MigrationRunner for F
+where
+    F: Fn(&TestClusterConnection, &str, &Path) -> BootstrapResult<()>,
+{
+    fn run(
+        &self,
+        connection: &TestClusterConnection,
+        database: &str,
+        dir: &Path,
+    ) -> BootstrapResult<()> {
+        self(connection, database, dir)
+    }
+}
+
+/// Runs plain SQL migration scripts in name order.
+///
+/// Each `*.sql` file directly inside the directory is a migration, as is the
+/// `up.sql` of each subdirectory (the Diesel layout). Scripts are applied in
+/// lexicographic order of their file or directory names, each inside its own
+/// transaction, so zero-padded or timestamped prefixes give the expected
+/// order. Hidden entries and `down.sql` files are ignored.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pg_embedded_setup_unpriv::{SqlFileRunner, TestCluster};
+///
+/// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+/// let cluster = TestCluster::new()?;
+/// let template = cluster.migrated_template("migrations", &SqlFileRunner)?;
+/// let db = cluster.temporary_database_from_template("my_test", template.as_str())?;
+/// # drop(db);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SqlFileRunner;
+
+impl MigrationRunner for SqlFileRunner {
+    fn run(
+        &self,
+        connection: &TestClusterConnection,
+        database: &str,
+        dir: &Path,
+    ) -> BootstrapResult<()> {
+        let scripts = discover_scripts(dir)?;
+        let mut client = super::connection::connect(&connection.database_url(database))
+            .wrap_err(format!("failed to connect to template '{database}'"))
+            .map_err(BootstrapError::from)?;
+        for script in scripts {
+            let sql = script.read(dir)?;
+            let mut tx = client
+                .transaction()
+                .wrap_err(format!("failed to open transaction on '{database}'"))
+                .map_err(BootstrapError::from)?;
+            tx.batch_execute(&sql)
+                .and_then(|()| tx.commit())
+                .wrap_err(format!("failed to apply migration '{}'", script.name))
+                .map_err(BootstrapError::from)?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies Diesel-layout migrations and records them as Diesel would.
+///
+/// Runs each `<version>_<name>/up.sql` in order inside a transaction and
+/// inserts its version into `__diesel_schema_migrations`, so Diesel's own
+/// migration harness treats the cloned databases as already migrated.
+#[cfg(feature = "diesel-support")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DieselMigrationRunner;
+
+#[cfg(feature = "diesel-support")]
+impl MigrationRunner for DieselMigrationRunner {
+    fn run(
+        &self,
+        connection: &TestClusterConnection,
+        database: &str,
+        dir: &Path,
+    ) -> BootstrapResult<()> {
+        use diesel::connection::SimpleConnection;
+        use diesel::{Connection, RunQueryDsl};
+
+        let scripts = discover_scripts(dir)?;
+        let mut conn = connection.diesel_connection(database)?;
+        conn.batch_execute(
+            "CREATE TABLE IF NOT EXISTS __diesel_schema_migrations (\
+             version VARCHAR(50) PRIMARY KEY NOT NULL, \
+             run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+        )
+        .wrap_err("failed to create __diesel_schema_migrations")
+        .map_err(BootstrapError::from)?;
+        for script in scripts {
+            let sql = script.read(dir)?;
+            let version = diesel_version(&script.name);
+            conn.transaction::<_, diesel::result::Error, _>(|tx| {
+                tx.batch_execute(&sql)?;
+                diesel::sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES ($1)")
+                    .bind::<diesel::sql_types::Text, _>(&version)
+                    .execute(tx)?;
+                Ok(())
+            })
+            .wrap_err(format!("failed to apply migration '{}'", script.name))
+            .map_err(BootstrapError::from)?;
+        }
+        Ok(())
+    }
+}
+
+/// Derives Diesel's migration version from a migration directory name.
+#[cfg(feature = "diesel-support")]
+fn diesel_version(name: &str) -> String {
+    name.split('_').next().unwrap_or(name).replace('-', "")
+}
+
+/// Runs sqlx migrations from the directory at runtime.
+///
+/// Equivalent to `sqlx::migrate!(dir).run(...)`, but loads the scripts when
+/// the template is built, so the directory hash and the applied migrations
+/// always agree. sqlx records applied versions in `_sqlx_migrations` as
+/// usual.
+#[cfg(feature = "sqlx-support")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SqlxMigrationRunner;
+
+#[cfg(feature = "sqlx-support")]
+impl MigrationRunner for SqlxMigrationRunner {
+    fn run(
+        &self,
+        connection: &TestClusterConnection,
+        database: &str,
+        dir: &Path,
+    ) -> BootstrapResult<()> {
+        super::runtime::run_with_runtime("sqlx-migrations", |runtime| {
+            runtime.block_on(async {
+                let migrator = sqlx::migrate::Migrator::new(dir)
+                    .await
+                    .wrap_err(format!(
+                        "failed to load sqlx migrations from '{}'",
+                        dir.display()
+                    ))
+                    .map_err(BootstrapError::from)?;
+                let mut conn = connection.sqlx_connection(database).await?;
+                migrator
+                    .run(&mut conn)
+                    .await
+                    .wrap_err(format!("failed to apply sqlx migrations to '{database}'"))
+                    .map_err(BootstrapError::from)
+            })
+        })
+    }
+}
+
+/// A migration script discovered in a migrations directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MigrationScript {
+    /// File or directory name that orders the script.
+    name: String,
+    /// Script path relative to the migrations directory.
+    relative: PathBuf,
+}
+
+impl MigrationScript {
+    fn read(&self, dir: &Path) -> BootstrapResult<String> {
+        let path = dir.join(&self.relative);
+        std::fs::read_to_string(&path)
+            .wrap_err(format!("failed to read migration '{}'", path.display()))
+            .map_err(BootstrapError::from)
+    }
+}
+
+/// Lists migration scripts in application order.
+fn discover_scripts(dir: &Path) -> BootstrapResult<Vec<MigrationScript>> {
+    let root = Dir::open_ambient_dir(dir, ambient_authority()).map_err(|e| {
+        eyre!(
+            "failed to open migrations directory '{}': {e}",
+            dir.display()
+        )
+    })?;
+    let entries = root.entries().map_err(|e| {
+        eyre!(
+            "failed to read migrations directory '{}': {e}",
+            dir.display()
+        )
+    })?;
+
+    let mut scripts = Vec::new();
+    for item in entries {
+        let entry = item.map_err(|e| eyre!("failed to read entry in '{}': {e}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let file_type = entry
+            .file_type()
+            .map_err(|e| eyre!("failed to inspect '{name}' in '{}': {e}", dir.display()))?;
+        if let Some(relative) = script_path(&root, &name, file_type.is_dir()) {
+            scripts.push(MigrationScript { name, relative });
+        }
+    }
+    scripts.sort_by(|left, right| left.name.cmp(&right.name));
+    Ok(scripts)
+}
+
+/// Resolves the script for a directory entry, if it is a migration.
+fn script_path(root: &Dir, name: &str, is_dir: bool) -> Option<PathBuf> {
+    if is_dir {
+        let up = Path::new(name).join("up.sql");
+        return root.is_file(&up).then_some(up);
+    }
+    let is_sql = Path::new(name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("sql"));
+    is_sql.then(|| PathBuf::from(name))
+}
+
+/// Template family prefix and current template name for a migrations
+/// directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MigratedTemplateName {
+    family: String,
+    name: String,
+}
+
+impl MigratedTemplateName {
+    fn for_directory(dir: &Path) -> BootstrapResult<Self> {
+        let canonical = std::fs::canonicalize(dir)
+            .wrap_err(format!(
+                "failed to resolve migrations directory '{}'",
+                dir.display()
+            ))
+            .map_err(BootstrapError::from)?;
+        let contents = hash_directory(&canonical)?;
+        Ok(Self::from_parts(&canonical, &contents))
+    }
+
+    fn from_parts(canonical: &Path, contents_hash: &str) -> Self {
+        let path_hash = format!(
+            "{:x}",
+            Sha256::digest(canonical.as_os_str().as_encoded_bytes())
+        );
+        let dir_key = path_hash.get(..DIR_KEY_LEN).unwrap_or(&path_hash);
+        let content_key = contents_hash
+            .get(..CONTENT_KEY_LEN)
+            .unwrap_or(contents_hash);
+        let family = format!("{MIGRATED_TEMPLATE_PREFIX}{dir_key}_");
+        let name = format!("{family}{content_key}");
+        Self { family, name }
+    }
+}
+
+impl TestClusterConnection {
+    /// Returns a template database built from the migrations in `dir`.
+    ///
+    /// The template name combines a hash of the directory path with a hash of
+    /// its contents (see [`hash_directory`]), so it changes whenever a
+    /// migration changes. The template is created with
+    /// [`TestClusterConnection::ensure_template_exists`], migrated by
+    /// `runner`, and flagged with `datistemplate` so it cannot be dropped by
+    /// accident. Templates left over from earlier contents of the same
+    /// directory are then dropped on a best-effort basis.
+    ///
+    /// Returns the template name for use with
+    /// [`TestClusterConnection::temporary_database_from_template`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be hashed, the template cannot
+    /// be created, or `runner` fails. Failures while removing stale templates
+    /// are logged rather than returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::{SqlFileRunner, TestCluster};
+    ///
+    /// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+    /// let cluster = TestCluster::new()?;
+    /// let template = cluster
+    ///     .connection()
+    ///     .migrated_template("migrations", &SqlFileRunner)?;
+    /// let db = cluster
+    ///     .connection()
+    ///     .temporary_database_from_template("my_test", template.as_str())?;
+    /// # drop(db);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn migrated_template(
+        &self,
+        dir: impl AsRef<Path>,
+        runner: &impl MigrationRunner,
+    ) -> BootstrapResult<DatabaseName> {
+        let migrations = dir.as_ref();
+        let template = MigratedTemplateName::for_directory(migrations)?;
+        let _span = info_span!("migrated_template", template = %template.name).entered();
+
+        self.ensure_template_exists(template.name.as_str(), |db_name| {
+            runner.run(self, db_name, migrations)?;
+            set_template_flag(&mut self.admin_client()?, db_name)
+        })?;
+        self.drop_stale_templates_best_effort(&template);
+        Ok(DatabaseName::new(template.name))
+    }
+
+    fn drop_stale_templates_best_effort(&self, template: &MigratedTemplateName) {
+        if let Err(err) = self.drop_stale_templates(template) {
+            warn!(
+                target: LOG_TARGET,
+                template = %template.name,
+                error = ?err,
+                "failed to remove stale migrated templates"
+            );
+        }
+    }
+
+    /// Drops templates from the same family whose contents hash is outdated.
+    fn drop_stale_templates(&self, template: &MigratedTemplateName) -> BootstrapResult<()> {
+        let mut client = self.admin_client()?;
+        let rows = client
+            .query(
+                "SELECT datname FROM pg_database \
+                 WHERE left(datname, length($1)) = $1 AND datname <> $2",
+                &[&template.family, &template.name],
+            )
+            .wrap_err("failed to list stale migrated templates")
+            .map_err(BootstrapError::from)?;
+        for row in rows {
+            let stale: String = row.get(0);
+            // Hold the template's advisory lock so a concurrent build of the
+            // same stale version is never dropped mid-setup.
+            let _lock = TemplateAdvisoryLock::acquire(self.admin_client()?, &stale)?;
+            drop_template_database(&mut client, &stale)?;
+            info!(
+                target: LOG_TARGET,
+                template = %stale,
+                "dropped stale migrated template"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "diesel-support")]
+    use rstest::rstest;
+    use tempfile::TempDir;
+
+    fn write(dir: &Path, relative: &str, contents: &str) {
+        let path = dir.join(relative);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("create parent");
+        }
+        std::fs::write(path, contents).expect("write migration");
+    }
+
+    #[test]
+    fn discovers_sql_files_and_up_scripts_in_name_order() {
+        let temp = TempDir::new().expect("tempdir");
+        write(temp.path(), "0002_second.sql", "SELECT 2;");
+        write(temp.path(), "0001_first.sql", "SELECT 1;");
+        write(temp.path(), "0003_diesel/up.sql", "SELECT 3;");
+        write(temp.path(), "0003_diesel/down.sql", "SELECT -3;");
+        write(temp.path(), "README.md", "ignored");
+        write(temp.path(), ".hidden.sql", "ignored");
+        write(temp.path(), "0004_empty/notes.txt", "ignored");
+
+        let scripts = discover_scripts(temp.path()).expect("discover");
+        let relative: Vec<_> = scripts.iter().map(|s| s.relative.clone()).collect();
+
+        assert_eq!(
+            relative,
+            vec![
+                PathBuf::from("0001_first.sql"),
+                PathBuf::from("0002_second.sql"),
+                PathBuf::from("0003_diesel").join("up.sql"),
+            ]
+        );
+    }
+
+    #[test]
+    fn template_names_share_a_family_per_directory() {
+        let dir = Path::new("/srv/app/migrations");
+        let first = MigratedTemplateName::from_parts(dir, "aaaaaaaaaaaaaaaaffff");
+        let second = MigratedTemplateName::from_parts(dir, "bbbbbbbbbbbbbbbbffff");
+        let other = MigratedTemplateName::from_parts(Path::new("/srv/other"), "aaaaaaaaaaaaaaaa");
+
+        assert_eq!(first.family, second.family);
+        assert_ne!(first.name, second.name);
+        assert_ne!(first.family, other.family);
+        assert!(first.name.starts_with(&first.family));
+        assert!(first.name.ends_with("aaaaaaaaaaaaaaaa"));
+        assert!(first.name.len() < 64, "template names must fit NAMEDATALEN");
+    }
+
+    #[test]
+    fn template_name_tracks_directory_contents() {
+        let temp = TempDir::new().expect("tempdir");
+        write(temp.path(), "0001_init.sql", "CREATE TABLE a (id INT);");
+        let before = MigratedTemplateName::for_directory(temp.path()).expect("name");
+        write(temp.path(), "0002_more.sql", "CREATE TABLE b (id INT);");
+        let after = MigratedTemplateName::for_directory(temp.path()).expect("name");
+
+        assert_eq!(before.family, after.family);
+        assert_ne!(before.name, after.name);
+    }
+
+    #[cfg(feature = "diesel-support")]
+    #[rstest]
+    #[case("2024-01-31-120000_create_users", "20240131120000")]
+    #[case("00000000000000_diesel_initial_setup", "00000000000000")]
+    #[case("noversion", "noversion")]
+    fn derives_diesel_versions(#[case] name: &str, #[case] expected: &str) {
+        assert_eq!(diesel_version(name), expected);
+    }
+}
@@ -46,51 +46,85 @@
 //! pg-embedded-setup-unpriv = { version = "...", features = ["async-api"] }
 //! ```
 
+mod backend;
+mod builder;
 mod cache_integration;
 mod cleanup;
 mod connection;
+mod data_snapshot;
+mod database_name;
 mod delegation;
+mod external;
 mod guard;
 mod handle;
 mod installation;
 mod lifecycle;
+#[cfg(feature = "async-api")]
+mod lifecycle_async;
+mod migrations;
 pub(crate) mod panic_utils;
-mod runtime;
+#[cfg(feature = "pglite")]
+mod pglite;
+mod roles;
+pub(crate) mod runtime;
 mod runtime_mode;
+mod server_log;
 mod shutdown;
 #[cfg(unix)]
 mod shutdown_hook;
+mod tls;
+#[cfg(unix)]
+pub(crate) use self::shutdown::stop_unowned_cluster;
+#[cfg(unix)]
+pub(crate) use self::shutdown_hook::{postmaster_is_running, stop_detached_cluster};
 #[cfg(all(
     unix,
     any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker")
 ))]
 pub use self::shutdown_hook::{process_is_running, read_postmaster_pid};
 mod startup;
+mod template_lock;
 mod temporary_database;
+mod test_transaction;
 mod worker_invoker;
 mod worker_operation;
 
+pub use self::builder::TestClusterBuilder;
+pub(crate) use self::connection::percent_decode;
 pub use self::connection::{ConnectionMetadata, TestClusterConnection};
+pub use self::database_name::{DatabaseName, MAX_DATABASE_NAME_BYTES};
 pub use self::guard::ClusterGuard;
 pub use self::handle::ClusterHandle;
-pub use self::lifecycle::DatabaseName;
+#[cfg(feature = "diesel-support")]
+pub use self::migrations::DieselMigrationRunner;
+#[cfg(feature = "sqlx-support")]
+pub use self::migrations::SqlxMigrationRunner;
+pub use self::migrations::{MigrationRunner, SqlFileRunner};
+pub use self::roles::{Grant, Role, RoleOptions, TemporaryRole};
+pub use self::server_log::ServerLogMarker;
 pub use self::temporary_database::TemporaryDatabase;
+#[cfg(feature = "async-api")]
+pub use self::test_transaction::AsyncTestTransaction;
+pub use self::test_transaction::TestTransaction;
 #[cfg(any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker"))]
 pub use self::worker_invoker::WorkerInvoker;
 #[doc(hidden)]
 pub use self::worker_operation::WorkerOperation;
 
+use self::backend::{ClusterBackend, select_backend};
 use self::runtime::build_runtime;
 use self::runtime_mode::ClusterRuntime;
+use self::server_log::ServerLogForwarder;
 pub(crate) use self::startup::setup_postgres_only;
-#[cfg(feature = "async-api")]
-use self::startup::start_postgres_async;
-use self::startup::{cache_config_from_bootstrap, start_postgres};
-use crate::bootstrap_for_tests;
+pub(crate) use self::tls::enable_tls;
+pub(crate) use self::tls::missing_feature as tls_missing_feature;
+use crate::bootstrap::{BootstrapOverrides, bootstrap_for_tests_with};
 use crate::env::ScopedEnv;
 use crate::error::BootstrapResult;
 use crate::observability::LOG_TARGET;
+use crate::worker_process::DaemonLease;
 use std::ops::Deref;
+use tracing::field::Empty;
 use tracing::info_span;
 
 /// Embedded `PostgreSQL` instance whose lifecycle follows Rust's drop semantics.
@@ -139,7 +173,7 @@ enum StopAsyncPath {
 }
 
 impl TestCluster {
-    /// Boots a `PostgreSQL` instance configured by [`bootstrap_for_tests`].
+    /// Boots a `PostgreSQL` instance configured by [`bootstrap_for_tests`](crate::bootstrap_for_tests).
     ///
     /// The constructor blocks until the underlying server process is running and returns an
     /// error when startup fails.
@@ -197,32 +231,60 @@ impl TestCluster {
     /// **Warning**: Dropping the guard shuts down the cluster. Do not use the
     /// handle after the guard has been dropped unless the guard was forgotten.
     pub fn new_split() -> BootstrapResult<(ClusterHandle, ClusterGuard)> {
-        let span = info_span!(target: LOG_TARGET, "test_cluster");
-        // Resolve cache directory BEFORE applying test environment.
+        Self::new_split_with(&BootstrapOverrides::default())
+    }
+
+    /// Boots a `PostgreSQL` instance using the supplied overrides.
+    fn new_split_with(
+        overrides: &BootstrapOverrides,
+    ) -> BootstrapResult<(ClusterHandle, ClusterGuard)> {
+        let span = info_span!(target: LOG_TARGET, "test_cluster", backend = Empty);
+        // Select the backend BEFORE applying test environment.
         // Otherwise, the test sandbox's XDG_CACHE_HOME would be used.
-        let (runtime, env_vars, env_guard, outcome) = {
+        let (runtime, env_vars, env_guard, worker_daemon, backend_kind, outcome) = {
             let _entered = span.enter();
-            let initial_bootstrap = bootstrap_for_tests()?;
-            let cache_config = cache_config_from_bootstrap(&initial_bootstrap);
+            let initial_bootstrap = bootstrap_for_tests_with(overrides)?;
+            let backend = select_backend(&initial_bootstrap)?;
+            let backend_kind = backend.kind();
+            span.record("backend", backend_kind.as_str());
             let runtime = build_runtime()?;
             let env_vars = initial_bootstrap.environment.to_env();
             let env_guard = ScopedEnv::apply(&env_vars);
-            let outcome = start_postgres(&runtime, initial_bootstrap, &env_vars, &cache_config)?;
-            (runtime, env_vars, env_guard, outcome)
+            // Taken before starting so a failed start still stops the worker.
+            let worker_daemon = DaemonLease::for_bootstrap(&initial_bootstrap);
+            let outcome = backend.start(&runtime, initial_bootstrap, &env_vars)?;
+            (
+                runtime,
+                env_vars,
+                env_guard,
+                worker_daemon,
+                backend_kind,
+                outcome,
+            )
         };
 
-        let handle = ClusterHandle::new(outcome.bootstrap.clone());
+        let log_forwarder = ServerLogForwarder::spawn_if_enabled(&outcome.bootstrap);
         let guard = ClusterGuard {
             runtime: ClusterRuntime::Sync(runtime),
             postgres: outcome.postgres,
             bootstrap: outcome.bootstrap,
+            backend: backend_kind,
             is_managed_via_worker: outcome.is_managed_via_worker,
             env_vars,
             worker_guard: None,
+            log_forwarder,
+            attachment: outcome.attachment,
+            _worker_daemon: worker_daemon,
             _env_guard: env_guard,
             _cluster_span: span,
         };
 
+        let handle = ClusterHandle::new(
+            guard.bootstrap.clone(),
+            guard.backend,
+            guard.created_databases(),
+        );
+
         Ok((handle, guard))
     }
 
@@ -277,40 +339,62 @@ impl TestCluster {
     /// starting the embedded cluster fails.
     #[cfg(feature = "async-api")]
     pub async fn start_async_split() -> BootstrapResult<(ClusterHandle, ClusterGuard)> {
+        Self::start_async_split_with(&BootstrapOverrides::default()).await
+    }
+
+    /// Boots a `PostgreSQL` instance asynchronously using the supplied overrides.
+    #[cfg(feature = "async-api")]
+    async fn start_async_split_with(
+        overrides: &BootstrapOverrides,
+    ) -> BootstrapResult<(ClusterHandle, ClusterGuard)> {
         use tracing::Instrument;
 
-        let span = info_span!(target: LOG_TARGET, "test_cluster", async_mode = true);
+        let span = info_span!(
+            target: LOG_TARGET,
+            "test_cluster",
+            async_mode = true,
+            backend = Empty
+        );
 
         // Sync bootstrap preparation (no await needed).
-        // Resolve cache directory BEFORE applying test environment.
+        // Select the backend BEFORE applying test environment.
         // Otherwise, the test sandbox's XDG_CACHE_HOME would be used.
-        let initial_bootstrap = bootstrap_for_tests()?;
-        let cache_config = cache_config_from_bootstrap(&initial_bootstrap);
+        let initial_bootstrap = bootstrap_for_tests_with(overrides)?;
+        let backend = select_backend(&initial_bootstrap)?;
+        span.record("backend", backend.kind().as_str());
         let env_vars = initial_bootstrap.environment.to_env();
         let env_guard = ScopedEnv::apply(&env_vars);
+        // Taken before starting so a failed start still stops the worker.
+        let worker_daemon = DaemonLease::for_bootstrap(&initial_bootstrap);
 
         // Async postgres startup, instrumented with the span.
         // Box::pin to avoid large future on the stack.
-        let outcome = Box::pin(start_postgres_async(
-            initial_bootstrap,
-            &env_vars,
-            &cache_config,
-        ))
-        .instrument(span.clone())
-        .await?;
+        let outcome = Box::pin(backend.start_async(initial_bootstrap, &env_vars))
+            .instrument(span.clone())
+            .await?;
 
-        let handle = ClusterHandle::new(outcome.bootstrap.clone());
+        let log_forwarder = ServerLogForwarder::spawn_if_enabled(&outcome.bootstrap);
         let guard = ClusterGuard {
             runtime: ClusterRuntime::Async,
             postgres: outcome.postgres,
             bootstrap: outcome.bootstrap,
+            backend: backend.kind(),
             is_managed_via_worker: outcome.is_managed_via_worker,
             env_vars,
             worker_guard: None,
+            log_forwarder,
+            attachment: outcome.attachment,
+            _worker_daemon: worker_daemon,
             _env_guard: env_guard,
             _cluster_span: span,
         };
 
+        let handle = ClusterHandle::new(
+            guard.bootstrap.clone(),
+            guard.backend,
+            guard.created_databases(),
+        );
+
         Ok((handle, guard))
     }
 
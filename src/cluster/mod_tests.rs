@@ -7,6 +7,7 @@ use super::guard::ClusterGuard;
 use super::handle::ClusterHandle;
 use super::runtime_mode::ClusterRuntime;
 use crate::ExecutionPrivileges;
+use crate::bootstrap::BackendKind;
 use crate::env::ScopedEnv;
 use crate::observability::LOG_TARGET;
 use crate::test_support::{dummy_settings, scoped_env};
@@ -47,14 +48,18 @@ fn dummy_cluster() -> TestCluster {
     let env_vars = bootstrap.environment.to_env();
     let env_guard = ScopedEnv::apply(&env_vars);
 
-    let handle = ClusterHandle::new(bootstrap.clone());
+    let handle = ClusterHandle::new(bootstrap.clone(), BackendKind::PostgresqlEmbedded, None);
     let guard = ClusterGuard {
         runtime: ClusterRuntime::Sync(runtime),
         postgres: None,
         bootstrap,
+        backend: BackendKind::PostgresqlEmbedded,
         is_managed_via_worker: false,
         env_vars,
         worker_guard: None,
+        log_forwarder: None,
+        attachment: None,
+        _worker_daemon: None,
         _env_guard: env_guard,
         _cluster_span: span,
     };
@@ -0,0 +1,149 @@
+//! Backend that runs `PostgreSQL` compiled to WebAssembly via `pglite-oxide`.
+//!
+//! `PG_TEST_BACKEND=pglite_oxide` (with the `pglite` feature) starts an
+//! in-process cluster on a private temporary root and serves it through a
+//! loopback proxy, so tests need neither native binaries nor privilege
+//! dropping. The proxy serves one database, `template1`, and one client
+//! connection at a time: a second client waits until the first disconnects.
+//! The guard stops the proxy and removes the sandbox on drop.
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::eyre;
+use pglite_oxide::PgliteServer;
+use tokio::runtime::Runtime;
+use tracing::{info, warn};
+
+use super::backend::{Attachment, ClusterBackend};
+use super::startup::StartupOutcome;
+use crate::TestBootstrapSettings;
+use crate::bootstrap::BackendKind;
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+
+/// A running `pglite-oxide` proxy and the sandbox holding its client files,
+/// released on drop.
+#[derive(Debug)]
+pub(super) struct PgliteAttachment {
+    server: Option<PgliteServer>,
+    sandbox: Utf8PathBuf,
+    shutdown_timeout: Duration,
+}
+
+impl PgliteAttachment {
+    /// Stops the proxy, waiting at most the shutdown timeout.
+    ///
+    /// The proxy only notices the stop request between connections, so a
+    /// client that stays connected keeps it running; the proxy thread is then
+    /// left to exit once that client disconnects.
+    fn stop(&mut self) -> BootstrapResult<()> {
+        let Some(server) = self.server.take() else {
+            return Ok(());
+        };
+        let (sender, receiver) = mpsc::sync_channel(1);
+        std::thread::spawn(move || {
+            // The receiver is gone once the timeout elapsed; nothing to report.
+            drop(sender.send(server.shutdown()));
+        });
+        match receiver.recv_timeout(self.shutdown_timeout) {
+            Ok(result) => result.map_err(|err| {
+                BootstrapError::from(eyre!("failed to stop the pglite-oxide cluster: {err:#}"))
+            }),
+            Err(_) => Err(BootstrapError::from(eyre!(
+                "pglite-oxide cluster did not stop within {:?}; a client is still connected",
+                self.shutdown_timeout
+            ))),
+        }
+    }
+
+    fn remove_sandbox(&self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.sandbox) {
+            warn!(
+                target: LOG_TARGET,
+                sandbox = %self.sandbox,
+                error = %err,
+                "failed to remove pglite-oxide backend sandbox"
+            );
+        }
+    }
+}
+
+impl Drop for PgliteAttachment {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            warn!(
+                target: LOG_TARGET,
+                error = ?err,
+                "failed to stop pglite-oxide cluster"
+            );
+        }
+        self.remove_sandbox();
+    }
+}
+
+/// Starts a `pglite-oxide` cluster on a loopback port.
+pub(super) struct PgliteBackend;
+
+impl PgliteBackend {
+    fn launch(mut bootstrap: TestBootstrapSettings) -> BootstrapResult<StartupOutcome> {
+        let server = PgliteServer::builder()
+            .temporary()
+            .tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .start()
+            .map_err(|err| {
+                BootstrapError::from(eyre!("failed to start the pglite-oxide cluster: {err:#}"))
+            })?;
+        let addr = server
+            .tcp_addr()
+            .ok_or_else(|| eyre!("pglite-oxide cluster reported no TCP address"))?;
+        bootstrap.settings.host = addr.ip().to_string();
+        bootstrap.settings.port = addr.port();
+        info!(
+            target: LOG_TARGET,
+            host = %bootstrap.settings.host,
+            port = bootstrap.settings.port,
+            "started pglite-oxide cluster"
+        );
+        let attachment = PgliteAttachment {
+            server: Some(server),
+            sandbox: bootstrap.environment.home.clone(),
+            shutdown_timeout: bootstrap.shutdown_timeout,
+        };
+        Ok(StartupOutcome {
+            bootstrap,
+            postgres: None,
+            is_managed_via_worker: false,
+            attachment: Some(Attachment::Pglite(attachment)),
+        })
+    }
+}
+
+impl ClusterBackend for PgliteBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::PgliteOxide
+    }
+
+    fn start(
+        &self,
+        _runtime: &Runtime,
+        bootstrap: TestBootstrapSettings,
+        _env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        Self::launch(bootstrap)
+    }
+
+    #[cfg(feature = "async-api")]
+    async fn start_async(
+        &self,
+        bootstrap: TestBootstrapSettings,
+        _env_vars: &[(String, Option<String>)],
+    ) -> BootstrapResult<StartupOutcome> {
+        // Installing and compiling the runtime blocks for a while.
+        tokio::task::spawn_blocking(move || Self::launch(bootstrap))
+            .await
+            .map_err(|err| BootstrapError::from(eyre!("pglite-oxide start task panicked: {err}")))?
+    }
+}
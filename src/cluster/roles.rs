@@ -0,0 +1,882 @@
+//! Role management for `TestClusterConnection`.
+//!
+//! Roles are cluster-wide, so they are created and dropped through the
+//! administration database, while object privileges are granted
+//! from inside the database that holds the object. Building a URL for a role
+//! records its password in the cluster's `.pgpass` file, so libpq-based tools
+//! such as `psql` authenticate as that role without further setup.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::ops::Deref;
+
+use camino::Utf8Path;
+use color_eyre::eyre::{WrapErr, eyre};
+use postgres::Client;
+use tracing::info_span;
+
+#[cfg(feature = "async-api")]
+use super::connection::connect_admin_async;
+use super::connection::{
+    ConnectionMetadata, TestClusterConnection, connect_admin, escape_identifier,
+};
+use super::temporary_database::off_runtime;
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+
+/// libpq ignores password files readable by group or others.
+const PGPASS_MODE: u32 = 0o600;
+
+/// Lists the databases holding objects or privileges that depend on a role.
+const DEPENDENT_DATABASES_SQL: &str = "SELECT DISTINCT d.datname \
+     FROM pg_shdepend s JOIN pg_database d ON d.oid = s.dbid \
+     WHERE s.refclassid = 'pg_authid'::regclass \
+     AND s.refobjid = (SELECT oid FROM pg_roles WHERE rolname = $1)";
+
+/// How the password of a new role is chosen.
+#[derive(Clone, PartialEq, Eq)]
+enum RolePassword {
+    Generated,
+    Given(String),
+    Absent,
+}
+
+/// Attributes for [`TestClusterConnection::create_role`].
+///
+/// The default is a login role with a generated password and no special
+/// privileges.
+///
+/// # Examples
+/// ```
+/// use pg_embedded_setup_unpriv::RoleOptions;
+///
+/// let readers = RoleOptions::default().no_login();
+/// let owner = RoleOptions::default()
+///     .create_db()
+///     .password("owner-secret")
+///     .member_of("readers");
+/// # let _ = (readers, owner);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct RoleOptions {
+    login: bool,
+    attributes: Vec<&'static str>,
+    password: RolePassword,
+    member_of: Vec<String>,
+}
+
+impl Default for RoleOptions {
+    fn default() -> Self {
+        Self {
+            login: true,
+            attributes: Vec::new(),
+            password: RolePassword::Generated,
+            member_of: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for RoleOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoleOptions")
+            .field("login", &self.login)
+            .field("attributes", &self.attributes)
+            .field("password", &redacted(&self.password))
+            .field("member_of", &self.member_of)
+            .finish()
+    }
+}
+
+impl RoleOptions {
+    /// Creates a role that cannot log in, such as a group role.
+    ///
+    /// No password is generated for it.
+    #[must_use]
+    pub fn no_login(mut self) -> Self {
+        self.login = false;
+        if self.password == RolePassword::Generated {
+            self.password = RolePassword::Absent;
+        }
+        self
+    }
+
+    /// Grants the `SUPERUSER` attribute.
+    #[must_use]
+    pub fn superuser(self) -> Self {
+        self.with_attribute("SUPERUSER")
+    }
+
+    /// Grants the `CREATEDB` attribute.
+    #[must_use]
+    pub fn create_db(self) -> Self {
+        self.with_attribute("CREATEDB")
+    }
+
+    /// Grants the `CREATEROLE` attribute.
+    #[must_use]
+    pub fn create_role(self) -> Self {
+        self.with_attribute("CREATEROLE")
+    }
+
+    /// Grants the `BYPASSRLS` attribute, exempting the role from row-level
+    /// security policies.
+    #[must_use]
+    pub fn bypass_rls(self) -> Self {
+        self.with_attribute("BYPASSRLS")
+    }
+
+    /// Uses `password` instead of a generated one.
+    #[must_use]
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = RolePassword::Given(password.into());
+        self
+    }
+
+    /// Creates the role without a password, for `trust` or `peer`
+    /// authentication.
+    #[must_use]
+    pub fn no_password(mut self) -> Self {
+        self.password = RolePassword::Absent;
+        self
+    }
+
+    /// Makes the new role a member of the existing role `group`.
+    #[must_use]
+    pub fn member_of(mut self, group: impl Into<String>) -> Self {
+        self.member_of.push(group.into());
+        self
+    }
+
+    fn with_attribute(mut self, attribute: &'static str) -> Self {
+        if !self.attributes.contains(&attribute) {
+            self.attributes.push(attribute);
+        }
+        self
+    }
+
+    /// Resolves the password the role is created with.
+    fn resolve_password(&self) -> BootstrapResult<Option<String>> {
+        match &self.password {
+            RolePassword::Generated => generate_password().map(Some),
+            RolePassword::Given(password) => Ok(Some(password.clone())),
+            RolePassword::Absent => Ok(None),
+        }
+    }
+}
+
+/// Name and password of a role created through
+/// [`TestClusterConnection::create_role`].
+///
+/// Pass it to [`TestClusterConnection::database_url_as`] to connect as the
+/// role.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Role {
+    name: String,
+    password: Option<String>,
+}
+
+impl fmt::Debug for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Role")
+            .field("name", &self.name)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Role {
+    /// Describes an existing role, for example one created by migrations.
+    #[must_use]
+    pub fn new(name: impl Into<String>, password: Option<String>) -> Self {
+        Self {
+            name: name.into(),
+            password,
+        }
+    }
+
+    /// Returns the role name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the role's password, if it has one.
+    #[must_use]
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+}
+
+/// Privileges on a database object, granted to or revoked from a role.
+///
+/// `privileges` and `object` are inserted into the statement verbatim, so
+/// they accept anything `GRANT` does, such as `SELECT, INSERT` and
+/// `ALL TABLES IN SCHEMA public`. The grantee is quoted as an identifier and
+/// defaults to `PUBLIC`.
+///
+/// # Examples
+/// ```
+/// use pg_embedded_setup_unpriv::Grant;
+///
+/// let grant = Grant::new("SELECT, INSERT", "TABLE accounts").to("app_user");
+/// assert_eq!(grant.grant_sql(), "GRANT SELECT, INSERT ON TABLE accounts TO \"app_user\"");
+/// assert_eq!(grant.revoke_sql(), "REVOKE SELECT, INSERT ON TABLE accounts FROM \"app_user\"");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grant {
+    privileges: String,
+    object: String,
+    grantee: Option<String>,
+}
+
+impl Grant {
+    /// Describes `privileges` on `object`, granted to `PUBLIC`.
+    #[must_use]
+    pub fn new(privileges: impl Into<String>, object: impl Into<String>) -> Self {
+        Self {
+            privileges: privileges.into(),
+            object: object.into(),
+            grantee: None,
+        }
+    }
+
+    /// Targets `role` instead of `PUBLIC`.
+    #[must_use]
+    pub fn to(mut self, role: impl Into<String>) -> Self {
+        self.grantee = Some(role.into());
+        self
+    }
+
+    /// Renders the `GRANT` statement.
+    #[must_use]
+    pub fn grant_sql(&self) -> String {
+        format!(
+            "GRANT {} ON {} TO {}",
+            self.privileges,
+            self.object,
+            self.grantee_sql()
+        )
+    }
+
+    /// Renders the matching `REVOKE` statement.
+    #[must_use]
+    pub fn revoke_sql(&self) -> String {
+        format!(
+            "REVOKE {} ON {} FROM {}",
+            self.privileges,
+            self.object,
+            self.grantee_sql()
+        )
+    }
+
+    fn grantee_sql(&self) -> String {
+        self.grantee
+            .as_deref()
+            .map_or_else(|| "PUBLIC".to_owned(), quote_identifier)
+    }
+}
+
+/// RAII guard that drops a role when it goes out of scope.
+///
+/// Dereferences to the [`Role`], so it can be passed straight to
+/// [`TestClusterConnection::database_url_as`]. Before the role is dropped,
+/// `DROP OWNED BY` runs in every database where it owns objects or holds
+/// privileges, which `DROP ROLE` would otherwise refuse, and its `.pgpass`
+/// entry is removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pg_embedded_setup_unpriv::{Grant, RoleOptions, TestCluster};
+///
+/// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+/// let cluster = TestCluster::new()?;
+/// let connection = cluster.connection();
+/// let reader = connection.temporary_role("reader", &RoleOptions::default())?;
+/// connection.grant("postgres", &Grant::new("SELECT", "ALL TABLES IN SCHEMA public").to("reader"))?;
+///
+/// let url = connection.database_url_as(&reader, "postgres")?;
+/// // ... connect as the reader ...
+/// # let _ = url;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TemporaryRole {
+    role: Role,
+    metadata: ConnectionMetadata,
+    dropped: bool,
+}
+
+impl Deref for TemporaryRole {
+    type Target = Role;
+
+    fn deref(&self) -> &Role {
+        &self.role
+    }
+}
+
+impl TemporaryRole {
+    /// Drops the role, first dropping what it owns and revoking its
+    /// privileges in every database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role owns a database, if the connection fails,
+    /// or if the role cannot be dropped.
+    pub fn drop_role(mut self) -> BootstrapResult<()> {
+        self.dropped = true;
+        self.try_drop()
+    }
+
+    fn try_drop(&self) -> BootstrapResult<()> {
+        let _span = info_span!("drop_role", role = %self.role.name).entered();
+        drop_role_and_dependencies(&self.metadata, &self.role.name)?;
+        forget_pgpass_entry(&self.metadata, &self.role.name)
+    }
+}
+
+#[cfg(feature = "async-api")]
+impl TemporaryRole {
+    /// Drops the role without blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TemporaryRole::drop_role`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role owns a database, if the connection fails,
+    /// or if the role cannot be dropped.
+    pub async fn drop_role_async(mut self) -> BootstrapResult<()> {
+        self.dropped = true;
+        drop_role_and_dependencies_async(&self.metadata, &self.role.name).await?;
+        forget_pgpass_entry(&self.metadata, &self.role.name)
+    }
+}
+
+/// Runs the blocking drop unless the role was already dropped explicitly.
+fn drop_blocking(role: &TemporaryRole) -> BootstrapResult<()> {
+    if role.dropped {
+        return Ok(());
+    }
+    off_runtime(|| role.try_drop())
+}
+
+impl Drop for TemporaryRole {
+    fn drop(&mut self) {
+        if let Err(e) = drop_blocking(self) {
+            tracing::warn!(
+                target: LOG_TARGET,
+                role = %self.role.name,
+                error = ?e,
+                "failed to drop temporary role"
+            );
+        }
+    }
+}
+
+impl TestClusterConnection {
+    /// Creates a role with the given options.
+    ///
+    /// Login roles receive a generated password unless
+    /// [`RoleOptions::password`] or [`RoleOptions::no_password`] says
+    /// otherwise; the returned [`Role`] carries it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists, a group named by
+    /// [`RoleOptions::member_of`] does not, or the connection fails.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use pg_embedded_setup_unpriv::{RoleOptions, TestCluster};
+    ///
+    /// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+    /// let cluster = TestCluster::new()?;
+    /// let role = cluster.connection().create_role("app_user", &RoleOptions::default())?;
+    /// let url = cluster.connection().database_url_as(&role, "postgres")?;
+    /// # let _ = url;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_role(&self, name: &str, options: &RoleOptions) -> BootstrapResult<Role> {
+        let _span = info_span!("create_role", role = %name).entered();
+        let role = Role::new(name, options.resolve_password()?);
+        let sql = create_role_sql(&role, options);
+        execute(&mut self.admin_client()?, &sql, || {
+            format!("failed to create role '{name}'")
+        })?;
+        Ok(role)
+    }
+
+    /// Creates a role that is dropped when the guard is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists or the connection fails.
+    pub fn temporary_role(
+        &self,
+        name: &str,
+        options: &RoleOptions,
+    ) -> BootstrapResult<TemporaryRole> {
+        let role = self.create_role(name, options)?;
+        Ok(TemporaryRole {
+            role,
+            metadata: self.metadata(),
+            dropped: false,
+        })
+    }
+
+    /// Drops a role.
+    ///
+    /// Unlike [`TemporaryRole`], this does not drop what the role owns, so it
+    /// fails while the role holds privileges or owns objects.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role does not exist, other objects depend on
+    /// it, or the connection fails.
+    pub fn drop_role(&self, name: &str) -> BootstrapResult<()> {
+        let _span = info_span!("drop_role", role = %name).entered();
+        execute(&mut self.admin_client()?, &drop_role_sql(name), || {
+            format!("failed to drop role '{name}'")
+        })?;
+        forget_pgpass_entry(&self.metadata(), name)
+    }
+
+    /// Grants privileges on an object in `database`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub fn grant(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        let mut client = connect_admin(&self.database_url(database))?;
+        execute(&mut client, &grant.grant_sql(), || {
+            format!("failed to grant privileges in database '{database}'")
+        })
+    }
+
+    /// Revokes privileges on an object in `database`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub fn revoke(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        let mut client = connect_admin(&self.database_url(database))?;
+        execute(&mut client, &grant.revoke_sql(), || {
+            format!("failed to revoke privileges in database '{database}'")
+        })
+    }
+
+    /// Makes `member` a member of the role `group`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either role does not exist or the connection fails.
+    pub fn grant_role(&self, group: &str, member: &str) -> BootstrapResult<()> {
+        execute(
+            &mut self.admin_client()?,
+            &grant_role_sql(group, member),
+            || format!("failed to grant role '{group}' to '{member}'"),
+        )
+    }
+
+    /// Removes `member` from the role `group`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either role does not exist or the connection fails.
+    pub fn revoke_role(&self, group: &str, member: &str) -> BootstrapResult<()> {
+        execute(
+            &mut self.admin_client()?,
+            &revoke_role_sql(group, member),
+            || format!("failed to revoke role '{group}' from '{member}'"),
+        )
+    }
+
+    /// Builds a libpq-compatible URL for `database` that authenticates as
+    /// `role`.
+    ///
+    /// Roles with a password also get an entry in the cluster's `.pgpass`
+    /// file ([`TestClusterConnection::pgpass_file`]), replacing any earlier
+    /// entry for the same role.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the `.pgpass` file cannot be updated.
+    pub fn database_url_as(&self, role: &Role, database: &str) -> BootstrapResult<String> {
+        let metadata = self.metadata();
+        if let Some(password) = role.password() {
+            record_pgpass_entry(&metadata, role.name(), password)?;
+        }
+        Ok(metadata.database_url_as(role, database))
+    }
+}
+
+#[cfg(feature = "async-api")]
+impl TestClusterConnection {
+    /// Creates a role without blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::create_role`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists, a group named by
+    /// [`RoleOptions::member_of`] does not, or the connection fails.
+    pub async fn create_role_async(
+        &self,
+        name: &str,
+        options: &RoleOptions,
+    ) -> BootstrapResult<Role> {
+        let role = Role::new(name, options.resolve_password()?);
+        let sql = create_role_sql(&role, options);
+        let client = self.admin_client_async().await?;
+        execute_async(&client, &sql, || format!("failed to create role '{name}'")).await?;
+        Ok(role)
+    }
+
+    /// Creates a role that is dropped when the guard is dropped, without
+    /// blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::temporary_role`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role already exists or the connection fails.
+    pub async fn temporary_role_async(
+        &self,
+        name: &str,
+        options: &RoleOptions,
+    ) -> BootstrapResult<TemporaryRole> {
+        let role = self.create_role_async(name, options).await?;
+        Ok(TemporaryRole {
+            role,
+            metadata: self.metadata(),
+            dropped: false,
+        })
+    }
+
+    /// Drops a role without blocking the caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::drop_role`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the role does not exist, other objects depend on
+    /// it, or the connection fails.
+    pub async fn drop_role_async(&self, name: &str) -> BootstrapResult<()> {
+        let client = self.admin_client_async().await?;
+        execute_async(&client, &drop_role_sql(name), || {
+            format!("failed to drop role '{name}'")
+        })
+        .await?;
+        forget_pgpass_entry(&self.metadata(), name)
+    }
+
+    /// Grants privileges on an object in `database` without blocking the
+    /// caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::grant`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub async fn grant_async(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        let client = connect_admin_async(&self.database_url(database)).await?;
+        execute_async(&client, &grant.grant_sql(), || {
+            format!("failed to grant privileges in database '{database}'")
+        })
+        .await
+    }
+
+    /// Revokes privileges on an object in `database` without blocking the
+    /// caller's runtime.
+    ///
+    /// Async counterpart of [`TestClusterConnection::revoke`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the object or grantee does not exist or the
+    /// connection fails.
+    pub async fn revoke_async(&self, database: &str, grant: &Grant) -> BootstrapResult<()> {
+        let client = connect_admin_async(&self.database_url(database)).await?;
+        execute_async(&client, &grant.revoke_sql(), || {
+            format!("failed to revoke privileges in database '{database}'")
+        })
+        .await
+    }
+}
+
+/// Runs `sql`, describing failures with `context`.
+fn execute(
+    client: &mut Client,
+    sql: &str,
+    context: impl FnOnce() -> String,
+) -> BootstrapResult<()> {
+    client
+        .batch_execute(sql)
+        .wrap_err_with(context)
+        .map_err(BootstrapError::from)
+}
+
+/// Async counterpart of [`execute`].
+#[cfg(feature = "async-api")]
+async fn execute_async(
+    client: &tokio_postgres::Client,
+    sql: &str,
+    context: impl FnOnce() -> String,
+) -> BootstrapResult<()> {
+    client
+        .batch_execute(sql)
+        .await
+        .wrap_err_with(context)
+        .map_err(BootstrapError::from)
+}
+
+/// Drops what `name` owns in every database, then the role itself.
+fn drop_role_and_dependencies(metadata: &ConnectionMetadata, name: &str) -> BootstrapResult<()> {
+    let mut admin = connect_admin(&metadata.admin_url())?;
+    let databases: Vec<String> = admin
+        .query(DEPENDENT_DATABASES_SQL, &[&name])
+        .wrap_err_with(|| format!("failed to list objects depending on role '{name}'"))
+        .map_err(BootstrapError::from)?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    for database in databases {
+        let mut client = connect_admin(&metadata.database_url(&database))?;
+        execute(&mut client, &drop_owned_sql(name), || {
+            format!("failed to drop objects owned by role '{name}' in database '{database}'")
+        })?;
+    }
+    execute(&mut admin, &drop_role_sql(name), || {
+        format!("failed to drop role '{name}'")
+    })
+}
+
+/// Async counterpart of [`drop_role_and_dependencies`].
+#[cfg(feature = "async-api")]
+async fn drop_role_and_dependencies_async(
+    metadata: &ConnectionMetadata,
+    name: &str,
+) -> BootstrapResult<()> {
+    let admin = connect_admin_async(&metadata.admin_url()).await?;
+    let databases: Vec<String> = admin
+        .query(DEPENDENT_DATABASES_SQL, &[&name])
+        .await
+        .wrap_err_with(|| format!("failed to list objects depending on role '{name}'"))
+        .map_err(BootstrapError::from)?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    for database in databases {
+        let client = connect_admin_async(&metadata.database_url(&database)).await?;
+        execute_async(&client, &drop_owned_sql(name), || {
+            format!("failed to drop objects owned by role '{name}' in database '{database}'")
+        })
+        .await?;
+    }
+    execute_async(&admin, &drop_role_sql(name), || {
+        format!("failed to drop role '{name}'")
+    })
+    .await
+}
+
+/// Quotes `name` as a SQL identifier.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", escape_identifier(name))
+}
+
+/// Quotes `value` as a SQL string literal.
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Renders the `CREATE ROLE` statement for `role`.
+fn create_role_sql(role: &Role, options: &RoleOptions) -> String {
+    let mut sql = format!(
+        "CREATE ROLE {} WITH {}",
+        quote_identifier(role.name()),
+        if options.login { "LOGIN" } else { "NOLOGIN" }
+    );
+    for attribute in &options.attributes {
+        sql.push(' ');
+        sql.push_str(attribute);
+    }
+    if let Some(password) = role.password() {
+        sql.push_str(" PASSWORD ");
+        sql.push_str(&quote_literal(password));
+    }
+    if !options.member_of.is_empty() {
+        let groups: Vec<String> = options
+            .member_of
+            .iter()
+            .map(|group| quote_identifier(group))
+            .collect();
+        sql.push_str(" IN ROLE ");
+        sql.push_str(&groups.join(", "));
+    }
+    sql
+}
+
+fn drop_role_sql(name: &str) -> String {
+    format!("DROP ROLE {}", quote_identifier(name))
+}
+
+fn drop_owned_sql(name: &str) -> String {
+    format!("DROP OWNED BY {}", quote_identifier(name))
+}
+
+fn grant_role_sql(group: &str, member: &str) -> String {
+    format!(
+        "GRANT {} TO {}",
+        quote_identifier(group),
+        quote_identifier(member)
+    )
+}
+
+fn revoke_role_sql(group: &str, member: &str) -> String {
+    format!(
+        "REVOKE {} FROM {}",
+        quote_identifier(group),
+        quote_identifier(member)
+    )
+}
+
+/// Generates a random 128-bit password, hex encoded.
+fn generate_password() -> BootstrapResult<String> {
+    let mut bytes = [0_u8; 16];
+    getrandom::fill(&mut bytes)
+        .map_err(|err| BootstrapError::from(eyre!("failed to generate role password: {err}")))?;
+    Ok(bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .filter_map(|nibble| char::from_digit(u32::from(nibble), 16))
+        .collect())
+}
+
+const fn redacted(password: &RolePassword) -> &'static str {
+    match password {
+        RolePassword::Generated => "<generated>",
+        RolePassword::Given(_) => "<redacted>",
+        RolePassword::Absent => "<none>",
+    }
+}
+
+/// Escapes `:` and `\` in a `.pgpass` field.
+fn pgpass_field(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(':', "\\:")
+}
+
+/// Returns the `.pgpass` prefix matching `user` on any database of the
+/// cluster.
+fn pgpass_prefix(metadata: &ConnectionMetadata, user: &str) -> String {
+    format!(
+        "{}:{}:*:{}:",
+        pgpass_field(metadata.host()),
+        metadata.port(),
+        pgpass_field(user)
+    )
+}
+
+/// Adds or replaces the `.pgpass` entry for `user`.
+pub(super) fn record_pgpass_entry(
+    metadata: &ConnectionMetadata,
+    user: &str,
+    password: &str,
+) -> BootstrapResult<()> {
+    let prefix = pgpass_prefix(metadata, user);
+    let entry = format!("{prefix}{}", pgpass_field(password));
+    rewrite_pgpass(metadata.pgpass_file(), &prefix, Some(&entry))
+}
+
+/// Removes the `.pgpass` entry for `user`, if any.
+fn forget_pgpass_entry(metadata: &ConnectionMetadata, user: &str) -> BootstrapResult<()> {
+    let path = metadata.pgpass_file();
+    if !path.exists() {
+        return Ok(());
+    }
+    rewrite_pgpass(path, &pgpass_prefix(metadata, user), None)
+}
+
+/// Rewrites `path` without lines starting with `prefix`, appending `entry`.
+///
+/// Other lines are kept in order; the first line is the superuser password
+/// `initdb` reads. The file is truncated in place so its owner is preserved.
+fn rewrite_pgpass(path: &Utf8Path, prefix: &str, entry: Option<&str>) -> BootstrapResult<()> {
+    update_pgpass(path, |existing| {
+        let mut contents: String = existing
+            .lines()
+            .filter(|line| !line.starts_with(prefix))
+            .flat_map(|line| [line, "\n"])
+            .collect();
+        if let Some(line) = entry {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        contents
+    })
+    .wrap_err_with(|| format!("failed to update {path}"))
+    .map_err(BootstrapError::from)
+}
+
+/// Rewrites the password file in place under an exclusive `flock(2)`.
+///
+/// Processes sharing a cluster each record their own roles, so the lock
+/// spans the whole read-modify-write cycle. The file is truncated and
+/// rewritten rather than replaced, which keeps its owner and mode.
+fn update_pgpass(path: &Utf8Path, update: impl FnOnce(&str) -> String) -> io::Result<()> {
+    let mut file = open_pgpass(path)?;
+    lock_exclusive(&file)?;
+    let mut existing = String::new();
+    file.read_to_string(&mut existing)?;
+    let contents = update(&existing);
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(contents.as_bytes())
+}
+
+fn open_pgpass(path: &Utf8Path) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(PGPASS_MODE);
+    }
+    options.open(path)
+}
+
+#[cfg(unix)]
+fn lock_exclusive(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        // SAFETY: The descriptor belongs to `file`, which the caller borrows
+        // for the duration of this call, so it stays open while `flock` runs.
+        let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) };
+        if result == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+        // EINTR: signal interrupted syscall, retry.
+    }
+}
+
+/// Cross-process locking is not supported off Unix; rewrites may race.
+#[cfg(not(unix))]
+const fn lock_exclusive(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+#[path = "roles_tests.rs"]
+mod tests;
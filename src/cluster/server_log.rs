@@ -0,0 +1,311 @@
+//! Server log capture for `TestCluster`.
+//!
+//! Test clusters run with `logging_collector` enabled and a fixed
+//! `log_filename`, so the postmaster writes its log to
+//! `<data_dir>/log/server.log` for the lifetime of the cluster. Messages
+//! emitted before the collector starts, such as configuration errors, land in
+//! the `start.log` file that `pg_ctl` redirects the postmaster's output to.
+//!
+//! This module resolves the log location, reads it back for
+//! [`ClusterHandle::server_log`](super::ClusterHandle::server_log), forwards new
+//! lines into `tracing` when requested, and attaches the log tail to start
+//! failures.
+
+use crate::TestBootstrapSettings;
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+use color_eyre::eyre::Context;
+use postgresql_embedded::Settings;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// File `pg_ctl` redirects the postmaster's standard error to.
+const START_LOG_FILE: &str = "start.log";
+
+/// Default `log_directory`, relative to the data directory.
+const DEFAULT_LOG_DIRECTORY: &str = "log";
+
+/// Number of log lines attached to start failures.
+const TAIL_LINES: usize = 20;
+
+/// Interval between polls of the server log while forwarding.
+const FORWARD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Position in a cluster's server log.
+///
+/// Obtain a marker with
+/// [`ClusterHandle::server_log_marker`](super::ClusterHandle::server_log_marker)
+/// before exercising the server, then read only the lines written since with
+/// [`ClusterHandle::server_log_since`](super::ClusterHandle::server_log_since).
+///
+/// # Examples
+/// ```no_run
+/// use pg_embedded_setup_unpriv::TestCluster;
+///
+/// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+/// let cluster = TestCluster::new()?;
+/// let marker = cluster.server_log_marker()?;
+/// // ... run the statement under test ...
+/// let log = cluster.server_log_since(marker)?;
+/// assert!(!log.contains("ERROR:"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerLogMarker {
+    offset: u64,
+}
+
+/// Returns the file the server writes its log to.
+///
+/// Follows the `logging_collector`, `log_directory`, and `log_filename`
+/// entries of the server configuration. Without the collector the log is the
+/// `start.log` file `pg_ctl` writes. `log_filename` patterns containing
+/// `strftime` escapes are not expanded.
+pub(super) fn server_log_path(settings: &Settings) -> PathBuf {
+    let collector = settings
+        .configuration
+        .get("logging_collector")
+        .is_some_and(|value| is_enabled(value));
+    if !collector {
+        return settings.data_dir.join(START_LOG_FILE);
+    }
+    let directory = settings
+        .configuration
+        .get("log_directory")
+        .map_or(DEFAULT_LOG_DIRECTORY, String::as_str);
+    let filename = settings
+        .configuration
+        .get("log_filename")
+        .map_or(crate::SERVER_LOG_FILENAME, String::as_str);
+    settings.data_dir.join(directory).join(filename)
+}
+
+/// Returns `true` for the boolean spellings `PostgreSQL` accepts as "on".
+pub(super) fn is_enabled(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "on" | "true" | "yes" | "1"
+    )
+}
+
+/// Reads the server log from `marker` to its current end.
+///
+/// A missing log reads as empty. When the log is shorter than the marker, for
+/// example because the data directory was recreated, the whole log is read.
+pub(super) fn read_server_log(
+    settings: &Settings,
+    marker: ServerLogMarker,
+) -> BootstrapResult<String> {
+    let path = server_log_path(settings);
+    let bytes = match read_from(&path, marker.offset) {
+        Ok((_, bytes)) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("failed to read server log {}", path.display()))
+                .map_err(BootstrapError::from);
+        }
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Returns a marker positioned at the current end of the server log.
+pub(super) fn current_marker(settings: &Settings) -> BootstrapResult<ServerLogMarker> {
+    let path = server_log_path(settings);
+    match fs::metadata(&path) {
+        Ok(metadata) => Ok(ServerLogMarker {
+            offset: metadata.len(),
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(ServerLogMarker::default()),
+        Err(err) => Err(err)
+            .with_context(|| format!("failed to inspect server log {}", path.display()))
+            .map_err(BootstrapError::from),
+    }
+}
+
+/// Reads `path` from `offset`, restarting at zero when the file is shorter.
+///
+/// Returns the position reading started at alongside the bytes read.
+fn read_from(path: &Path, offset: u64) -> io::Result<(u64, Vec<u8>)> {
+    let mut file = fs::File::open(path)?;
+    let start = if file.metadata()?.len() < offset {
+        0
+    } else {
+        offset
+    };
+    file.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok((start, bytes))
+}
+
+/// Appends the tail of the server log to a start failure.
+///
+/// Both the collector log and `start.log` are consulted, since the postmaster
+/// reports early failures before the collector takes over. The error kind is
+/// preserved and the original report remains the source.
+pub(super) fn attach_log_tail(err: BootstrapError, settings: &Settings) -> BootstrapError {
+    let sections: Vec<String> = tail_sources(settings)
+        .iter()
+        .filter_map(|path| tail_section(path))
+        .collect();
+    if sections.is_empty() {
+        return err;
+    }
+    let kind = err.kind();
+    let message = format!("{err}\n\n{}", sections.join("\n\n"));
+    BootstrapError::new(kind, err.into_report().wrap_err(message))
+}
+
+/// Lists the log files that may hold start failure diagnostics.
+fn tail_sources(settings: &Settings) -> Vec<PathBuf> {
+    let start_log = settings.data_dir.join(START_LOG_FILE);
+    let server_log = server_log_path(settings);
+    if server_log == start_log {
+        vec![start_log]
+    } else {
+        vec![start_log, server_log]
+    }
+}
+
+/// Formats the last lines of `path`, or `None` when it is missing or empty.
+fn tail_section(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let tail = lines
+        .get(lines.len().saturating_sub(TAIL_LINES)..)
+        .unwrap_or_default()
+        .join("\n");
+    Some(format!("server log tail ({}):\n{tail}", path.display()))
+}
+
+/// Background thread that forwards new server log lines into `tracing`.
+///
+/// Lines are emitted as `INFO` events under the `pg_embed::observability`
+/// target. Dropping the forwarder flushes any remaining lines and joins the
+/// thread.
+#[derive(Debug)]
+pub(super) struct ServerLogForwarder {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ServerLogForwarder {
+    /// Starts forwarding from the current end of the log when the cluster
+    /// requested it.
+    pub(super) fn spawn_if_enabled(bootstrap: &TestBootstrapSettings) -> Option<Self> {
+        if !bootstrap.forward_server_log {
+            return None;
+        }
+        let path = server_log_path(&bootstrap.settings);
+        let offset = current_marker(&bootstrap.settings)
+            .map(|marker| marker.offset)
+            .unwrap_or_default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let tail = LogTail {
+            path,
+            offset,
+            pending: Vec::new(),
+        };
+        let thread_stop = Arc::clone(&stop);
+        let thread = thread::Builder::new()
+            .name("pg-server-log".into())
+            .spawn(move || tail.forward_until(&thread_stop))
+            .map_err(|err| log_forwarding_failed(&err))
+            .ok()?;
+        Some(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Logs a forwarding thread that could not be started.
+fn log_forwarding_failed(err: &io::Error) {
+    warn!(
+        target: LOG_TARGET,
+        error = %err,
+        "failed to start server log forwarding"
+    );
+}
+
+impl Drop for ServerLogForwarder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            join_forwarder(thread);
+        }
+    }
+}
+
+/// Waits for the forwarding thread to drain the log and exit.
+fn join_forwarder(thread: JoinHandle<()>) {
+    if thread.join().is_err() {
+        warn!(target: LOG_TARGET, "server log forwarding thread panicked");
+    }
+}
+
+/// Read position of the forwarding thread.
+struct LogTail {
+    path: PathBuf,
+    offset: u64,
+    pending: Vec<u8>,
+}
+
+impl LogTail {
+    /// Polls the log until `stop` is set, then drains it one final time.
+    fn forward_until(mut self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Acquire) {
+            self.poll();
+            thread::sleep(FORWARD_POLL_INTERVAL);
+        }
+        self.poll();
+        let rest = std::mem::take(&mut self.pending);
+        emit_line(&self.path, &rest);
+    }
+
+    /// Emits every complete line appended since the last poll.
+    fn poll(&mut self) {
+        let Ok((start, bytes)) = read_from(&self.path, self.offset) else {
+            return;
+        };
+        if start < self.offset {
+            self.pending.clear();
+        }
+        self.offset = start.saturating_add(u64::try_from(bytes.len()).unwrap_or(u64::MAX));
+        self.pending.extend_from_slice(&bytes);
+        while let Some(newline) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=newline).collect();
+            emit_line(&self.path, &line);
+        }
+    }
+}
+
+/// Emits one server log line, skipping blank lines.
+fn emit_line(path: &Path, line: &[u8]) {
+    let text = String::from_utf8_lossy(line);
+    let trimmed = text.trim_end();
+    if trimmed.is_empty() {
+        return;
+    }
+    info!(
+        target: LOG_TARGET,
+        server_log = %path.display(),
+        "{trimmed}"
+    );
+}
+
+#[cfg(test)]
+#[path = "server_log_tests.rs"]
+mod tests;
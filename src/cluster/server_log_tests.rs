@@ -0,0 +1,160 @@
+//! Unit tests for server log capture.
+
+use super::*;
+use crate::error::BootstrapErrorKind;
+use crate::test_support::capture_info_logs;
+use color_eyre::eyre::eyre;
+use std::collections::HashMap;
+use tempfile::{TempDir, tempdir};
+
+fn collector_settings(data_dir: PathBuf) -> Settings {
+    Settings {
+        data_dir,
+        configuration: HashMap::from([
+            ("logging_collector".to_owned(), "on".to_owned()),
+            ("log_directory".to_owned(), "log".to_owned()),
+            ("log_filename".to_owned(), "server.log".to_owned()),
+        ]),
+        ..Settings::default()
+    }
+}
+
+fn sandbox_with_log(contents: &str) -> (TempDir, Settings) {
+    let sandbox = tempdir().expect("tempdir");
+    let settings = collector_settings(sandbox.path().join("data"));
+    let log_path = server_log_path(&settings);
+    fs::create_dir_all(log_path.parent().expect("log directory")).expect("create log dir");
+    fs::write(&log_path, contents).expect("write server log");
+    (sandbox, settings)
+}
+
+#[test]
+fn server_log_path_defaults_to_start_log_without_collector() {
+    let settings = Settings {
+        data_dir: PathBuf::from("/tmp/data"),
+        ..Settings::default()
+    };
+
+    assert_eq!(
+        server_log_path(&settings),
+        PathBuf::from("/tmp/data/start.log")
+    );
+}
+
+#[test]
+fn server_log_path_follows_collector_settings() {
+    let mut settings = collector_settings(PathBuf::from("/tmp/data"));
+    assert_eq!(
+        server_log_path(&settings),
+        PathBuf::from("/tmp/data/log/server.log")
+    );
+
+    settings
+        .configuration
+        .insert("log_directory".into(), "/var/log/pg".into());
+    assert_eq!(
+        server_log_path(&settings),
+        PathBuf::from("/var/log/pg/server.log")
+    );
+}
+
+#[test]
+fn read_server_log_treats_missing_log_as_empty() {
+    let sandbox = tempdir().expect("tempdir");
+    let settings = collector_settings(sandbox.path().join("data"));
+
+    let log = read_server_log(&settings, ServerLogMarker::default()).expect("read log");
+
+    assert!(log.is_empty());
+    assert_eq!(
+        current_marker(&settings).expect("marker"),
+        ServerLogMarker::default()
+    );
+}
+
+#[test]
+fn read_server_log_returns_lines_written_since_marker() {
+    let (_sandbox, settings) = sandbox_with_log("LOG:  ready\n");
+    let marker = current_marker(&settings).expect("marker");
+    let mut file = fs::File::options()
+        .append(true)
+        .open(server_log_path(&settings))
+        .expect("open log");
+    std::io::Write::write_all(&mut file, b"ERROR:  boom\n").expect("append log");
+
+    let recent = read_server_log(&settings, marker).expect("read log");
+
+    assert_eq!(recent, "ERROR:  boom\n");
+}
+
+#[test]
+fn read_server_log_rereads_truncated_log() {
+    let (_sandbox, settings) = sandbox_with_log("LOG:  a much longer first run\n");
+    let marker = current_marker(&settings).expect("marker");
+    fs::write(server_log_path(&settings), "LOG:  new\n").expect("truncate log");
+
+    let log = read_server_log(&settings, marker).expect("read log");
+
+    assert_eq!(log, "LOG:  new\n");
+}
+
+#[test]
+fn attach_log_tail_appends_last_lines_and_keeps_kind() {
+    let lines: Vec<String> = (0..30).map(|n| format!("LOG:  line {n}")).collect();
+    let (_sandbox, settings) = sandbox_with_log(&lines.join("\n"));
+    let original = BootstrapError::new(
+        BootstrapErrorKind::WorkerBinaryMissing,
+        eyre!("postgresql_embedded::start() failed"),
+    );
+
+    let err = attach_log_tail(original, &settings);
+    let message = err.to_string();
+
+    assert_eq!(err.kind(), BootstrapErrorKind::WorkerBinaryMissing);
+    assert!(message.contains("postgresql_embedded::start() failed"));
+    assert!(message.contains("LOG:  line 29"), "got: {message}");
+    assert!(message.contains("LOG:  line 10"), "got: {message}");
+    assert!(!message.contains("LOG:  line 9\n"), "got: {message}");
+}
+
+#[test]
+fn attach_log_tail_includes_start_log() {
+    let (_sandbox, settings) = sandbox_with_log("");
+    fs::write(
+        settings.data_dir.join(START_LOG_FILE),
+        "FATAL:  could not bind IPv4 address\n",
+    )
+    .expect("write start log");
+
+    let message =
+        attach_log_tail(BootstrapError::from(eyre!("start failed")), &settings).to_string();
+
+    assert!(message.contains("FATAL:  could not bind"), "got: {message}");
+}
+
+#[test]
+fn attach_log_tail_leaves_error_unchanged_without_log() {
+    let sandbox = tempdir().expect("tempdir");
+    let settings = collector_settings(sandbox.path().join("data"));
+
+    let message =
+        attach_log_tail(BootstrapError::from(eyre!("start failed")), &settings).to_string();
+
+    assert_eq!(message, "start failed");
+}
+
+#[test]
+fn log_tail_forwards_complete_lines_only() {
+    let (_sandbox, settings) = sandbox_with_log("LOG:  first\nLOG:  partial");
+    let mut tail = LogTail {
+        path: server_log_path(&settings),
+        offset: 0,
+        pending: Vec::new(),
+    };
+
+    let (logs, ()) = capture_info_logs(|| tail.poll());
+
+    assert!(logs.iter().any(|line| line.contains("LOG:  first")));
+    assert!(!logs.iter().any(|line| line.contains("partial")));
+    assert_eq!(tail.pending, b"LOG:  partial");
+}
@@ -5,7 +5,7 @@
 
 use crate::error::BootstrapResult;
 use crate::observability::LOG_TARGET;
-use crate::{CleanupMode, TestBootstrapSettings};
+use crate::{CleanupMode, ExecutionPrivileges, TestBootstrapSettings};
 use postgresql_embedded::{PostgreSQL, Settings};
 use std::{fmt::Display, time::Duration};
 use tokio::time;
@@ -146,6 +146,31 @@ fn stop_worker_managed_with_runtime(
     result
 }
 
+/// Stops a cluster that no live guard owns, such as one shared across test
+/// processes, and applies its cleanup mode.
+///
+/// Clusters started as root stop through the worker recorded in `bootstrap`,
+/// so `pg_ctl` runs as the data directory's owner. Signals to the postmaster
+/// remain the fallback when no worker can be reached.
+#[cfg(unix)]
+pub(crate) fn stop_unowned_cluster(bootstrap: &TestBootstrapSettings, context: &str) {
+    if bootstrap.privileges == ExecutionPrivileges::Root {
+        let env_vars = bootstrap.environment.to_env();
+        let stopped = build_runtime().and_then(|runtime| {
+            stop_worker_managed_with_runtime(&runtime, bootstrap, &env_vars, context)
+        });
+        if stopped.is_ok() {
+            return;
+        }
+    }
+    super::stop_detached_cluster(
+        &bootstrap.settings,
+        bootstrap.shutdown_timeout,
+        bootstrap.cleanup_mode,
+        context,
+    );
+}
+
 /// Synchronous drop path: stops the cluster using the owned runtime.
 pub(super) fn drop_sync_cluster(runtime: &tokio::runtime::Runtime, ctx: DropContext<'_>) {
     let DropContext {
@@ -119,26 +119,38 @@ extern "C" fn shutdown_callback() {
         return;
     };
 
-    let Some(pid) = read_postmaster_pid(&state.settings.data_dir) else {
-        // PID file missing — cluster already stopped or was never started.
-        best_effort_cleanup(state);
-        return;
-    };
+    stop_detached_cluster(
+        &state.settings,
+        state.shutdown_timeout,
+        state.cleanup_mode,
+        "atexit-shutdown-hook",
+    );
+}
 
-    if !process_is_running(pid) {
-        best_effort_cleanup(state);
-        return;
+/// Stops a postmaster that is not owned by a live [`ClusterGuard`](super::ClusterGuard)
+/// and then applies `cleanup_mode` to its directories.
+///
+/// Reads the postmaster PID from disk, sends SIGTERM, waits for exit, and
+/// escalates to SIGKILL if `shutdown_timeout` expires.
+pub(crate) fn stop_detached_cluster(
+    settings: &Settings,
+    shutdown_timeout: Duration,
+    cleanup_mode: CleanupMode,
+    context: &str,
+) {
+    // A missing PID file means the cluster already stopped or never started.
+    let running = read_postmaster_pid(&settings.data_dir).filter(|pid| process_is_running(*pid));
+    if let Some(pid) = running {
+        stop_postmaster(pid, shutdown_timeout);
     }
-
-    stop_postmaster(pid, state);
-    best_effort_cleanup(state);
+    super::cleanup::cleanup_in_process(cleanup_mode, settings, context);
 }
 
 /// Sends SIGTERM to the postmaster and escalates to SIGKILL on timeout.
-fn stop_postmaster(pid: libc::pid_t, state: &ShutdownState) {
+fn stop_postmaster(pid: libc::pid_t, shutdown_timeout: Duration) {
     send_sigterm(pid);
 
-    if wait_for_exit(pid, state.shutdown_timeout) {
+    if wait_for_exit(pid, shutdown_timeout) {
         return;
     }
 
@@ -204,6 +216,11 @@ pub fn read_postmaster_pid(data_dir: &Path) -> Option<libc::pid_t> {
     if pid > 0 { Some(pid) } else { None }
 }
 
+/// Returns `true` if the postmaster recorded in `data_dir` is still running.
+pub(crate) fn postmaster_is_running(data_dir: &Path) -> bool {
+    read_postmaster_pid(data_dir).is_some_and(process_is_running)
+}
+
 /// Returns `true` if a process with the given PID is currently running.
 ///
 /// Non-positive PIDs are rejected immediately (returns `false`) to avoid
@@ -227,15 +244,6 @@ pub fn process_is_running(pid: libc::pid_t) -> bool {
     )
 }
 
-// ---------------------------------------------------------------------------
-// Cleanup
-// ---------------------------------------------------------------------------
-
-/// Best-effort directory cleanup after the postmaster has stopped.
-fn best_effort_cleanup(state: &ShutdownState) {
-    super::cleanup::cleanup_in_process(state.cleanup_mode, &state.settings, "atexit-shutdown-hook");
-}
-
 #[cfg(all(test, feature = "cluster-unit-tests"))]
 mod tests {
     use super::*;
@@ -1,7 +1,10 @@
 //! Startup orchestration for `TestCluster` and the CLI setup-only path.
 //!
 //! Contains logic for bootstrapping and starting the embedded `PostgreSQL` instance,
-//! including cache integration, lifecycle invocation, and privilege handling.
+//! including cache integration, data directory snapshots, lifecycle invocation,
+//! and privilege handling. TLS certificates and `pg_hba.conf` are written
+//! between setup and start; root runs hand `pg_hba.conf` to the worker's start
+//! operation. Start failures carry the tail of the server log.
 //! The [`setup_postgres_only`] entry point drives download + `initdb` without
 //! starting the server, used by the CLI binary.
 
@@ -14,8 +17,12 @@ use postgresql_embedded::PostgreSQL;
 use tokio::runtime::Runtime;
 use tracing::info;
 
+use super::backend::Attachment;
 use super::cache_integration;
+use super::data_snapshot::{self, SnapshotCapture};
 use super::installation;
+use super::server_log;
+use super::tls;
 #[cfg(feature = "async-api")]
 use super::worker_invoker::AsyncInvoker;
 use super::worker_invoker::WorkerInvoker as ClusterWorkerInvoker;
@@ -41,6 +48,8 @@ pub(super) struct StartupOutcome {
     pub(super) bootstrap: TestBootstrapSettings,
     pub(super) postgres: Option<PostgreSQL>,
     pub(super) is_managed_via_worker: bool,
+    /// Resources of backends that run no native `PostgreSQL` process.
+    pub(super) attachment: Option<Attachment>,
 }
 
 /// Creates a `BinaryCacheConfig` from bootstrap settings.
@@ -52,7 +61,7 @@ pub(super) fn cache_config_from_bootstrap(bootstrap: &TestBootstrapSettings) ->
         .binary_cache_dir
         .as_ref()
         .map_or_else(BinaryCacheConfig::new, |dir| {
-            BinaryCacheConfig::with_dir(dir.clone())
+            BinaryCacheConfig::from_env_with_dir(dir.clone())
         })
 }
 
@@ -66,20 +75,22 @@ pub(super) fn start_postgres(
     let privileges = bootstrap.privileges;
     log_lifecycle_start(privileges, &bootstrap, false);
 
-    let version_req = bootstrap.settings.version.clone();
-    let cache_hit =
-        cache_integration::try_use_binary_cache(cache_config, &version_req, &mut bootstrap);
+    let cache_hit = use_binary_cache(cache_config, &mut bootstrap)?;
+    let restored = data_snapshot::try_restore_data_snapshot(cache_config, cache_hit, &bootstrap);
+    let capture = SnapshotCapture::new(cache_config, restored);
 
     let (is_managed_via_worker, postgres) =
-        handle_privilege_lifecycle(privileges, runtime, &mut bootstrap, env_vars)?;
+        handle_privilege_lifecycle(runtime, &mut bootstrap, env_vars, capture)?;
 
     populate_cache_on_miss(cache_hit, cache_config, &bootstrap);
+    cache_integration::enforce_cache_retention(cache_config);
     log_lifecycle_complete(privileges, is_managed_via_worker, cache_hit, false);
 
     Ok(StartupOutcome {
         bootstrap,
         postgres,
         is_managed_via_worker,
+        attachment: None,
     })
 }
 
@@ -115,13 +126,36 @@ fn log_lifecycle_complete(
     );
 }
 
+/// Heals and seeds the binary cache, then points `bootstrap` at a matching
+/// entry.
+///
+/// Returns `true` on a cache hit. System binaries bypass the cache.
+fn use_binary_cache(
+    cache_config: &BinaryCacheConfig,
+    bootstrap: &mut TestBootstrapSettings,
+) -> BootstrapResult<bool> {
+    if bootstrap.system_binaries.is_some() {
+        return Ok(false);
+    }
+    let version_req = bootstrap.settings.version.clone();
+    cache_integration::heal_binary_cache(cache_config, &version_req);
+    cache_integration::provision_offline_binaries(cache_config, &version_req, bootstrap)?;
+    Ok(cache_integration::try_use_binary_cache(
+        cache_config,
+        &version_req,
+        bootstrap,
+    ))
+}
+
 /// Populates the cache after successful setup if it was a cache miss.
+///
+/// System binaries are never copied into the cache.
 fn populate_cache_on_miss(
     cache_hit: bool,
     cache_config: &BinaryCacheConfig,
     bootstrap: &TestBootstrapSettings,
 ) {
-    if !cache_hit {
+    if !cache_hit && bootstrap.system_binaries.is_none() {
         cache_integration::try_populate_binary_cache(cache_config, &bootstrap.settings);
     }
 }
@@ -132,17 +166,16 @@ fn populate_cache_on_miss(
 /// - Root execution: worker-managed (true, None)
 /// - Unprivileged execution: in-process (false, Some(embedded))
 fn handle_privilege_lifecycle(
-    privileges: ExecutionPrivileges,
     runtime: &Runtime,
     bootstrap: &mut TestBootstrapSettings,
     env_vars: &[(String, Option<String>)],
+    capture: SnapshotCapture<'_>,
 ) -> BootstrapResult<(bool, Option<PostgreSQL>)> {
-    if privileges == ExecutionPrivileges::Root {
-        invoke_lifecycle_root(runtime, bootstrap, env_vars)?;
+    if bootstrap.privileges == ExecutionPrivileges::Root {
+        invoke_lifecycle_root(runtime, bootstrap, env_vars, capture)?;
         Ok((true, None))
     } else {
-        let mut embedded = PostgreSQL::new(bootstrap.settings.clone());
-        invoke_lifecycle(runtime, bootstrap, env_vars, &mut embedded)?;
+        let embedded = invoke_lifecycle(runtime, bootstrap, env_vars, capture)?;
         Ok((false, prepare_postgres_handle(false, bootstrap, embedded)))
     }
 }
@@ -190,28 +223,40 @@ pub(super) fn invoke_lifecycle_root(
     runtime: &Runtime,
     bootstrap: &mut TestBootstrapSettings,
     env_vars: &[(String, Option<String>)],
+    capture: SnapshotCapture<'_>,
 ) -> BootstrapResult<()> {
     let setup_invoker = ClusterWorkerInvoker::new(runtime, bootstrap, env_vars);
     invoke_root_operation(&setup_invoker, LifecycleStep::Setup)?;
     installation::refresh_worker_installation_dir(bootstrap);
+    capture.after_setup(&bootstrap.settings);
+    tls::provision_certificates(bootstrap)?;
     let start_invoker = ClusterWorkerInvoker::new(runtime, bootstrap, env_vars);
-    invoke_root_operation(&start_invoker, LifecycleStep::Start)?;
+    invoke_root_operation(&start_invoker, LifecycleStep::Start)
+        .map_err(|err| server_log::attach_log_tail(err, &bootstrap.settings))?;
     installation::refresh_worker_port(bootstrap)
 }
 
 /// Invokes the lifecycle for unprivileged in-process execution.
+///
+/// Returns the started in-process `PostgreSQL` handle.
 pub(super) fn invoke_lifecycle(
     runtime: &Runtime,
     bootstrap: &mut TestBootstrapSettings,
     env_vars: &[(String, Option<String>)],
-    embedded: &mut PostgreSQL,
-) -> BootstrapResult<()> {
+    capture: SnapshotCapture<'_>,
+) -> BootstrapResult<PostgreSQL> {
+    let mut embedded = PostgreSQL::new(bootstrap.settings.clone());
     let setup_invoker = ClusterWorkerInvoker::new(runtime, bootstrap, env_vars);
-    invoke_unprivileged_operation(&setup_invoker, embedded, LifecycleStep::Setup)?;
+    invoke_unprivileged_operation(&setup_invoker, &mut embedded, LifecycleStep::Setup)?;
     installation::refresh_worker_installation_dir(bootstrap);
+    capture.after_setup(embedded.settings());
+    tls::provision_certificates(bootstrap)?;
+    crate::bootstrap::write_pg_hba(&bootstrap.settings.data_dir, &bootstrap.hba)?;
     let start_invoker = ClusterWorkerInvoker::new(runtime, bootstrap, env_vars);
-    invoke_unprivileged_operation(&start_invoker, embedded, LifecycleStep::Start)?;
-    installation::refresh_worker_port(bootstrap)
+    invoke_unprivileged_operation(&start_invoker, &mut embedded, LifecycleStep::Start)
+        .map_err(|err| server_log::attach_log_tail(err, &bootstrap.settings))?;
+    installation::refresh_worker_port(bootstrap)?;
+    Ok(embedded)
 }
 
 /// Performs `PostgreSQL` setup (download + `initdb`) without starting the server.
@@ -248,13 +293,14 @@ fn setup_lifecycle(
     let privileges = bootstrap.privileges;
     log_lifecycle_start(privileges, &bootstrap, false);
 
-    let version_req = bootstrap.settings.version.clone();
-    let cache_hit =
-        cache_integration::try_use_binary_cache(cache_config, &version_req, &mut bootstrap);
+    let cache_hit = use_binary_cache(cache_config, &mut bootstrap)?;
+    let restored = data_snapshot::try_restore_data_snapshot(cache_config, cache_hit, &bootstrap);
 
     setup_with_privileges(privileges, runtime, &mut bootstrap, env_vars)?;
     installation::refresh_worker_installation_dir(&mut bootstrap);
+    SnapshotCapture::new(cache_config, restored).after_setup(&bootstrap.settings);
     populate_cache_on_miss(cache_hit, cache_config, &bootstrap);
+    cache_integration::enforce_cache_retention(cache_config);
     log_setup_complete(privileges, cache_hit);
 
     Ok(bootstrap)
@@ -302,12 +348,17 @@ pub(super) async fn start_postgres_async(
     log_lifecycle_start(privileges, &bootstrap, true);
 
     // Try to use cached binaries before starting the lifecycle
-    let version_req = bootstrap.settings.version.clone();
-    let cache_hit =
-        cache_integration::try_use_binary_cache(cache_config, &version_req, &mut bootstrap);
+    let cache_hit = use_binary_cache(cache_config, &mut bootstrap)?;
+    let restored = data_snapshot::try_restore_data_snapshot(cache_config, cache_hit, &bootstrap);
+    let capture = SnapshotCapture::new(cache_config, restored);
 
     let (is_managed_via_worker, postgres) = if privileges == ExecutionPrivileges::Root {
-        Box::pin(invoke_lifecycle_root_async(&mut bootstrap, env_vars)).await?;
+        Box::pin(invoke_lifecycle_root_async(
+            &mut bootstrap,
+            env_vars,
+            capture,
+        ))
+        .await?;
         (true, None)
     } else {
         let mut embedded = PostgreSQL::new(bootstrap.settings.clone());
@@ -315,6 +366,7 @@ pub(super) async fn start_postgres_async(
             &mut bootstrap,
             env_vars,
             &mut embedded,
+            capture,
         ))
         .await?;
         (
@@ -324,11 +376,13 @@ pub(super) async fn start_postgres_async(
     };
 
     populate_cache_on_miss(cache_hit, cache_config, &bootstrap);
+    cache_integration::enforce_cache_retention(cache_config);
     log_lifecycle_complete(privileges, is_managed_via_worker, cache_hit, true);
     Ok(StartupOutcome {
         bootstrap,
         postgres,
         is_managed_via_worker,
+        attachment: None,
     })
 }
 
@@ -338,6 +392,7 @@ pub(super) async fn invoke_lifecycle_async(
     bootstrap: &mut TestBootstrapSettings,
     env_vars: &[(String, Option<String>)],
     embedded: &mut PostgreSQL,
+    capture: SnapshotCapture<'_>,
 ) -> BootstrapResult<()> {
     let invoker = AsyncInvoker::new(bootstrap, env_vars);
     Box::pin(
@@ -347,13 +402,17 @@ pub(super) async fn invoke_lifecycle_async(
     )
     .await?;
     installation::refresh_worker_installation_dir(bootstrap);
+    capture.after_setup(embedded.settings());
+    tls::provision_certificates(bootstrap)?;
+    crate::bootstrap::write_pg_hba(&bootstrap.settings.data_dir, &bootstrap.hba)?;
     let start_invoker = AsyncInvoker::new(bootstrap, env_vars);
     Box::pin(
         start_invoker.invoke(worker_operation::WorkerOperation::Start, async {
             embedded.start().await
         }),
     )
-    .await?;
+    .await
+    .map_err(|err| server_log::attach_log_tail(err, &bootstrap.settings))?;
     installation::refresh_worker_port_async(bootstrap).await
 }
 
@@ -362,6 +421,7 @@ pub(super) async fn invoke_lifecycle_async(
 pub(super) async fn invoke_lifecycle_root_async(
     bootstrap: &mut TestBootstrapSettings,
     env_vars: &[(String, Option<String>)],
+    capture: SnapshotCapture<'_>,
 ) -> BootstrapResult<()> {
     let setup_invoker = AsyncInvoker::new(bootstrap, env_vars);
     // No-op future: the worker subprocess performs the actual setup; this drives the invocation.
@@ -372,6 +432,8 @@ pub(super) async fn invoke_lifecycle_root_async(
     )
     .await?;
     installation::refresh_worker_installation_dir(bootstrap);
+    capture.after_setup(&bootstrap.settings);
+    tls::provision_certificates(bootstrap)?;
     let start_invoker = AsyncInvoker::new(bootstrap, env_vars);
     // No-op future: the worker subprocess performs the actual start; this drives the invocation.
     Box::pin(
@@ -379,7 +441,8 @@ pub(super) async fn invoke_lifecycle_root_async(
             Ok::<(), postgresql_embedded::Error>(())
         }),
     )
-    .await?;
+    .await
+    .map_err(|err| server_log::attach_log_tail(err, &bootstrap.settings))?;
     installation::refresh_worker_port_async(bootstrap).await
 }
 
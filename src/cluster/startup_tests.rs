@@ -370,3 +370,59 @@ fn setup_postgres_only_inside_runtime_returns_recoverable_error(
     );
     Ok(())
 }
+
+#[rstest]
+#[case::mirror_without_archive(None, "no archive satisfying")]
+#[case::archive_version_mismatch(
+    Some("postgresql-16.8.0-x86_64-unknown-linux-gnu.tar.gz"),
+    "does not satisfy"
+)]
+fn provision_offline_binaries_fails_without_usable_source(
+    temp_base_paths: TempBasePaths,
+    #[case] archive_name: Option<&str>,
+    #[case] expected: &str,
+) -> Result<()> {
+    let mut bootstrap = dummy_settings(ExecutionPrivileges::Unprivileged);
+    bootstrap.settings.releases_url = format!("file://{}", temp_base_paths.base);
+    bootstrap.binary_archive = archive_name.map(|name| temp_base_paths.base.join(name));
+    let cache_config = BinaryCacheConfig::with_dir(temp_base_paths.base.join("cache"));
+    let version_req = VersionReq::parse("^17")?;
+
+    let Err(err) =
+        cache_integration::provision_offline_binaries(&cache_config, &version_req, &bootstrap)
+    else {
+        return Err(eyre!(
+            "provisioning should fail without a usable offline source"
+        ));
+    };
+    ensure!(
+        err.to_string().contains(expected),
+        "unexpected error: {err}"
+    );
+    Ok(())
+}
+
+#[rstest]
+fn heal_binary_cache_falls_back_past_corrupt_entries(temp_base_paths: TempBasePaths) -> Result<()> {
+    let cache_config = BinaryCacheConfig::with_dir(temp_base_paths.base.join("cache"));
+    let source = temp_base_paths.base.join("source");
+    fs::create_dir_all(source.join("bin"))?;
+    fs::write(source.join("bin/postgres"), b"mock postgres")?;
+    for version in ["17.2.0", TEST_POSTGRES_VERSION] {
+        crate::cache::populate_cache(&source, &cache_config.cache_dir, version)?;
+    }
+    let corrupt = cache_config.cache_dir.join(TEST_POSTGRES_VERSION);
+    fs::write(corrupt.join("bin/postgres"), b"truncated")?;
+
+    cache_integration::heal_binary_cache(&cache_config, &VersionReq::parse("^17")?);
+
+    ensure!(!corrupt.exists(), "corrupt entry should be quarantined");
+    let remaining =
+        crate::cache::find_matching_cached_version(&cache_config.cache_dir, &VersionReq::STAR)
+            .map(|(version, _path)| version);
+    ensure!(
+        remaining.as_deref() == Some("17.2.0"),
+        "older valid entry should remain: {remaining:?}"
+    );
+    Ok(())
+}
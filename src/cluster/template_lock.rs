@@ -0,0 +1,468 @@
+//! Cross-process coordination for template database creation.
+//!
+//! Tests running in separate processes (for example under `cargo nextest`)
+//! share one cluster but not the in-process locks in `lifecycle.rs`. Template
+//! creation is therefore serialised with a session-level `PostgreSQL` advisory
+//! lock. A template is built under a staging name derived from its own,
+//! carrying a building marker stored as the database comment, and is renamed
+//! into place only once its setup succeeded and the marker became a ready
+//! marker. A build interrupted at any point therefore leaves only the staging
+//! database, which the next build drops. Templates under their own name with
+//! a building marker come from older versions of this crate and are rebuilt;
+//! templates without either marker were not built by this crate, or by an
+//! older version of it, and are used as they are.
+
+use color_eyre::eyre::WrapErr;
+use postgres::Client;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::connection::escape_identifier;
+use super::temporary_database::{terminate_and_drop, terminate_sessions};
+#[cfg(feature = "async-api")]
+use super::temporary_database::{terminate_and_drop_async, terminate_sessions_async};
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+
+/// Comment written to a template database once its setup closure succeeds.
+pub(crate) const TEMPLATE_READY_MARKER: &str = "pg-embedded-setup-unpriv:template-ready";
+
+/// Comment written to a template database before its setup closure runs.
+pub(crate) const TEMPLATE_BUILDING_MARKER: &str = "pg-embedded-setup-unpriv:template-building";
+
+/// Namespace prefixed to template names before hashing them into lock keys.
+const ADVISORY_LOCK_NAMESPACE: &str = "pg-embedded-setup-unpriv:template:";
+
+/// Takes the session-level advisory lock for a template.
+const LOCK_SQL: &str = "SELECT pg_advisory_lock(hashtextextended($1 || $2, 0))";
+
+/// Releases the session-level advisory lock for a template.
+const UNLOCK_SQL: &str = "SELECT pg_advisory_unlock(hashtextextended($1 || $2, 0))";
+
+/// Reads the comment of a template database, if the database exists.
+const STATE_SQL: &str = "SELECT shobj_description(oid, 'pg_database') \
+                         FROM pg_database WHERE datname = $1";
+
+/// Prefix of the staging databases templates are built in.
+const STAGING_PREFIX: &str = "pg_embed_staging_";
+
+/// Hex digits of the template name's hash kept in its staging name.
+const STAGING_HASH_HEX_DIGITS: usize = 16;
+
+/// Returns the staging database `template` is built in before being renamed.
+///
+/// The name depends only on the template name, so a build interrupted by a
+/// crash leaves a database the next build of the same template finds and
+/// drops. It never shares a prefix with migrated template families.
+pub(crate) fn staging_name(template: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(template.as_bytes()));
+    let hash = digest.get(..STAGING_HASH_HEX_DIGITS).unwrap_or_default();
+    format!("{STAGING_PREFIX}{hash}")
+}
+
+/// Observed state of a template database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TemplateState {
+    /// No database with the template name exists.
+    Missing,
+    /// An older version of this crate started building the template in
+    /// place but never marked it ready.
+    Incomplete,
+    /// The database exists and its setup completed.
+    Ready,
+    /// The database exists without either marker, so its origin is unknown.
+    Unmarked,
+}
+
+impl TemplateState {
+    /// Classifies a template from its existence and database comment.
+    pub(crate) fn from_comment(exists: bool, comment: Option<&str>) -> Self {
+        match (exists, comment) {
+            (false, _) => Self::Missing,
+            (true, Some(TEMPLATE_READY_MARKER)) => Self::Ready,
+            (true, Some(TEMPLATE_BUILDING_MARKER)) => Self::Incomplete,
+            (true, _) => Self::Unmarked,
+        }
+    }
+}
+
+/// Session-level advisory lock guarding creation of a single template.
+///
+/// The lock is keyed on a hash of the template name, so unrelated templates do
+/// not contend. `PostgreSQL` releases the lock automatically if the owning
+/// session disconnects, so a crashed process never wedges other test runs.
+pub(crate) struct TemplateAdvisoryLock {
+    client: Client,
+    name: String,
+    staging: String,
+}
+
+impl TemplateAdvisoryLock {
+    /// Blocks until the advisory lock for `name` is held by `client`.
+    pub(crate) fn acquire(mut client: Client, name: &str) -> BootstrapResult<Self> {
+        client
+            .execute(LOCK_SQL, &[&ADVISORY_LOCK_NAMESPACE, &name])
+            .wrap_err(format!(
+                "failed to acquire advisory lock for template '{name}'"
+            ))
+            .map_err(BootstrapError::from)?;
+        Ok(Self {
+            client,
+            name: name.to_owned(),
+            staging: staging_name(name),
+        })
+    }
+
+    /// Reports whether the template is missing, incomplete, or ready.
+    fn template_state(&mut self) -> BootstrapResult<TemplateState> {
+        let row = self
+            .client
+            .query_opt(STATE_SQL, &[&self.name])
+            .wrap_err(format!("failed to inspect template '{}'", self.name))
+            .map_err(BootstrapError::from)?;
+        let comment = row
+            .as_ref()
+            .and_then(|found| found.get::<_, Option<String>>(0));
+        Ok(TemplateState::from_comment(
+            row.is_some(),
+            comment.as_deref(),
+        ))
+    }
+
+    /// Reports whether the template must be (re)built by its setup closure.
+    ///
+    /// An incomplete template is dropped first, terminating any sessions that
+    /// were still attached to it. Unmarked templates are adopted as they are.
+    pub(crate) fn needs_setup(&mut self) -> BootstrapResult<bool> {
+        match self.template_state()? {
+            TemplateState::Ready => Ok(false),
+            TemplateState::Unmarked => {
+                log_adopted(&self.name);
+                Ok(false)
+            }
+            TemplateState::Missing => Ok(true),
+            TemplateState::Incomplete => {
+                warn_incomplete(&self.name);
+                drop_template_database(&mut self.client, &self.name)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Creates the staging database the setup closure populates, returning
+    /// its name.
+    ///
+    /// A staging database left by an interrupted build is dropped first. The
+    /// new one is marked as building before setup runs.
+    pub(crate) fn create_staging(&mut self) -> BootstrapResult<String> {
+        let leftover = self
+            .client
+            .query_opt(STATE_SQL, &[&self.staging])
+            .wrap_err(format!("failed to inspect template '{}'", self.name))
+            .map_err(BootstrapError::from)?;
+        if leftover.is_some() {
+            warn_interrupted(&self.name);
+            drop_template_database(&mut self.client, &self.staging)?;
+        }
+        self.client
+            .batch_execute(&create_staging_sql(&self.staging))
+            .wrap_err(format!("failed to create database '{}'", self.staging))
+            .map_err(BootstrapError::from)?;
+        // `CREATE DATABASE` cannot share a transaction with the comment, but
+        // a crash in between only leaves an unmarked staging database, which
+        // the next build drops like any other.
+        self.client
+            .batch_execute(&marker_sql(&self.staging, TEMPLATE_BUILDING_MARKER))
+            .wrap_err(format!(
+                "failed to mark template '{}' as building",
+                self.name
+            ))
+            .map_err(BootstrapError::from)?;
+        Ok(self.staging.clone())
+    }
+
+    /// Marks the staging database ready and renames it to the template name.
+    ///
+    /// Sessions the setup closure left connected to the staging database are
+    /// terminated, since `PostgreSQL` cannot rename a database in use.
+    pub(crate) fn publish(&mut self) -> BootstrapResult<()> {
+        self.client
+            .batch_execute(&marker_sql(&self.staging, TEMPLATE_READY_MARKER))
+            .wrap_err(format!("failed to mark template '{}' as ready", self.name))
+            .map_err(BootstrapError::from)?;
+        terminate_sessions(&mut self.client, &self.staging)?;
+        self.client
+            .batch_execute(&rename_sql(&self.staging, &self.name))
+            .wrap_err(format!(
+                "failed to rename staging database to template '{}'",
+                self.name
+            ))
+            .map_err(BootstrapError::from)
+    }
+}
+
+impl Drop for TemplateAdvisoryLock {
+    fn drop(&mut self) {
+        if let Err(err) = self
+            .client
+            .execute(UNLOCK_SQL, &[&ADVISORY_LOCK_NAMESPACE, &self.name])
+        {
+            warn_unlock_failed(&self.name, &err);
+        }
+    }
+}
+
+fn warn_incomplete(name: &str) {
+    warn!(
+        target: LOG_TARGET,
+        template = %name,
+        "template was left incomplete by an earlier setup; rebuilding"
+    );
+}
+
+fn warn_interrupted(name: &str) {
+    warn!(
+        target: LOG_TARGET,
+        template = %name,
+        "dropping staging database left by an interrupted template build"
+    );
+}
+
+fn log_adopted(name: &str) {
+    info!(
+        target: LOG_TARGET,
+        template = %name,
+        "using existing template without a readiness marker as is"
+    );
+}
+
+fn warn_unlock_failed(name: &str, err: &impl std::fmt::Display) {
+    warn!(
+        target: LOG_TARGET,
+        template = %name,
+        error = %err,
+        "failed to release template advisory lock; it will be released on disconnect"
+    );
+}
+
+/// Builds the statement that lets a template database be dropped again.
+fn clear_template_flag_sql(name: &str) -> String {
+    let escaped = escape_identifier(name);
+    format!("ALTER DATABASE \"{escaped}\" WITH IS_TEMPLATE false")
+}
+
+/// Flags a database as a template (`datistemplate`), which stops it from being
+/// dropped by accident and lets non-owners clone it.
+pub(crate) fn set_template_flag(client: &mut Client, name: &str) -> BootstrapResult<()> {
+    let escaped = escape_identifier(name);
+    client
+        .batch_execute(&format!(
+            "ALTER DATABASE \"{escaped}\" WITH IS_TEMPLATE true"
+        ))
+        .wrap_err(format!("failed to flag '{name}' as a template"))
+        .map_err(BootstrapError::from)
+}
+
+/// Drops a template database, clearing `datistemplate` first because
+/// `PostgreSQL` refuses to drop databases flagged as templates.
+pub(crate) fn drop_template_database(client: &mut Client, name: &str) -> BootstrapResult<()> {
+    client
+        .batch_execute(&clear_template_flag_sql(name))
+        .wrap_err(format!("failed to clear template flag on '{name}'"))
+        .map_err(BootstrapError::from)?;
+    terminate_and_drop(client, name)
+}
+
+/// Builds the statement that creates a staging database.
+fn create_staging_sql(staging: &str) -> String {
+    let escaped = escape_identifier(staging);
+    format!("CREATE DATABASE \"{escaped}\"")
+}
+
+/// Builds the statement that records a template's build `marker`.
+fn marker_sql(name: &str, marker: &str) -> String {
+    let escaped = escape_identifier(name);
+    format!("COMMENT ON DATABASE \"{escaped}\" IS '{marker}'")
+}
+
+/// Builds the statement that moves a finished staging database into place.
+fn rename_sql(staging: &str, name: &str) -> String {
+    format!(
+        "ALTER DATABASE \"{}\" RENAME TO \"{}\"",
+        escape_identifier(staging),
+        escape_identifier(name)
+    )
+}
+
+/// Async counterpart of [`TemplateAdvisoryLock`] backed by `tokio-postgres`.
+///
+/// `Drop` cannot await, so callers release the lock with
+/// [`AsyncTemplateAdvisoryLock::release`]. If the guard is dropped early (for
+/// example because setup failed), closing the connection releases the lock.
+#[cfg(feature = "async-api")]
+pub(crate) struct AsyncTemplateAdvisoryLock {
+    client: tokio_postgres::Client,
+    name: String,
+    staging: String,
+}
+
+#[cfg(feature = "async-api")]
+impl AsyncTemplateAdvisoryLock {
+    /// Waits until the advisory lock for `name` is held by `client`.
+    pub(crate) async fn acquire(
+        client: tokio_postgres::Client,
+        name: &str,
+    ) -> BootstrapResult<Self> {
+        client
+            .execute(LOCK_SQL, &[&ADVISORY_LOCK_NAMESPACE, &name])
+            .await
+            .wrap_err(format!(
+                "failed to acquire advisory lock for template '{name}'"
+            ))
+            .map_err(BootstrapError::from)?;
+        Ok(Self {
+            client,
+            name: name.to_owned(),
+            staging: staging_name(name),
+        })
+    }
+
+    /// Reports whether the template must be (re)built by its setup closure.
+    ///
+    /// Mirrors [`TemplateAdvisoryLock::needs_setup`].
+    pub(crate) async fn needs_setup(&self) -> BootstrapResult<bool> {
+        let row = self
+            .client
+            .query_opt(STATE_SQL, &[&self.name])
+            .await
+            .wrap_err(format!("failed to inspect template '{}'", self.name))
+            .map_err(BootstrapError::from)?;
+        let comment = row
+            .as_ref()
+            .and_then(|found| found.get::<_, Option<String>>(0));
+        match TemplateState::from_comment(row.is_some(), comment.as_deref()) {
+            TemplateState::Ready => Ok(false),
+            TemplateState::Unmarked => {
+                log_adopted(&self.name);
+                Ok(false)
+            }
+            TemplateState::Missing => Ok(true),
+            TemplateState::Incomplete => {
+                warn_incomplete(&self.name);
+                self.drop_database(&self.name).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Creates the staging database the setup future populates, returning
+    /// its name.
+    ///
+    /// Mirrors [`TemplateAdvisoryLock::create_staging`].
+    pub(crate) async fn create_staging(&self) -> BootstrapResult<String> {
+        let leftover = self
+            .client
+            .query_opt(STATE_SQL, &[&self.staging])
+            .await
+            .wrap_err(format!("failed to inspect template '{}'", self.name))
+            .map_err(BootstrapError::from)?;
+        if leftover.is_some() {
+            warn_interrupted(&self.name);
+            self.drop_database(&self.staging).await?;
+        }
+        self.client
+            .batch_execute(&create_staging_sql(&self.staging))
+            .await
+            .wrap_err(format!("failed to create database '{}'", self.staging))
+            .map_err(BootstrapError::from)?;
+        self.client
+            .batch_execute(&marker_sql(&self.staging, TEMPLATE_BUILDING_MARKER))
+            .await
+            .wrap_err(format!(
+                "failed to mark template '{}' as building",
+                self.name
+            ))
+            .map_err(BootstrapError::from)?;
+        Ok(self.staging.clone())
+    }
+
+    /// Marks the staging database ready and renames it to the template name.
+    ///
+    /// Mirrors [`TemplateAdvisoryLock::publish`].
+    pub(crate) async fn publish(&self) -> BootstrapResult<()> {
+        self.client
+            .batch_execute(&marker_sql(&self.staging, TEMPLATE_READY_MARKER))
+            .await
+            .wrap_err(format!("failed to mark template '{}' as ready", self.name))
+            .map_err(BootstrapError::from)?;
+        terminate_sessions_async(&self.client, &self.staging).await?;
+        self.client
+            .batch_execute(&rename_sql(&self.staging, &self.name))
+            .await
+            .wrap_err(format!(
+                "failed to rename staging database to template '{}'",
+                self.name
+            ))
+            .map_err(BootstrapError::from)
+    }
+
+    /// Drops a template database, clearing its template flag first.
+    async fn drop_database(&self, name: &str) -> BootstrapResult<()> {
+        self.client
+            .batch_execute(&clear_template_flag_sql(name))
+            .await
+            .wrap_err(format!("failed to clear template flag on '{name}'"))
+            .map_err(BootstrapError::from)?;
+        terminate_and_drop_async(&self.client, name).await
+    }
+
+    /// Releases the advisory lock and closes the session.
+    pub(crate) async fn release(self) {
+        if let Err(err) = self
+            .client
+            .execute(UNLOCK_SQL, &[&ADVISORY_LOCK_NAMESPACE, &self.name])
+            .await
+        {
+            warn_unlock_failed(&self.name, &err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::missing(false, None, TemplateState::Missing)]
+    #[case::building(true, Some(TEMPLATE_BUILDING_MARKER), TemplateState::Incomplete)]
+    #[case::no_comment(true, None, TemplateState::Unmarked)]
+    #[case::foreign_comment(true, Some("user comment"), TemplateState::Unmarked)]
+    #[case::ready(true, Some(TEMPLATE_READY_MARKER), TemplateState::Ready)]
+    fn classifies_template_state(
+        #[case] exists: bool,
+        #[case] comment: Option<&str>,
+        #[case] expected: TemplateState,
+    ) {
+        assert_eq!(TemplateState::from_comment(exists, comment), expected);
+    }
+
+    #[test]
+    fn staging_name_is_stable_per_template_and_fits() {
+        let long = "t".repeat(crate::MAX_DATABASE_NAME_BYTES);
+        let staging = staging_name(&long);
+
+        assert_eq!(staging, staging_name(&long));
+        assert_ne!(staging, staging_name("other_template"));
+        assert!(staging.starts_with(STAGING_PREFIX));
+        assert!(staging.len() <= crate::MAX_DATABASE_NAME_BYTES);
+    }
+
+    #[test]
+    fn rename_sql_escapes_both_names() {
+        assert_eq!(
+            rename_sql("stage", "my\"template"),
+            "ALTER DATABASE \"stage\" RENAME TO \"my\"\"template\""
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Certificate generation and verified connectors for TLS clusters.
+
+use std::fs;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+
+use color_eyre::eyre::Context;
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509, X509Builder, X509Name};
+use postgres_native_tls::MakeTlsConnector;
+
+use super::{CA_CERT_FILE, SERVER_CERT_FILE, SERVER_KEY_FILE};
+
+/// `PostgreSQL` refuses private keys readable by group or others.
+pub(super) const SERVER_KEY_MODE: u32 = 0o600;
+
+/// Lifetime of the generated certificates.
+const VALIDITY_DAYS: u32 = 30;
+
+/// Connector the crate's own connections use for TLS clusters.
+pub(crate) type Connector = MakeTlsConnector;
+
+/// Builds a connector that trusts only the certificate authority at `ca_path`.
+///
+/// Server host names are verified, matching libpq's `sslmode=verify-full`.
+pub(crate) fn connector(ca_path: &Path) -> color_eyre::Result<Connector> {
+    let pem = fs::read(ca_path)
+        .wrap_err_with(|| format!("failed to read TLS root certificate {}", ca_path.display()))?;
+    let certificate = native_tls::Certificate::from_pem(&pem)
+        .wrap_err_with(|| format!("invalid TLS root certificate {}", ca_path.display()))?;
+    let connector = native_tls::TlsConnector::builder()
+        .disable_built_in_roots(true)
+        .add_root_certificate(certificate)
+        .build()
+        .wrap_err("failed to build TLS connector")?;
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// Builds a connector that verifies servers against the system's trusted
+/// roots, for `sslmode=verify-full` without `sslrootcert`.
+pub(crate) fn system_connector() -> color_eyre::Result<Connector> {
+    let connector = native_tls::TlsConnector::new().wrap_err("failed to build TLS connector")?;
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// Builds a connector that encrypts without verifying the server, matching
+/// libpq's `sslmode=require`.
+pub(crate) fn unverified_connector() -> color_eyre::Result<Connector> {
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .wrap_err("failed to build TLS connector")?;
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// PEM-encoded certificates and key for one cluster.
+pub(super) struct TlsMaterial {
+    pub(super) ca_cert: Vec<u8>,
+    pub(super) server_cert: Vec<u8>,
+    pub(super) server_key: Vec<u8>,
+}
+
+/// Generates a certificate authority and a server certificate for `host`.
+pub(super) fn generate(host: &str) -> Result<TlsMaterial, ErrorStack> {
+    let ca_key = new_key()?;
+    let ca_cert = build_ca(&ca_key)?;
+    let server_key = new_key()?;
+    let server_cert = build_server_cert(&server_key, &ca_cert, &ca_key, host)?;
+    Ok(TlsMaterial {
+        ca_cert: ca_cert.to_pem()?,
+        server_cert: server_cert.to_pem()?,
+        server_key: server_key.private_key_to_pem_pkcs8()?,
+    })
+}
+
+/// Generates a P-256 private key.
+fn new_key() -> Result<PKey<Private>, ErrorStack> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    PKey::from_ec_key(EcKey::generate(&group)?)
+}
+
+/// Builds a certificate name with the given common name.
+fn common_name(name: &str) -> Result<X509Name, ErrorStack> {
+    let mut builder = X509Name::builder()?;
+    builder.append_entry_by_nid(Nid::COMMONNAME, name)?;
+    Ok(builder.build())
+}
+
+/// Returns a random positive serial number.
+fn random_serial() -> Result<Asn1Integer, ErrorStack> {
+    let mut serial = BigNum::new()?;
+    serial.rand(63, MsbOption::MAYBE_ZERO, false)?;
+    serial.to_asn1_integer()
+}
+
+/// Starts a version 3 certificate valid from now for [`VALIDITY_DAYS`].
+fn certificate_builder(subject: &X509Name, key: &PKey<Private>) -> Result<X509Builder, ErrorStack> {
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    let serial = random_serial()?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(subject)?;
+    builder.set_pubkey(key)?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    builder.set_not_before(&not_before)?;
+    let not_after = Asn1Time::days_from_now(VALIDITY_DAYS)?;
+    builder.set_not_after(&not_after)?;
+    Ok(builder)
+}
+
+/// Builds the self-signed certificate authority.
+fn build_ca(key: &PKey<Private>) -> Result<X509, ErrorStack> {
+    let name = common_name("pg-embed-setup-unpriv test CA")?;
+    let mut builder = certificate_builder(&name, key)?;
+    builder.set_issuer_name(&name)?;
+    builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .key_cert_sign()
+            .crl_sign()
+            .build()?,
+    )?;
+    builder.sign(key, MessageDigest::sha256())?;
+    Ok(builder.build())
+}
+
+/// Builds the server certificate, valid for loopback and `host`.
+fn build_server_cert(
+    key: &PKey<Private>,
+    ca_cert: &X509,
+    ca_key: &PKey<Private>,
+    host: &str,
+) -> Result<X509, ErrorStack> {
+    let mut builder = certificate_builder(&common_name("localhost")?, key)?;
+    builder.set_issuer_name(ca_cert.subject_name())?;
+    builder.append_extension(BasicConstraints::new().critical().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .key_encipherment()
+            .build()?,
+    )?;
+    builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+    let san = subject_alt_names(host).build(&builder.x509v3_context(Some(ca_cert), None))?;
+    builder.append_extension(san)?;
+    builder.sign(ca_key, MessageDigest::sha256())?;
+    Ok(builder.build())
+}
+
+/// Lists the loopback names plus `host` when it names another interface.
+fn subject_alt_names(host: &str) -> SubjectAlternativeName {
+    let mut san = SubjectAlternativeName::new();
+    san.dns("localhost").ip("127.0.0.1").ip("::1");
+    let is_loopback = matches!(host, "" | "localhost" | "127.0.0.1" | "::1");
+    if !is_loopback && !host.starts_with('/') {
+        if host.parse::<IpAddr>().is_ok() {
+            san.ip(host);
+        } else {
+            san.dns(host);
+        }
+    }
+    san
+}
+
+/// Writes the generated files and hands them to the data directory owner.
+pub(super) fn write_material(data_dir: &Path, material: &TlsMaterial) -> std::io::Result<()> {
+    let key_path = data_dir.join(SERVER_KEY_FILE);
+    write_private(&key_path, &material.server_key)?;
+    let cert_path = data_dir.join(SERVER_CERT_FILE);
+    fs::write(&cert_path, &material.server_cert)?;
+    let ca_path = data_dir.join(CA_CERT_FILE);
+    fs::write(&ca_path, &material.ca_cert)?;
+    match_owner(data_dir, &[&key_path, &cert_path, &ca_path])
+}
+
+/// Writes `contents` to a file only its owner can read.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(SERVER_KEY_MODE);
+        let mut file = options.open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(SERVER_KEY_MODE))?;
+        file.write_all(contents)
+    }
+    #[cfg(not(unix))]
+    {
+        options.open(path)?.write_all(contents)
+    }
+}
+
+/// Gives `paths` the owner and group of `data_dir` when running as root.
+#[cfg(unix)]
+fn match_owner(data_dir: &Path, paths: &[&Path]) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    if !nix::unistd::geteuid().is_root() {
+        return Ok(());
+    }
+    let metadata = fs::metadata(data_dir)?;
+    for path in paths {
+        std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn match_owner(_data_dir: &Path, _paths: &[&Path]) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! TLS support for `TestCluster`.
+//!
+//! TLS clusters run with `ssl = on`. Between `initdb` and the first start a
+//! throwaway certificate authority and a server certificate signed by it are
+//! written into the data directory, replacing any left by a previous run.
+//! Connection URLs then carry `sslmode=verify-full` and `sslrootcert`, and the
+//! crate's own admin connections verify the server against the generated
+//! authority.
+//!
+//! Generating certificates and verifying servers needs `OpenSSL`, so both sit
+//! behind the `tls` feature. Without it, TLS clusters are rejected and URLs
+//! naming a root certificate fail to connect.
+
+use crate::TestBootstrapSettings;
+use crate::error::{BootstrapError, BootstrapResult};
+#[cfg(feature = "tls")]
+use color_eyre::eyre::Context;
+use color_eyre::eyre::eyre;
+use postgresql_embedded::Settings;
+#[cfg(not(feature = "tls"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(feature = "tls")]
+mod certificates;
+
+#[cfg(feature = "tls")]
+pub(crate) use self::certificates::{Connector, connector, system_connector, unverified_connector};
+
+/// Server certificate file, relative to the data directory.
+const SERVER_CERT_FILE: &str = "server.crt";
+
+/// Server private key file, relative to the data directory.
+const SERVER_KEY_FILE: &str = "server.key";
+
+/// Certificate authority file clients verify the server against.
+const CA_CERT_FILE: &str = "ca.crt";
+
+/// Enables TLS in the server configuration.
+///
+/// Certificate paths are relative so `PostgreSQL` resolves them against the
+/// data directory, where [`provision_certificates`] writes them.
+pub(crate) fn enable_tls(settings: &mut Settings) {
+    for (key, value) in [
+        ("ssl", "on"),
+        ("ssl_cert_file", SERVER_CERT_FILE),
+        ("ssl_key_file", SERVER_KEY_FILE),
+    ] {
+        settings.configuration.insert(key.into(), value.into());
+    }
+}
+
+/// Returns `true` when the server configuration enables TLS.
+pub(super) fn is_tls_enabled(settings: &Settings) -> bool {
+    settings
+        .configuration
+        .get("ssl")
+        .is_some_and(|value| super::server_log::is_enabled(value))
+}
+
+/// Returns the certificate authority file of a TLS cluster.
+pub(super) fn ca_cert_path(settings: &Settings) -> Option<PathBuf> {
+    is_tls_enabled(settings).then(|| settings.data_dir.join(CA_CERT_FILE))
+}
+
+/// Writes a fresh certificate authority and server certificate into the data
+/// directory of a TLS cluster.
+///
+/// Clusters without TLS are left untouched. When running as root the files
+/// are handed to the owner of the data directory, which runs the server.
+#[cfg(feature = "tls")]
+pub(super) fn provision_certificates(bootstrap: &TestBootstrapSettings) -> BootstrapResult<()> {
+    let settings = &bootstrap.settings;
+    if !is_tls_enabled(settings) {
+        return Ok(());
+    }
+    let material = certificates::generate(&settings.host)
+        .map_err(|err| BootstrapError::from(eyre!("failed to generate TLS certificates: {err}")))?;
+    certificates::write_material(&settings.data_dir, &material)
+        .wrap_err_with(|| {
+            format!(
+                "failed to write TLS certificates into {}",
+                settings.data_dir.display()
+            )
+        })
+        .map_err(BootstrapError::from)
+}
+
+/// Rejects TLS clusters, which need the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub(super) fn provision_certificates(bootstrap: &TestBootstrapSettings) -> BootstrapResult<()> {
+    if is_tls_enabled(&bootstrap.settings) {
+        return Err(missing_feature());
+    }
+    Ok(())
+}
+
+/// Connector type kept for call sites when the `tls` feature is off; no
+/// connector is ever built.
+#[cfg(not(feature = "tls"))]
+pub(crate) type Connector = postgres::NoTls;
+
+/// Fails for URLs naming a root certificate, which need the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub(crate) fn connector(ca_path: &Path) -> color_eyre::Result<Connector> {
+    Err(eyre!(
+        "connection names TLS root certificate {}; enable the `tls` feature to verify it",
+        ca_path.display()
+    ))
+}
+
+/// Fails for URLs that ask for TLS, which needs the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub(crate) fn system_connector() -> color_eyre::Result<Connector> {
+    Err(eyre!(
+        "connection requires TLS; enable the `tls` feature to connect"
+    ))
+}
+
+/// Fails for URLs that ask for TLS, which needs the `tls` feature.
+#[cfg(not(feature = "tls"))]
+pub(crate) fn unverified_connector() -> color_eyre::Result<Connector> {
+    system_connector()
+}
+
+/// Error for TLS clusters requested without the `tls` feature.
+pub(crate) fn missing_feature() -> BootstrapError {
+    BootstrapError::from(eyre!(
+        "TLS clusters need the `tls` feature of pg-embed-setup-unpriv; enable it or unset PG_TLS"
+    ))
+}
+
+#[cfg(test)]
+mod tests;
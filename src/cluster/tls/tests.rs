@@ -0,0 +1,140 @@
+//! Unit tests for TLS certificate provisioning.
+
+#[cfg(feature = "tls")]
+use super::certificates::{SERVER_KEY_MODE, generate, write_material};
+use super::*;
+#[cfg(feature = "tls")]
+use openssl::x509::X509;
+#[cfg(feature = "tls")]
+use std::fs;
+#[cfg(feature = "tls")]
+use std::io::{Read, Write};
+#[cfg(feature = "tls")]
+use std::net::{TcpListener, TcpStream};
+#[cfg(feature = "tls")]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(feature = "tls")]
+use std::thread;
+use tempfile::tempdir;
+
+fn tls_settings(data_dir: PathBuf) -> Settings {
+    let mut settings = Settings {
+        data_dir,
+        ..Settings::default()
+    };
+    enable_tls(&mut settings);
+    settings
+}
+
+#[test]
+fn enable_tls_configures_server_and_ca_path() {
+    let settings = tls_settings(PathBuf::from("/tmp/data"));
+
+    assert_eq!(
+        settings.configuration.get("ssl").map(String::as_str),
+        Some("on")
+    );
+    assert_eq!(
+        settings
+            .configuration
+            .get("ssl_key_file")
+            .map(String::as_str),
+        Some("server.key")
+    );
+    assert_eq!(
+        ca_cert_path(&settings),
+        Some(PathBuf::from("/tmp/data/ca.crt"))
+    );
+    assert_eq!(ca_cert_path(&Settings::default()), None);
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn generated_server_certificate_is_signed_by_ca() {
+    let material = generate("db.internal").expect("generate certificates");
+    let ca = X509::from_pem(&material.ca_cert).expect("parse CA");
+    let server = X509::from_pem(&material.server_cert).expect("parse server certificate");
+
+    let ca_key = ca.public_key().expect("CA public key");
+    assert!(server.verify(&ca_key).expect("verify signature"));
+    let names: Vec<String> = server
+        .subject_alt_names()
+        .expect("subject alternative names")
+        .iter()
+        .filter_map(|name| name.dnsname().map(str::to_owned))
+        .collect();
+    assert_eq!(names, ["localhost", "db.internal"]);
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn write_material_keeps_server_key_private() {
+    let sandbox = tempdir().expect("tempdir");
+    let material = generate("localhost").expect("generate certificates");
+
+    write_material(sandbox.path(), &material).expect("write certificates");
+
+    let mode = fs::metadata(sandbox.path().join(SERVER_KEY_FILE))
+        .expect("key metadata")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, SERVER_KEY_MODE);
+    assert!(sandbox.path().join(SERVER_CERT_FILE).is_file());
+    assert!(sandbox.path().join(CA_CERT_FILE).is_file());
+}
+
+#[cfg(feature = "tls")]
+#[test]
+fn generated_certificates_pass_hostname_verification() {
+    let material = generate("localhost").expect("generate certificates");
+    let identity = native_tls::Identity::from_pkcs8(&material.server_cert, &material.server_key)
+        .expect("server identity");
+    let acceptor = native_tls::TlsAcceptor::new(identity).expect("acceptor");
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind listener");
+    let address = listener.local_addr().expect("listener address");
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept");
+        let mut tls = acceptor.accept(stream).expect("server handshake");
+        tls.write_all(b"ok").expect("write");
+    });
+
+    let connector = native_tls::TlsConnector::builder()
+        .disable_built_in_roots(true)
+        .add_root_certificate(
+            native_tls::Certificate::from_pem(&material.ca_cert).expect("CA certificate"),
+        )
+        .build()
+        .expect("connector");
+    let stream = TcpStream::connect(address).expect("connect");
+    let mut tls = connector
+        .connect("localhost", stream)
+        .expect("client handshake verifies the server");
+    let mut reply = String::new();
+    tls.read_to_string(&mut reply).expect("read");
+
+    assert_eq!(reply, "ok");
+    server.join().expect("server thread");
+}
+
+#[test]
+fn connector_rejects_missing_ca_file() {
+    let sandbox = tempdir().expect("tempdir");
+
+    let err = connector(&sandbox.path().join(CA_CERT_FILE))
+        .err()
+        .expect("missing CA should fail");
+
+    assert!(err.to_string().contains("ca.crt"), "got: {err}");
+}
+
+#[cfg(not(feature = "tls"))]
+#[test]
+fn tls_clusters_need_the_tls_feature() {
+    let bootstrap = crate::test_support::dummy_settings(crate::ExecutionPrivileges::Unprivileged);
+    let mut tls_bootstrap = bootstrap.clone();
+    enable_tls(&mut tls_bootstrap.settings);
+
+    assert!(provision_certificates(&bootstrap).is_ok());
+    let err = provision_certificates(&tls_bootstrap).expect_err("TLS needs the feature");
+    assert!(err.to_string().contains("`tls` feature"), "got: {err}");
+}
@@ -68,7 +68,9 @@ fn execute_root_operation(
             "UID/GID changes race in multi-threaded tests; switch to ",
             "ExecutionMode::Subprocess"
         )))),
-        ExecutionMode::Subprocess => spawn_worker_inner(bootstrap, env_vars, operation),
+        ExecutionMode::Subprocess | ExecutionMode::PersistentWorker => {
+            spawn_worker_inner(bootstrap, env_vars, operation)
+        }
     }
 }
 
@@ -76,6 +78,8 @@ fn execute_root_operation(
 ///
 /// This is the shared implementation for worker spawning, used by both sync and
 /// async code paths. Contains platform-specific guards for privilege dropping.
+/// In [`ExecutionMode::PersistentWorker`] the request goes to the cluster's
+/// long-lived worker instead of a fresh subprocess.
 fn spawn_worker_inner(
     bootstrap: &TestBootstrapSettings,
     env_vars: &[(String, Option<String>)],
@@ -123,7 +127,17 @@ fn spawn_worker_inner(
             operation,
             timeout: operation.timeout(bootstrap),
         };
-        let request = WorkerRequest::new(args);
+        let pg_hba = if matches!(operation, WorkerOperation::Start) {
+            bootstrap.hba.render()?
+        } else {
+            None
+        };
+        let request = WorkerRequest::new(args)
+            .with_pg_hba(pg_hba.as_deref())
+            .with_embedded_entry(bootstrap.embedded_worker);
+        if bootstrap.execution_mode == ExecutionMode::PersistentWorker {
+            return worker_process::run_persistent(&request);
+        }
         return worker_process::run(&request);
     }
 
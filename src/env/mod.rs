@@ -65,6 +65,24 @@ fn exit_scope_std(index: usize) {
     with_state(|state| state.exit_scope(index));
 }
 
+/// Applies `vars` for the rest of the process.
+///
+/// Unlike [`ScopedEnv`], nothing is restored and the environment lock is not
+/// held once the variables are set, so other threads can still open scopes.
+pub(crate) fn apply_process_env(vars: &[(String, Option<String>)]) {
+    let owned: Vec<(OsString, Option<OsString>)> = vars
+        .iter()
+        .map(|(key, value)| (OsString::from(key), value.as_ref().map(OsString::from)))
+        .collect();
+    let change_count = owned.len();
+    with_state(|state| state.apply_persistent(owned));
+    info!(
+        target: LOG_TARGET,
+        change_count,
+        "applied process environment variables"
+    );
+}
+
 /// Restores the process environment when dropped, reverting to prior values.
 #[derive(Debug)]
 #[must_use = "Hold the guard until the end of the environment scope"]
@@ -72,6 +72,13 @@ impl ThreadState {
     pub fn exit_scope(&mut self, index: usize) {
         self.inner.exit_scope(index);
     }
+
+    pub fn apply_persistent<I>(&mut self, vars: I)
+    where
+        I: IntoIterator<Item = (OsString, Option<OsString>)>,
+    {
+        self.inner.apply_persistent(vars);
+    }
 }
 
 #[cfg(all(test, feature = "loom-tests"))]
@@ -104,6 +111,24 @@ impl<L: EnvLockOps> ThreadStateCore<L> {
         index
     }
 
+    /// Applies `vars` without recording them for restoration.
+    ///
+    /// Outside a scope the lock is held only while the variables are set; an
+    /// enclosing scope on this thread already holds it.
+    pub fn apply_persistent<I>(&mut self, vars: I)
+    where
+        I: IntoIterator<Item = (OsString, Option<OsString>)>,
+    {
+        let is_outermost = self.depth == 0;
+        if is_outermost {
+            self.acquire_lock_if_needed();
+        }
+        drop(self.apply_env_vars(vars));
+        if is_outermost {
+            self.lock = None;
+        }
+    }
+
     pub fn exit_scope(&mut self, index: usize) {
         if self.depth == 0 {
             self.force_restore_and_reset("ScopedEnv drop without matching apply", None);
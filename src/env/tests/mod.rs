@@ -44,6 +44,23 @@ fn recovers_from_poisoned_lock() {
     assert!(env::var("POISON_TEST").is_err());
 }
 
+#[test]
+#[serial]
+fn process_env_persists_without_holding_the_lock() {
+    let restore = ScopedEnv::apply(&[(String::from("PROCESS_ENV_TEST"), None)]);
+    super::apply_process_env(&[(
+        String::from("PROCESS_ENV_TEST"),
+        Some(String::from("inner")),
+    )]);
+    assert_eq!(env::var("PROCESS_ENV_TEST").as_deref(), Ok("inner"));
+    drop(restore);
+
+    super::apply_process_env(&[(String::from("PROCESS_ENV_TEST"), Some(String::from("kept")))]);
+    assert_eq!(env::var("PROCESS_ENV_TEST").as_deref(), Ok("kept"));
+    assert!(ENV_LOCK.try_lock().is_ok(), "lock must be released");
+    super::apply_process_env(&[(String::from("PROCESS_ENV_TEST"), None)]);
+}
+
 #[test]
 #[serial]
 fn allows_reentrant_scopes() {
@@ -28,6 +28,8 @@ mod privileges;
 pub mod test_support;
 #[doc(hidden)]
 pub mod worker;
+#[cfg(unix)]
+mod worker_entry;
 pub(crate) mod worker_process;
 
 #[doc(hidden)]
@@ -178,14 +180,22 @@ pub mod worker_process_test_api {
 /// # }
 /// ```
 pub use crate::fs::ambient_dir_and_path;
+#[cfg(unix)]
+pub use crate::worker_entry::worker_main;
 
 #[doc(hidden)]
 pub use crate::env::ScopedEnv;
 pub use bootstrap::{
-    CleanupMode, ExecutionMode, ExecutionPrivileges, TestBootstrapEnvironment,
-    TestBootstrapSettings, bootstrap_for_tests, detect_execution_privileges, find_timezone_dir,
-    run,
+    AuthMethod, CleanupMode, ClientSettings, ExecutionMode, ExecutionPrivileges, HbaConfig,
+    HbaConnectionType, HbaRule, TestBootstrapEnvironment, TestBootstrapSettings,
+    bootstrap_for_tests, detect_execution_privileges, find_timezone_dir, run,
 };
+#[cfg(feature = "async-api")]
+pub use cluster::AsyncTestTransaction;
+#[cfg(feature = "diesel-support")]
+pub use cluster::DieselMigrationRunner;
+#[cfg(feature = "sqlx-support")]
+pub use cluster::SqlxMigrationRunner;
 #[cfg(any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker"))]
 #[doc(hidden)]
 pub use cluster::WorkerInvoker;
@@ -193,8 +203,9 @@ pub use cluster::WorkerInvoker;
 #[doc(hidden)]
 pub use cluster::WorkerOperation;
 pub use cluster::{
-    ClusterGuard, ClusterHandle, ConnectionMetadata, DatabaseName, TemporaryDatabase, TestCluster,
-    TestClusterConnection,
+    ClusterGuard, ClusterHandle, ConnectionMetadata, DatabaseName, Grant, MAX_DATABASE_NAME_BYTES,
+    MigrationRunner, Role, RoleOptions, ServerLogMarker, SqlFileRunner, TemporaryDatabase,
+    TemporaryRole, TestCluster, TestClusterBuilder, TestClusterConnection, TestTransaction,
 };
 #[doc(hidden)]
 pub use error::BootstrapResult;
@@ -280,6 +291,67 @@ pub struct PgEnvCfg {
     pub binary_cache_dir: Option<Utf8PathBuf>,
 }
 
+/// Captures where `PostgreSQL` binaries come from, supplied via `PG_*`
+/// environment variables alongside [`PgEnvCfg`].
+///
+/// The struct is `#[non_exhaustive]` so new sources can be added without a
+/// breaking release. Start from [`PgBinarySourceCfg::default`] or
+/// [`PgBinarySourceCfg::load`] and assign the fields to change.
+///
+/// # Examples
+/// ```
+/// use pg_embedded_setup_unpriv::PgBinarySourceCfg;
+///
+/// let mut cfg = PgBinarySourceCfg::default();
+/// cfg.binary_archive = Some("/srv/pg/postgresql-17.4.0-x86_64-unknown-linux-gnu.tar.gz".into());
+/// assert!(cfg.binary_archive_sha256.is_none());
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, OrthoConfig, Default)]
+#[ortho_config(prefix = "PG")]
+#[non_exhaustive]
+pub struct PgBinarySourceCfg {
+    /// Base URL of the `PostgreSQL` binary releases.
+    ///
+    /// A `file://` URL names a local mirror directory holding release
+    /// archives named `postgresql-<version>-<target>.tar.gz`, optionally with
+    /// `.sha256` files beside them. A matching archive is imported into the
+    /// binary cache without network access. Other URLs are handed to
+    /// `postgresql_embedded` unchanged.
+    pub releases_url: Option<String>,
+    /// Local `PostgreSQL` release archive imported into the binary cache
+    /// before any download is attempted.
+    pub binary_archive: Option<Utf8PathBuf>,
+    /// Expected SHA-256 digest of the [`binary_archive`](Self::binary_archive).
+    ///
+    /// When unset, an `<archive>.sha256` file beside the archive is used if
+    /// present.
+    pub binary_archive_sha256: Option<String>,
+    /// Uses system-installed `PostgreSQL` binaries instead of downloading.
+    ///
+    /// `auto` searches distribution install locations and `PATH` for the
+    /// newest installation satisfying [`PgEnvCfg::version_req`]; any
+    /// other value names the installation's `bin` directory.
+    pub system_binaries: Option<String>,
+}
+
+impl PgBinarySourceCfg {
+    /// Loads configuration from environment variables without parsing CLI arguments.
+    ///
+    /// # Errors
+    /// Returns an error when environment parsing fails.
+    pub fn load() -> ConfigResult<Self> {
+        let args = [OsString::from("pg-embedded-setup-unpriv")];
+        Self::load_from_iter(args).map_err(|err| ConfigError::from(eyre!(err)))
+    }
+
+    /// Points `settings` at the configured releases URL, if any.
+    pub(crate) fn apply_releases_url(&self, settings: &mut Settings) {
+        if let Some(ref url) = self.releases_url {
+            settings.releases_url.clone_from(url);
+        }
+    }
+}
+
 impl PgEnvCfg {
     /// Loads configuration from environment variables without parsing CLI arguments.
     ///
@@ -318,6 +390,7 @@ impl PgEnvCfg {
     /// Converts the configuration into `Settings`, applying test-only worker limits.
     ///
     /// Use this helper for ephemeral test clusters where resource limits are desirable.
+    /// The server log is also routed to `log/server.log` inside the data directory.
     ///
     /// # Examples
     /// ```no_run
@@ -367,6 +440,7 @@ impl PgEnvCfg {
         self.apply_locale(&mut s);
         if for_tests {
             Self::apply_worker_limits(&mut s);
+            Self::apply_server_log(&mut s);
         }
 
         Ok(s)
@@ -425,8 +499,30 @@ impl PgEnvCfg {
                 .or_insert_with(|| value.to_owned());
         }
     }
+
+    /// Routes the server log to a single collector file for test clusters.
+    fn apply_server_log(settings: &mut Settings) {
+        for (key, value) in SERVER_LOG_DEFAULTS {
+            settings
+                .configuration
+                .entry(key.to_owned())
+                .or_insert_with(|| value.to_owned());
+        }
+    }
 }
 
+/// File name test clusters write their server log to.
+pub(crate) const SERVER_LOG_FILENAME: &str = "server.log";
+
+/// Collector settings that keep the whole server log in one known file.
+const SERVER_LOG_DEFAULTS: [(&str, &str); 5] = [
+    ("logging_collector", "on"),
+    ("log_directory", "log"),
+    ("log_filename", SERVER_LOG_FILENAME),
+    ("log_rotation_age", "0"),
+    ("log_rotation_size", "0"),
+];
+
 const WORKER_LIMIT_DEFAULTS: [(&str, &str); 8] = [
     ("max_connections", "20"),
     ("max_worker_processes", "2"),
@@ -6,8 +6,200 @@
 //! other tools. Configuration is provided via environment variables parsed by
 //! [`OrthoConfig`](https://github.com/leynos/ortho-config). The binary exits
 //! with status code `0` on success and `1` on error.
+//!
+//! The `cache` subcommands manage the shared binary cache instead, so CI
+//! images can pre-populate and audit it during a build step:
+//!
+//! - `cache list` prints each cached version with its size and state.
+//! - `cache warm [--version-req REQ] [--allow-unverified]` downloads or imports
+//!   a matching version.
+//! - `cache verify` checks every entry and fails if any is unusable.
+//! - `cache prune --keep N` removes all but the newest `N` versions.
+//! - `cache import <ARCHIVE> [--sha256 DIGEST] [--allow-unverified]` imports a
+//!   local release archive.
+
+use std::io::{self, Write};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{Context, Result, bail, eyre};
+use pg_embedded_setup_unpriv::cache::{
+    CacheVerification, ensure_archive_satisfies, import_archive, list_cached_versions, prune_cache,
+    resolve_cache_dir, verify_cache, warm_cache,
+};
+use pg_embedded_setup_unpriv::{PgBinarySourceCfg, PgEnvCfg};
+use postgresql_embedded::{Version, VersionReq};
+
+/// Environment variable that allows importing archives without a digest.
+const ALLOW_UNVERIFIED_ENV: &str = "PG_BINARY_ARCHIVE_ALLOW_UNVERIFIED";
+
+/// Command-line interface for the setup helper.
+#[derive(Debug, Parser)]
+#[command(name = "pg_embedded_setup_unpriv", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Top-level subcommands; without one the helper runs the setup lifecycle.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage the shared `PostgreSQL` binary cache.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+/// Binary cache maintenance operations.
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// List cached versions with their size and completion state.
+    List,
+    /// Ensure a version satisfying the requirement is cached.
+    Warm {
+        /// Semver requirement to cache; defaults to `PG_VERSION_REQ`.
+        #[arg(long)]
+        version_req: Option<String>,
+        /// Import archives that have no SHA-256 digest.
+        #[arg(long, env = ALLOW_UNVERIFIED_ENV)]
+        allow_unverified: bool,
+    },
+    /// Check every cache entry and fail if any is unusable.
+    Verify,
+    /// Remove all but the newest complete versions.
+    Prune {
+        /// Number of complete versions to retain.
+        #[arg(long)]
+        keep: usize,
+    },
+    /// Import a local release archive into the cache.
+    Import {
+        /// Archive named `postgresql-<version>-<target>.tar.gz`.
+        archive: Utf8PathBuf,
+        /// Expected SHA-256 digest; defaults to `<archive>.sha256` if present.
+        #[arg(long)]
+        sha256: Option<String>,
+        /// Import the archive even if it has no SHA-256 digest.
+        #[arg(long, env = ALLOW_UNVERIFIED_ENV)]
+        allow_unverified: bool,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        None => pg_embedded_setup_unpriv::run().map_err(|err| eyre!(err)),
+        Some(Command::Cache(command)) => run_cache_command(command),
+    }
+}
+
+/// Dispatches a cache subcommand against the resolved cache directory.
+fn run_cache_command(command: CacheCommand) -> Result<()> {
+    let cfg = PgEnvCfg::load().context("failed to load configuration via OrthoConfig")?;
+    let source =
+        PgBinarySourceCfg::load().context("failed to load configuration via OrthoConfig")?;
+    let cache_dir = cfg
+        .binary_cache_dir
+        .clone()
+        .unwrap_or_else(resolve_cache_dir);
+    let mut out = io::stdout().lock();
+    match command {
+        CacheCommand::List => list(&mut out, &cache_dir),
+        CacheCommand::Warm {
+            version_req,
+            allow_unverified,
+        } => {
+            let req = resolve_version_req(&cfg, version_req)?;
+            let version = warm(&cache_dir, &source, &req, allow_unverified)?;
+            writeln!(out, "cached {version} in {cache_dir}")?;
+            Ok(())
+        }
+        CacheCommand::Verify => verify(&mut out, &cache_dir),
+        CacheCommand::Prune { keep } => prune(&mut out, &cache_dir, keep),
+        CacheCommand::Import {
+            archive,
+            sha256,
+            allow_unverified,
+        } => {
+            let version =
+                import_archive(&archive, sha256.as_deref(), allow_unverified, &cache_dir)?;
+            writeln!(out, "imported {version} into {cache_dir}")?;
+            Ok(())
+        }
+    }
+}
+
+fn list(out: &mut impl Write, cache_dir: &Utf8Path) -> Result<()> {
+    for entry in list_cached_versions(cache_dir)? {
+        let state = if entry.complete {
+            "complete"
+        } else {
+            "incomplete"
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}",
+            entry.version, entry.size_bytes, state, entry.path
+        )?;
+    }
+    Ok(())
+}
+
+/// Resolves the requirement to warm, defaulting to `PG_VERSION_REQ`.
+fn resolve_version_req(cfg: &PgEnvCfg, version_req: Option<String>) -> Result<VersionReq> {
+    let raw_req = version_req
+        .or_else(|| cfg.version_req.clone())
+        .unwrap_or_else(|| "*".to_owned());
+    VersionReq::parse(&raw_req).with_context(|| format!("invalid version requirement '{raw_req}'"))
+}
+
+/// Caches a matching version, preferring `PG_BINARY_ARCHIVE` when set.
+fn warm(
+    cache_dir: &Utf8Path,
+    source: &PgBinarySourceCfg,
+    req: &VersionReq,
+    allow_unverified: bool,
+) -> Result<Version> {
+    if let Some(archive) = source.binary_archive.as_deref() {
+        ensure_archive_satisfies(archive, req)?;
+        return Ok(import_archive(
+            archive,
+            source.binary_archive_sha256.as_deref(),
+            allow_unverified,
+            cache_dir,
+        )?);
+    }
+    let releases_url = source
+        .releases_url
+        .clone()
+        .unwrap_or_else(|| postgresql_embedded::Settings::default().releases_url);
+    Ok(warm_cache(cache_dir, req, &releases_url, allow_unverified)?)
+}
+
+fn verify(out: &mut impl Write, cache_dir: &Utf8Path) -> Result<()> {
+    let reports = verify_cache(cache_dir)?;
+    for report in &reports {
+        write_verification(out, report)?;
+    }
+    let broken = reports.iter().filter(|report| !report.is_valid()).count();
+    if broken > 0 {
+        bail!("{broken} cache entries in {cache_dir} failed verification");
+    }
+    Ok(())
+}
+
+fn write_verification(out: &mut impl Write, report: &CacheVerification) -> io::Result<()> {
+    if report.is_valid() {
+        return writeln!(out, "{}\tok", report.version);
+    }
+    for problem in &report.problems {
+        writeln!(out, "{}\t{problem}", report.version)?;
+    }
+    Ok(())
+}
 
-fn main() -> color_eyre::eyre::Result<()> {
-    pg_embedded_setup_unpriv::run().map_err(|err| color_eyre::eyre::eyre!(err))?;
+fn prune(out: &mut impl Write, cache_dir: &Utf8Path, keep: usize) -> Result<()> {
+    for version in prune_cache(cache_dir, keep)? {
+        writeln!(out, "removed {version}")?;
+    }
     Ok(())
 }
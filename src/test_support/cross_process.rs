@@ -0,0 +1,548 @@
+//! Cluster sharing across test processes.
+//!
+//! `cargo nextest` runs every test in its own process, so the per-process
+//! singletons in `shared_singleton` still bootstrap one cluster per test. This
+//! module lets those processes share a single postmaster instead:
+//!
+//! - The first process starts the cluster and writes a discovery file (port,
+//!   data directory, credentials, owner PID, worker location, and
+//!   authentication rules) into a per-key directory under `$XDG_RUNTIME_DIR`
+//!   or the system temporary directory.
+//! - Later processes read the discovery file, confirm the postmaster is still
+//!   alive via `postmaster.pid`, and attach to it, adopting the cluster's
+//!   environment (`PGPASSFILE`, `HOME`, XDG directories, and time zone) as the
+//!   starting process did.
+//! - Every attached process holds a shared `flock(2)` on a users lock for its
+//!   lifetime. At exit, the process that can upgrade that lock to exclusive is
+//!   the last user, so it stops the postmaster and removes the discovery file.
+//!
+//! Reading or writing the discovery file always happens under an exclusive
+//! coordination lock, so attaching never races with the final shutdown. Locks
+//! held by a crashed process are released by the kernel; if the last user
+//! crashes, the postmaster keeps running and the next run attaches to it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{WrapErr, eyre};
+use serde::{Deserialize, Serialize};
+use serde_with::{DurationSeconds, serde_as};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::fixtures::ensure_worker_env;
+use crate::cluster::{postmaster_is_running, stop_unowned_cluster};
+use crate::env::apply_process_env;
+use crate::error::BootstrapResult;
+use crate::observability::LOG_TARGET;
+use crate::worker::SettingsSnapshot;
+use crate::{
+    CleanupMode, ClientSettings, ExecutionMode, ExecutionPrivileges, HbaConfig,
+    TestBootstrapEnvironment, TestBootstrapSettings, TestCluster, detect_execution_privileges,
+};
+
+/// Environment variable naming the shared cluster.
+///
+/// Processes that agree on the key share a cluster. When unset, the key is
+/// derived from the effective user and the current working directory, which
+/// `cargo` sets to the package root for every test binary.
+pub const SHARED_CLUSTER_KEY_ENV: &str = "PG_SHARED_CLUSTER_KEY";
+
+const DISCOVERY_FILE: &str = "cluster.json";
+const COORDINATION_LOCK_FILE: &str = "coordination.lock";
+const USERS_LOCK_FILE: &str = "users.lock";
+
+/// Contents of the discovery file published by the process that started the
+/// cluster.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+struct Discovery {
+    owner_pid: u32,
+    settings: SettingsSnapshot,
+    environment: TestBootstrapEnvironment,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    setup_timeout: Duration,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    start_timeout: Duration,
+    #[serde_as(as = "DurationSeconds<u64>")]
+    shutdown_timeout: Duration,
+    cleanup_mode: CleanupMode,
+    execution_mode: ExecutionMode,
+    worker_binary: Option<Utf8PathBuf>,
+    embedded_worker: bool,
+    forward_server_log: bool,
+    hba: HbaConfig,
+    client: ClientSettings,
+}
+
+impl Discovery {
+    fn from_bootstrap(bootstrap: &TestBootstrapSettings) -> BootstrapResult<Self> {
+        Ok(Self {
+            owner_pid: std::process::id(),
+            settings: SettingsSnapshot::try_from(&bootstrap.settings)?,
+            environment: bootstrap.environment.clone(),
+            setup_timeout: bootstrap.setup_timeout,
+            start_timeout: bootstrap.start_timeout,
+            shutdown_timeout: bootstrap.shutdown_timeout,
+            cleanup_mode: bootstrap.cleanup_mode,
+            execution_mode: bootstrap.execution_mode,
+            worker_binary: bootstrap.worker_binary.clone(),
+            embedded_worker: bootstrap.embedded_worker,
+            forward_server_log: bootstrap.forward_server_log,
+            hba: bootstrap.hba.clone(),
+            client: bootstrap.client.clone(),
+        })
+    }
+
+    /// Rebuilds the starting process's settings, including how it reached
+    /// the worker, so the last user can stop a cluster started by root.
+    ///
+    /// TLS is part of the server configuration carried by `settings`.
+    fn into_bootstrap(
+        self,
+        privileges: ExecutionPrivileges,
+    ) -> BootstrapResult<TestBootstrapSettings> {
+        Ok(TestBootstrapSettings {
+            privileges,
+            execution_mode: self.execution_mode,
+            settings: self.settings.into_settings()?,
+            environment: self.environment,
+            worker_binary: self.worker_binary,
+            embedded_worker: self.embedded_worker,
+            setup_timeout: self.setup_timeout,
+            start_timeout: self.start_timeout,
+            shutdown_timeout: self.shutdown_timeout,
+            cleanup_mode: self.cleanup_mode,
+            forward_server_log: self.forward_server_log,
+            hba: self.hba,
+            client: self.client,
+            binary_cache_dir: None,
+            binary_archive: None,
+            binary_archive_sha256: None,
+            binary_archive_allow_unverified: false,
+            system_binaries: None,
+        })
+    }
+}
+
+/// `flock(2)` guard over a file in the shared cluster directory.
+#[derive(Debug)]
+struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    fn exclusive(path: &Utf8Path) -> io::Result<Self> {
+        Self::acquire(path, libc::LOCK_EX)
+    }
+
+    fn shared(path: &Utf8Path) -> io::Result<Self> {
+        Self::acquire(path, libc::LOCK_SH)
+    }
+
+    fn acquire(path: &Utf8Path, operation: libc::c_int) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        flock(&file, operation)?;
+        Ok(Self { file })
+    }
+
+    /// Converts the lock to exclusive without blocking.
+    ///
+    /// Returns `false` when another open file description still holds the
+    /// lock. Linux does not convert locks atomically, so callers must hold the
+    /// coordination lock to keep new holders out while the conversion runs.
+    fn try_upgrade(&self) -> io::Result<bool> {
+        match flock(&self.file, libc::LOCK_EX | libc::LOCK_NB) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn flock(file: &File, operation: libc::c_int) -> io::Result<()> {
+    loop {
+        // SAFETY: The descriptor belongs to `file`, which the caller borrows
+        // for the duration of this call, so it stays open while `flock` runs.
+        let result = unsafe { libc::flock(file.as_raw_fd(), operation) };
+        if result == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+        // EINTR: signal interrupted syscall, retry.
+    }
+}
+
+/// Membership of this process in a shared cluster, released at exit.
+struct Lease {
+    dir: Utf8PathBuf,
+    users: FileLock,
+    bootstrap: TestBootstrapSettings,
+}
+
+/// Lease held by this process; read by the atexit callback.
+static LEASE: Mutex<Option<Lease>> = Mutex::new(None);
+
+/// Attaches to the cluster published under the shared key, starting and
+/// publishing a new one when none is running.
+pub(super) fn attach_or_start() -> BootstrapResult<TestBootstrapSettings> {
+    let dir = shared_cluster_dir()?;
+    fs::create_dir_all(&dir)
+        .wrap_err(format!("failed to create shared cluster directory '{dir}'"))?;
+    let _coordination = FileLock::exclusive(&dir.join(COORDINATION_LOCK_FILE))
+        .wrap_err(format!("failed to lock shared cluster directory '{dir}'"))?;
+
+    let bootstrap = match read_live_discovery(&dir)? {
+        Some(bootstrap) => {
+            info!(
+                target: LOG_TARGET,
+                dir = %dir,
+                port = bootstrap.settings.port,
+                "attached to shared cluster started by another process"
+            );
+            adopt_environment(&bootstrap);
+            bootstrap
+        }
+        None => start_and_publish(&dir)?,
+    };
+
+    let users = FileLock::shared(&dir.join(USERS_LOCK_FILE))
+        .wrap_err(format!("failed to register with shared cluster in '{dir}'"))?;
+    register_lease(Lease {
+        dir,
+        users,
+        bootstrap: bootstrap.clone(),
+    })?;
+    Ok(bootstrap)
+}
+
+/// Applies the shared cluster's environment for the rest of this process.
+///
+/// The starting process keeps it applied by forgetting its cluster guard, so
+/// libpq-based tools behave the same in every process using the cluster.
+fn adopt_environment(bootstrap: &TestBootstrapSettings) {
+    apply_process_env(&bootstrap.environment.to_env());
+}
+
+/// Reads the discovery file, discarding it when its postmaster has stopped.
+fn read_live_discovery(dir: &Utf8Path) -> BootstrapResult<Option<TestBootstrapSettings>> {
+    let path = dir.join(DISCOVERY_FILE);
+    let Some(discovery) = read_discovery(&path)? else {
+        return Ok(None);
+    };
+
+    let owner_pid = discovery.owner_pid;
+    let bootstrap = discovery.into_bootstrap(detect_execution_privileges())?;
+    if postmaster_is_running(&bootstrap.settings.data_dir) {
+        return Ok(Some(bootstrap));
+    }
+    warn!(
+        target: LOG_TARGET,
+        path = %path,
+        owner_pid,
+        "discarding discovery file for a shared cluster that is no longer running"
+    );
+    discard_discovery(&path);
+    Ok(None)
+}
+
+/// Parses the discovery file, discarding it when it cannot be decoded.
+fn read_discovery(path: &Utf8Path) -> BootstrapResult<Option<Discovery>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(
+                eyre!("failed to read shared cluster discovery file '{path}': {err}").into(),
+            );
+        }
+    };
+
+    match serde_json::from_str::<Discovery>(&contents) {
+        Ok(discovery) => Ok(Some(discovery)),
+        Err(err) => {
+            warn!(
+                target: LOG_TARGET,
+                path = %path,
+                error = %err,
+                "discarding unreadable shared cluster discovery file"
+            );
+            discard_discovery(path);
+            Ok(None)
+        }
+    }
+}
+
+/// Starts a cluster that outlives this process and publishes its discovery
+/// file.
+fn start_and_publish(dir: &Utf8Path) -> BootstrapResult<TestBootstrapSettings> {
+    let worker_guard = ensure_worker_env();
+    let (handle, guard) = TestCluster::new_split()?;
+    // Forget the guard so the postmaster keeps running after this process
+    // exits; the last process holding a lease stops it instead.
+    std::mem::forget(guard.with_worker_guard(worker_guard));
+
+    let bootstrap = handle.bootstrap().clone();
+    if let Err(err) =
+        Discovery::from_bootstrap(&bootstrap).and_then(|discovery| write_discovery(dir, &discovery))
+    {
+        stop_unowned_cluster(&bootstrap, "shared-cluster-publish-failed");
+        return Err(err);
+    }
+    info!(
+        target: LOG_TARGET,
+        dir = %dir,
+        port = bootstrap.settings.port,
+        "started shared cluster for other test processes"
+    );
+    Ok(bootstrap)
+}
+
+/// Writes the discovery file atomically with owner-only permissions, since it
+/// carries the superuser password.
+fn write_discovery(dir: &Utf8Path, discovery: &Discovery) -> BootstrapResult<()> {
+    let path = dir.join(DISCOVERY_FILE);
+    let staging = dir.join(format!("{DISCOVERY_FILE}.{}.tmp", std::process::id()));
+    let encoded =
+        serde_json::to_vec(discovery).wrap_err("failed to encode shared cluster discovery file")?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&staging)
+        .wrap_err(format!("failed to create '{staging}'"))?;
+    file.write_all(&encoded)
+        .and_then(|()| file.sync_all())
+        .wrap_err(format!("failed to write '{staging}'"))?;
+    fs::rename(&staging, &path).wrap_err(format!("failed to publish '{path}'"))?;
+    Ok(())
+}
+
+fn discard_discovery(path: &Utf8Path) {
+    if let Err(err) = fs::remove_file(path) {
+        warn!(
+            target: LOG_TARGET,
+            path = %path,
+            error = %err,
+            "failed to remove stale shared cluster discovery file"
+        );
+    }
+}
+
+fn register_lease(lease: Lease) -> BootstrapResult<()> {
+    let mut slot = LEASE.lock().unwrap_or_else(PoisonError::into_inner);
+    if slot.is_none() {
+        // SAFETY: `release_lease_at_exit` is an `extern "C"` function with no
+        // parameters and no return value, matching `atexit(3)`. It only reads
+        // the `LEASE` static, which lives for the whole process.
+        let rc = unsafe { libc::atexit(release_lease_at_exit) };
+        if rc != 0 {
+            return Err(eyre!("libc::atexit registration failed (rc={rc})").into());
+        }
+    }
+    *slot = Some(lease);
+    Ok(())
+}
+
+/// Callback invoked by the C runtime during process exit.
+extern "C" fn release_lease_at_exit() {
+    let Ok(mut slot) = LEASE.try_lock() else {
+        // Poisoned or held by another thread; bail rather than block in atexit.
+        return;
+    };
+    if let Some(lease) = slot.take() {
+        release_lease(&lease);
+    }
+}
+
+/// Drops this process's lease and stops the cluster if no other process
+/// still holds one.
+fn release_lease(lease: &Lease) {
+    let Ok(_coordination) = FileLock::exclusive(&lease.dir.join(COORDINATION_LOCK_FILE)) else {
+        return;
+    };
+    if !matches!(lease.users.try_upgrade(), Ok(true)) {
+        return;
+    }
+    stop_unowned_cluster(&lease.bootstrap, "shared-cluster-last-user");
+    drop(fs::remove_file(lease.dir.join(DISCOVERY_FILE)));
+}
+
+/// Resolves the directory holding the discovery file and locks for the
+/// configured shared cluster key.
+fn shared_cluster_dir() -> BootstrapResult<Utf8PathBuf> {
+    let key = match std::env::var(SHARED_CLUSTER_KEY_ENV) {
+        Ok(key) if !key.is_empty() => key,
+        _ => {
+            let cwd = std::env::current_dir().wrap_err("failed to read current directory")?;
+            default_cluster_key(&cwd, nix::unistd::geteuid().as_raw())
+        }
+    };
+    validate_key(&key)?;
+
+    let raw_base = std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(std::env::temp_dir, PathBuf::from);
+    let base = Utf8PathBuf::from_path_buf(raw_base).map_err(|path| {
+        eyre!(
+            "shared cluster base directory must be valid UTF-8: {}",
+            path.display()
+        )
+    })?;
+    Ok(base.join("pg-embedded").join("shared-clusters").join(key))
+}
+
+/// Derives a key unique to the effective user and working directory.
+fn default_cluster_key(cwd: &Path, uid: u32) -> String {
+    let digest = format!("{:x}", Sha256::digest(cwd.as_os_str().as_encoded_bytes()));
+    let short = digest.get(..16).unwrap_or(&digest);
+    format!("{uid}-{short}")
+}
+
+/// Rejects keys that would escape the shared cluster directory.
+fn validate_key(key: &str) -> BootstrapResult<()> {
+    let mut components = Path::new(key).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(
+            eyre!("{SHARED_CLUSTER_KEY_ENV} must be a single path component, got '{key}'").into(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::dummy_settings;
+    use rstest::rstest;
+    use std::ffi::OsString;
+
+    /// Encodes `bootstrap` as a discovery file and restores it as a root
+    /// attacher would.
+    fn round_trip(bootstrap: &TestBootstrapSettings) -> TestBootstrapSettings {
+        let encoded = serde_json::to_string(
+            &Discovery::from_bootstrap(bootstrap).expect("discovery from bootstrap"),
+        )
+        .expect("encode discovery");
+        let decoded: Discovery = serde_json::from_str(&encoded).expect("decode discovery");
+        assert_eq!(decoded.owner_pid, std::process::id());
+        decoded
+            .into_bootstrap(ExecutionPrivileges::Root)
+            .expect("bootstrap from discovery")
+    }
+
+    #[test]
+    fn discovery_round_trips_connection_details() {
+        let mut bootstrap = dummy_settings(ExecutionPrivileges::Root);
+        bootstrap.settings.port = 54_329;
+        bootstrap.settings.password = "shared-secret".into();
+        bootstrap.cleanup_mode = CleanupMode::Full;
+
+        let restored = round_trip(&bootstrap);
+
+        assert_eq!(restored.settings.port, 54_329);
+        assert_eq!(restored.settings.password, "shared-secret");
+        assert_eq!(restored.cleanup_mode, CleanupMode::Full);
+        assert_eq!(restored.execution_mode, ExecutionMode::Subprocess);
+        assert_eq!(
+            restored.environment.timezone,
+            bootstrap.environment.timezone
+        );
+    }
+
+    #[test]
+    fn discovery_restores_worker_and_authentication_settings() {
+        let mut bootstrap = dummy_settings(ExecutionPrivileges::Root);
+        bootstrap.execution_mode = ExecutionMode::PersistentWorker;
+        bootstrap.worker_binary = Some(Utf8PathBuf::from("/opt/pg/pg_worker"));
+        bootstrap.embedded_worker = true;
+        bootstrap.forward_server_log = true;
+        bootstrap.hba = HbaConfig {
+            rules: vec![crate::HbaRule::host("127.0.0.1/32", crate::AuthMethod::Md5).user("app")],
+            ..HbaConfig::default()
+        };
+        crate::cluster::enable_tls(&mut bootstrap.settings);
+
+        let restored = round_trip(&bootstrap);
+
+        assert_eq!(restored.execution_mode, ExecutionMode::PersistentWorker);
+        assert_eq!(restored.worker_binary, bootstrap.worker_binary);
+        assert!(restored.embedded_worker);
+        assert!(restored.forward_server_log);
+        assert_eq!(restored.hba, bootstrap.hba);
+        assert_eq!(
+            restored
+                .settings
+                .configuration
+                .get("ssl")
+                .map(String::as_str),
+            Some("on")
+        );
+    }
+
+    #[test]
+    fn attaching_adopts_the_cluster_environment() {
+        let bootstrap = dummy_settings(ExecutionPrivileges::Unprivileged);
+        let expected = bootstrap.environment.to_env();
+        // Restores the caller's values once the test finishes.
+        let _restore = crate::test_support::scoped_env(
+            expected
+                .iter()
+                .map(|(key, _)| (OsString::from(key), std::env::var_os(key))),
+        );
+
+        adopt_environment(&bootstrap);
+
+        for (key, value) in expected {
+            assert_eq!(std::env::var(&key).ok(), value, "{key}");
+        }
+    }
+
+    #[test]
+    fn upgrade_fails_while_another_process_holds_the_users_lock() {
+        let temp = tempfile::tempdir().expect("tempdir");
+        let path =
+            Utf8PathBuf::from_path_buf(temp.path().join(USERS_LOCK_FILE)).expect("utf8 path");
+
+        // Separate opens behave like separate processes for `flock(2)`.
+        let first = FileLock::shared(&path).expect("first shared lock");
+        let second = FileLock::shared(&path).expect("second shared lock");
+        assert!(!first.try_upgrade().expect("try upgrade"));
+
+        drop(second);
+        assert!(first.try_upgrade().expect("try upgrade"));
+    }
+
+    #[test]
+    fn default_key_differs_by_directory_and_user() {
+        let key = default_cluster_key(Path::new("/work/crate"), 1000);
+
+        assert!(key.starts_with("1000-"));
+        assert_ne!(key, default_cluster_key(Path::new("/work/other"), 1000));
+        assert_ne!(key, default_cluster_key(Path::new("/work/crate"), 0));
+        assert!(validate_key(&key).is_ok());
+    }
+
+    #[rstest]
+    #[case::parent("..")]
+    #[case::nested("a/b")]
+    #[case::absolute("/tmp")]
+    fn rejects_keys_outside_the_shared_directory(#[case] key: &str) {
+        assert!(validate_key(key).is_err());
+    }
+}
@@ -11,8 +11,9 @@ use tokio::runtime::{Builder, Runtime};
 
 use super::worker_env;
 use crate::{
-    CleanupMode, ClusterHandle, ExecutionMode, ExecutionPrivileges, TestBootstrapEnvironment,
-    TestBootstrapSettings, TestCluster, detect_execution_privileges, env::ScopedEnv,
+    CleanupMode, ClusterHandle, DatabaseName, ExecutionMode, ExecutionPrivileges,
+    TemporaryDatabase, TestBootstrapEnvironment, TestBootstrapSettings, TestCluster,
+    detect_execution_privileges, env::ScopedEnv,
 };
 use postgresql_embedded::Settings;
 
@@ -78,11 +79,19 @@ pub fn dummy_settings(privileges: ExecutionPrivileges) -> TestBootstrapSettings
         settings: Settings::default(),
         environment: dummy_environment(),
         worker_binary: None,
+        embedded_worker: false,
         setup_timeout: Duration::from_secs(180),
         start_timeout: Duration::from_secs(60),
         shutdown_timeout: Duration::from_secs(15),
         cleanup_mode: CleanupMode::default(),
+        forward_server_log: false,
+        hba: crate::HbaConfig::default(),
+        client: crate::ClientSettings::default(),
         binary_cache_dir: None,
+        binary_archive: None,
+        binary_archive_sha256: None,
+        binary_archive_allow_unverified: false,
+        system_binaries: None,
     }
 }
 
@@ -102,8 +111,10 @@ pub fn test_cluster() -> TestCluster {
 /// Ensures `PG_EMBEDDED_WORKER` is set when privileged test runs require it.
 ///
 /// Returns `Some(ScopedEnv)` when the helper configures the environment, and
-/// `None` when no changes are needed (for example, when already unprivileged
-/// or when `PG_EMBEDDED_WORKER` is present).
+/// `None` when no changes are needed (for example, when already unprivileged,
+/// when `PG_EMBEDDED_WORKER` is present, or when Linux root runs without a
+/// `pg_worker` binary fall back to re-executing the test binary under the
+/// `embedded-worker` feature).
 ///
 /// # Examples
 ///
@@ -139,7 +150,8 @@ fn is_worker_env_required(privileges: ExecutionPrivileges, worker_env_present: b
 ///
 /// Returns `Some(path)` when running as root without `PG_EMBEDDED_WORKER` set,
 /// `None` when no worker setup is needed (unprivileged or already configured).
-/// Panics if root execution requires a worker but none can be found.
+/// When no worker binary can be found, Linux builds with the `embedded-worker`
+/// feature fall back to the embedded worker and other builds panic.
 fn resolve_worker_path(
     privileges: ExecutionPrivileges,
     worker_env_present: bool,
@@ -149,16 +161,17 @@ fn resolve_worker_path(
         return None;
     }
 
-    let Some(worker) = worker_finder() else {
-        panic!(
-            "SKIP-TEST-CLUSTER: PG_EMBEDDED_WORKER is not set and pg_worker binary was not found"
-        );
-    };
-
-    Some(worker)
+    let worker = worker_finder();
+    assert!(
+        worker.is_some() || cfg!(all(target_os = "linux", feature = "embedded-worker")),
+        "SKIP-TEST-CLUSTER: PG_EMBEDDED_WORKER is not set and pg_worker binary was not found"
+    );
+    worker
 }
 
 // Re-export shared singleton functions from submodule.
+#[cfg(unix)]
+pub use super::shared_singleton::cross_process_cluster_handle;
 pub use super::shared_singleton::{shared_cluster, shared_cluster_handle};
 
 // ============================================================================
@@ -212,6 +225,63 @@ pub fn shared_test_cluster_handle() -> &'static ClusterHandle {
     }
 }
 
+/// rstest fixture returning a `ClusterHandle` shared across test processes.
+///
+/// See [`cross_process_cluster_handle`] for how processes discover and share
+/// the cluster.
+///
+/// Panics if the cluster cannot be started or attached, enabling tests to fail
+/// fast with a clear error message.
+#[cfg(unix)]
+#[must_use]
+#[cfg_attr(not(doc), fixture)]
+pub fn cross_process_test_cluster_handle() -> &'static ClusterHandle {
+    match cross_process_cluster_handle() {
+        Ok(handle) => handle,
+        Err(err) => panic!(
+            "SKIP-TEST-CLUSTER: cross_process_test_cluster_handle fixture failed to start PostgreSQL: {err:?}"
+        ),
+    }
+}
+
+/// rstest fixture returning a temporary database on the shared cluster.
+///
+/// The database is named after the running test via
+/// [`DatabaseName::for_current_test`] and cloned from `template` when one is
+/// given with `#[with(Some("template"))]`.
+///
+/// Panics if the shared cluster cannot be started or the database cannot be
+/// created.
+#[cfg(not(doc))]
+#[must_use]
+#[fixture]
+pub fn test_database(#[default(None)] template: Option<&'static str>) -> TemporaryDatabase {
+    temporary_test_database(template)
+}
+
+/// Creates a uniquely named temporary database on the shared cluster,
+/// optionally cloned from `template`.
+pub(super) fn temporary_test_database(template: Option<&str>) -> TemporaryDatabase {
+    let handle = match shared_cluster_handle() {
+        Ok(handle) => handle,
+        Err(err) => {
+            panic!("SKIP-TEST-CLUSTER: test_database fixture failed to start PostgreSQL: {err:?}")
+        }
+    };
+    let name = DatabaseName::for_current_test();
+    let created = template.map_or_else(
+        || handle.temporary_database(name.clone()),
+        |source| handle.temporary_database_from_template(name.clone(), source),
+    );
+    match created {
+        Ok(database) => database,
+        Err(err) => panic!(
+            "test_database fixture failed to create database '{}': {err:?}",
+            name.as_str()
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,8 +340,20 @@ mod tests {
         );
     }
 
-    /// Privileged users without `PG_EMBEDDED_WORKER` and without a locatable
-    /// worker binary should trigger the skip panic.
+    /// Privileged users on Linux without `PG_EMBEDDED_WORKER` and without a
+    /// locatable worker binary leave the environment alone, so bootstrap
+    /// re-executes the test binary as the worker.
+    #[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+    #[test]
+    fn privileged_user_without_worker_binary_uses_embedded_worker() {
+        let result = resolve_worker_path(ExecutionPrivileges::Root, false, || None);
+        assert_eq!(result, None, "should defer to the embedded worker");
+    }
+
+    /// Privileged users without `PG_EMBEDDED_WORKER`, without a locatable
+    /// worker binary, and without the embedded worker should trigger the skip
+    /// panic.
+    #[cfg(not(all(target_os = "linux", feature = "embedded-worker")))]
     #[test]
     #[should_panic(expected = "SKIP-TEST-CLUSTER")]
     fn privileged_user_without_worker_binary_panics() {
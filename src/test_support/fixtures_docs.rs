@@ -6,6 +6,7 @@
 //! `function_attrs_follow_docs` lint happy while preserving documentation.
 
 use crate::ClusterHandle;
+use crate::TemporaryDatabase;
 use crate::TestCluster;
 use crate::test_support::fixtures as runtime_fixtures;
 
@@ -93,3 +94,71 @@ pub fn shared_test_cluster() -> &'static TestCluster {
 pub fn shared_test_cluster_handle() -> &'static ClusterHandle {
     runtime_fixtures::shared_test_cluster_handle()
 }
+
+/// `rstest` fixture that yields a [`ClusterHandle`] shared across test
+/// processes.
+///
+/// The first process to request the fixture starts the cluster and publishes a
+/// discovery file; later processes, such as those spawned by `cargo nextest`,
+/// attach to the same postmaster. The last process to exit stops it. Set
+/// `PG_SHARED_CLUSTER_KEY` to choose which processes share a cluster.
+///
+/// # Panics
+///
+/// Panics with a `SKIP-TEST-CLUSTER:`-prefixed message if the shared cluster
+/// cannot be started or attached.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pg_embedded_setup_unpriv::ClusterHandle;
+/// use pg_embedded_setup_unpriv::test_support::cross_process_test_cluster_handle;
+/// use rstest::rstest;
+///
+/// #[rstest]
+/// fn uses_cross_process_handle(cross_process_test_cluster_handle: &'static ClusterHandle) {
+///     let metadata = cross_process_test_cluster_handle.connection().metadata();
+///     assert!(metadata.port() > 0);
+/// }
+/// ```
+#[cfg(unix)]
+#[must_use]
+pub fn cross_process_test_cluster_handle() -> &'static ClusterHandle {
+    runtime_fixtures::cross_process_test_cluster_handle()
+}
+
+/// `rstest` fixture that yields a [`TemporaryDatabase`] on the shared cluster.
+///
+/// Each test receives its own database, named after the test by
+/// [`DatabaseName::for_current_test`](crate::DatabaseName::for_current_test)
+/// so parallel tests never collide, and dropped when the test ends. Pass
+/// `#[with(Some("template"))]` to clone an existing template instead of
+/// creating an empty database.
+///
+/// # Panics
+///
+/// Panics with a `SKIP-TEST-CLUSTER:`-prefixed message if the shared cluster
+/// cannot be started, and with a plain message if the database cannot be
+/// created.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pg_embedded_setup_unpriv::TemporaryDatabase;
+/// use pg_embedded_setup_unpriv::test_support::test_database;
+/// use rstest::rstest;
+///
+/// #[rstest]
+/// fn uses_own_database(test_database: TemporaryDatabase) {
+///     assert!(test_database.url().contains(test_database.name()));
+/// }
+///
+/// #[rstest]
+/// fn uses_migrated_copy(#[with(Some("migrated"))] test_database: TemporaryDatabase) {
+///     assert!(!test_database.name().is_empty());
+/// }
+/// ```
+#[must_use]
+pub fn test_database(template: Option<&'static str>) -> TemporaryDatabase {
+    runtime_fixtures::temporary_test_database(template)
+}
@@ -6,6 +6,8 @@
 //! helper registers a closure for the duration of a `HookGuard`, ensuring
 //! `TestCluster` calls are observable without leaking state across suites.
 
+#[cfg(unix)]
+mod cross_process;
 mod errors;
 mod filesystem;
 mod fixtures;
@@ -30,19 +32,29 @@ mod fixtures_docs;
     any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker")
 ))]
 pub use crate::cluster::{process_is_running, read_postmaster_pid};
+#[cfg(unix)]
+pub use cross_process::SHARED_CLUSTER_KEY_ENV;
 #[cfg(any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker"))]
 pub use errors::{bootstrap_error, privilege_error};
 pub use filesystem::ambient_dir_and_path;
 #[cfg(any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker"))]
 pub use filesystem::{CapabilityTempDir, ensure_dir_exists, metadata, set_permissions};
+#[cfg(unix)]
+pub use fixtures::cross_process_cluster_handle;
+#[cfg(all(unix, not(doc)))]
+pub use fixtures::cross_process_test_cluster_handle;
 pub use fixtures::{
     dummy_environment, dummy_settings, ensure_worker_env, shared_cluster, shared_cluster_handle,
     test_runtime,
 };
 #[cfg(not(doc))]
-pub use fixtures::{shared_test_cluster, shared_test_cluster_handle, test_cluster};
+pub use fixtures::{shared_test_cluster, shared_test_cluster_handle, test_cluster, test_database};
+#[cfg(all(unix, doc))]
+pub use fixtures_docs::cross_process_test_cluster_handle;
 #[cfg(doc)]
-pub use fixtures_docs::{shared_test_cluster, shared_test_cluster_handle, test_cluster};
+pub use fixtures_docs::{
+    shared_test_cluster, shared_test_cluster_handle, test_cluster, test_database,
+};
 pub use hash::hash_directory;
 #[cfg(any(doc, test, feature = "cluster-unit-tests", feature = "dev-worker"))]
 pub use hook::{
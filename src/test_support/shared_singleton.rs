@@ -74,7 +74,36 @@ enum SharedHandleState {
 /// # }
 /// ```
 pub fn shared_cluster_handle() -> BootstrapResult<&'static ClusterHandle> {
-    let mutex = SHARED_CLUSTER_HANDLE.get_or_init(|| Mutex::new(SharedHandleState::Uninitialised));
+    get_or_init_handle(&SHARED_CLUSTER_HANDLE, || {
+        let worker_guard = ensure_worker_env();
+        let (handle, cluster_guard) = TestCluster::new_split()?;
+        // Attach worker guard to cluster guard, then leak it.
+        // The guard manages shutdown; leaking it means the cluster
+        // runs for the process lifetime.
+        let guarded = cluster_guard.with_worker_guard(worker_guard);
+
+        // Best-effort atexit registration. On Unix this sends
+        // SIGTERM to the postmaster on process exit. On other
+        // platforms it is a silent no-op. Failure is non-fatal:
+        // the cluster remains usable, but the postmaster may be
+        // orphaned when the process terminates.
+        best_effort_register_shutdown_hook_for_handle(&handle);
+
+        // Leak the guard so the cluster keeps running.
+        // This is intentional: shared clusters live for the entire
+        // process lifetime.
+        std::mem::forget(guarded);
+        Ok(handle)
+    })
+}
+
+/// Returns the leaked handle stored in `cell`, initialising it with `init` on
+/// first access.
+fn get_or_init_handle(
+    cell: &'static OnceLock<Mutex<SharedHandleState>>,
+    init: impl FnOnce() -> BootstrapResult<ClusterHandle>,
+) -> BootstrapResult<&'static ClusterHandle> {
+    let mutex = cell.get_or_init(|| Mutex::new(SharedHandleState::Uninitialised));
     let mut guard = mutex
         .lock()
         .unwrap_or_else(std::sync::PoisonError::into_inner);
@@ -88,45 +117,73 @@ pub fn shared_cluster_handle() -> BootstrapResult<&'static ClusterHandle> {
             );
             Err(BootstrapError::new(original_err.kind(), report))
         }
-        SharedHandleState::Uninitialised => {
-            let worker_guard = ensure_worker_env();
-            match TestCluster::new_split() {
-                Ok((handle, cluster_guard)) => {
-                    // Attach worker guard to cluster guard, then leak it.
-                    // The guard manages shutdown; leaking it means the cluster
-                    // runs for the process lifetime.
-                    let guarded = cluster_guard.with_worker_guard(worker_guard);
+        SharedHandleState::Uninitialised => match init() {
+            Ok(handle) => {
+                // Leak the handle to get a 'static reference.
+                let leaked: &'static ClusterHandle = Box::leak(Box::new(handle));
+                *guard = SharedHandleState::Initialised(leaked);
+                Ok(leaked)
+            }
+            Err(err) => {
+                // Store error info for subsequent callers to retrieve.
+                let stored = Arc::new(BootstrapError::new(
+                    err.kind(),
+                    color_eyre::eyre::eyre!("bootstrap failed: {:?}", err),
+                ));
+                *guard = SharedHandleState::Failed(stored);
+                // Return the original error with full diagnostics.
+                Err(err)
+            }
+        },
+    }
+}
 
-                    // Best-effort atexit registration. On Unix this sends
-                    // SIGTERM to the postmaster on process exit. On other
-                    // platforms it is a silent no-op. Failure is non-fatal:
-                    // the cluster remains usable, but the postmaster may be
-                    // orphaned when the process terminates.
-                    best_effort_register_shutdown_hook_for_handle(&handle);
+// ============================================================================
+// Cross-process shared cluster handle
+// ============================================================================
 
-                    // Leak the guard so the cluster keeps running.
-                    // This is intentional: shared clusters live for the entire
-                    // process lifetime.
-                    std::mem::forget(guarded);
+/// Global state for the cross-process shared cluster handle.
+#[cfg(unix)]
+static CROSS_PROCESS_CLUSTER_HANDLE: OnceLock<Mutex<SharedHandleState>> = OnceLock::new();
 
-                    // Leak the handle to get a 'static reference.
-                    let leaked: &'static ClusterHandle = Box::leak(Box::new(handle));
-                    *guard = SharedHandleState::Initialised(leaked);
-                    Ok(leaked)
-                }
-                Err(err) => {
-                    // Store error info for subsequent callers to retrieve.
-                    let stored = Arc::new(BootstrapError::new(
-                        err.kind(),
-                        color_eyre::eyre::eyre!("bootstrap failed: {:?}", err),
-                    ));
-                    *guard = SharedHandleState::Failed(stored);
-                    // Return the original error with full diagnostics.
-                    Err(err)
-                }
-            }
-        }
-    }
+/// Returns a reference to a cluster shared by every test process using the
+/// same shared cluster key.
+///
+/// Under `cargo nextest`, each test runs in its own process, so
+/// [`shared_cluster_handle()`] still bootstraps one cluster per test. This
+/// variant coordinates through a discovery file instead: the first process
+/// starts the cluster, later processes attach to it, and the last process to
+/// exit stops it and applies the configured cleanup mode.
+///
+/// Processes share a cluster when they agree on `PG_SHARED_CLUSTER_KEY`. When
+/// the variable is unset, the key is derived from the effective user and the
+/// current working directory, so all test binaries of one package share a
+/// cluster. Use distinct keys for suites that need different configuration,
+/// since attaching processes reuse whatever the first process started.
+///
+/// # Errors
+///
+/// Returns a [`BootstrapError`] if the shared cluster directory cannot be
+/// prepared or locked, or if a new cluster cannot be started. Once
+/// initialisation fails, subsequent calls in the same process return an error
+/// indicating the previous failure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use pg_embedded_setup_unpriv::test_support::cross_process_cluster_handle;
+///
+/// # fn main() -> pg_embedded_setup_unpriv::BootstrapResult<()> {
+/// let handle = cross_process_cluster_handle()?;
+/// assert!(handle.database_exists("postgres")?);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(unix)]
+pub fn cross_process_cluster_handle() -> BootstrapResult<&'static ClusterHandle> {
+    get_or_init_handle(&CROSS_PROCESS_CLUSTER_HANDLE, || {
+        super::cross_process::attach_or_start().map(ClusterHandle::from)
+    })
 }
 
 // ============================================================================
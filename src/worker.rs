@@ -1,7 +1,8 @@
 //! Serialization helpers for subprocess workers.
 //!
 //! Provides UTF-8 safe snapshots of [`postgresql_embedded::Settings`] so the
-//! worker binary can restore settings and environment state received via IPC.
+//! worker binary can restore settings and environment state received via IPC,
+//! plus the request and response frames exchanged with a persistent worker.
 //!
 //! # Examples
 //! ```no_run
@@ -120,6 +121,9 @@ impl TryFrom<&Settings> for SettingsSnapshot {
 pub struct WorkerPayload {
     pub settings: SettingsSnapshot,
     pub environment: Vec<(String, Option<PlainSecret>)>,
+    /// `pg_hba.conf` contents written into the data directory before start.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pg_hba: Option<String>,
 }
 
 impl WorkerPayload {
@@ -133,8 +137,52 @@ impl WorkerPayload {
                 .into_iter()
                 .map(|(key, value)| (key, value.map(PlainSecret::from)))
                 .collect(),
+            pg_hba: None,
         })
     }
+
+    /// Sets the `pg_hba.conf` contents the worker writes before starting.
+    #[must_use]
+    pub fn with_pg_hba(mut self, pg_hba: Option<String>) -> Self {
+        self.pg_hba = pg_hba;
+        self
+    }
+}
+
+/// Version of the line protocol spoken by `pg_worker serve`.
+///
+/// The worker announces it in [`WorkerResponse::Ready`] and rejects requests
+/// tagged with any other version.
+pub const WORKER_PROTOCOL_VERSION: u32 = 1;
+
+/// Hidden first argument that makes a re-executed program run as the worker.
+pub(crate) const EMBEDDED_WORKER_ARG: &str = "__pg_embedded_setup_unpriv_worker";
+
+/// Request sent to a persistent worker as one JSON line on its stdin.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkerRequestFrame {
+    /// Protocol version the parent speaks.
+    pub protocol: u32,
+    /// Lifecycle operation, spelt as on the one-shot command line.
+    pub operation: String,
+    /// Settings and environment for the operation.
+    pub payload: WorkerPayload,
+}
+
+/// Message a persistent worker writes as one JSON line on its stdout.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorkerResponse {
+    /// Sent once at startup, naming the protocol version the worker speaks.
+    Ready {
+        /// Protocol version the worker speaks.
+        protocol: u32,
+    },
+    /// Outcome of one request; `error` is `None` on success.
+    Done {
+        /// Failure reported by the worker.
+        error: Option<String>,
+    },
 }
 
 impl From<SettingsSnapshot> for Settings {
@@ -203,9 +251,25 @@ impl Serialize for PlainSecret {
     }
 }
 
+/// Runs the worker command line: `serve`, or `<operation> <config.json>`.
+///
+/// The `pg_worker` binary delegates its `main` here; the first argument is the
+/// program name and is ignored.
+///
+/// # Errors
+///
+/// Returns an error when the arguments or payload are invalid or the
+/// requested operation fails.
+#[cfg(unix)]
+pub fn run_cli(
+    args: impl Iterator<Item = std::ffi::OsString>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    crate::worker_entry::run_cli(args).map_err(Into::into)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::PlainSecret;
+    use super::{PlainSecret, WORKER_PROTOCOL_VERSION, WorkerResponse};
 
     #[test]
     fn plain_secret_serializes_as_string() {
@@ -214,6 +278,24 @@ mod tests {
         assert_eq!(encoded, "\"super-secret-value\"");
     }
 
+    #[test]
+    fn worker_responses_are_tagged_json_lines() {
+        let encoded = serde_json::to_string(&WorkerResponse::Done {
+            error: Some("boom".into()),
+        })
+        .expect("serialize response");
+        assert_eq!(encoded, r#"{"type":"done","error":"boom"}"#);
+
+        let decoded: WorkerResponse =
+            serde_json::from_str(r#"{"type":"ready","protocol":1}"#).expect("parse response");
+        assert_eq!(
+            decoded,
+            WorkerResponse::Ready {
+                protocol: WORKER_PROTOCOL_VERSION
+            }
+        );
+    }
+
     #[test]
     fn plain_secret_debug_redacts() {
         let secret = PlainSecret::from("super-secret-value");
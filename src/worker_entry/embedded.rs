@@ -0,0 +1,100 @@
+//! Embedded worker: the current executable re-executed as its own worker.
+//!
+//! Root runs that find no `pg_worker` binary spawn `/proc/self/exe` with
+//! [`EMBEDDED_WORKER_ARG`] ahead of the usual worker arguments. The new
+//! process has to divert into the worker before its own program logic runs.
+//! [`worker_main`] does this for programs that call it first. With the
+//! `embedded-worker` feature on Linux, an `.init_array` hook also does it
+//! before `main`, which covers test binaries whose `main` belongs to the test
+//! harness. The hook and the fallback are opt-in because they let any program
+//! linking the crate be started as a worker.
+
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+
+use crate::worker::EMBEDDED_WORKER_ARG;
+
+#[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+/// Path that re-executes the running program.
+///
+/// The link resolves without searching the directories above the executable,
+/// so the demoted worker can start even when those are closed to "nobody".
+pub(crate) const SELF_EXE: &str = "/proc/self/exe";
+
+/// Runs the embedded worker if this process was started as one, then exits.
+///
+/// With the `embedded-worker` feature, root runs that find no `pg_worker`
+/// binary re-execute the current program with a hidden argument instead. The
+/// feature also diverts such a process into the worker before `main` runs on
+/// Linux, so calling this function is optional there. Programs that do call
+/// it should do so first thing in `main`, before spawning threads or parsing
+/// arguments. In every other process it returns immediately.
+///
+/// # Examples
+///
+/// ```no_run
+/// // First statement of `main`:
+/// pg_embedded_setup_unpriv::worker_main();
+/// ```
+pub fn worker_main() {
+    run_if_requested(std::env::args_os());
+}
+
+/// Runs the worker and exits when the first argument after the program name
+/// is [`EMBEDDED_WORKER_ARG`].
+fn run_if_requested(mut args: impl Iterator<Item = OsString>) {
+    let program = args.next();
+    if args.next().as_deref() != Some(OsStr::new(EMBEDDED_WORKER_ARG)) {
+        return;
+    }
+    let code = match super::run_cli(program.into_iter().chain(args)) {
+        Ok(()) => 0,
+        Err(err) => {
+            // Mirrors the report `pg_worker` prints when `main` fails.
+            drop(writeln!(io::stderr(), "Error: {err:?}"));
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+#[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+mod init_hook {
+    //! Diverts a re-executed process into the worker before `main` runs.
+
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    #[used]
+    #[unsafe(link_section = ".init_array")]
+    static EMBEDDED_WORKER_HOOK: extern "C" fn() = run_before_main;
+
+    extern "C" fn run_before_main() {
+        // `std::env::args` is not reliably populated this early, so read the
+        // arguments from procfs instead.
+        let Ok(cmdline) = std::fs::read("/proc/self/cmdline") else {
+            return;
+        };
+        let args = cmdline.strip_suffix(&[0]).unwrap_or(&cmdline);
+        super::run_if_requested(
+            args.split(|byte| *byte == 0)
+                .map(|arg| OsString::from_vec(arg.to_vec())),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn other_processes_continue_past_the_entry_point() {
+        // Returning at all shows the worker did not run and exit.
+        run_if_requested(
+            ["test-binary", "--exact", "some::test"]
+                .map(OsString::from)
+                .into_iter(),
+        );
+        run_if_requested(std::iter::empty());
+    }
+}
@@ -0,0 +1,411 @@
+//! Worker side of privileged lifecycle operations.
+//!
+//! Deserializes a [`WorkerPayload`] and invokes `postgresql_embedded`, either
+//! once per process (`<operation> <config.json>`) or for every request of a
+//! persistent session (`serve`). The `pg_worker` binary and the embedded
+//! worker entry point both run this code, so the caller can demote
+//! credentials before spawning either.
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use postgresql_embedded::{PostgreSQL, Settings, Status};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    io::{ErrorKind, Read},
+    path::PathBuf,
+};
+use thiserror::Error;
+use tokio::runtime::Builder;
+use tracing::info;
+
+use crate::ambient_dir_and_path;
+use crate::cleanup_helpers;
+use crate::worker::{PlainSecret, WorkerPayload};
+
+mod embedded;
+mod removal;
+mod serve;
+
+#[cfg(all(target_os = "linux", feature = "embedded-worker"))]
+pub(crate) use self::embedded::SELF_EXE;
+pub use self::embedded::worker_main;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Marker file that indicates a valid `PostgreSQL` data directory.
+///
+/// This path is created by `initdb` during successful initialization and is used
+/// to distinguish complete setups from partial or interrupted ones. The stub at
+/// `tests/support/fixtures/pg_ctl_stub.sh` must create this file to match.
+const PG_FILENODE_MAP_MARKER: &str = "global/pg_filenode.map";
+#[derive(Debug, Error)]
+pub(crate) enum WorkerError {
+    #[error("invalid arguments: {0}")]
+    InvalidArgs(String),
+    #[error("failed to read worker config: {0}")]
+    ConfigRead(#[source] BoxError),
+    #[error("failed to parse worker config: {0}")]
+    ConfigParse(#[source] serde_json::Error),
+    #[error("settings conversion failed: {0}")]
+    SettingsConversion(String),
+    #[error("runtime init failed: {0}")]
+    RuntimeInit(#[source] std::io::Error),
+    #[error("postgres operation failed: {0}")]
+    PostgresOperation(String),
+    #[error("cleanup failed: {0}")]
+    CleanupFailed(String),
+    #[error("data dir recovery: {0}")]
+    DataDirRecovery(String),
+    #[error("worker protocol i/o failed: {0}")]
+    Protocol(#[source] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Setup,
+    Start,
+    Stop,
+    Cleanup,
+    CleanupFull,
+}
+
+impl Operation {
+    fn parse(arg: &OsStr) -> Result<Self, WorkerError> {
+        match arg.to_string_lossy().as_ref() {
+            "setup" => Ok(Self::Setup),
+            "start" => Ok(Self::Start),
+            "stop" => Ok(Self::Stop),
+            "cleanup" => Ok(Self::Cleanup),
+            "cleanup-full" => Ok(Self::CleanupFull),
+            other => Err(WorkerError::InvalidArgs(format!(
+                "unknown operation '{other}'; expected setup, start, stop, cleanup, or cleanup-full"
+            ))),
+        }
+    }
+}
+
+/// Argument selecting persistent mode instead of a single operation.
+const SERVE_COMMAND: &str = "serve";
+
+/// Runs the worker command line: `serve`, or `<operation> <config.json>`.
+///
+/// The first argument is the program name and is ignored.
+pub(crate) fn run_cli(args: impl Iterator<Item = OsString>) -> Result<(), WorkerError> {
+    let collected: Vec<OsString> = args.collect();
+    if is_serve_command(collected.iter().cloned()) {
+        return serve::run();
+    }
+    run_worker(collected.into_iter())
+}
+
+/// Reports whether the arguments are exactly `pg_worker serve`.
+fn is_serve_command(mut args: impl Iterator<Item = OsString>) -> bool {
+    let _ = args.next();
+    args.next().is_some_and(|arg| arg == SERVE_COMMAND) && args.next().is_none()
+}
+
+fn run_worker(args: impl Iterator<Item = OsString>) -> Result<(), WorkerError> {
+    let (op, cfg_path) = parse_args(args)?;
+    let payload = load_payload(&cfg_path)?;
+    run_operation(op, payload)
+}
+
+/// Executes one operation described by `payload`.
+fn run_operation(op: Operation, payload: WorkerPayload) -> Result<(), WorkerError> {
+    let settings = payload
+        .settings
+        .into_settings()
+        .map_err(|e| WorkerError::SettingsConversion(e.to_string()))?;
+    let data_dir = extract_data_dir(&settings)?;
+    apply_worker_environment(&payload.environment);
+    match op {
+        Operation::Cleanup => execute_cleanup(&data_dir, None, None),
+        Operation::CleanupFull => {
+            let install_dir = extract_install_dir(&settings)?;
+            if !cleanup_helpers::is_sandboxed_installation(
+                install_dir.as_std_path(),
+                &settings.password_file,
+            ) {
+                // System binaries live outside the sandbox and are never
+                // removed; the sandbox holding `.pgpass` still is.
+                let sandbox = settings
+                    .password_file
+                    .parent()
+                    .and_then(|parent| Utf8Path::from_path(parent));
+                return execute_cleanup(&data_dir, None, sandbox);
+            }
+            let install_root = extract_install_root(&settings, &install_dir)?;
+            execute_cleanup(&data_dir, Some(&install_dir), install_root.as_deref())
+        }
+        Operation::Setup => run_setup_op(settings, &data_dir),
+        Operation::Start => run_start_op(settings, &data_dir, payload.pg_hba.as_deref()),
+        Operation::Stop => run_stop_op(settings),
+    }
+}
+
+fn parse_args(
+    mut args: impl Iterator<Item = OsString>,
+) -> Result<(Operation, Utf8PathBuf), WorkerError> {
+    let _ = args.next();
+    let op = args
+        .next()
+        .ok_or_else(|| WorkerError::InvalidArgs("missing operation".into()))
+        .and_then(|a| Operation::parse(&a))?;
+    let path = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| WorkerError::InvalidArgs("missing config path".into()))?;
+    let cfg = Utf8PathBuf::from_path_buf(path)
+        .map_err(|p| WorkerError::InvalidArgs(format!("config path not UTF-8: {}", p.display())))?;
+    if let Some(e) = args.next() {
+        return Err(WorkerError::InvalidArgs(format!(
+            "unexpected extra argument: {}",
+            e.to_string_lossy()
+        )));
+    }
+    Ok((op, cfg))
+}
+
+fn load_payload(path: &Utf8Path) -> Result<WorkerPayload, WorkerError> {
+    let cfg_err = |e: BoxError| WorkerError::ConfigRead(e);
+    let (dir, rel) = ambient_dir_and_path(path).map_err(|e| cfg_err(e.into()))?;
+    let mut f = dir.open(rel.as_std_path()).map_err(|e| cfg_err(e.into()))?;
+    let mut b = Vec::new();
+    f.read_to_end(&mut b).map_err(|e| cfg_err(e.into()))?;
+    serde_json::from_slice(&b).map_err(WorkerError::ConfigParse)
+}
+
+fn build_runtime() -> Result<tokio::runtime::Runtime, WorkerError> {
+    Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(WorkerError::RuntimeInit)
+}
+
+fn extract_data_dir(settings: &postgresql_embedded::Settings) -> Result<Utf8PathBuf, WorkerError> {
+    Utf8PathBuf::from_path_buf(settings.data_dir.clone())
+        .map_err(|_| WorkerError::SettingsConversion("data_dir must be valid UTF-8".into()))
+}
+
+fn extract_install_dir(settings: &Settings) -> Result<Utf8PathBuf, WorkerError> {
+    Utf8PathBuf::from_path_buf(settings.installation_dir.clone())
+        .map_err(|_| WorkerError::SettingsConversion("installation_dir must be valid UTF-8".into()))
+}
+
+fn extract_install_root(
+    settings: &Settings,
+    install_dir: &Utf8Path,
+) -> Result<Option<Utf8PathBuf>, WorkerError> {
+    let pgpass = Utf8PathBuf::from_path_buf(settings.password_file.clone())
+        .map_err(|_| WorkerError::SettingsConversion("password_file must be valid UTF-8".into()))?;
+    let Some(parent) = pgpass.parent() else {
+        return Ok(None);
+    };
+    if parent.as_str().is_empty() || parent == Utf8Path::new("/") {
+        return Ok(None);
+    }
+    if parent == install_dir {
+        return Ok(None);
+    }
+    if parent
+        .components()
+        .any(|component| matches!(component, Utf8Component::ParentDir))
+    {
+        return Ok(None);
+    }
+    if !parent.starts_with(install_dir) {
+        return Ok(None);
+    }
+    Ok(Some(parent.to_owned()))
+}
+
+fn run_setup_op(settings: Settings, data_dir: &Utf8Path) -> Result<(), WorkerError> {
+    let runtime = build_runtime()?;
+    let mut pg = PostgreSQL::new(settings);
+    runtime.block_on(async { execute_setup(&mut pg, data_dir).await })
+}
+
+fn run_start_op(
+    settings: Settings,
+    data_dir: &Utf8Path,
+    pg_hba: Option<&str>,
+) -> Result<(), WorkerError> {
+    let runtime = build_runtime()?;
+    let mut pg = PostgreSQL::new(settings);
+    runtime.block_on(async { execute_start(&mut pg, data_dir, pg_hba).await })?;
+    std::mem::forget(pg);
+    Ok(())
+}
+
+fn run_stop_op(settings: Settings) -> Result<(), WorkerError> {
+    let runtime = build_runtime()?;
+    let mut pg = PostgreSQL::new(settings);
+    runtime.block_on(async { execute_stop(&mut pg).await })
+}
+
+fn is_setup_complete(pg: &PostgreSQL, data_dir: &Utf8Path) -> bool {
+    data_dir.is_dir() && data_dir.join("PG_VERSION").exists() && pg.status() != Status::NotInstalled
+}
+
+async fn run_setup(pg: &mut PostgreSQL) -> Result<(), WorkerError> {
+    pg.setup()
+        .await
+        .map_err(|e| WorkerError::PostgresOperation(format!("setup failed: {e}")))
+}
+
+mod log {
+    //! Logging helpers for recovery flow; extracted to avoid cognitive complexity inflation.
+    use super::{Utf8Path, info};
+    pub fn check(p: &Utf8Path, exists: bool) {
+        info!("Check: path={p}, exists={exists}");
+    }
+    pub fn valid(p: &Utf8Path, v: bool) {
+        info!("Validation: path={p}, valid={v}");
+    }
+}
+
+fn perform_data_dir_reset(path: &Utf8Path) -> Result<(), WorkerError> {
+    info!("Reset: path={path}");
+    reset_data_dir(path).map_err(|e| WorkerError::DataDirRecovery(format!("reset: {e}")))
+}
+
+fn is_dir_empty(path: &Utf8Path) -> Result<bool, BoxError> {
+    let (dir, rel) = ambient_dir_and_path(path)?;
+    Ok(dir.read_dir(rel.as_std_path())?.next().is_none())
+}
+
+fn recover_invalid_data_dir(data_dir: &Utf8Path) -> Result<(), WorkerError> {
+    let exists = data_dir.exists();
+    log::check(data_dir, exists);
+    if !exists {
+        return Ok(());
+    }
+    let is_valid = has_valid_data_dir(data_dir)
+        .map_err(|e| WorkerError::DataDirRecovery(format!("validation: {e}")))?;
+    log::valid(data_dir, is_valid);
+    let is_empty = is_dir_empty(data_dir)
+        .map_err(|e| WorkerError::DataDirRecovery(format!("empty check: {e}")))?;
+    if !is_valid && !is_empty {
+        perform_data_dir_reset(data_dir)?;
+    }
+    Ok(())
+}
+
+#[expect(
+    clippy::cognitive_complexity,
+    reason = "lint triggers (16/9) despite simple 6-line body; caused by async desugaring"
+)]
+async fn run_postgres_setup(pg: &mut PostgreSQL, data_dir: &Utf8Path) -> Result<(), WorkerError> {
+    if is_setup_complete(pg, data_dir) {
+        info!("Setup complete");
+        return Ok(());
+    }
+    recover_invalid_data_dir(data_dir)?;
+    info!("Running setup");
+    run_setup(pg).await
+}
+
+async fn start_if_not_started(pg: &mut PostgreSQL) -> Result<(), WorkerError> {
+    if pg.status() == Status::Started {
+        info!("PostgreSQL already started");
+        return Ok(());
+    }
+    pg.start()
+        .await
+        .map_err(|e| WorkerError::PostgresOperation(format!("start failed: {e}")))
+}
+
+async fn execute_setup(pg: &mut PostgreSQL, data_dir: &Utf8Path) -> Result<(), WorkerError> {
+    run_postgres_setup(pg, data_dir).await
+}
+
+async fn execute_start(
+    pg: &mut PostgreSQL,
+    data_dir: &Utf8Path,
+    pg_hba: Option<&str>,
+) -> Result<(), WorkerError> {
+    run_postgres_setup(pg, data_dir).await?;
+    write_pg_hba(data_dir, pg_hba)?;
+    start_if_not_started(pg).await
+}
+
+/// Replaces `pg_hba.conf` with the rules the caller configured, if any.
+fn write_pg_hba(data_dir: &Utf8Path, pg_hba: Option<&str>) -> Result<(), WorkerError> {
+    let Some(contents) = pg_hba else {
+        return Ok(());
+    };
+    std::fs::write(data_dir.join("pg_hba.conf"), contents)
+        .map_err(|e| WorkerError::PostgresOperation(format!("writing pg_hba.conf failed: {e}")))
+}
+
+async fn execute_stop(pg: &mut PostgreSQL) -> Result<(), WorkerError> {
+    match pg.stop().await {
+        Ok(()) => Ok(()),
+        Err(e) if stop_missing_pid_is_ok(&e) => Ok(()),
+        Err(e) => Err(WorkerError::PostgresOperation(format!("stop failed: {e}"))),
+    }
+}
+
+fn collect_removal_error(failures: &mut Vec<String>, path: &Utf8Path, label: &str) {
+    if let Err(err) = removal::remove_dir_all_if_exists(path, label) {
+        failures.push(err);
+    }
+}
+
+fn execute_cleanup(
+    data_dir: &Utf8Path,
+    install_dir: Option<&Utf8Path>,
+    install_root: Option<&Utf8Path>,
+) -> Result<(), WorkerError> {
+    let mut failures = Vec::new();
+    collect_removal_error(&mut failures, data_dir, "data");
+    if let Some(path) = install_dir {
+        collect_removal_error(&mut failures, path, "installation");
+    }
+    if let Some(path) = install_root {
+        let is_already_removed = install_dir.is_some_and(|install_path| install_path == path);
+        if !is_already_removed {
+            collect_removal_error(&mut failures, path, "installation-root");
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(WorkerError::CleanupFailed(failures.join("; ")))
+    }
+}
+
+fn apply_worker_environment(environment: &[(String, Option<PlainSecret>)]) {
+    for (key, value) in environment {
+        // SAFETY: worker is single-threaded; environment updates cannot race.
+        match value {
+            Some(v) => unsafe { env::set_var(key, v.expose()) },
+            None => unsafe { env::remove_var(key) },
+        }
+    }
+}
+
+fn stop_missing_pid_is_ok(err: &postgresql_embedded::Error) -> bool {
+    use postgresql_embedded::Error::{DatabaseStopError, IoError};
+    matches!(err, DatabaseStopError(m) | IoError(m) if m.contains("postmaster.pid") && m.contains("does not exist"))
+}
+
+fn has_valid_data_dir(data_dir: &Utf8Path) -> Result<bool, BoxError> {
+    let (dir, rel) = ambient_dir_and_path(data_dir)?;
+    Ok(dir.exists(rel.join(PG_FILENODE_MAP_MARKER).as_std_path()))
+}
+
+fn reset_data_dir(data_dir: &Utf8Path) -> Result<(), BoxError> {
+    let (dir, rel) = ambient_dir_and_path(data_dir)?;
+    if rel.as_str().is_empty() {
+        return Err("cannot reset root directory".into());
+    }
+    match dir.remove_dir_all(rel.as_std_path()) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests;
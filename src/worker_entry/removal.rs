@@ -1,9 +1,9 @@
-//! Directory removal helpers for the `pg_worker` binary.
+//! Directory removal helpers for the worker's cleanup operations.
 
 use camino::Utf8Path;
 use tracing::info;
 
-use super::cleanup_helpers::{RemovalOutcome, try_remove_dir_all};
+use crate::cleanup_helpers::{RemovalOutcome, try_remove_dir_all};
 
 pub(super) fn remove_dir_all_if_exists(path: &Utf8Path, label: &str) -> Result<(), String> {
     match try_remove_dir_all(path.as_std_path()) {
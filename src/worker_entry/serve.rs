@@ -0,0 +1,96 @@
+//! Persistent mode: `serve` executes lifecycle requests read as JSON
+//! lines from stdin until the parent closes it.
+//!
+//! Responses go to the original stdout, which is moved to a private
+//! descriptor first so output from `initdb`, `pg_ctl`, or the postmaster can
+//! never interleave with them. Progress and failures are written to stderr,
+//! which the parent relays into its logs.
+
+use super::{Operation, WorkerError, run_operation};
+use crate::worker::{WORKER_PROTOCOL_VERSION, WorkerRequestFrame, WorkerResponse};
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::os::fd::AsFd;
+
+/// Serves requests until stdin reaches end of file.
+pub(super) fn run() -> Result<(), WorkerError> {
+    let mut responses = protocol_output()?;
+    send(
+        &mut responses,
+        &WorkerResponse::Ready {
+            protocol: WORKER_PROTOCOL_VERSION,
+        },
+    )?;
+    for read in io::stdin().lock().lines() {
+        let line = read.map_err(WorkerError::Protocol)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = handle_request(&line).err().map(|err| err.to_string());
+        note(&line_summary(&line), error.as_deref());
+        send(&mut responses, &WorkerResponse::Done { error })?;
+    }
+    Ok(())
+}
+
+/// Protocol version of a request, read before the rest of the frame since
+/// other versions may shape their payloads differently.
+#[derive(Deserialize)]
+struct FrameHeader {
+    protocol: u32,
+}
+
+/// Parses and executes one request line.
+pub(super) fn handle_request(line: &str) -> Result<(), WorkerError> {
+    let header: FrameHeader = serde_json::from_str(line).map_err(WorkerError::ConfigParse)?;
+    if header.protocol != WORKER_PROTOCOL_VERSION {
+        return Err(WorkerError::InvalidArgs(format!(
+            "unsupported protocol version {}; this worker speaks {WORKER_PROTOCOL_VERSION}",
+            header.protocol
+        )));
+    }
+    let request: WorkerRequestFrame =
+        serde_json::from_str(line).map_err(WorkerError::ConfigParse)?;
+    let operation = Operation::parse(OsStr::new(&request.operation))?;
+    run_operation(operation, request.payload)
+}
+
+/// Names the operation a request line asked for, for progress notes.
+fn line_summary(line: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(line)
+        .ok()
+        .and_then(|value| value.get("operation")?.as_str().map(str::to_owned))
+        .unwrap_or_else(|| "request".to_owned())
+}
+
+/// Writes a progress note to stderr; failures to do so are ignored.
+fn note(operation: &str, error: Option<&str>) {
+    let mut stderr = io::stderr().lock();
+    drop(match error {
+        None => writeln!(stderr, "pg_worker: {operation} completed"),
+        Some(err) => writeln!(stderr, "pg_worker: {operation} failed: {err}"),
+    });
+}
+
+/// Takes over stdout for responses and points descriptor 1 at stderr.
+fn protocol_output() -> Result<File, WorkerError> {
+    let responses = io::stdout()
+        .as_fd()
+        .try_clone_to_owned()
+        .map_err(WorkerError::Protocol)?;
+    // SAFETY: `dup2` atomically replaces descriptor 1 with a copy of
+    // descriptor 2. Both stay open for the life of the process, and nothing
+    // else holds descriptor 1 by ownership.
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } == -1 {
+        return Err(WorkerError::Protocol(io::Error::last_os_error()));
+    }
+    Ok(File::from(responses))
+}
+
+fn send(out: &mut File, response: &WorkerResponse) -> Result<(), WorkerError> {
+    let mut line = serde_json::to_vec(response).map_err(WorkerError::ConfigParse)?;
+    line.push(b'\n');
+    out.write_all(&line).map_err(WorkerError::Protocol)
+}
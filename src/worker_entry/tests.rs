@@ -1,7 +1,8 @@
-//! Unit tests for `pg_worker` data directory recovery and argument parsing.
+//! Unit tests for worker data directory recovery, argument parsing, and
+//! persistent requests.
 
 use super::*;
-use pg_embedded_setup_unpriv::test_support::create_partial_data_dir;
+use crate::test_support::create_partial_data_dir;
 use rstest::{fixture, rstest};
 use std::{
     ffi::{OsStr, OsString},
@@ -128,3 +129,20 @@ fn recover_removes_partial_initialisation(temp_data_dir: TempDataDirResult) -> R
     // After recovery, the directory should be gone
     ensure(!p.exists(), "partial dir should be removed by recovery")
 }
+
+#[test]
+fn serve_rejects_other_protocol_versions() -> R {
+    let line = r#"{"protocol":999,"operation":"setup","payload":{}}"#;
+    match serve::handle_request(line) {
+        Err(WorkerError::InvalidArgs(m)) => ensure(m.contains("protocol version 999"), "bad msg"),
+        o => Err(format!("expected InvalidArgs: {o:?}").into()),
+    }
+}
+
+#[test]
+fn serve_rejects_malformed_requests() -> R {
+    match serve::handle_request("not json") {
+        Err(WorkerError::ConfigParse(_)) => Ok(()),
+        o => Err(format!("expected ConfigParse: {o:?}").into()),
+    }
+}
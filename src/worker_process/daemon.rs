@@ -0,0 +1,447 @@
+//! Persistent worker serving every lifecycle operation of one cluster.
+//!
+//! [`ExecutionMode::PersistentWorker`](crate::ExecutionMode::PersistentWorker)
+//! spawns `pg_worker serve` once per cluster, already demoted to "nobody",
+//! instead of one worker per operation. Requests and responses are JSON lines
+//! on the worker's stdin and stdout, tagged with
+//! [`WORKER_PROTOCOL_VERSION`], and the worker's stderr is relayed into
+//! `tracing`. Workers are keyed by data directory; dropping the cluster's
+//! [`DaemonLease`] closes the worker's stdin, which makes it exit.
+//!
+//! The worker serves the same operations as the one-shot worker: setup,
+//! start, stop, and cleanup. Database and role management never needs
+//! reduced privileges, so it stays on client connections opened by the
+//! calling process.
+
+use super::{WorkerRequest, privileges};
+use crate::error::{BootstrapError, BootstrapResult};
+use crate::observability::LOG_TARGET;
+use crate::worker::{WORKER_PROTOCOL_VERSION, WorkerRequestFrame, WorkerResponse};
+use crate::{ExecutionMode, TestBootstrapSettings};
+use color_eyre::eyre::{Context, eyre};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, warn};
+use wait_timeout::ChildExt;
+
+/// Argument that starts `pg_worker` in persistent mode.
+const SERVE_COMMAND: &str = "serve";
+
+/// Time a healthy worker is given to exit once its stdin closes.
+const EXIT_GRACE: Duration = Duration::from_secs(5);
+
+/// Worker shared by every operation on one data directory.
+type Slot = Arc<Mutex<Option<WorkerDaemon>>>;
+
+static DAEMONS: LazyLock<Mutex<HashMap<PathBuf, Slot>>> = LazyLock::new(Mutex::default);
+
+/// Executes `request` on the cluster's persistent worker, spawning the worker
+/// on first use.
+///
+/// A worker that times out, exits, or breaks the protocol is discarded, so
+/// the next operation starts a fresh one.
+pub(super) fn run(request: &WorkerRequest<'_>) -> BootstrapResult<()> {
+    let slot = slot_for(&request.settings.data_dir);
+    let mut daemon = slot.lock().unwrap_or_else(PoisonError::into_inner);
+    if daemon.is_none() {
+        *daemon = Some(WorkerDaemon::spawn(request)?);
+    }
+    let Some(worker) = daemon.as_mut() else {
+        return Err(BootstrapError::from(eyre!("persistent worker unavailable")));
+    };
+    let result = worker.call(request);
+    if !worker.is_healthy {
+        *daemon = None;
+    }
+    result
+}
+
+fn slot_for(data_dir: &Path) -> Slot {
+    let mut daemons = DAEMONS.lock().unwrap_or_else(PoisonError::into_inner);
+    Arc::clone(daemons.entry(data_dir.to_path_buf()).or_default())
+}
+
+/// Shuts down the worker serving `data_dir`, waiting for any operation in
+/// flight.
+fn release(data_dir: &Path) {
+    let slot = DAEMONS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(data_dir);
+    if let Some(released) = slot {
+        drop(
+            released
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(),
+        );
+    }
+}
+
+/// Shuts down a cluster's persistent worker when dropped.
+#[derive(Debug)]
+pub(crate) struct DaemonLease {
+    data_dir: PathBuf,
+}
+
+impl DaemonLease {
+    /// Returns a lease when `bootstrap` runs its lifecycle on a persistent
+    /// worker.
+    pub(crate) fn for_bootstrap(bootstrap: &TestBootstrapSettings) -> Option<Self> {
+        (bootstrap.execution_mode == ExecutionMode::PersistentWorker).then(|| Self {
+            data_dir: bootstrap.settings.data_dir.clone(),
+        })
+    }
+}
+
+impl Drop for DaemonLease {
+    fn drop(&mut self) {
+        release(&self.data_dir);
+    }
+}
+
+/// A running `pg_worker serve` process.
+struct WorkerDaemon {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    responses: Receiver<WorkerResponse>,
+    relays: Vec<JoinHandle<()>>,
+    is_healthy: bool,
+}
+
+impl WorkerDaemon {
+    fn spawn(request: &WorkerRequest<'_>) -> BootstrapResult<Self> {
+        let mut command = request.command();
+        command
+            .arg(SERVE_COMMAND)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        privileges::demote(&mut command)?;
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to spawn persistent worker {}", request.worker))?;
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        let (sender, responses) = mpsc::channel();
+        let mut daemon = Self {
+            stdin: child.stdin.take(),
+            child,
+            responses,
+            relays: Vec::new(),
+            is_healthy: true,
+        };
+        daemon.start_relays(stdout, stderr, sender)?;
+        daemon.await_ready(request.timeout)?;
+        log_spawned(request, daemon.child.id());
+        Ok(daemon)
+    }
+
+    fn start_relays(
+        &mut self,
+        stdout: Option<ChildStdout>,
+        stderr: Option<ChildStderr>,
+        sender: Sender<WorkerResponse>,
+    ) -> BootstrapResult<()> {
+        let (Some(responses), Some(log)) = (stdout, stderr) else {
+            return Err(BootstrapError::from(eyre!(
+                "persistent worker pipes unavailable"
+            )));
+        };
+        let pid = self.child.id();
+        self.relays
+            .push(spawn_relay("pg-worker-responses", move || {
+                read_responses(responses, &sender);
+            })?);
+        self.relays.push(spawn_relay("pg-worker-log", move || {
+            relay_log(log, pid);
+        })?);
+        Ok(())
+    }
+
+    fn await_ready(&mut self, timeout: Duration) -> BootstrapResult<()> {
+        match self.receive(timeout, "persistent worker startup")? {
+            WorkerResponse::Ready { protocol } if protocol == WORKER_PROTOCOL_VERSION => Ok(()),
+            other => {
+                self.is_healthy = false;
+                Err(BootstrapError::from(eyre!(
+                    "persistent worker does not speak protocol version \
+                     {WORKER_PROTOCOL_VERSION}: {other:?}"
+                )))
+            }
+        }
+    }
+
+    fn call(&mut self, request: &WorkerRequest<'_>) -> BootstrapResult<()> {
+        let ctx = request.operation.error_context();
+        self.send(&WorkerRequestFrame {
+            protocol: WORKER_PROTOCOL_VERSION,
+            operation: request.operation.as_str().to_owned(),
+            payload: request.payload()?,
+        })?;
+        match self.receive(request.timeout, ctx)? {
+            WorkerResponse::Done { error: None } => Ok(()),
+            WorkerResponse::Done { error: Some(err) } => {
+                Err(BootstrapError::from(eyre!("{ctx}: {err}")))
+            }
+            WorkerResponse::Ready { .. } => {
+                self.is_healthy = false;
+                Err(BootstrapError::from(eyre!(
+                    "{ctx}: persistent worker restarted mid-request"
+                )))
+            }
+        }
+    }
+
+    fn send(&mut self, frame: &WorkerRequestFrame) -> BootstrapResult<()> {
+        let mut line = serde_json::to_vec(frame).context("failed to serialise worker request")?;
+        line.push(b'\n');
+        let Some(stdin) = self.stdin.as_mut() else {
+            return Err(BootstrapError::from(eyre!(
+                "persistent worker stdin closed"
+            )));
+        };
+        let result = stdin.write_all(&line).and_then(|()| stdin.flush());
+        if result.is_err() {
+            self.is_healthy = false;
+        }
+        result
+            .context("failed to send request to persistent worker")
+            .map_err(BootstrapError::from)
+    }
+
+    fn receive(&mut self, timeout: Duration, ctx: &str) -> BootstrapResult<WorkerResponse> {
+        let received = self.responses.recv_timeout(timeout);
+        if received.is_err() {
+            self.is_healthy = false;
+        }
+        match received {
+            Ok(response) => Ok(response),
+            Err(RecvTimeoutError::Timeout) => Err(timed_out(ctx, timeout)),
+            Err(RecvTimeoutError::Disconnected) => Err(BootstrapError::from(eyre!(
+                "{ctx}: persistent worker exited unexpectedly"
+            ))),
+        }
+    }
+
+    /// Waits briefly for a healthy worker to exit, then kills it.
+    fn reap(&mut self) {
+        if self.is_healthy && matches!(self.child.wait_timeout(EXIT_GRACE), Ok(Some(_))) {
+            return;
+        }
+        if let Err(err) = self.child.kill().and_then(|()| self.child.wait().map(drop)) {
+            warn_reap_failed(&err);
+        }
+    }
+}
+
+impl Drop for WorkerDaemon {
+    fn drop(&mut self) {
+        // End of file on stdin asks the worker to exit.
+        drop(self.stdin.take());
+        self.reap();
+        let panicked = self
+            .relays
+            .drain(..)
+            .map(JoinHandle::join)
+            .filter(Result::is_err)
+            .count();
+        if panicked > 0 {
+            warn!(target: LOG_TARGET, panicked, "persistent worker relay threads panicked");
+        }
+    }
+}
+
+fn spawn_relay(
+    name: &str,
+    body: impl FnOnce() + Send + 'static,
+) -> BootstrapResult<JoinHandle<()>> {
+    thread::Builder::new()
+        .name(name.to_owned())
+        .spawn(body)
+        .context("failed to start persistent worker relay thread")
+        .map_err(BootstrapError::from)
+}
+
+/// Forwards parsed responses until the worker closes stdout.
+fn read_responses(stdout: ChildStdout, sender: &Sender<WorkerResponse>) {
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        match serde_json::from_str(&line) {
+            Ok(response) => {
+                if sender.send(response).is_err() {
+                    return;
+                }
+            }
+            Err(err) => warn_malformed_response(&err),
+        }
+    }
+}
+
+/// Relays the worker's stderr into `tracing` line by line.
+fn relay_log(stderr: ChildStderr, pid: u32) {
+    BufReader::new(stderr)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .for_each(|line| info!(target: LOG_TARGET, worker_pid = pid, "{}", line.trim_end()));
+}
+
+fn timed_out(ctx: &str, timeout: Duration) -> BootstrapError {
+    let timeout_secs = timeout.as_secs();
+    warn!(
+        target: LOG_TARGET,
+        timeout_secs,
+        "SKIP-TEST-CLUSTER: persistent worker timed out after {timeout_secs}s"
+    );
+    BootstrapError::from(eyre!("{ctx} timed out after {timeout_secs}s"))
+}
+
+fn log_spawned(request: &WorkerRequest<'_>, pid: u32) {
+    info!(
+        target: LOG_TARGET,
+        worker = %request.worker,
+        worker_pid = pid,
+        protocol = WORKER_PROTOCOL_VERSION,
+        "started persistent worker"
+    );
+}
+
+fn warn_malformed_response(err: &serde_json::Error) {
+    warn!(
+        target: LOG_TARGET,
+        error = %err,
+        "ignoring malformed persistent worker response"
+    );
+}
+
+fn warn_reap_failed(err: &std::io::Error) {
+    warn!(
+        target: LOG_TARGET,
+        error = %err,
+        "failed to stop persistent worker"
+    );
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::cluster::WorkerOperation;
+    use crate::worker_process::{WorkerRequestArgs, disable_privilege_drop_for_tests};
+    use camino::Utf8PathBuf;
+    use postgresql_embedded::Settings;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    /// Stand-in for `pg_worker serve` that records each spawn and refuses
+    /// `stop`.
+    const FAKE_WORKER: &str = r#"#!/bin/sh
+echo spawned >> "$0.spawns"
+echo '{"type":"ready","protocol":PROTOCOL}'
+while read -r line; do
+  case "$line" in
+    *'"operation":"stop"'*) echo '{"type":"done","error":"stop refused"}' ;;
+    *) echo "handled request" >&2; echo '{"type":"done","error":null}' ;;
+  esac
+done
+"#;
+
+    struct FakeWorker {
+        dir: TempDir,
+        worker: Utf8PathBuf,
+        settings: Settings,
+    }
+
+    impl FakeWorker {
+        fn new(protocol: u32) -> std::io::Result<Self> {
+            let dir = tempfile::tempdir()?;
+            let worker =
+                Utf8PathBuf::from_path_buf(dir.path().join("pg_worker")).map_err(|path| {
+                    std::io::Error::other(format!("non-UTF-8 path {}", path.display()))
+                })?;
+            let script = FAKE_WORKER.replace("PROTOCOL", &protocol.to_string());
+            fs::write(&worker, script)?;
+            fs::set_permissions(&worker, fs::Permissions::from_mode(0o755))?;
+            let settings = Settings {
+                data_dir: dir.path().join("data"),
+                ..Settings::default()
+            };
+            Ok(Self {
+                dir,
+                worker,
+                settings,
+            })
+        }
+
+        fn run(&self, operation: WorkerOperation) -> BootstrapResult<()> {
+            let request = WorkerRequest::new(WorkerRequestArgs {
+                worker: &self.worker,
+                settings: &self.settings,
+                env_vars: &[],
+                operation,
+                timeout: Duration::from_secs(10),
+            });
+            run(&request)
+        }
+
+        fn spawns(&self) -> usize {
+            fs::read_to_string(self.dir.path().join("pg_worker.spawns"))
+                .map_or(0, |log| log.lines().count())
+        }
+
+        fn release(&self) {
+            release(&self.settings.data_dir);
+        }
+    }
+
+    #[test]
+    fn one_worker_serves_every_operation_until_released() {
+        let _privileges = disable_privilege_drop_for_tests();
+        let fake = FakeWorker::new(WORKER_PROTOCOL_VERSION).expect("fake worker");
+
+        fake.run(WorkerOperation::Setup).expect("setup");
+        fake.run(WorkerOperation::Start).expect("start");
+        assert_eq!(fake.spawns(), 1);
+
+        fake.release();
+        fake.run(WorkerOperation::Cleanup).expect("cleanup");
+        assert_eq!(fake.spawns(), 2);
+        fake.release();
+    }
+
+    #[test]
+    fn worker_failures_surface_with_the_operation_context() {
+        let _privileges = disable_privilege_drop_for_tests();
+        let fake = FakeWorker::new(WORKER_PROTOCOL_VERSION).expect("fake worker");
+
+        let err = fake
+            .run(WorkerOperation::Stop)
+            .expect_err("stop is refused");
+        fake.release();
+
+        let message = err.to_string();
+        assert!(message.contains("stop() failed"), "got: {message}");
+        assert!(message.contains("stop refused"), "got: {message}");
+    }
+
+    #[test]
+    fn workers_speaking_another_protocol_are_rejected() {
+        let _privileges = disable_privilege_drop_for_tests();
+        let fake = FakeWorker::new(WORKER_PROTOCOL_VERSION + 1).expect("fake worker");
+
+        let err = fake
+            .run(WorkerOperation::Setup)
+            .expect_err("protocol mismatch");
+        fake.release();
+
+        assert!(
+            err.to_string().contains("does not speak protocol version"),
+            "got: {err}"
+        );
+    }
+}
@@ -3,15 +3,18 @@
 //! The helpers serialise worker payloads, prepare commands, and enforce timeouts
 //! so `TestCluster` can remain focused on orchestration logic.
 
+mod daemon;
 mod output;
 mod privileges;
 
+pub(crate) use self::daemon::DaemonLease;
+
 pub(crate) use self::output::render_failure_for_tests;
 use self::output::{append_error_context, combine_errors, render_failure};
 use crate::cluster::WorkerOperation;
 use crate::error::{BootstrapError, BootstrapResult};
 use crate::observability::LOG_TARGET;
-use crate::worker::WorkerPayload;
+use crate::worker::{EMBEDDED_WORKER_ARG, WorkerPayload};
 use camino::Utf8Path;
 use color_eyre::eyre::{Context, Report, eyre};
 use postgresql_embedded::Settings;
@@ -109,6 +112,10 @@ pub(crate) struct WorkerRequest<'a> {
     /// Maximum duration the worker is allowed to run before it is terminated
     /// and treated as a timeout failure.
     timeout: Duration,
+    /// `pg_hba.conf` contents the worker writes before starting the server.
+    pg_hba: Option<&'a str>,
+    /// Whether `worker` is the current program re-executed as the worker.
+    is_embedded: bool,
 }
 
 impl<'a> WorkerRequest<'a> {
@@ -146,7 +153,39 @@ impl<'a> WorkerRequest<'a> {
             env_vars: args.env_vars,
             operation: args.operation,
             timeout: args.timeout,
+            pg_hba: None,
+            is_embedded: false,
+        }
+    }
+
+    /// Attaches `pg_hba.conf` contents for a start operation.
+    #[must_use]
+    pub(crate) const fn with_pg_hba(mut self, pg_hba: Option<&'a str>) -> Self {
+        self.pg_hba = pg_hba;
+        self
+    }
+
+    /// Marks `worker` as the current program, which is then started through
+    /// its hidden worker entry point.
+    #[must_use]
+    pub(crate) const fn with_embedded_entry(mut self, is_embedded: bool) -> Self {
+        self.is_embedded = is_embedded;
+        self
+    }
+
+    /// Starts a command for the worker, before any operation arguments.
+    fn command(&self) -> Command {
+        let mut command = Command::new(self.worker.as_std_path());
+        if self.is_embedded {
+            command.arg(EMBEDDED_WORKER_ARG);
         }
+        command
+    }
+
+    /// Builds the payload handed to the worker.
+    fn payload(&self) -> BootstrapResult<WorkerPayload> {
+        Ok(WorkerPayload::new(self.settings, self.env_vars.to_vec())?
+            .with_pg_hba(self.pg_hba.map(str::to_owned)))
     }
 }
 
@@ -202,6 +241,21 @@ pub(crate) fn run(request: &WorkerRequest<'_>) -> BootstrapResult<()> {
     WorkerProcess::new(request).run()
 }
 
+/// Executes a privileged cluster operation on the cluster's persistent
+/// worker, spawning `pg_worker serve` with dropped privileges on first use.
+///
+/// The worker stays up until the cluster's [`DaemonLease`] drops.
+///
+/// # Errors
+///
+/// Returns an error when the worker cannot be spawned or does not speak the
+/// current protocol version, when the request cannot be delivered, when the
+/// worker exceeds the request's timeout or exits, or when the operation
+/// itself fails.
+pub(crate) fn run_persistent(request: &WorkerRequest<'_>) -> BootstrapResult<()> {
+    daemon::run(request)
+}
+
 struct WorkerProcess<'a> {
     request: &'a WorkerRequest<'a>,
 }
@@ -256,7 +310,7 @@ impl<'a> WorkerProcess<'a> {
     }
 
     fn write_payload(&self) -> BootstrapResult<TempPath> {
-        let payload = WorkerPayload::new(self.request.settings, self.request.env_vars.to_vec())?;
+        let payload = self.request.payload()?;
         let mut file = NamedTempFile::new().context("failed to create worker payload file")?;
         to_writer(&mut file, &payload).context("failed to serialise worker payload")?;
         file.flush().context("failed to flush worker payload")?;
@@ -264,7 +318,7 @@ impl<'a> WorkerProcess<'a> {
     }
 
     fn configure_command(&self, payload_path: &Path) -> BootstrapResult<Command> {
-        let mut command = Command::new(self.request.worker.as_std_path());
+        let mut command = self.request.command();
         command.arg(self.request.operation.as_str());
         command.arg(payload_path);
         privileges::apply(payload_path, &mut command)?;
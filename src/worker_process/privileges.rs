@@ -65,11 +65,43 @@ pub(crate) fn apply(payload_path: &Path, command: &mut Command) -> BootstrapResu
     apply_impl(payload_path, command)
 }
 
+/// Arranges for a worker command to run as "nobody" without a payload file.
+///
+/// Persistent workers receive their payloads over a pipe, so only the
+/// credential demotion applies. Unsupported platforms leave the command
+/// unchanged.
+///
+/// # Errors
+///
+/// Returns an error if resolving the "nobody" account fails.
+pub(crate) fn demote(command: &mut Command) -> BootstrapResult<()> {
+    demote_impl(command)
+}
+
 cfg_privilege_drop! {
     fn apply_impl(payload_path: &Path, command: &mut Command) -> BootstrapResult<()> {
         apply_unix(payload_path, command)
     }
 
+    fn demote_impl(command: &mut Command) -> BootstrapResult<()> {
+        if skip_privilege_drop_for_tests() {
+            return Ok(());
+        }
+        let (uid, gid) = resolve_nobody_ids()?;
+        configure_pre_exec(command, uid, gid);
+        log_demoted(uid, gid);
+        Ok(())
+    }
+
+    fn log_demoted(uid: u32, gid: u32) {
+        info!(
+            target: LOG_TARGET,
+            uid,
+            gid,
+            "configured persistent worker to drop privileges"
+        );
+    }
+
     // Tracks nesting so privilege drop stays disabled while any guard is held.
     static SKIP_PRIVILEGE_DROP: AtomicUsize = AtomicUsize::new(0);
 
@@ -186,6 +218,20 @@ fn apply_impl(payload_path: &Path, command: &mut Command) -> BootstrapResult<()>
     apply_noop(payload_path, command)
 }
 
+#[cfg(not(all(
+    unix,
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "dragonfly",
+    ),
+)))]
+fn demote_impl(_command: &mut Command) -> BootstrapResult<()> {
+    Ok(())
+}
+
 cfg_privilege_drop! {
     /// Guard that restores the privilege-drop toggle when dropped.
     ///
@@ -0,0 +1,130 @@
+//! Integration tests for the `pg_embedded_setup_unpriv cache` subcommands.
+//!
+//! The binary runs against a temporary cache directory seeded with mock
+//! entries, so no `PostgreSQL` download is involved.
+
+use std::fs;
+use std::process::{Command, Output};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{Result, ensure, eyre};
+use tempfile::TempDir;
+
+struct CacheSandbox {
+    _tempdir: TempDir,
+    cache_dir: Utf8PathBuf,
+}
+
+impl CacheSandbox {
+    fn new() -> Result<Self> {
+        let tempdir = tempfile::tempdir()?;
+        let cache_dir = Utf8Path::from_path(tempdir.path())
+            .ok_or_else(|| eyre!("tempdir is not valid UTF-8"))?
+            .join("binaries");
+        fs::create_dir_all(&cache_dir)?;
+        Ok(Self {
+            _tempdir: tempdir,
+            cache_dir,
+        })
+    }
+
+    /// Writes a complete cache entry with every binary `cache verify` expects.
+    fn seed(&self, version: &str) -> Result<()> {
+        let bin_dir = self.cache_dir.join(version).join("bin");
+        fs::create_dir_all(&bin_dir)?;
+        for binary in ["postgres", "initdb", "pg_ctl"] {
+            fs::write(bin_dir.join(binary), "mock binary")?;
+        }
+        fs::write(self.cache_dir.join(version).join(".complete"), "")?;
+        Ok(())
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        Ok(Command::new(env!("CARGO_BIN_EXE_pg_embedded_setup_unpriv"))
+            .arg("cache")
+            .args(args)
+            .env("PG_BINARY_CACHE_DIR", self.cache_dir.as_str())
+            .env_remove("PG_BINARY_ARCHIVE")
+            .env_remove("PG_RELEASES_URL")
+            .output()?)
+    }
+}
+
+fn stdout_lines(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_owned)
+        .collect()
+}
+
+#[test]
+fn cache_list_prints_versions_oldest_first() -> Result<()> {
+    let sandbox = CacheSandbox::new()?;
+    sandbox.seed("17.4.0")?;
+    sandbox.seed("16.8.0")?;
+
+    let output = sandbox.run(&["list"])?;
+
+    ensure!(output.status.success(), "cache list failed: {output:?}");
+    let versions: Vec<String> = stdout_lines(&output)
+        .iter()
+        .filter_map(|line| line.split('\t').next().map(str::to_owned))
+        .collect();
+    ensure!(
+        versions == ["16.8.0", "17.4.0"],
+        "unexpected listing: {versions:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn cache_verify_fails_on_incomplete_entries() -> Result<()> {
+    let sandbox = CacheSandbox::new()?;
+    sandbox.seed("17.4.0")?;
+    fs::create_dir_all(sandbox.cache_dir.join("16.8.0"))?;
+
+    let output = sandbox.run(&["verify"])?;
+
+    ensure!(
+        !output.status.success(),
+        "cache verify should fail for an incomplete entry"
+    );
+    let lines = stdout_lines(&output);
+    ensure!(
+        lines.contains(&"17.4.0\tok".to_owned()),
+        "valid entry should be reported ok: {lines:?}"
+    );
+    ensure!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("16.8.0\t") && line.contains("marker")),
+        "incomplete entry should be reported: {lines:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn cache_prune_keeps_newest_versions() -> Result<()> {
+    let sandbox = CacheSandbox::new()?;
+    for version in ["15.10.0", "16.8.0", "17.4.0"] {
+        sandbox.seed(version)?;
+    }
+
+    let output = sandbox.run(&["prune", "--keep", "1"])?;
+
+    ensure!(output.status.success(), "cache prune failed: {output:?}");
+    ensure!(
+        stdout_lines(&output) == ["removed 16.8.0", "removed 15.10.0"],
+        "unexpected prune output: {:?}",
+        stdout_lines(&output)
+    );
+    ensure!(
+        sandbox.cache_dir.join("17.4.0").is_dir(),
+        "newest version should remain"
+    );
+    ensure!(
+        !sandbox.cache_dir.join("15.10.0").exists(),
+        "older versions should be removed"
+    );
+    Ok(())
+}
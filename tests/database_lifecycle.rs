@@ -5,9 +5,11 @@ use std::cell::RefCell;
 use std::sync::atomic::Ordering;
 
 use color_eyre::eyre::{Context, Result, ensure};
+use pg_embedded_setup_unpriv::{SqlFileRunner, TestCluster};
 use postgres::NoTls;
 use rstest::fixture;
 use rstest_bdd_macros::{given, scenario, then, when};
+use sha2::{Digest, Sha256};
 
 #[path = "support/cap_fs_bootstrap.rs"]
 mod cap_fs;
@@ -37,6 +39,10 @@ const TEST_DB_NAME: &str = "test_lifecycle_db";
 const TEMP_DB_NAME: &str = "temp_lifecycle_db";
 const TEMPLATE_NAME: &str = "test_template_db";
 const CLONED_DB_NAME: &str = "cloned_from_template_db";
+/// Comment `ensure_template_exists` sets on a template while it is built.
+const TEMPLATE_BUILDING_MARKER: &str = "pg-embedded-setup-unpriv:template-building";
+const SQL_MIGRATIONS_DIR: &str = "tests/fixtures/sql_migrations";
+const EXTERNAL_DB_NAME: &str = "external_attachment_db";
 
 #[fixture]
 fn world() -> DatabaseWorldFixture {
@@ -221,11 +227,183 @@ fn when_ensure_template_called_again(world: &DatabaseWorldFixture) -> Result<()>
     Ok(())
 }
 
+#[when("a template database is left with a building marker")]
+fn when_template_left_incomplete(world: &DatabaseWorldFixture) -> Result<()> {
+    // Simulates a process that created the template but failed during setup.
+    create_template_with_comment(world, Some(TEMPLATE_BUILDING_MARKER))
+}
+
+#[when("a template database exists without a marker")]
+fn when_template_unmarked(world: &DatabaseWorldFixture) -> Result<()> {
+    // Simulates a template created by hand or by an earlier release.
+    create_template_with_comment(world, None)
+}
+
+#[when("a template build is interrupted before its setup finishes")]
+fn when_template_build_interrupted(world: &DatabaseWorldFixture) -> Result<()> {
+    // Simulates a process killed after creating the staging database and
+    // running part of its setup.
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let world_ref = world_cell.borrow();
+    let cluster = world_ref.cluster()?;
+    let staging = staging_name(TEMPLATE_NAME);
+    let url = cluster.connection().database_url("postgres");
+    let mut client = postgres::Client::connect(&url, NoTls).context("connect to postgres")?;
+    client
+        .batch_execute(&format!("CREATE DATABASE \"{staging}\""))
+        .context("create staging database")?;
+    Ok(())
+}
+
+#[then("no staging database is left behind")]
+fn then_no_staging_database(world: &DatabaseWorldFixture) -> Result<()> {
+    check_db_exists(world, &staging_name(TEMPLATE_NAME), false)
+}
+
+/// Mirrors the staging name `ensure_template_exists` builds templates under.
+fn staging_name(template: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(template.as_bytes()));
+    format!("pg_embed_staging_{}", digest.get(..16).unwrap_or_default())
+}
+
+/// Creates the template database directly, optionally setting its comment.
+fn create_template_with_comment(world: &DatabaseWorldFixture, comment: Option<&str>) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let world_ref = world_cell.borrow();
+    let cluster = world_ref.cluster()?;
+    cluster.create_database(TEMPLATE_NAME)?;
+    let Some(marker) = comment else {
+        return Ok(());
+    };
+    let url = cluster.connection().database_url("postgres");
+    let mut client = postgres::Client::connect(&url, NoTls).context("connect to postgres")?;
+    client
+        .batch_execute(&format!(
+            "COMMENT ON DATABASE \"{TEMPLATE_NAME}\" IS '{marker}'"
+        ))
+        .context("set template comment")?;
+    Ok(())
+}
+
+#[then("the setup function was not called")]
+fn then_setup_not_called(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let start = world_cell.borrow().setup_call_count_at_start;
+    let calls = SETUP_CALL_COUNT.load(Ordering::SeqCst) - start;
+    ensure!(
+        calls == 0,
+        "expected setup function not to be called, was called {calls} times"
+    );
+    Ok(())
+}
+
 #[then("the setup function was still called exactly once")]
 fn then_setup_still_called_once(world: &DatabaseWorldFixture) -> Result<()> {
     then_setup_called_once(world)
 }
 
+// --- Test transaction scenario steps ---
+
+#[when("a test transaction commits a nested transaction")]
+fn when_test_transaction_commits_nested(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let rows = world_cell
+        .borrow()
+        .cluster()?
+        .with_test_transaction("postgres", |tx| {
+            tx.batch_execute("CREATE TABLE tx_probe (id int)")
+                .context("create probe table")?;
+            // Code under test committing its own transaction on the handle.
+            let mut inner = tx.transaction().context("open nested transaction")?;
+            inner
+                .batch_execute("INSERT INTO tx_probe VALUES (1)")
+                .context("insert probe row")?;
+            inner.commit().context("commit nested transaction")?;
+            let row = tx
+                .query_one("SELECT count(*) FROM tx_probe", &[])
+                .context("count probe rows")?;
+            Ok(row.get::<_, i64>(0))
+        })?;
+    world_cell.borrow_mut().nested_commit_rows = Some(rows);
+    Ok(())
+}
+
+/// Stands in for code under test that commits its own transaction.
+fn insert_probe_row(tx: &mut postgres::Transaction<'_>) -> Result<()> {
+    let mut inner = tx.transaction().context("open nested transaction")?;
+    inner
+        .batch_execute("INSERT INTO tx_probe VALUES (1)")
+        .context("insert probe row")?;
+    inner.commit().context("commit nested transaction")
+}
+
+#[when("a test transaction guard commits a nested transaction and drops")]
+fn when_test_transaction_guard_commits_nested(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let mut guard = world_cell
+        .borrow()
+        .cluster()?
+        .test_transaction("postgres")?;
+    guard
+        .batch_execute("CREATE TABLE tx_probe (id int)")
+        .context("create probe table")?;
+    insert_probe_row(&mut guard.transaction()?)?;
+    let row = guard
+        .query_one("SELECT count(*) FROM tx_probe", &[])
+        .context("count probe rows")?;
+    world_cell.borrow_mut().nested_commit_rows = Some(row.get::<_, i64>(0));
+    drop(guard);
+    Ok(())
+}
+
+#[then("the nested commit was visible inside the test transaction")]
+fn then_nested_commit_visible(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let rows = world_cell.borrow().nested_commit_rows;
+    ensure!(rows == Some(1), "expected one probe row, found {rows:?}");
+    Ok(())
+}
+
+#[then("the nested commit is gone after the test transaction")]
+fn then_nested_commit_rolled_back(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let url = world_cell
+        .borrow()
+        .cluster()?
+        .connection()
+        .database_url("postgres");
+    let mut client = postgres::Client::connect(&url, NoTls).context("connect to postgres")?;
+    let row = client
+        .query_one("SELECT to_regclass('tx_probe') IS NULL", &[])
+        .context("look up probe table")?;
+    ensure!(
+        row.get::<_, bool>(0),
+        "probe table should be rolled back with the test transaction"
+    );
+    Ok(())
+}
+
 // --- Create from template scenario steps ---
 
 #[when("a template database is created and populated")]
@@ -291,6 +469,105 @@ fn then_cloned_database_contains_template_data(world: &DatabaseWorldFixture) ->
     Ok(())
 }
 
+// --- External attachment scenario steps ---
+
+#[when("an external attachment builds a migrated template and a database")]
+fn when_external_attachment_builds_template(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let url = world_cell
+        .borrow()
+        .cluster()?
+        .connection()
+        .database_url("postgres");
+    let _env = env::apply_env(env::build_env([
+        ("PG_TEST_BACKEND", "external"),
+        ("DATABASE_URL", url.as_str()),
+    ]));
+    let attachment = TestCluster::new().context("attach to the running cluster")?;
+    attachment.create_database(EXTERNAL_DB_NAME)?;
+    let template = attachment.migrated_template(SQL_MIGRATIONS_DIR, &SqlFileRunner)?;
+    // Dropping the attachment sweeps the databases it recorded.
+    drop(attachment);
+    world_cell.borrow_mut().migrated_template = Some(template.as_str().to_owned());
+    Ok(())
+}
+
+#[then("the attachment's database was swept")]
+fn then_external_database_swept(world: &DatabaseWorldFixture) -> Result<()> {
+    check_db_exists(world, EXTERNAL_DB_NAME, false)
+}
+
+// --- Migrated template scenario steps ---
+
+#[when("a migrated template is built from the SQL migrations fixture")]
+fn when_migrated_template_built(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let template = world_cell
+        .borrow()
+        .cluster()?
+        .migrated_template(SQL_MIGRATIONS_DIR, &SqlFileRunner)?;
+    world_cell.borrow_mut().migrated_template = Some(template.as_str().to_owned());
+    Ok(())
+}
+
+#[then("the migrated template is flagged as a template")]
+fn then_migrated_template_flagged(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let world_ref = world_cell.borrow();
+    let template = world_ref
+        .migrated_template
+        .as_deref()
+        .ok_or_else(|| color_eyre::eyre::eyre!("migrated template was not recorded"))?;
+    let url = world_ref.cluster()?.connection().database_url("postgres");
+    let mut client = postgres::Client::connect(&url, NoTls).context("connect to postgres")?;
+    let row = client
+        .query_one(
+            "SELECT datistemplate FROM pg_database WHERE datname = $1",
+            &[&template],
+        )
+        .context("query datistemplate")?;
+    let is_template: bool = row.get(0);
+    ensure!(
+        is_template,
+        "expected '{template}' to be flagged as a template"
+    );
+    Ok(())
+}
+
+#[then("a clone of the migrated template contains the seeded rows")]
+fn then_migrated_clone_contains_rows(world: &DatabaseWorldFixture) -> Result<()> {
+    let world_cell = borrow_world(world)?;
+    if world_cell.borrow().is_skipped() {
+        return Ok(());
+    }
+    let world_ref = world_cell.borrow();
+    let template = world_ref
+        .migrated_template
+        .as_deref()
+        .ok_or_else(|| color_eyre::eyre::eyre!("migrated template was not recorded"))?;
+    let clone = world_ref
+        .cluster()?
+        .temporary_database_from_template(CLONED_DB_NAME, template)?;
+    let mut client = postgres::Client::connect(clone.url(), NoTls).context("connect to clone")?;
+    let row = client
+        .query_one("SELECT count(*) FROM widgets", &[])
+        .context("query widgets")?;
+    let count: i64 = row.get(0);
+    ensure!(count == 2, "expected 2 seeded widgets, found {count}");
+    drop(client);
+    clone.drop_database()?;
+    Ok(())
+}
+
 // --- Scenario declarations ---
 
 #[scenario(path = "tests/features/database_lifecycle.feature", index = 0)]
@@ -334,3 +611,63 @@ fn scenario_create_from_template(serial_guard: ScenarioSerialGuard, world: Datab
     let _guard = serial_guard;
     let _ = expect_fixture(world, "database lifecycle create from template world");
 }
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 7)]
+fn scenario_incomplete_template_rebuilt(
+    serial_guard: ScenarioSerialGuard,
+    world: DatabaseWorldFixture,
+) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle incomplete template world");
+}
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 8)]
+fn scenario_migrated_template(serial_guard: ScenarioSerialGuard, world: DatabaseWorldFixture) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle migrated template world");
+}
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 9)]
+fn scenario_unmarked_template_adopted(
+    serial_guard: ScenarioSerialGuard,
+    world: DatabaseWorldFixture,
+) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle unmarked template world");
+}
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 10)]
+fn scenario_test_transaction_rolls_back(
+    serial_guard: ScenarioSerialGuard,
+    world: DatabaseWorldFixture,
+) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle test transaction world");
+}
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 11)]
+fn scenario_external_sweep_keeps_templates(
+    serial_guard: ScenarioSerialGuard,
+    world: DatabaseWorldFixture,
+) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle external attachment world");
+}
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 12)]
+fn scenario_test_transaction_guard_rolls_back(
+    serial_guard: ScenarioSerialGuard,
+    world: DatabaseWorldFixture,
+) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle test transaction guard world");
+}
+
+#[scenario(path = "tests/features/database_lifecycle.feature", index = 13)]
+fn scenario_interrupted_template_build_discarded(
+    serial_guard: ScenarioSerialGuard,
+    world: DatabaseWorldFixture,
+) {
+    let _guard = serial_guard;
+    let _ = expect_fixture(world, "database lifecycle interrupted template world");
+}
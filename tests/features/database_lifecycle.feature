@@ -45,3 +45,50 @@ Feature: Database lifecycle management
     And a database is created from the template
     Then the cloned database exists
     And the cloned database contains the template data
+
+  Scenario: Incomplete template is rebuilt instead of cloned
+    Given a sandboxed TestCluster is running
+    When a template database is left with a building marker
+    And ensure_template_exists is called with a setup function
+    Then the template database exists
+    And the setup function was called exactly once
+
+  Scenario: Migrated template is built from SQL migrations
+    Given a sandboxed TestCluster is running
+    When a migrated template is built from the SQL migrations fixture
+    Then the migrated template is flagged as a template
+    And a clone of the migrated template contains the seeded rows
+
+  Scenario: Unmarked template is used as it is
+    Given a sandboxed TestCluster is running
+    When a template database exists without a marker
+    And ensure_template_exists is called with a setup function
+    Then the template database exists
+    And the setup function was not called
+
+  Scenario: Test transaction rolls back nested commits
+    Given a sandboxed TestCluster is running
+    When a test transaction commits a nested transaction
+    Then the nested commit was visible inside the test transaction
+    And the nested commit is gone after the test transaction
+
+  Scenario: External attachment sweep keeps templates
+    Given a sandboxed TestCluster is running
+    When an external attachment builds a migrated template and a database
+    Then the attachment's database was swept
+    And the migrated template is flagged as a template
+    And a clone of the migrated template contains the seeded rows
+
+  Scenario: Test transaction guard rolls back when dropped
+    Given a sandboxed TestCluster is running
+    When a test transaction guard commits a nested transaction and drops
+    Then the nested commit was visible inside the test transaction
+    And the nested commit is gone after the test transaction
+
+  Scenario: Interrupted template build is discarded
+    Given a sandboxed TestCluster is running
+    When a template build is interrupted before its setup finishes
+    And ensure_template_exists is called with a setup function
+    Then the template database exists
+    And the setup function was called exactly once
+    And no staging database is left behind
@@ -0,0 +1 @@
+INSERT INTO widgets (name) VALUES ('sprocket'), ('gear');
@@ -0,0 +1,55 @@
+//! Integration coverage for the `pglite-oxide` backend.
+//!
+//! The first run compiles the WebAssembly runtime, which can take minutes;
+//! later runs reuse the compiled module from the caller's cache.
+
+use std::ffi::OsString;
+
+use color_eyre::eyre::{Context, Result, ensure};
+use pg_embedded_setup_unpriv::TestCluster;
+use pg_embedded_setup_unpriv::test_support::scoped_env;
+
+// The `pg_tables` view aborts the WebAssembly backend, so probe the catalog
+// through `to_regclass` instead.
+const PROBE_TABLE_SQL: &str = "SELECT to_regclass('probe') IS NULL";
+
+#[test]
+fn pglite_cluster_isolates_tests_with_transactions() -> Result<()> {
+    let _env = scoped_env([(
+        OsString::from("PG_TEST_BACKEND"),
+        Some(OsString::from("pglite_oxide")),
+    )]);
+    let cluster = TestCluster::new().context("start pglite cluster")?;
+    let metadata = cluster.connection().metadata();
+    ensure!(
+        metadata.admin_database() == "template1",
+        "pglite serves template1, got {}",
+        metadata.admin_database()
+    );
+    let sandbox = metadata.pgpass_file().parent().map(ToOwned::to_owned);
+
+    {
+        let mut transaction = cluster.test_transaction("template1")?;
+        transaction.batch_execute("CREATE TABLE probe (id INT)")?;
+        let answer: i32 = transaction.query_one("SELECT 42", &[])?.get(0);
+        ensure!(answer == 42, "expected 42, got {answer}");
+    }
+    let mut transaction = cluster.test_transaction("template1")?;
+    let missing: bool = transaction.query_one(PROBE_TABLE_SQL, &[])?.get(0);
+    ensure!(missing, "the dropped guard should have rolled back");
+    drop(transaction);
+
+    let err = cluster
+        .create_database("other_db")
+        .expect_err("pglite serves a single database");
+    ensure!(
+        err.to_string().contains("PG_TEST_BACKEND=pglite_oxide"),
+        "unexpected error: {err}"
+    );
+
+    drop(cluster);
+    if let Some(dir) = sandbox {
+        ensure!(!dir.exists(), "the guard should remove the sandbox {dir}");
+    }
+    Ok(())
+}
@@ -45,6 +45,7 @@ fn create_minimal_worker_config(temp_dir: &Path, data_dir: &Path) -> Result<std:
     let payload = WorkerPayload {
         settings: snapshot,
         environment: vec![],
+        pg_hba: None,
     };
 
     let config_path = temp_dir.join("config.json");
@@ -153,7 +154,7 @@ fn pg_worker_binary_detects_partial_data_dir_and_triggers_recovery() -> Result<(
 //
 // The validation that a valid data directory (with `global/pg_filenode.map`)
 // is NOT removed by recovery is covered by the unit test
-// `has_valid_data_dir::valid_data_dir_detected` in `src/bin/pg_worker.rs`.
+// `has_valid_data_dir::valid_data_dir_detected` in `src/worker_entry/tests.rs`.
 //
 // We cannot easily test that a valid data directory survives the full
 // binary invocation because `pg.setup()` may modify or reset the data directory
@@ -124,6 +124,23 @@ fn to_settings_for_tests_applies_worker_limits(default_pg_env: PgEnvCfg) -> colo
     Ok(())
 }
 
+#[rstest]
+fn to_settings_for_tests_routes_server_log(default_pg_env: PgEnvCfg) -> color_eyre::Result<()> {
+    let settings = default_pg_env.to_settings_for_tests()?;
+
+    for (key, expected) in [("logging_collector", "on"), ("log_filename", "server.log")] {
+        ensure!(
+            settings
+                .configuration
+                .get(key)
+                .is_some_and(|value| value == expected),
+            "expected {key} = {expected} for tests",
+        );
+    }
+
+    Ok(())
+}
+
 #[rstest]
 fn to_settings_omits_worker_limits_by_default(default_pg_env: PgEnvCfg) -> color_eyre::Result<()> {
     let settings = default_pg_env.to_settings()?;
@@ -53,6 +53,8 @@ pub struct DatabaseWorld {
     pub skip_reason: Option<String>,
     pub bootstrap_error: Option<String>,
     pub temp_database: Option<TemporaryDatabase>,
+    pub migrated_template: Option<String>,
+    pub nested_commit_rows: Option<i64>,
     pub setup_call_count_at_start: usize,
 }
 
@@ -71,6 +73,8 @@ impl DatabaseWorld {
             skip_reason: None,
             bootstrap_error: None,
             temp_database: None,
+            migrated_template: None,
+            nested_commit_rows: None,
             setup_call_count_at_start: SETUP_CALL_COUNT.load(Ordering::SeqCst),
         })
     }
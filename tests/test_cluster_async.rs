@@ -9,7 +9,7 @@
 
 #![cfg(all(unix, feature = "async-api"))]
 
-use color_eyre::eyre::{Result, ensure};
+use color_eyre::eyre::{Result, WrapErr, ensure};
 use pg_embedded_setup_unpriv::test_support::scoped_env;
 use pg_embedded_setup_unpriv::{ScopedEnv, TestCluster};
 use rstest::rstest;
@@ -145,3 +145,145 @@ async fn async_drop_without_stop_does_not_panic(
 
     Ok(())
 }
+
+/// Verifies that the async lifecycle methods create, clone, and drop
+/// databases without blocking the test runtime.
+#[rstest]
+#[tokio::test(flavor = "current_thread")]
+async fn async_lifecycle_methods_manage_databases(
+    local_serial_guard: ScenarioLocalGuard,
+) -> Result<()> {
+    let _guard = local_serial_guard;
+    let (cluster, _sandbox, _env_guard) = start_sandboxed_cluster().await?;
+    let connection = cluster.connection();
+
+    connection
+        .create_database_async("async_lifecycle_db")
+        .await?;
+    ensure!(
+        connection
+            .database_exists_async("async_lifecycle_db")
+            .await?,
+        "database should exist after create_database_async"
+    );
+    connection.drop_database_async("async_lifecycle_db").await?;
+    ensure!(
+        !connection
+            .database_exists_async("async_lifecycle_db")
+            .await?,
+        "database should not exist after drop_database_async"
+    );
+
+    cluster.stop_async().await?;
+    Ok(())
+}
+
+/// Verifies that `ensure_template_exists_async` runs its setup future once
+/// and that temporary clones are cleaned up by both explicit and implicit
+/// drops.
+#[rstest]
+#[tokio::test(flavor = "current_thread")]
+async fn async_template_and_temporary_database_round_trip(
+    local_serial_guard: ScenarioLocalGuard,
+) -> Result<()> {
+    let _guard = local_serial_guard;
+    let (cluster, _sandbox, _env_guard) = start_sandboxed_cluster().await?;
+    let connection = cluster.connection();
+    let calls = std::sync::atomic::AtomicUsize::new(0);
+
+    for _ in 0..2 {
+        connection
+            .ensure_template_exists_async("async_template", |_| async {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(())
+            })
+            .await?;
+    }
+    ensure!(
+        calls.load(std::sync::atomic::Ordering::SeqCst) == 1,
+        "setup future should run exactly once"
+    );
+
+    let explicit = connection
+        .temporary_database_from_template_async("async_clone_explicit", "async_template")
+        .await?;
+    explicit.drop_database_async().await?;
+
+    // Dropping the guard inside the runtime must not panic.
+    let implicit = connection
+        .temporary_database_async("async_clone_implicit")
+        .await?;
+    drop(implicit);
+    ensure!(
+        !connection
+            .database_exists_async("async_clone_implicit")
+            .await?,
+        "temporary database should be dropped by its guard"
+    );
+
+    cluster.stop_async().await?;
+    Ok(())
+}
+
+/// Verifies that a nested commit inside an async test transaction is only a
+/// savepoint and disappears with the test transaction.
+#[rstest]
+#[tokio::test(flavor = "current_thread")]
+async fn async_test_transaction_rolls_back_nested_commits(
+    local_serial_guard: ScenarioLocalGuard,
+) -> Result<()> {
+    let _guard = local_serial_guard;
+    let (cluster, _sandbox, _env_guard) = start_sandboxed_cluster().await?;
+
+    let rows = cluster
+        .with_test_transaction_async("postgres", async |tx| {
+            let inner = tx.transaction().await.wrap_err("open nested transaction")?;
+            inner
+                .batch_execute("CREATE TABLE tx_probe (id int); INSERT INTO tx_probe VALUES (1)")
+                .await
+                .wrap_err("populate probe table")?;
+            inner.commit().await.wrap_err("commit nested transaction")?;
+            let row = tx
+                .query_one("SELECT count(*) FROM tx_probe", &[])
+                .await
+                .wrap_err("count probe rows")?;
+            Ok(row.get::<_, i64>(0))
+        })
+        .await?;
+    ensure!(
+        rows == 1,
+        "nested commit should be visible inside the test transaction"
+    );
+
+    let mut guard = cluster.test_transaction_async("postgres").await?;
+    let inner = guard
+        .transaction()
+        .await
+        .wrap_err("open nested transaction")?;
+    inner
+        .batch_execute("CREATE TABLE tx_guard_probe (id int)")
+        .await
+        .wrap_err("create guard probe table")?;
+    inner.commit().await.wrap_err("commit nested transaction")?;
+    guard.rollback().await?;
+
+    let rolled_back = cluster
+        .with_test_transaction_async("postgres", async |tx| {
+            let row = tx
+                .query_one(
+                    "SELECT to_regclass('tx_probe') IS NULL AND to_regclass('tx_guard_probe') IS NULL",
+                    &[],
+                )
+                .await
+                .wrap_err("look up probe table")?;
+            Ok(row.get::<_, bool>(0))
+        })
+        .await?;
+    ensure!(
+        rolled_back,
+        "probe tables should be rolled back with the test transactions"
+    );
+
+    cluster.stop_async().await?;
+    Ok(())
+}